@@ -265,8 +265,7 @@ async def convert_heap_dump(
     # Determine session ID before creating directories
     sid = session_id or hprof.stem
 
-    # Create a session-specific working directory next to the HPROF file.
-    # The Rust binary writes to parquet/ relative to CWD, so we get:
+    # Create a session-specific working directory next to the HPROF file:
     #   <hprof_parent>/<session_id>/parquet/
     work_dir = hprof.parent / sid
     work_dir.mkdir(parents=True, exist_ok=True)
@@ -277,6 +276,7 @@ async def convert_heap_dump(
         "-f", str(hprof),
         "dump-objects-to-parquet",
         "--flush-rows", str(flush_rows),
+        "--out-dir", str(parquet_dir),
         "--robo-mode",
     ]
 
@@ -0,0 +1,253 @@
+// SPDX-License-Identifier: MIT
+// Copyright (c) 2026 Zac Policzer
+
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
+use std::sync::atomic::AtomicBool;
+use std::sync::{Arc, OnceLock};
+use jvm_hprof::{Hprof, Id, IdSize, Record, RecordTag};
+use jvm_hprof::heap_dump::{FieldValue, SubRecord};
+use crate::commands::{approximate_class_histogram, class_histogram, tally, ClassFilter, ClassStat};
+use crate::cancel::cancelled;
+use crate::edges::instance_field_names;
+use crate::error::AnalyzeError;
+use crate::hprof_index::{HprofIndex, Utf8Mode};
+use crate::skip_errors::sub_records;
+
+/// A field value with references resolved to the referenced object's type.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ResolvedValue {
+    Null,
+    Reference { id: u64, type_name: String },
+    Boolean(bool),
+    Char(u16),
+    Float(f32),
+    Double(f64),
+    Byte(i8),
+    Short(i16),
+    Int(i32),
+    Long(i64),
+}
+
+impl ResolvedValue {
+    fn resolve(value: FieldValue, index: &HprofIndex) -> Self {
+        match value {
+            FieldValue::ObjectId(None) => ResolvedValue::Null,
+            FieldValue::ObjectId(Some(id)) => ResolvedValue::Reference {
+                id: id.id(),
+                type_name: index.resolve_ref_type(id).into_owned(),
+            },
+            FieldValue::Boolean(v) => ResolvedValue::Boolean(v),
+            FieldValue::Char(v) => ResolvedValue::Char(v),
+            FieldValue::Float(v) => ResolvedValue::Float(v),
+            FieldValue::Double(v) => ResolvedValue::Double(v),
+            FieldValue::Byte(v) => ResolvedValue::Byte(v),
+            FieldValue::Short(v) => ResolvedValue::Short(v),
+            FieldValue::Int(v) => ResolvedValue::Int(v),
+            FieldValue::Long(v) => ResolvedValue::Long(v),
+        }
+    }
+}
+
+/// One instance with its fields decoded, subclass fields first. Field names are the
+/// instance parquet column names, so shadowed superclass fields are `Parent@name`.
+#[derive(Debug, Clone)]
+pub struct ResolvedInstance<'a> {
+    pub obj_id: u64,
+    pub class_name: &'a str,
+    pub fields: Vec<(String, ResolvedValue)>,
+}
+
+impl ResolvedInstance<'_> {
+    pub fn field(&self, name: &str) -> Option<&ResolvedValue> {
+        self.fields.iter().find(|(n, _)| n == name).map(|(_, v)| v)
+    }
+}
+
+/// How a `HeapDumpAnalyzer` builds its index, and how its passes over the dump can be
+/// stopped.
+#[derive(Debug, Clone, Default)]
+pub struct AnalyzerOptions {
+    /// Keep the obj id -> class map in a memory-mapped sorted file under this directory
+    /// instead of an in-memory hash map: slower lookups, but it no longer counts against
+    /// the heap for dumps with hundreds of millions of objects.
+    pub off_heap_dir: Option<PathBuf>,
+    /// What a Utf8 record whose bytes aren't valid UTF-8 becomes.
+    pub utf8: Utf8Mode,
+    /// Storing `true` here, from any thread, stops every pass over the dump at its next
+    /// record or sub-record. `cancel_after` sets it after a timeout, as `--timeout` does.
+    pub cancel: Arc<AtomicBool>,
+    /// Cut a heap dump segment short at a sub-record that doesn't parse, with a warning,
+    /// and carry on with the next one, instead of failing (`--skip-errors`).
+    pub skip_errors: bool,
+}
+
+/// Entry point for embedding the analyses: wraps a parsed heap dump and builds the
+/// class/utf8/object index on first use, then shares it across every query. The commands
+/// take one too, so running several against the same dump indexes it once.
+///
+/// ```ignore
+/// let hprof = jvm_hprof::parse_hprof(&mmap[..])?;
+/// let analyzer = HeapDumpAnalyzer::new(&hprof);
+/// for stat in analyzer.class_histogram()?.iter().take(10) {
+///     println!("{} {}", stat.class_name, stat.total_shallow_bytes);
+/// }
+/// ```
+pub struct HeapDumpAnalyzer<'a> {
+    hprof: &'a Hprof<'a>,
+    options: AnalyzerOptions,
+    indexed: OnceLock<(HprofIndex<'a>, Vec<Record<'a>>)>,
+}
+
+impl<'a> HeapDumpAnalyzer<'a> {
+    pub fn new(hprof: &'a Hprof<'a>) -> Self {
+        Self::with_options(hprof, AnalyzerOptions::default())
+    }
+
+    pub fn with_options(hprof: &'a Hprof<'a>, options: AnalyzerOptions) -> Self {
+        HeapDumpAnalyzer { hprof, options, indexed: OnceLock::new() }
+    }
+
+    pub fn hprof(&self) -> &'a Hprof<'a> {
+        self.hprof
+    }
+
+    pub fn options(&self) -> &AnalyzerOptions {
+        &self.options
+    }
+
+    /// Whether `options().cancel` has been set, so anything read since is partial.
+    pub fn cancelled(&self) -> bool {
+        cancelled(&self.options.cancel)
+    }
+
+    /// Heap dump segments cut short by a bad sub-record under `options().skip_errors`,
+    /// counted once when the index is built, however many passes read them after that.
+    /// 0 until something has built the index.
+    pub fn skipped_segments(&self) -> u64 {
+        self.indexed.get().map_or(0, |(index, _)| index.skipped_segments)
+    }
+
+    /// The index and the heap dump segment records, built by the first caller.
+    pub(crate) fn indexed(&self) -> Result<(&HprofIndex<'a>, &[Record<'a>]), AnalyzeError> {
+        if self.indexed.get().is_none() {
+            let built = HprofIndex::build_with_options(self.hprof, &self.options)?;
+            // Another thread may have built it first; either copy will do
+            let _ = self.indexed.set(built);
+        }
+        let (index, segments) = self.indexed.get().unwrap();
+        Ok((index, segments))
+    }
+
+    /// Number of top-level records of each tag, including tags that don't occur. For their
+    /// sizes as well, see `record_totals`.
+    pub fn record_counts(&self) -> Result<HashMap<RecordTag, u64>, AnalyzeError> {
+        let records = self.hprof.records_iter()
+            .take_while(|_| !self.cancelled())
+            .map(|r| Ok((r.map_err(|_| AnalyzeError::parse("top level record"))?.tag(), 0)));
+        Ok(tally(records)?.into_iter().map(|(tag, totals)| (tag, totals.count)).collect())
+    }
+
+    /// Instance count and total shallow size per class, largest total first.
+    pub fn class_histogram(&self) -> Result<Vec<ClassStat>, AnalyzeError> {
+        let (index, segments) = self.indexed()?;
+        class_histogram(self.hprof, index, segments)
+    }
+
+    /// `class_histogram` estimated from a deterministic sample of `fraction` (0 < fraction
+    /// <= 1) of the heap dump segments, with counts and shallow sizes scaled up to the whole
+    /// dump. The other segments aren't parsed. Classes with no object in the sampled segments
+    /// are left out.
+    pub fn approximate_class_histogram(&self, fraction: f64) -> Result<Vec<ClassStat>, AnalyzeError> {
+        let (index, segments) = self.indexed()?;
+        approximate_class_histogram(self.hprof, index, segments, fraction)
+    }
+
+    /// Every instance of exactly `class_name`, given in either internal (`java/util/HashMap`)
+    /// or dotted form. Segments are decoded one at a time as the iterator advances. Like
+    /// `std::fs::read_dir`, building the index can fail up front and each instance can fail
+    /// on its own; a segment that fails to decode yields its error and nothing else.
+    pub fn instances_of(
+        &self,
+        class_name: &str,
+    ) -> Result<impl Iterator<Item = Result<ResolvedInstance<'_>, AnalyzeError>> + '_, AnalyzeError> {
+        let (index, segments) = self.indexed()?;
+        let filter = ClassFilter::Exact(class_name.to_string());
+        let class_ids: HashSet<Id> = index.classes.iter()
+            .filter(|(_, c)| filter.matches(c.name))
+            .map(|(id, _)| *id)
+            .collect();
+        let field_names: HashMap<Id, Vec<String>> = instance_field_names(index).into_iter()
+            .filter(|(id, _)| class_ids.contains(id))
+            .collect();
+        let id_size = self.hprof.header().id_size();
+
+        Ok(segments.iter().flat_map(move |record| {
+            if class_ids.is_empty() {
+                return Vec::new();
+            }
+            match segment_instances(record, index, &class_ids, &field_names, id_size) {
+                Ok(instances) => instances.into_iter().map(Ok).collect(),
+                Err(e) => vec![Err(e)],
+            }
+        }))
+    }
+}
+
+/// The instances in `record` of the classes in `class_ids`, in dump order.
+fn segment_instances<'i>(
+    record: &Record,
+    index: &'i HprofIndex,
+    class_ids: &HashSet<Id>,
+    field_names: &HashMap<Id, Vec<String>>,
+    id_size: IdSize,
+) -> Result<Vec<ResolvedInstance<'i>>, AnalyzeError> {
+    let segment = record.as_heap_dump_segment().unwrap()
+        .map_err(|_| AnalyzeError::parse("heap dump segment"))?;
+    let mut instances = Vec::new();
+    for p in sub_records(&index.options, segment.sub_records()) {
+        let SubRecord::Instance(instance) = p? else { continue };
+        let class_id = instance.class_obj_id();
+        if !class_ids.contains(&class_id) {
+            continue;
+        }
+        let fds = index.class_instance_field_descriptors.get(&class_id).map_or(&[][..], Vec::as_slice);
+        let names = field_names.get(&class_id).map_or(&[][..], Vec::as_slice);
+        let mut input: &[u8] = instance.fields();
+        let mut fields = Vec::with_capacity(fds.len());
+        for (fd, name) in fds.iter().zip(names) {
+            let (rest, value) = fd.field_type().parse_value(input, id_size)
+                .map_err(|_| AnalyzeError::parse(format!("fields of instance {}", instance.obj_id())))?;
+            input = rest;
+            fields.push((name.clone(), ResolvedValue::resolve(value, index)));
+        }
+        instances.push(ResolvedInstance {
+            obj_id: instance.obj_id().id(),
+            class_name: index.classes[&class_id].name,
+            fields,
+        });
+    }
+    Ok(instances)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resolved_instance_field_lookup() {
+        let instance = ResolvedInstance {
+            obj_id: 1,
+            class_name: "com/example/Child",
+            fields: vec![
+                ("count".to_string(), ResolvedValue::Int(3)),
+                ("Parent@count".to_string(), ResolvedValue::Int(7)),
+                ("next".to_string(), ResolvedValue::Null),
+            ],
+        };
+        assert_eq!(instance.field("count"), Some(&ResolvedValue::Int(3)));
+        assert_eq!(instance.field("Parent@count"), Some(&ResolvedValue::Int(7)));
+        assert_eq!(instance.field("next"), Some(&ResolvedValue::Null));
+        assert_eq!(instance.field("missing"), None);
+    }
+}
@@ -0,0 +1,297 @@
+// SPDX-License-Identifier: MIT
+// Copyright (c) 2026 Zac Policzer
+
+//! Avro object container files from the export's Arrow batches, for `--format avro`. The
+//! Arrow schema is mapped to an Avro record schema once per file and each batch becomes one
+//! data block, so the column construction is the same as for parquet.
+//!
+//! `apache_avro::Writer` borrows its schema for as long as the file is open, which doesn't
+//! fit a writer kept open across segments, so the container framing is written here and
+//! only the records are encoded by `apache_avro`.
+
+use std::collections::hash_map::RandomState;
+use std::collections::HashSet;
+use std::fs::File;
+use std::hash::{BuildHasher, Hasher};
+use std::io::Write;
+use apache_avro::types::Value;
+use apache_avro::Schema as AvroSchema;
+use arrow_array::cast::AsArray;
+use arrow_array::types::{
+    Float32Type, Float64Type, Int16Type, Int32Type, Int64Type, Int8Type, UInt16Type, UInt32Type, UInt64Type,
+};
+use arrow_array::{Array, RecordBatch};
+use arrow_schema::{DataType, Fields, Schema};
+
+const MAGIC: &[u8; 4] = b"Obj\x01";
+
+/// Avro names are `[A-Za-z_][A-Za-z0-9_]*`. Other characters, such as the `@` of a shadowed
+/// field's `Parent@name` column, become `_`, with a numeric suffix where that would make two
+/// names the same.
+fn avro_names<'f>(names: impl Iterator<Item = &'f str>) -> Vec<String> {
+    let mut taken = HashSet::new();
+    names
+        .map(|name| {
+            let mut candidate: String = name.chars()
+                .map(|c| if c.is_ascii_alphanumeric() || c == '_' { c } else { '_' })
+                .collect();
+            if !candidate.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_') {
+                candidate.insert(0, '_');
+            }
+            let mut name = candidate.clone();
+            let mut suffix = 2;
+            while !taken.insert(name.clone()) {
+                name = format!("{}_{}", candidate, suffix);
+                suffix += 1;
+            }
+            name
+        })
+        .collect()
+}
+
+/// The Avro JSON schema of a record with `fields`. Nested records are named after their
+/// path, `<record>_<field>`, since Avro names must be unique within a schema.
+fn record_json(name: &str, fields: &Fields) -> String {
+    let names = avro_names(fields.iter().map(|f| f.name().as_str()));
+    let fields: Vec<String> = fields.iter().zip(&names)
+        .map(|(field, field_name)| {
+            let field_type = type_json(&format!("{}_{}", name, field_name), field.data_type());
+            match field.is_nullable() {
+                true => format!(r#"{{"name":"{}","type":["null",{}],"default":null}}"#, field_name, field_type),
+                false => format!(r#"{{"name":"{}","type":{}}}"#, field_name, field_type),
+            }
+        })
+        .collect();
+    format!(r#"{{"type":"record","name":"{}","fields":[{}]}}"#, name, fields.join(","))
+}
+
+fn type_json(name: &str, data_type: &DataType) -> String {
+    match data_type {
+        DataType::Boolean => r#""boolean""#.to_string(),
+        DataType::Int8 | DataType::Int16 | DataType::Int32 | DataType::UInt16 => r#""int""#.to_string(),
+        // Avro has no unsigned types; ids keep their bits as a signed long
+        DataType::Int64 | DataType::UInt32 | DataType::UInt64 => r#""long""#.to_string(),
+        DataType::Float32 => r#""float""#.to_string(),
+        DataType::Float64 => r#""double""#.to_string(),
+        DataType::Utf8 | DataType::Dictionary(_, _) => r#""string""#.to_string(),
+        DataType::Binary => r#""bytes""#.to_string(),
+        DataType::List(item) => {
+            let items = type_json(name, item.data_type());
+            match item.is_nullable() {
+                true => format!(r#"{{"type":"array","items":["null",{}]}}"#, items),
+                false => format!(r#"{{"type":"array","items":{}}}"#, items),
+            }
+        }
+        DataType::Struct(fields) => record_json(name, fields),
+        other => unreachable!("the export writes no {} columns", other),
+    }
+}
+
+/// The Avro JSON schema for batches of `schema`, a record named `name`.
+pub(crate) fn avro_schema_json(name: &str, schema: &Schema) -> String {
+    record_json(&avro_names(std::iter::once(name))[0], schema.fields())
+}
+
+/// The Avro names of a record's fields, and of the fields of each struct among them or
+/// inside a list, worked out once per file instead of for every row.
+struct RecordNames {
+    names: Vec<String>,
+    nested: Vec<Option<RecordNames>>,
+}
+
+impl RecordNames {
+    fn new(fields: &Fields) -> Self {
+        RecordNames {
+            names: avro_names(fields.iter().map(|f| f.name().as_str())),
+            nested: fields.iter().map(|f| nested_fields(f.data_type()).map(RecordNames::new)).collect(),
+        }
+    }
+}
+
+/// The fields of a struct column, or of the structs in a list column.
+fn nested_fields(data_type: &DataType) -> Option<&Fields> {
+    match data_type {
+        DataType::Struct(fields) => Some(fields),
+        DataType::List(item) => nested_fields(item.data_type()),
+        _ => None,
+    }
+}
+
+/// One cell as the Avro value its schema expects; a nullable cell is a `["null", T]` union.
+/// `names` are those of a struct cell's fields, or of the structs in a list cell.
+fn avro_value(array: &dyn Array, row: usize, nullable: bool, names: Option<&RecordNames>) -> Value {
+    if nullable {
+        return match array.is_null(row) {
+            true => Value::Union(0, Box::new(Value::Null)),
+            false => Value::Union(1, Box::new(avro_value(array, row, false, names))),
+        };
+    }
+    match array.data_type() {
+        DataType::Boolean => Value::Boolean(array.as_boolean().value(row)),
+        DataType::Int8 => Value::Int(array.as_primitive::<Int8Type>().value(row).into()),
+        DataType::Int16 => Value::Int(array.as_primitive::<Int16Type>().value(row).into()),
+        DataType::Int32 => Value::Int(array.as_primitive::<Int32Type>().value(row)),
+        DataType::UInt16 => Value::Int(array.as_primitive::<UInt16Type>().value(row).into()),
+        DataType::Int64 => Value::Long(array.as_primitive::<Int64Type>().value(row)),
+        DataType::UInt32 => Value::Long(array.as_primitive::<UInt32Type>().value(row).into()),
+        DataType::UInt64 => Value::Long(array.as_primitive::<UInt64Type>().value(row) as i64),
+        DataType::Float32 => Value::Float(array.as_primitive::<Float32Type>().value(row)),
+        DataType::Float64 => Value::Double(array.as_primitive::<Float64Type>().value(row)),
+        DataType::Utf8 => Value::String(array.as_string::<i32>().value(row).to_string()),
+        DataType::Dictionary(_, _) => {
+            let dictionary = array.as_dictionary::<Int32Type>();
+            let key = dictionary.keys().value(row) as usize;
+            Value::String(dictionary.values().as_string::<i32>().value(key).to_string())
+        }
+        DataType::Binary => Value::Bytes(array.as_binary::<i32>().value(row).to_vec()),
+        DataType::List(item) => {
+            let elements = array.as_list::<i32>().value(row);
+            Value::Array((0..elements.len()).map(|i| avro_value(&elements, i, item.is_nullable(), names)).collect())
+        }
+        DataType::Struct(fields) => {
+            let columns = array.as_struct().columns();
+            record_value(fields, columns, row, names.expect("struct columns have their field names"))
+        }
+        other => unreachable!("the export writes no {} columns", other),
+    }
+}
+
+fn record_value(fields: &Fields, columns: &[arrow_array::ArrayRef], row: usize, names: &RecordNames) -> Value {
+    Value::Record(
+        names.names.iter().zip(&names.nested).zip(fields.iter().zip(columns))
+            .map(|((name, nested), (field, column))| {
+                (name.clone(), avro_value(column, row, field.is_nullable(), nested.as_ref()))
+            })
+            .collect(),
+    )
+}
+
+/// Avro's zig-zag varint encoding of a long, used for every count and length in the framing.
+fn encode_long(value: i64, out: &mut Vec<u8>) {
+    let mut n = ((value << 1) ^ (value >> 63)) as u64;
+    while n >= 0x80 {
+        out.push((n as u8 & 0x7f) | 0x80);
+        n >>= 7;
+    }
+    out.push(n as u8);
+}
+
+fn encode_bytes(bytes: &[u8], out: &mut Vec<u8>) {
+    encode_long(bytes.len() as i64, out);
+    out.extend_from_slice(bytes);
+}
+
+/// An open `.avro` file: the header is written on creation and a data block per batch.
+/// Errors come back as I/O errors for the caller to name the file in, encoding failures
+/// included.
+pub(crate) struct AvroFileWriter {
+    file: File,
+    schema: AvroSchema,
+    names: RecordNames,
+    sync: [u8; 16],
+    pub rows: usize,
+    pub bytes: u64,
+}
+
+impl AvroFileWriter {
+    pub(crate) fn create(mut file: File, name: &str, schema: &Schema) -> std::io::Result<Self> {
+        let json = avro_schema_json(name, schema);
+        let avro = AvroSchema::parse_str(&json).map_err(std::io::Error::other)?;
+        let mut sync = [0_u8; 16];
+        for half in sync.chunks_mut(8) {
+            half.copy_from_slice(&RandomState::new().build_hasher().finish().to_le_bytes());
+        }
+
+        let mut header = MAGIC.to_vec();
+        // File metadata: one block of two entries, then the empty block ending the map
+        encode_long(2, &mut header);
+        encode_bytes(b"avro.schema", &mut header);
+        encode_bytes(json.as_bytes(), &mut header);
+        encode_bytes(b"avro.codec", &mut header);
+        encode_bytes(b"null", &mut header);
+        encode_long(0, &mut header);
+        header.extend_from_slice(&sync);
+        file.write_all(&header)?;
+
+        let names = RecordNames::new(schema.fields());
+        Ok(AvroFileWriter { file, schema: avro, names, sync, rows: 0, bytes: header.len() as u64 })
+    }
+
+    /// Write `batch`, which has the schema the file was created with, as one data block.
+    pub(crate) fn write(&mut self, batch: &RecordBatch) -> std::io::Result<()> {
+        if batch.num_rows() == 0 {
+            return Ok(());
+        }
+        let schema = batch.schema();
+        let mut records = Vec::new();
+        for row in 0..batch.num_rows() {
+            let record = record_value(schema.fields(), batch.columns(), row, &self.names);
+            records.extend(apache_avro::to_avro_datum(&self.schema, record).map_err(std::io::Error::other)?);
+        }
+
+        let mut block = Vec::with_capacity(records.len() + 36);
+        encode_long(batch.num_rows() as i64, &mut block);
+        encode_bytes(&records, &mut block);
+        block.extend_from_slice(&self.sync);
+        self.file.write_all(&block)?;
+        self.rows += batch.num_rows();
+        self.bytes += block.len() as u64;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use arrow_array::{ArrayRef, StringArray, StructArray, UInt64Array};
+    use arrow_schema::Field;
+
+    #[test]
+    fn test_avro_names_are_valid_and_unique() {
+        let names = avro_names(["obj_id", "Base@count", "Base_count", "$assertionsDisabled", "9lives"].into_iter());
+        assert_eq!(names, vec!["obj_id", "Base_count", "Base_count_2", "_assertionsDisabled", "_9lives"]);
+    }
+
+    #[test]
+    fn test_encode_long_zig_zags() {
+        let encoded = |value| {
+            let mut out = Vec::new();
+            encode_long(value, &mut out);
+            out
+        };
+        assert_eq!(encoded(0), vec![0x00]);
+        assert_eq!(encoded(-1), vec![0x01]);
+        assert_eq!(encoded(1), vec![0x02]);
+        assert_eq!(encoded(64), vec![0x80, 0x01]);
+    }
+
+    #[test]
+    fn test_reference_struct_becomes_nested_record() {
+        let reference = Fields::from(vec![
+            Field::new("id", DataType::UInt64, false),
+            Field::new("type", DataType::Utf8, false),
+        ]);
+        let schema = Schema::new(vec![
+            Field::new("obj_id", DataType::UInt64, false),
+            Field::new("next", DataType::Struct(reference.clone()), true),
+        ]);
+        let json = avro_schema_json("com.example.Node_200", &schema);
+        assert!(AvroSchema::parse_str(&json).is_ok(), "{}", json);
+        assert!(json.contains(r#""name":"com_example_Node_200_next""#), "{}", json);
+
+        let next = StructArray::new(
+            reference,
+            vec![
+                Arc::new(UInt64Array::from(vec![1001])) as ArrayRef,
+                Arc::new(StringArray::from(vec!["com/example/Node"])),
+            ],
+            None,
+        );
+        let value = avro_value(&next, 0, true, Some(&RecordNames::new(&reference)));
+        assert_eq!(value, Value::Union(1, Box::new(Value::Record(vec![
+            ("id".to_string(), Value::Long(1001)),
+            ("type".to_string(), Value::String("com/example/Node".to_string())),
+        ]))));
+    }
+}
@@ -0,0 +1,105 @@
+// SPDX-License-Identifier: MIT
+// Copyright (c) 2026 Zac Policzer
+
+//! Values of boxed primitives (`java.lang.Integer` and friends), so a reference to one can
+//! be shown with the number it holds.
+
+use jvm_hprof::{Hprof, Record};
+use jvm_hprof::heap_dump::{FieldType, FieldValue, SubRecord};
+use rayon::prelude::*;
+use crate::hprof_index::HprofIndex;
+use crate::id_hash::IdMap;
+use crate::skip_errors::sub_records;
+
+/// Each wrapper class and the type of its `value` field.
+const BOXED_CLASSES: [(&str, FieldType); 8] = [
+    ("java/lang/Boolean", FieldType::Boolean),
+    ("java/lang/Character", FieldType::Char),
+    ("java/lang/Float", FieldType::Float),
+    ("java/lang/Double", FieldType::Double),
+    ("java/lang/Byte", FieldType::Byte),
+    ("java/lang/Short", FieldType::Short),
+    ("java/lang/Integer", FieldType::Int),
+    ("java/lang/Long", FieldType::Long),
+];
+
+/// For each wrapper class in the dump, the position of its `value` field in the flattened
+/// field list. A class whose `value` isn't of the expected type is left out.
+fn boxed_layouts(index: &HprofIndex) -> IdMap<usize> {
+    index.classes.iter()
+        .filter_map(|(class_obj_id, c)| {
+            let (_, value_type) = BOXED_CLASSES.iter().find(|(name, _)| *name == c.name)?;
+            let fds = index.class_instance_field_descriptors.get(class_obj_id)?;
+            let position = fds.iter().position(|fd| {
+                fd.field_type() == *value_type && index.utf8.get(&fd.name_id()).copied() == Some("value")
+            })?;
+            Some((*class_obj_id, position))
+        })
+        .collect()
+}
+
+/// The value of every boxed primitive in the dump, keyed by the wrapper's object id. One
+/// parallel pass over the segments; empty if the dump has no wrapper classes.
+pub fn decode_boxed(hprof: &Hprof, index: &HprofIndex, segments: &[Record]) -> IdMap<FieldValue> {
+    let layouts = boxed_layouts(index);
+    if layouts.is_empty() {
+        return IdMap::default();
+    }
+    let id_size = hprof.header().id_size();
+
+    segments.par_iter().flat_map_iter(|record| {
+        let mut found = Vec::new();
+        let segment = record.as_heap_dump_segment().unwrap().unwrap();
+        for p in sub_records(&index.options, segment.sub_records()) {
+            let SubRecord::Instance(instance) = p.unwrap() else { continue };
+            let Some(&value_field) = layouts.get(&instance.class_obj_id()) else { continue };
+            let fds = &index.class_instance_field_descriptors[&instance.class_obj_id()];
+            let mut input: &[u8] = instance.fields();
+            for (i, fd) in fds.iter().enumerate().take(value_field + 1) {
+                let Ok((rest, value)) = fd.field_type().parse_value(input, id_size) else { break };
+                input = rest;
+                if i == value_field {
+                    found.push((instance.obj_id(), value));
+                }
+            }
+        }
+        found
+    }).collect()
+}
+
+/// A boxed value as `dump-objects` prints it.
+pub fn boxed_value_text(value: &FieldValue) -> String {
+    match value {
+        FieldValue::Boolean(v) => v.to_string(),
+        FieldValue::Char(v) => match char::from_u32(u32::from(*v)) {
+            Some(c) => format!("'{}'", c.escape_debug()),
+            None => format!("\\u{{{:04x}}}", v),
+        },
+        FieldValue::Float(v) => v.to_string(),
+        FieldValue::Double(v) => v.to_string(),
+        FieldValue::Byte(v) => v.to_string(),
+        FieldValue::Short(v) => v.to_string(),
+        FieldValue::Int(v) => v.to_string(),
+        FieldValue::Long(v) => v.to_string(),
+        FieldValue::ObjectId(Some(id)) => format!("id {}", id),
+        FieldValue::ObjectId(None) => "null".to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_boxed_value_text() {
+        assert_eq!(boxed_value_text(&FieldValue::Int(42)), "42");
+        assert_eq!(boxed_value_text(&FieldValue::Long(-7)), "-7");
+        assert_eq!(boxed_value_text(&FieldValue::Boolean(true)), "true");
+        assert_eq!(boxed_value_text(&FieldValue::Double(0.5)), "0.5");
+        assert_eq!(boxed_value_text(&FieldValue::Char(u16::from(b'x'))), "'x'");
+        assert_eq!(boxed_value_text(&FieldValue::Char(u16::from(b'\n'))), "'\\n'");
+        // A lone surrogate isn't a char
+        assert_eq!(boxed_value_text(&FieldValue::Char(0xD800)), "\\u{d800}");
+    }
+
+}
@@ -0,0 +1,25 @@
+// SPDX-License-Identifier: MIT
+// Copyright (c) 2026 Zac Policzer
+
+//! `--timeout`, and cancelling from the library: once `AnalyzerOptions::cancel` is set,
+//! every pass over the dump stops at the next record or sub-record, as if the dump ended
+//! there. Commands then finish with what they have, so files already being written are
+//! still closed properly.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Set `token` once `timeout` has passed.
+pub fn cancel_after(token: &Arc<AtomicBool>, timeout: Duration) {
+    let token = token.clone();
+    std::thread::spawn(move || {
+        std::thread::sleep(timeout);
+        token.store(true, Ordering::Relaxed);
+    });
+}
+
+/// Whether `token` has been set, so the analysis stops and its output is partial.
+pub(crate) fn cancelled(token: &AtomicBool) -> bool {
+    token.load(Ordering::Relaxed)
+}
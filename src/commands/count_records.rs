@@ -1,29 +1,144 @@
 // SPDX-License-Identifier: MIT
 // Copyright (c) 2026 Zac Policzer
 
+use std::cmp::Reverse;
 use std::collections::HashMap;
-use jvm_hprof::{EnumIterable, Hprof, RecordTag};
+use jvm_hprof::{EnumIterable, RecordTag};
+use crate::analyzer::HeapDumpAnalyzer;
+use crate::error::AnalyzeError;
+use crate::util::record_sizes;
 
-pub fn count_records(hprof: &Hprof) {
-    let mut counts = RecordTag::iter()
-        .map(|r| (r, 0_u64))
-        .collect::<HashMap<RecordTag, u64>>();
+/// Which column `count-records` sorts by, largest first.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RecordSort {
+    Count,
+    Bytes,
+}
+
+/// Records of one tag: how many there are and their total size, framing included.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RecordTotals {
+    pub count: u64,
+    pub bytes: u64,
+}
+
+/// Number and total size of the top-level records of each tag, including tags that don't
+/// occur. `bytes` is the whole dump; sizes come from its record framing, which lines up one
+/// to one with `records_iter`. Once the analyzer is cancelled, counting ends at the next
+/// record.
+pub fn record_totals(analyzer: &HeapDumpAnalyzer, bytes: &[u8]) -> Result<HashMap<RecordTag, RecordTotals>, AnalyzeError> {
+    let mut sizes = record_sizes(bytes);
+    tally(analyzer.hprof().records_iter().take_while(|_| !analyzer.cancelled()).map(|r| {
+        let tag = r.map_err(|_| AnalyzeError::parse("top level record"))?.tag();
+        let size = sizes.next().ok_or_else(|| AnalyzeError::parse("top level record length"))?;
+        Ok((tag, size))
+    }))
+}
+
+/// Sum `(tag, size)` pairs per tag, starting every tag at zero.
+pub(crate) fn tally(
+    records: impl Iterator<Item = Result<(RecordTag, u64), AnalyzeError>>,
+) -> Result<HashMap<RecordTag, RecordTotals>, AnalyzeError> {
+    let mut totals: HashMap<RecordTag, RecordTotals> = RecordTag::iter()
+        .map(|tag| (tag, RecordTotals::default()))
+        .collect();
+    for record in records {
+        let (tag, size) = record?;
+        let entry = totals.entry(tag).or_default();
+        entry.count += 1;
+        entry.bytes += size;
+    }
+    Ok(totals)
+}
+
+/// Print the number and total size of each top-level record type, then a total line.
+pub fn count_records(analyzer: &HeapDumpAnalyzer, bytes: &[u8], sort_by: RecordSort) -> Result<(), AnalyzeError> {
+    let mut rows: Vec<(RecordTag, RecordTotals)> = record_totals(analyzer, bytes)?.into_iter().collect();
+    sort_totals(&mut rows, sort_by);
 
-    hprof
-        .records_iter()
-        .map(|r| r.unwrap().tag())
-        .for_each(|tag| {
-            counts.entry(tag).and_modify(|c| *c += 1).or_insert(1);
-        });
+    for (tag, totals) in &rows {
+        println!("{:?}: {} ({} bytes)", tag, totals.count, totals.bytes);
+    }
+    println!(
+        "Total: {} ({} bytes)",
+        rows.iter().map(|(_, t)| t.count).sum::<u64>(),
+        rows.iter().map(|(_, t)| t.bytes).sum::<u64>(),
+    );
+
+    Ok(())
+}
 
-    let mut counts: Vec<(RecordTag, u64)> = counts
-        .into_iter()
-        .collect::<Vec<(RecordTag, u64)>>();
+/// Largest first by `sort_by`; ties in tag name order so the output is stable across runs.
+fn sort_totals(rows: &mut [(RecordTag, RecordTotals)], sort_by: RecordSort) {
+    rows.sort_unstable_by_key(|(tag, totals)| {
+        let key = match sort_by {
+            RecordSort::Count => totals.count,
+            RecordSort::Bytes => totals.bytes,
+        };
+        (Reverse(key), format!("{:?}", tag))
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn totals(rows: &[(RecordTag, u64, u64)]) -> Vec<(RecordTag, RecordTotals)> {
+        rows.iter().map(|&(tag, count, bytes)| (tag, RecordTotals { count, bytes })).collect()
+    }
+
+    fn tags(rows: Vec<(RecordTag, RecordTotals)>) -> Vec<RecordTag> {
+        rows.into_iter().map(|(tag, _)| tag).collect()
+    }
+
+    #[test]
+    fn test_tally_counts_each_tag() {
+        let records = [
+            (RecordTag::Utf8, 20), (RecordTag::LoadClass, 33), (RecordTag::Utf8, 15),
+            (RecordTag::HeapDumpSegment, 4096), (RecordTag::Utf8, 9),
+        ];
+        let totals = tally(records.into_iter().map(Ok)).unwrap();
+        assert_eq!(totals[&RecordTag::Utf8], RecordTotals { count: 3, bytes: 44 });
+        assert_eq!(totals[&RecordTag::LoadClass], RecordTotals { count: 1, bytes: 33 });
+        assert_eq!(totals[&RecordTag::HeapDumpSegment], RecordTotals { count: 1, bytes: 4096 });
+        // Every tag is present, even with no records
+        assert_eq!(totals.len(), RecordTag::iter().count());
+        assert_eq!(totals[&RecordTag::StackTrace], RecordTotals::default());
+
+        let failing = vec![Ok((RecordTag::Utf8, 20)), Err(AnalyzeError::parse("top level record"))];
+        assert!(tally(failing.into_iter()).is_err());
+    }
+
+    #[test]
+    fn test_sort_counts_breaks_ties_by_tag_name() {
+        let mut counts = totals(&[
+            (RecordTag::StackTrace, 0, 0),
+            (RecordTag::Utf8, 5, 0),
+            (RecordTag::LoadClass, 0, 0),
+            (RecordTag::HeapDumpSegment, 5, 0),
+            (RecordTag::StackFrame, 9, 0),
+        ]);
+        sort_totals(&mut counts, RecordSort::Count);
+        assert_eq!(tags(counts), vec![
+            RecordTag::StackFrame, RecordTag::HeapDumpSegment, RecordTag::Utf8,
+            RecordTag::LoadClass, RecordTag::StackTrace,
+        ]);
+    }
 
-    counts.sort_unstable_by_key(|&(_, count)| count);
-    counts.reverse();
+    #[test]
+    fn test_sort_by_bytes() {
+        // Few segments, but they hold nearly all of the file
+        let rows = [
+            (RecordTag::Utf8, 48_206, 2_000_000),
+            (RecordTag::HeapDumpSegment, 14, 900_000_000),
+            (RecordTag::LoadClass, 2_079, 49_896),
+        ];
+        let mut by_count = totals(&rows);
+        sort_totals(&mut by_count, RecordSort::Count);
+        assert_eq!(tags(by_count), vec![RecordTag::Utf8, RecordTag::LoadClass, RecordTag::HeapDumpSegment]);
 
-    for (tag, count) in counts {
-        println!("{:?}: {}", tag, count);
+        let mut by_bytes = totals(&rows);
+        sort_totals(&mut by_bytes, RecordSort::Bytes);
+        assert_eq!(tags(by_bytes), vec![RecordTag::HeapDumpSegment, RecordTag::Utf8, RecordTag::LoadClass]);
     }
 }
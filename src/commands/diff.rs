@@ -0,0 +1,97 @@
+// SPDX-License-Identifier: MIT
+// Copyright (c) 2026 Zac Policzer
+
+use std::collections::HashMap;
+use crate::analyzer::HeapDumpAnalyzer;
+use crate::commands::ClassStat;
+use crate::error::AnalyzeError;
+
+/// How one class's instances changed between a baseline dump and a later one.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ClassDelta {
+    pub class_name: String,
+    pub count_delta: i64,
+    pub bytes_delta: i64,
+    pub count_before: u64,
+    pub count_after: u64,
+}
+
+/// Pair up two histograms by class name, keeping only the classes that changed, most growth
+/// in bytes first. Classes are matched by name because object ids differ between dumps, so
+/// a class loaded by two loaders counts once, summed.
+fn class_deltas(before: Vec<ClassStat>, after: Vec<ClassStat>) -> Vec<ClassDelta> {
+    // (count, bytes) before and after, per class
+    let mut by_name: HashMap<String, [(u64, u64); 2]> = HashMap::new();
+    for (side, stats) in [before, after].into_iter().enumerate() {
+        for stat in stats {
+            let entry = &mut by_name.entry(stat.class_name).or_default()[side];
+            entry.0 += stat.instance_count;
+            entry.1 += stat.total_shallow_bytes;
+        }
+    }
+
+    let mut deltas: Vec<ClassDelta> = by_name.into_iter()
+        .map(|(class_name, [(count_before, bytes_before), (count_after, bytes_after)])| ClassDelta {
+            class_name,
+            count_delta: count_after as i64 - count_before as i64,
+            bytes_delta: bytes_after as i64 - bytes_before as i64,
+            count_before,
+            count_after,
+        })
+        .filter(|d| d.count_delta != 0 || d.bytes_delta != 0)
+        .collect();
+    deltas.sort_unstable_by(|a, b| {
+        b.bytes_delta.cmp(&a.bytes_delta)
+            .then_with(|| b.count_delta.cmp(&a.count_delta))
+            .then_with(|| a.class_name.cmp(&b.class_name))
+    });
+    deltas
+}
+
+/// Per-class changes from `baseline` to `current`, as `diff` prints them.
+pub fn histogram_diff(baseline: &HeapDumpAnalyzer, current: &HeapDumpAnalyzer) -> Result<Vec<ClassDelta>, AnalyzeError> {
+    let before = baseline.class_histogram()?;
+    let after = current.class_histogram()?;
+    Ok(class_deltas(before, after))
+}
+
+pub fn diff(baseline: &HeapDumpAnalyzer, current: &HeapDumpAnalyzer) -> Result<(), AnalyzeError> {
+    println!("class_name  count_delta  bytes_delta  count_before  count_after");
+    for d in histogram_diff(baseline, current)? {
+        println!("{}  {:+}  {:+}  {}  {}", d.class_name, d.count_delta, d.bytes_delta, d.count_before, d.count_after);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stat(name: &str, count: u64, bytes: u64) -> ClassStat {
+        ClassStat { class_name: name.to_string(), instance_count: count, total_shallow_bytes: bytes }
+    }
+
+    #[test]
+    fn test_class_deltas_sorted_by_growth() {
+        let before = vec![stat("a/Grew", 10, 160), stat("a/Same", 3, 48), stat("a/Shrank", 5, 80), stat("a/Gone", 1, 16)];
+        let after = vec![stat("a/Grew", 110, 1760), stat("a/Same", 3, 48), stat("a/Shrank", 2, 32), stat("a/New", 4, 64)];
+        let names: Vec<String> = class_deltas(before, after).into_iter().map(|d| d.class_name).collect();
+        assert_eq!(names, vec!["a/Grew", "a/New", "a/Gone", "a/Shrank"]);
+    }
+
+    #[test]
+    fn test_class_deltas_values() {
+        let deltas = class_deltas(vec![stat("a/Shrank", 5, 80)], vec![stat("a/Shrank", 2, 32), stat("a/New", 4, 64)]);
+        assert_eq!(deltas, vec![
+            ClassDelta { class_name: "a/New".to_string(), count_delta: 4, bytes_delta: 64, count_before: 0, count_after: 4 },
+            ClassDelta { class_name: "a/Shrank".to_string(), count_delta: -3, bytes_delta: -48, count_before: 5, count_after: 2 },
+        ]);
+    }
+
+    #[test]
+    fn test_same_named_classes_are_summed() {
+        let deltas = class_deltas(vec![stat("a/B", 1, 16)], vec![stat("a/B", 1, 16), stat("a/B", 2, 32)]);
+        assert_eq!(deltas.len(), 1);
+        assert_eq!((deltas[0].count_before, deltas[0].count_after), (1, 3));
+    }
+}
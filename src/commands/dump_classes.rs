@@ -0,0 +1,101 @@
+// SPDX-License-Identifier: MIT
+// Copyright (c) 2026 Zac Policzer
+
+use std::fmt;
+use std::io::Write;
+use jvm_hprof::Id;
+use crate::analyzer::HeapDumpAnalyzer;
+use crate::commands::dump_objects::{print_field_val, ref_type_name, superclass_chain, ClassFilter, IdFormat};
+use crate::error::AnalyzeError;
+use crate::id_hash::IdMap;
+use crate::util::field_base_names;
+
+/// One line per flattened instance field, `name: type`, with a field declared by a
+/// superclass of `class_name` followed by `(from <superclass>)`.
+fn instance_field_lines(
+    class_name: &str,
+    names: &[&str],
+    types: &[impl fmt::Display],
+    declaring_classes: &[&str],
+) -> Vec<String> {
+    names.iter().zip(types).zip(declaring_classes)
+        .map(|((name, field_type), declared_by)| match *declared_by == class_name {
+            true => format!("{}: {}", name, field_type),
+            false => format!("{}: {} (from {})", name, field_type, declared_by),
+        })
+        .collect()
+}
+
+/// Print every class, or those `filter` matches, in name order: its id and superclass
+/// chain, its instance fields with the superclasses' after its own, and its static fields
+/// with their values. Only the index is built; no instance is read a second time.
+pub fn dump_classes(
+    analyzer: &HeapDumpAnalyzer,
+    filter: Option<&ClassFilter>,
+    ids: IdFormat,
+    out: &mut impl Write,
+) -> Result<(), AnalyzeError> {
+    let (index, _) = analyzer.indexed()?;
+    let ref_type = |id: Id| {
+        ref_type_name(id, index.obj_id_to_class_obj_id.as_ref(), &index.classes, &index.prim_array_obj_id_to_type)
+    };
+    // Boxed values need a pass over the instances, which this view skips
+    let boxed = IdMap::default();
+
+    let mut classes: Vec<_> = index.classes.iter()
+        .filter(|(_, c)| filter.is_none_or(|f| f.matches(c.name)))
+        .collect();
+    classes.sort_unstable_by_key(|(id, c)| (c.name, id.id()));
+    for (class_id, class) in classes {
+        writeln!(out, "\nid {}: class {}", ids.show(*class_id), class.name)?;
+        let supers = superclass_chain(&index.classes, *class_id);
+        if !supers.is_empty() {
+            writeln!(out, "  extends {}", supers.join(" -> "))?;
+        }
+
+        let descriptors = index.class_instance_field_descriptors.get(class_id).map_or(&[][..], |d| d);
+        if !descriptors.is_empty() {
+            writeln!(out, "  instance fields:")?;
+            let names = field_base_names(descriptors, &index.utf8);
+            let types: Vec<_> = descriptors.iter().map(|fd| fd.field_type().java_type_name()).collect();
+            let declaring = index.class_field_declaring_classes.get(class_id).map_or(&[][..], |d| d);
+            for line in instance_field_lines(class.name, &names, &types, declaring) {
+                writeln!(out, "    - {}", line)?;
+            }
+        }
+
+        if !class.static_fields.is_empty() {
+            writeln!(out, "  static fields:")?;
+            for sf in &class.static_fields {
+                let name = index.utf8.get(&sf.name_id()).copied().unwrap_or("(missing utf8)");
+                // print_field_val writes `  - ...`; indent it under the heading
+                let mut line = Vec::new();
+                print_field_val(&mut line, &sf.value(), name, sf.field_type(), &ref_type, &boxed, ids)?;
+                out.write_all(b"  ")?;
+                out.write_all(&line)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_instance_field_lines_name_inherited_fields() {
+        let lines = instance_field_lines(
+            "java/util/LinkedHashMap",
+            &["head", "tail", "size", "table"],
+            &["object", "object", "int", "object"],
+            &["java/util/LinkedHashMap", "java/util/LinkedHashMap", "java/util/HashMap", "java/util/HashMap"],
+        );
+        assert_eq!(lines, vec![
+            "head: object",
+            "tail: object",
+            "size: int (from java/util/HashMap)",
+            "table: object (from java/util/HashMap)",
+        ]);
+    }
+}
@@ -1,300 +1,1062 @@
 // SPDX-License-Identifier: MIT
 // Copyright (c) 2026 Zac Policzer
 
-use std::collections::HashMap;
-use dashmap::DashMap;
-use jvm_hprof::{EzClass, Hprof, Id, RecordTag};
-use jvm_hprof::heap_dump::{FieldType, FieldValue, PrimitiveArrayType, SubRecord};
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::io::Write;
+use jvm_hprof::{EzClass, Hprof, Id, IdSize, Record};
+use jvm_hprof::heap_dump::{FieldType, FieldValue, Instance, PrimitiveArrayType, SubRecord};
+use rayon::prelude::*;
+use regex::Regex;
+use crate::analyzer::HeapDumpAnalyzer;
+use crate::commands::object_json::write_sub_record_json;
+use crate::edges::instance_field_names;
+use crate::boxed::{boxed_value_text, decode_boxed};
+use crate::error::AnalyzeError;
 use crate::hprof_index::HprofIndex;
+use crate::id_hash::{IdDashMap, IdMap, IdSet};
+use crate::obj_class_index::ObjClassIndex;
+use crate::progress::Progress;
+use crate::strings::{decode_strings, decode_utf16_string, DecodedString, STRING_CLASS_NAME};
+use crate::unresolved::UnresolvedReferences;
+use crate::util::primitive_array_type_name;
+use crate::visit::{visit_segment, SubRecordVisitor};
 
-pub fn dump_objects(hprof: &Hprof) {
-    let index = HprofIndex::build(hprof);
+/// Restricts `dump_objects` output to classes whose fully-qualified (dotted) name matches.
+pub enum ClassFilter {
+    Exact(String),
+    Regex(Regex),
+}
+
+impl ClassFilter {
+    /// `class_name` may be in either internal (`java/lang/String`) or dotted form.
+    pub fn matches(&self, class_name: &str) -> bool {
+        let dotted = class_name.replace('/', ".");
+        match self {
+            ClassFilter::Exact(name) => dotted == name.replace('/', "."),
+            ClassFilter::Regex(re) => re.is_match(&dotted),
+        }
+    }
+}
+
+/// Which of the objects that pass the filter get printed: skip the first `skip`, then print
+/// at most `limit`. Counted across classes, instances and arrays in dump order.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ObjectWindow {
+    pub skip: usize,
+    pub limit: Option<usize>,
+}
+
+impl ObjectWindow {
+    /// Whether the next matching object should be printed; advances the window.
+    fn admit(&mut self) -> bool {
+        if self.skip > 0 {
+            self.skip -= 1;
+            return false;
+        }
+        match &mut self.limit {
+            Some(0) => false,
+            Some(remaining) => {
+                *remaining -= 1;
+                true
+            }
+            None => true,
+        }
+    }
+
+    /// True once `limit` objects have been printed, so the scan can stop.
+    fn is_exhausted(&self) -> bool {
+        self.skip == 0 && self.limit == Some(0)
+    }
+}
 
+/// How `dump_objects` writes each object.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ObjectFormat {
+    /// The indented, human-readable listing.
+    #[default]
+    Text,
+    /// One JSON object per line.
+    Json,
+}
+
+/// How object ids are printed in text output. JSON and parquet output keep ids numeric.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum IdFormat {
+    #[default]
+    Decimal,
+    /// `0x`-prefixed lowercase hex, to match addresses from gdb or async-profiler.
+    Hex,
+}
+
+/// How `byte[]` elements are printed in text output.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ByteArrayFormat {
+    /// Each byte as `0x`-prefixed hex, comma-separated.
+    #[default]
+    Hex,
+    /// `hexdump -C` lines: offset, 16 bytes of hex, and the bytes as ASCII.
+    Hexdump,
+    /// The bytes as an ASCII string, unprintable bytes shown as `.`.
+    Ascii,
+    Base64,
+}
+
+impl IdFormat {
+    pub fn show(self, id: Id) -> FormattedId {
+        FormattedId { id: id.id(), format: self }
+    }
+}
+
+/// An id wrapped for `Display` in an `IdFormat`.
+pub struct FormattedId {
+    id: u64,
+    format: IdFormat,
+}
+
+impl fmt::Display for FormattedId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.format {
+            IdFormat::Decimal => write!(f, "{}", self.id),
+            IdFormat::Hex => write!(f, "{:#x}", self.id),
+        }
+    }
+}
+
+/// Layout choices for `print_sub_record`.
+#[derive(Clone, Copy)]
+pub(crate) struct TextOptions<'r> {
+    /// Print at most this many elements of each array.
+    pub max_array_elements: usize,
+    pub ids: IdFormat,
+    /// Print `char[]` as the UTF-16 string it holds rather than code units.
+    pub decode_char_arrays: bool,
+    pub byte_arrays: ByteArrayFormat,
+    /// Instances to print nested under the references to them, for `--expand-depth`.
+    pub referents: &'r Referents,
+    /// Where the printed references whose type can't be resolved are counted.
+    pub unresolved: &'r UnresolvedReferences,
+}
+
+pub struct DumpObjectsOptions {
+    pub filter: Option<ClassFilter>,
+    pub window: ObjectWindow,
+    /// Only look at the first this many instances and arrays of the dump.
+    pub sample: Option<usize>,
+    /// Print at most this many elements of each array.
+    pub max_array_elements: usize,
+    pub format: ObjectFormat,
+    /// How ids are printed in `ObjectFormat::Text`.
+    pub id_format: IdFormat,
+    /// Print `char[]` as one decoded string in `ObjectFormat::Text`.
+    pub decode_char_arrays: bool,
+    /// How `byte[]` is printed in `ObjectFormat::Text`.
+    pub byte_array_format: ByteArrayFormat,
+    /// In `ObjectFormat::Text`, also print the fields of the instances an instance refers
+    /// to, and theirs, this many levels down. 0 prints just the referent's id and class.
+    pub expand_depth: usize,
+    /// Fail on the first printed reference whose type can't be resolved, instead of only
+    /// counting it.
+    pub fail_on_unresolved: bool,
+}
+
+/// The field values of the instances `--expand-depth` opens up under the printed ones, and
+/// how many levels down to open them.
+#[derive(Default)]
+pub(crate) struct Referents {
+    depth: usize,
+    /// Class object id and flattened field values, keyed by object id.
+    instances: IdMap<(Id, Vec<FieldValue>)>,
+}
+
+/// Every instance field of `instance`, in the flattened order of its class's descriptors.
+fn instance_field_values(instance: &Instance, index: &HprofIndex, id_size: IdSize) -> Result<Vec<FieldValue>, AnalyzeError> {
+    let missing_class = || AnalyzeError::MissingClass {
+        class_obj_id: instance.class_obj_id().id(),
+        obj_id: Some(instance.obj_id().id()),
+    };
+    let field_descriptors = index.class_instance_field_descriptors
+        .get(&instance.class_obj_id())
+        .ok_or_else(missing_class)?;
+
+    let mut field_val_input: &[u8] = instance.fields();
+    field_descriptors.iter()
+        .map(|fd| {
+            let (input, field_val) = fd
+                .field_type()
+                .parse_value(field_val_input, id_size)
+                .map_err(|_| AnalyzeError::parse(format!("fields of instance {}", instance.obj_id())))?;
+            field_val_input = input;
+            Ok(field_val)
+        })
+        .collect()
+}
+
+fn referenced_ids<'v>(values: impl Iterator<Item = &'v FieldValue>) -> impl Iterator<Item = Id> + 'v {
+    values.filter_map(|v| match v {
+        FieldValue::ObjectId(Some(id)) => Some(*id),
+        _ => None,
+    })
+}
+
+/// Collects the field values of one segment's instances in `frontier`, for one level of
+/// `collect_referents`.
+struct ReferentFinder<'f, 'i> {
+    frontier: &'f IdSet,
+    index: &'f HprofIndex<'i>,
+    id_size: IdSize,
+    found: Vec<(Id, (Id, Vec<FieldValue>))>,
+}
+
+impl<'a> SubRecordVisitor<'a> for ReferentFinder<'_, '_> {
+    fn on_instance(&mut self, instance: &Instance<'a>) -> Result<(), AnalyzeError> {
+        if !self.frontier.contains(&instance.obj_id()) {
+            return Ok(());
+        }
+        if let Ok(values) = instance_field_values(instance, self.index, self.id_size) {
+            self.found.push((instance.obj_id(), (instance.class_obj_id(), values)));
+        }
+        Ok(())
+    }
+}
+
+/// The instances within `depth` references of the printed ones, starting from `referenced`,
+/// the ids the printed instances refer to. One parallel pass over the segments per level,
+/// stopping early once a level finds nothing new; arrays and classes aren't opened.
+fn collect_referents(
+    hprof: &Hprof,
+    index: &HprofIndex,
+    segments: &[Record],
+    referenced: IdSet,
+    depth: usize,
+) -> Result<Referents, AnalyzeError> {
+    let id_size = hprof.header().id_size();
+    let mut instances: IdMap<(Id, Vec<FieldValue>)> = IdMap::default();
+    let mut frontier = referenced;
+    for _ in 0..depth {
+        frontier.retain(|id| !instances.contains_key(id));
+        if frontier.is_empty() {
+            break;
+        }
+        let per_segment = segments.par_iter()
+            .map(|record| {
+                let mut finder = ReferentFinder { frontier: &frontier, index, id_size, found: Vec::new() };
+                visit_segment(record, &index.options, &mut finder)?;
+                Ok(finder.found)
+            })
+            .collect::<Result<Vec<_>, AnalyzeError>>()?;
+        let found: IdMap<(Id, Vec<FieldValue>)> = per_segment.into_iter().flatten().collect();
+        frontier = referenced_ids(found.values().flat_map(|(_, values)| values)).collect();
+        instances.extend(found);
+    }
+    Ok(Referents { depth, instances })
+}
+
+/// Instances and arrays, the sub-records `--sample` counts.
+pub(crate) fn is_object(s: &SubRecord) -> bool {
+    matches!(s, SubRecord::Instance(_) | SubRecord::ObjectArray(_) | SubRecord::PrimitiveArray(_))
+}
+
+/// Resolve a filter to the set of class object ids it selects, so the per-record check is a
+/// set lookup rather than a string comparison.
+pub(crate) fn matching_class_ids<'a>(
+    classes: impl Iterator<Item = (Id, &'a str)>,
+    filter: &ClassFilter,
+) -> HashSet<Id> {
+    classes
+        .filter(|(_, name)| filter.matches(name))
+        .map(|(id, _)| id)
+        .collect()
+}
+
+/// A filter resolved against the index, for the per-record checks of the commands that
+/// take one. Without a filter everything is selected.
+pub(crate) struct Selection<'f> {
+    filter: Option<&'f ClassFilter>,
+    class_ids: Option<HashSet<Id>>,
+}
+
+impl<'f> Selection<'f> {
+    pub(crate) fn new(filter: Option<&'f ClassFilter>, index: &HprofIndex) -> Self {
+        let class_ids = filter.map(|f| matching_class_ids(index.classes.iter().map(|(id, c)| (*id, c.name)), f));
+        Selection { filter, class_ids }
+    }
+
+    /// Whether the class, or instances or object arrays of it, are selected.
+    pub(crate) fn class(&self, class_obj_id: Id) -> bool {
+        self.class_ids.as_ref().is_none_or(|ids| ids.contains(&class_obj_id))
+    }
+
+    /// Whether arrays of a primitive type are selected, matched as e.g. `int[]`.
+    pub(crate) fn primitive_array(&self, array_type: PrimitiveArrayType) -> bool {
+        self.filter.is_none_or(|f| f.matches(&format!("{}[]", array_type.java_type_name())))
+    }
+
+    /// Whether String instances are selected.
+    pub(crate) fn strings(&self) -> bool {
+        self.filter.is_none_or(|f| f.matches(STRING_CLASS_NAME))
+    }
+
+    /// Whether any object array class is selected, whose String elements are shown decoded.
+    pub(crate) fn object_arrays(&self, index: &HprofIndex) -> bool {
+        self.class_ids.as_ref().is_some_and(|ids| {
+            ids.iter().any(|id| index.classes.get(id).is_some_and(|c| c.name.starts_with('[')))
+        })
+    }
+
+    /// Whether `s` is a class, instance or array record that's selected.
+    pub(crate) fn sub_record(&self, s: &SubRecord) -> bool {
+        match s {
+            SubRecord::Class(class) => self.class(class.obj_id()),
+            SubRecord::Instance(instance) => self.class(instance.class_obj_id()),
+            SubRecord::ObjectArray(oa) => self.class(oa.array_class_obj_id()),
+            SubRecord::PrimitiveArray(pa) => self.primitive_array(pa.primitive_type()),
+            _ => false,
+        }
+    }
+}
+
+/// Print every class, instance and array in the heap, or only those selected by `options.filter`
+/// and `options.window`. Filtering happens after the index pass, so the whole dump is still read
+/// once to build the class/utf8 maps before anything is printed; the printing pass stops as
+/// soon as the window's limit is reached. Arrays print at most `max_array_elements` elements.
+/// With `sample`, only the first `sample` instances and arrays of the dump are looked at,
+/// matching or not. Returns how many printed references had a type that couldn't be resolved.
+pub fn dump_objects(analyzer: &HeapDumpAnalyzer, options: &DumpObjectsOptions, out: &mut impl Write) -> Result<u64, AnalyzeError> {
+    let hprof = analyzer.hprof();
+    let (index, segments) = analyzer.indexed()?;
+    let selection = Selection::new(options.filter.as_ref(), index);
+
+    // Only pay for decoding Strings when they'll be printed, as instances or array elements
+    let strings = if selection.strings() || selection.object_arrays(index) {
+        decode_strings(hprof, index, segments)
+    } else {
+        IdMap::default()
+    };
+    let boxed = match options.format {
+        ObjectFormat::Text => decode_boxed(hprof, index, segments),
+        ObjectFormat::Json => IdMap::default(),
+    };
+
+    let field_names = match options.format {
+        ObjectFormat::Json => instance_field_names(index),
+        ObjectFormat::Text => HashMap::new(),
+    };
+
+    let wanted = |s: &SubRecord| selection.sub_record(s);
+
+    // The referents are collected before printing, from what the printing pass will show
+    let referents = match (options.format, options.expand_depth) {
+        (ObjectFormat::Text, depth) if depth > 0 => {
+            let id_size = hprof.header().id_size();
+            let mut referenced = IdSet::default();
+            for_each_printed(index, segments, options, "Finding referents", &wanted, |s| {
+                if let SubRecord::Instance(instance) = s {
+                    referenced.extend(referenced_ids(instance_field_values(&instance, index, id_size)?.iter()));
+                }
+                Ok(())
+            })?;
+            collect_referents(hprof, index, segments, referenced, depth)?
+        }
+        _ => Referents::default(),
+    };
+    let unresolved = UnresolvedReferences::new(options.fail_on_unresolved);
+    let text_options = TextOptions {
+        max_array_elements: options.max_array_elements,
+        ids: options.id_format,
+        decode_char_arrays: options.decode_char_arrays,
+        byte_arrays: options.byte_array_format,
+        referents: &referents,
+        unresolved: &unresolved,
+    };
+
+    for_each_printed(index, segments, options, "Printing segments", &wanted, |s| match options.format {
+        ObjectFormat::Text => print_sub_record(out, s, hprof, index, &strings, &boxed, text_options),
+        ObjectFormat::Json => write_sub_record_json(
+            out, s, hprof, index, &strings, &field_names, options.max_array_elements,
+        ),
+    })?;
+
+    out.flush()?;
+    Ok(unresolved.count())
+}
+
+/// Hands the sub-records `for_each_printed` selects to `print`, finishing once the window
+/// or the sample has nothing left to admit.
+struct Printer<'w, P> {
+    wanted: &'w dyn Fn(&SubRecord) -> bool,
+    window: ObjectWindow,
+    unsampled: usize,
+    sample_spent: bool,
+    print: P,
+}
+
+impl<'a, P: FnMut(SubRecord<'a>) -> Result<(), AnalyzeError>> SubRecordVisitor<'a> for Printer<'_, P> {
+    fn on_sub_record(&mut self, s: SubRecord<'a>) -> Result<(), AnalyzeError> {
+        if is_object(&s) {
+            if self.unsampled == 0 {
+                self.sample_spent = true;
+                return Ok(());
+            }
+            self.unsampled -= 1;
+        }
+        if (self.wanted)(&s) && self.window.admit() {
+            (self.print)(s)?;
+        }
+        Ok(())
+    }
+
+    fn finished(&self) -> bool {
+        self.sample_spent || self.window.is_exhausted()
+    }
+}
+
+/// Hand each sub-record `wanted` selects to `print`, in dump order, honouring the window
+/// and the sample. Stops reading as soon as neither has anything left to admit, or at the
+/// first error `print` returns.
+fn for_each_printed(
+    index: &HprofIndex,
+    segments: &[Record],
+    options: &DumpObjectsOptions,
+    progress_label: &str,
+    wanted: &dyn Fn(&SubRecord) -> bool,
+    print: impl FnMut(SubRecord) -> Result<(), AnalyzeError>,
+) -> Result<(), AnalyzeError> {
+    let mut printer = Printer {
+        wanted,
+        window: options.window,
+        unsampled: options.sample.unwrap_or(usize::MAX),
+        sample_spent: false,
+        print,
+    };
+    let progress = Progress::start(progress_label, Some(segments.len() as u64));
+    for r in segments {
+        if printer.finished() {
+            break;
+        }
+        if let Some(p) = &progress {
+            p.advance(1);
+        }
+        visit_segment(r, &index.options, &mut printer)?;
+    }
+    Ok(())
+}
+
+/// Print one class, instance or array record the way `dump-objects` shows it: a header line
+/// with the id and type, then one line per field or element, with the fields of any
+/// `referents` nested under the references to them. Other sub-records print nothing.
+pub(crate) fn print_sub_record(
+    out: &mut impl Write,
+    s: SubRecord,
+    hprof: &Hprof,
+    index: &HprofIndex,
+    strings: &IdMap<DecodedString>,
+    boxed: &IdMap<FieldValue>,
+    options: TextOptions,
+) -> Result<(), AnalyzeError> {
     let missing_utf8 = "(missing utf8)";
+    let ids = options.ids;
+    let max_array_elements = options.max_array_elements;
+    let ref_type = |id: Id| {
+        ref_type_name(id, index.obj_id_to_class_obj_id.as_ref(), &index.classes, &index.prim_array_obj_id_to_type)
+    };
 
-    hprof
-        .records_iter()
-        .map(|r| r.unwrap())
-        .for_each(|r| match r.tag() {
-            RecordTag::HeapDump | RecordTag::HeapDumpSegment => {
-                let segment = r.as_heap_dump_segment().unwrap().unwrap();
-                for p in segment.sub_records() {
-                    let s = p.unwrap();
-
-                    match s {
-                        SubRecord::Class(class) => {
-                            let mc = match index.classes.get(&class.obj_id()) {
-                                None => panic!("Could not find class {}", class.obj_id()),
-                                Some(c) => c,
-                            };
-
-                            println!("\nid {}: class {}", class.obj_id(), mc.name);
-                            for sf in &mc.static_fields {
-                                let field_name =
-                                    index.utf8.get(&sf.name_id()).unwrap_or_else(|| &missing_utf8);
-
-                                print_field_val(
-                                    &sf.value(),
-                                    field_name,
-                                    sf.field_type(),
-                                    &index.obj_id_to_class_obj_id,
-                                    &index.classes,
-                                    &index.prim_array_obj_id_to_type,
-                                );
-                            }
-                        }
-                        SubRecord::Instance(instance) => {
-                            let mc = match index.classes.get(&instance.class_obj_id()) {
-                                None => panic!(
-                                    "Could not find class {} for instance {}",
-                                    instance.class_obj_id(),
-                                    instance.obj_id()
-                                ),
-                                Some(c) => c,
-                            };
-
-                            println!("\nid {}: {}", instance.obj_id(), mc.name);
-
-                            let field_descriptors = index.class_instance_field_descriptors
-                                .get(&instance.class_obj_id())
-                                .expect("Should have all classes available");
-
-                            let mut field_val_input: &[u8] = instance.fields();
-                            for fd in field_descriptors.iter() {
-                                let (input, field_val) = fd
-                                    .field_type()
-                                    .parse_value(field_val_input, hprof.header().id_size())
-                                    .unwrap();
-                                field_val_input = input;
-
-                                let field_name =
-                                    index.utf8.get(&fd.name_id()).unwrap_or_else(|| &missing_utf8);
-
-                                print_field_val(
-                                    &field_val,
-                                    field_name,
-                                    fd.field_type(),
-                                    &index.obj_id_to_class_obj_id,
-                                    &index.classes,
-                                    &index.prim_array_obj_id_to_type,
-                                );
-                            }
-                        }
-                        SubRecord::ObjectArray(oa) => {
-                            let mc = match index.classes.get(&oa.array_class_obj_id()) {
-                                None => panic!(
-                                    "Could not find class {} for instance {}",
-                                    oa.array_class_obj_id(),
-                                    oa.obj_id()
-                                ),
-                                Some(c) => c,
-                            };
-
-                            println!("\nid {}: {} = [", oa.obj_id(), mc.name);
-
-                            for pr in oa.elements(hprof.header().id_size()) {
-                                match pr.unwrap() {
-                                    Some(id) => {
-                                        let element_class_name = index.obj_id_to_class_obj_id
-                                            .get(&id)
-                                            .and_then(|class_id| index.classes.get(&*class_id))
-                                            .map(|c| c.name)
-                                            .unwrap_or_else(|| "(could not resolve class)");
-
-                                        println!("  - id {}: {}", id, element_class_name);
-                                    }
-                                    None => {
-                                        println!("  - null");
-                                    }
-                                }
-                            }
-
-                            println!("]");
+    match s {
+        SubRecord::Class(class) => {
+            let mc = index.classes.get(&class.obj_id())
+                .ok_or(AnalyzeError::MissingClass { class_obj_id: class.obj_id().id(), obj_id: None })?;
+
+            writeln!(out, "\nid {}: class {}", ids.show(class.obj_id()), mc.name)?;
+            let supers = superclass_chain(&index.classes, class.obj_id());
+            if !supers.is_empty() {
+                writeln!(out, "  extends {}", supers.join(" -> "))?;
+            }
+            for sf in &mc.static_fields {
+                let field_name =
+                    index.utf8.get(&sf.name_id()).unwrap_or_else(|| &missing_utf8);
+                if let FieldValue::ObjectId(Some(id)) = sf.value() {
+                    if index.is_unresolved_ref(id) {
+                        options.unresolved.report(class.obj_id(), field_name, id)?;
+                    }
+                }
+
+                print_field_val(
+                    out,
+                    &sf.value(),
+                    field_name,
+                    sf.field_type(),
+                    &ref_type,
+                    boxed,
+                    ids,
+                )?;
+            }
+        }
+        SubRecord::Instance(instance) => {
+            let missing_class = || AnalyzeError::MissingClass {
+                class_obj_id: instance.class_obj_id().id(),
+                obj_id: Some(instance.obj_id().id()),
+            };
+            let mc = index.classes.get(&instance.class_obj_id()).ok_or_else(missing_class)?;
+
+            match strings.get(&instance.obj_id()) {
+                Some(decoded) => writeln!(
+                    out,
+                    "\nid {}: {} = \"{}\"",
+                    ids.show(instance.obj_id()),
+                    mc.name,
+                    decoded.text.escape_debug()
+                )?,
+                None => writeln!(out, "\nid {}: {}", ids.show(instance.obj_id()), mc.name)?,
+            }
+
+            let values = instance_field_values(&instance, index, hprof.header().id_size())?;
+            let fields = FieldPrinter {
+                index,
+                ref_type: &ref_type,
+                boxed,
+                referents: options.referents,
+                unresolved: options.unresolved,
+                ids,
+            };
+            let seen = &mut IdSet::from_iter([instance.obj_id()]);
+            fields.print(out, instance.obj_id(), instance.class_obj_id(), &values, 1, seen)?;
+        }
+        SubRecord::ObjectArray(oa) => {
+            let mc = index.classes.get(&oa.array_class_obj_id())
+                .ok_or(AnalyzeError::MissingClass {
+                    class_obj_id: oa.array_class_obj_id().id(),
+                    obj_id: Some(oa.obj_id().id()),
+                })?;
+
+            writeln!(out, "\nid {}: {} = [", ids.show(oa.obj_id()), mc.name)?;
+
+            let mut position = 0;
+            let rest = write_capped(oa.elements(hprof.header().id_size()), max_array_elements, |pr| {
+                position += 1;
+                match pr.map_err(|_| AnalyzeError::parse(format!("elements of array {}", oa.obj_id())))? {
+                    Some(id) => {
+                        if index.is_unresolved_ref(id) {
+                            options.unresolved.report(oa.obj_id(), &format!("[{}]", position - 1), id)?;
                         }
-                        SubRecord::PrimitiveArray(pa) => {
-                            print!(
-                                "\n{}: {}[] = [",
-                                pa.obj_id(),
-                                pa.primitive_type().java_type_name()
-                            );
-
-                            match pa.primitive_type() {
-                                PrimitiveArrayType::Boolean => {
-                                    pa.booleans()
-                                        .unwrap()
-                                        .map(|r| r.unwrap())
-                                        .for_each(|e| print!("{}, ", e));
-                                }
-                                PrimitiveArrayType::Char => {
-                                    pa.chars()
-                                        .unwrap()
-                                        .map(|r| r.unwrap())
-                                        .for_each(|e| print!("{}, ", e));
-                                }
-                                PrimitiveArrayType::Float => {
-                                    pa.floats()
-                                        .unwrap()
-                                        .map(|r| r.unwrap())
-                                        .for_each(|e| print!("{}, ", e));
-                                }
-                                PrimitiveArrayType::Double => {
-                                    pa.doubles()
-                                        .unwrap()
-                                        .map(|r| r.unwrap())
-                                        .for_each(|e| print!("{}, ", e));
-                                }
-                                PrimitiveArrayType::Byte => {
-                                    pa.bytes()
-                                        .unwrap()
-                                        .map(|r| r.unwrap())
-                                        .for_each(|e| print!("{:#X}, ", e));
-                                }
-                                PrimitiveArrayType::Short => {
-                                    pa.shorts()
-                                        .unwrap()
-                                        .map(|r| r.unwrap())
-                                        .for_each(|e| print!("{}, ", e));
-                                }
-                                PrimitiveArrayType::Int => {
-                                    pa.ints()
-                                        .unwrap()
-                                        .map(|r| r.unwrap())
-                                        .for_each(|e| print!("{}, ", e));
-                                }
-                                PrimitiveArrayType::Long => {
-                                    pa.longs()
-                                        .unwrap()
-                                        .map(|r| r.unwrap())
-                                        .for_each(|e| print!("{}, ", e));
-                                }
-                            }
-
-                            println!("]");
+                        let element_class_name = index.obj_id_to_class_obj_id
+                            .class_of(id)
+                            .and_then(|class_id| index.classes.get(&class_id))
+                            .map(|c| c.name)
+                            .unwrap_or_else(|| "(could not resolve class)");
+
+                        match strings.get(&id) {
+                            Some(decoded) => writeln!(
+                                out,
+                                "  - id {}: {} = \"{}\"",
+                                ids.show(id),
+                                element_class_name,
+                                decoded.text.escape_debug()
+                            )?,
+                            None => writeln!(out, "  - id {}: {}", ids.show(id), element_class_name)?,
                         }
-                        _ => {}
+                    }
+                    None => {
+                        writeln!(out, "  - null")?;
                     }
                 }
+                Ok(())
+            })?;
+            if rest > 0 {
+                write_truncation(out, rest, "  ")?;
+                writeln!(out)?;
             }
-            _ => {}
-        });
-}
 
-fn print_field_val(
-    field_val: &FieldValue,
-    field_name: &str,
-    field_type: FieldType,
-    obj_id_to_class_obj_id: &DashMap<Id, Id>,
-    classes: &HashMap<Id, EzClass>,
-    prim_array_obj_id_to_type: &DashMap<Id, PrimitiveArrayType>,
-) {
-    match field_val {
-        FieldValue::ObjectId(Some(field_ref_id)) => {
-            obj_id_to_class_obj_id
-                .get(&field_ref_id)
-                .map(|class_obj_id| {
-                    println!(
-                        "  - {} = id {} ({})",
-                        field_name,
-                        field_ref_id,
-                        classes
-                            .get(&*class_obj_id)
-                            .map(|c| c.name)
-                            .unwrap_or("(class not found)"),
-                    );
-                })
-                .or_else(|| {
-                    prim_array_obj_id_to_type
-                        .get(&field_ref_id)
-                        .map(|prim_type| {
-                            println!(
-                                "  - {} = id {} ({}[])",
-                                field_name,
-                                field_ref_id,
-                                prim_type.java_type_name()
-                            );
-                        })
-                })
-                .or_else(|| {
-                    classes.get(&field_ref_id).map(|dest_class| {
-                        println!(
-                            "  - {} = id {} (class {})",
-                            field_name, field_ref_id, dest_class.name
-                        );
-                    })
-                })
-                .unwrap_or_else(|| {
-                    println!(
-                        "  - {} = id {} (type for obj id not found)",
-                        field_name, field_ref_id
-                    );
-                });
-        }
-        FieldValue::ObjectId(None) => {
-            println!("  - {} = null", field_name,);
+            writeln!(out, "]")?;
         }
-        FieldValue::Boolean(v) => {
-            println!(
-                "  - {}: {} = {}",
-                field_name,
-                field_type.java_type_name(),
-                v
-            );
+        SubRecord::PrimitiveArray(pa) if options.decode_char_arrays && matches!(pa.primitive_type(), PrimitiveArrayType::Char) => {
+            let units = pa.chars().unwrap()
+                .collect::<Result<Vec<u16>, _>>()
+                .map_err(|_| AnalyzeError::parse(format!("elements of array {}", pa.obj_id())))?;
+            let shown = units.len().min(max_array_elements);
+            write!(
+                out,
+                "\n{}: char[] = \"{}\"",
+                ids.show(pa.obj_id()),
+                decode_utf16_string(&units[..shown]).escape_debug()
+            )?;
+            write_truncation(out, units.len() - shown, " ")?;
+            writeln!(out)?;
         }
-        FieldValue::Char(v) => {
-            println!(
-                "  - {}: {} = {}",
-                field_name,
-                field_type.java_type_name(),
-                v
-            );
+        SubRecord::PrimitiveArray(pa)
+            if options.byte_arrays != ByteArrayFormat::Hex && matches!(pa.primitive_type(), PrimitiveArrayType::Byte) =>
+        {
+            let bytes = pa.bytes().unwrap()
+                .map(|b| b.map(|b| b as u8))
+                .collect::<Result<Vec<u8>, _>>()
+                .map_err(|_| AnalyzeError::parse(format!("elements of array {}", pa.obj_id())))?;
+            let shown = &bytes[..bytes.len().min(max_array_elements)];
+            let rest = bytes.len() - shown.len();
+            write!(out, "\n{}: byte[] =", ids.show(pa.obj_id()))?;
+            match options.byte_arrays {
+                ByteArrayFormat::Hexdump => {
+                    writeln!(out)?;
+                    for line in hexdump_lines(shown) {
+                        writeln!(out, "  {}", line)?;
+                    }
+                    write_truncation(out, rest, "  ")?;
+                    if rest > 0 {
+                        writeln!(out)?;
+                    }
+                }
+                ByteArrayFormat::Ascii => {
+                    write!(out, " \"{}\"", ascii_text(shown))?;
+                    write_truncation(out, rest, " ")?;
+                    writeln!(out)?;
+                }
+                _ => {
+                    write!(out, " {}", base64(shown))?;
+                    write_truncation(out, rest, " ")?;
+                    writeln!(out)?;
+                }
+            }
         }
-        FieldValue::Float(v) => {
-            println!(
-                "  - {}: {} = {}",
-                field_name,
-                field_type.java_type_name(),
-                v
-            );
+        SubRecord::PrimitiveArray(pa) => {
+            write!(
+                out,
+                "\n{}: {}[] = [",
+                ids.show(pa.obj_id()),
+                pa.primitive_type().java_type_name()
+            )?;
+
+            let elements_err = || AnalyzeError::parse(format!("elements of array {}", pa.obj_id()));
+            let rest = match pa.primitive_type() {
+                PrimitiveArrayType::Boolean => {
+                    write_capped(pa.booleans().unwrap(), max_array_elements, |e| {
+                        Ok(write!(out, "{}, ", e.map_err(|_| elements_err())?)?)
+                    })?
+                }
+                PrimitiveArrayType::Char => {
+                    write_capped(pa.chars().unwrap(), max_array_elements, |e| {
+                        Ok(write!(out, "{}, ", e.map_err(|_| elements_err())?)?)
+                    })?
+                }
+                PrimitiveArrayType::Float => {
+                    write_capped(pa.floats().unwrap(), max_array_elements, |e| {
+                        Ok(write!(out, "{}, ", e.map_err(|_| elements_err())?)?)
+                    })?
+                }
+                PrimitiveArrayType::Double => {
+                    write_capped(pa.doubles().unwrap(), max_array_elements, |e| {
+                        Ok(write!(out, "{}, ", e.map_err(|_| elements_err())?)?)
+                    })?
+                }
+                PrimitiveArrayType::Byte => {
+                    write_capped(pa.bytes().unwrap(), max_array_elements, |e| {
+                        Ok(write!(out, "{:#X}, ", e.map_err(|_| elements_err())?)?)
+                    })?
+                }
+                PrimitiveArrayType::Short => {
+                    write_capped(pa.shorts().unwrap(), max_array_elements, |e| {
+                        Ok(write!(out, "{}, ", e.map_err(|_| elements_err())?)?)
+                    })?
+                }
+                PrimitiveArrayType::Int => {
+                    write_capped(pa.ints().unwrap(), max_array_elements, |e| {
+                        Ok(write!(out, "{}, ", e.map_err(|_| elements_err())?)?)
+                    })?
+                }
+                PrimitiveArrayType::Long => {
+                    write_capped(pa.longs().unwrap(), max_array_elements, |e| {
+                        Ok(write!(out, "{}, ", e.map_err(|_| elements_err())?)?)
+                    })?
+                }
+            };
+            write_truncation(out, rest, "")?;
+
+            writeln!(out, "]")?;
         }
-        FieldValue::Double(v) => {
-            println!(
-                "  - {}: {} = {}",
-                field_name,
-                field_type.java_type_name(),
-                v
-            );
+        _ => {}
+    }
+
+    Ok(())
+}
+
+/// What printing an instance's fields needs besides the fields themselves.
+struct FieldPrinter<'p, 'a> {
+    index: &'p HprofIndex<'a>,
+    ref_type: &'p dyn Fn(Id) -> Cow<'a, str>,
+    boxed: &'p IdMap<FieldValue>,
+    referents: &'p Referents,
+    unresolved: &'p UnresolvedReferences,
+    ids: IdFormat,
+}
+
+impl FieldPrinter<'_, '_> {
+    /// Print an instance's fields, `level` deep, with the fields of each of `referents`
+    /// nested one level further until `referents.depth` levels are shown. An object already
+    /// opened under the same printed instance is marked `(already shown)`, which also
+    /// stops cycles. A reference whose type can't be resolved is reported against `obj_id`.
+    fn print(
+        &self,
+        out: &mut impl Write,
+        obj_id: Id,
+        class_obj_id: Id,
+        values: &[FieldValue],
+        level: usize,
+        seen: &mut IdSet,
+    ) -> Result<(), AnalyzeError> {
+        let field_descriptors = self.index.class_instance_field_descriptors.get(&class_obj_id).map_or(&[][..], |d| d);
+        let indent = "  ".repeat(level - 1);
+        for (fd, field_val) in field_descriptors.iter().zip(values) {
+            let field_name = self.index.utf8.get(&fd.name_id()).copied().unwrap_or("(missing utf8)");
+            if let FieldValue::ObjectId(Some(id)) = field_val {
+                if self.index.is_unresolved_ref(*id) {
+                    self.unresolved.report(obj_id, field_name, *id)?;
+                }
+            }
+            // print_field_val writes `  - ...`; indent it under the reference it's nested in
+            let mut line = Vec::new();
+            print_field_val(&mut line, field_val, field_name, fd.field_type(), self.ref_type, self.boxed, self.ids)?;
+            out.write_all(indent.as_bytes())?;
+
+            let opened = match field_val {
+                FieldValue::ObjectId(Some(id)) if level <= self.referents.depth => {
+                    self.referents.instances.get(id).map(|referent| (*id, referent))
+                }
+                _ => None,
+            };
+            match opened {
+                Some((id, (referent_class, referent_values))) if seen.insert(id) => {
+                    out.write_all(&line)?;
+                    self.print(out, id, *referent_class, referent_values, level + 1, seen)?;
+                }
+                Some(_) => {
+                    line.pop();
+                    out.write_all(&line)?;
+                    writeln!(out, " (already shown)")?;
+                }
+                None => out.write_all(&line)?,
+            }
         }
-        FieldValue::Byte(v) => {
-            println!(
-                "  - {}: {} = {}",
-                field_name,
-                field_type.java_type_name(),
-                v
-            );
+        Ok(())
+    }
+}
+
+/// Hand at most `max` of `items` to `write_one` and return how many were left unprinted.
+pub(crate) fn write_capped<T>(
+    items: impl Iterator<Item = T>,
+    max: usize,
+    mut write_one: impl FnMut(T) -> Result<(), AnalyzeError>,
+) -> Result<usize, AnalyzeError> {
+    let mut items = items;
+    for item in items.by_ref().take(max) {
+        write_one(item)?;
+    }
+    Ok(items.count())
+}
+
+/// Note how many array elements `write_capped` left out, if any.
+fn write_truncation(out: &mut impl Write, rest: usize, indent: &str) -> Result<(), AnalyzeError> {
+    if rest > 0 {
+        write!(out, "{}... ({} more)", indent, rest)?;
+    }
+    Ok(())
+}
+
+/// A byte as `hexdump -C` shows it in the ASCII column.
+fn ascii_char(b: u8) -> char {
+    if (0x20..0x7f).contains(&b) { b as char } else { '.' }
+}
+
+fn ascii_text(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| ascii_char(*b)).collect()
+}
+
+/// `bytes` as `hexdump -C` lays them out, one line per 16 bytes:
+/// `00000000  48 65 6c 6c 6f 20 77 6f  72 6c 64 0a              |Hello world.|`.
+fn hexdump_lines(bytes: &[u8]) -> Vec<String> {
+    bytes.chunks(16).enumerate()
+        .map(|(i, chunk)| {
+            let mut hex = String::new();
+            for (j, b) in chunk.iter().enumerate() {
+                if j == 8 {
+                    hex.push(' ');
+                }
+                hex.push_str(&format!("{:02x} ", b));
+            }
+            format!("{:08x}  {:<50}|{}|", i * 16, hex, ascii_text(chunk))
+        })
+        .collect()
+}
+
+/// Standard, padded base64.
+fn base64(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut text = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let n = chunk.iter().enumerate().fold(0u32, |n, (i, b)| n | ((*b as u32) << (16 - 8 * i)));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                text.push(ALPHABET[((n >> (18 - 6 * i)) & 0x3f) as usize] as char);
+            } else {
+                text.push('=');
+            }
         }
-        FieldValue::Short(v) => {
-            println!(
-                "  - {}: {} = {}",
-                field_name,
-                field_type.java_type_name(),
-                v
-            );
+    }
+    text
+}
+
+/// Names of `class_id`'s superclasses, nearest first, up to a root such as
+/// `java/lang/Object`. A superclass without a class record ends the chain as
+/// `(unresolved <id>)`, and a class seen twice, which a well-formed dump never has, as
+/// `(cycle at <name>)`.
+pub(crate) fn superclass_chain<'a>(classes: &HashMap<Id, EzClass<'a>>, class_id: Id) -> Vec<Cow<'a, str>> {
+    let mut chain = Vec::new();
+    let mut seen = HashSet::from([class_id]);
+    let mut next = classes.get(&class_id).and_then(|c| c.super_class_obj_id);
+    while let Some(super_id) = next {
+        let Some(super_class) = classes.get(&super_id) else {
+            chain.push(Cow::Owned(format!("(unresolved {})", super_id)));
+            break;
+        };
+        if !seen.insert(super_id) {
+            chain.push(Cow::Owned(format!("(cycle at {})", super_class.name)));
+            break;
         }
-        FieldValue::Int(v) => {
-            println!(
-                "  - {}: {} = {}",
-                field_name,
-                field_type.java_type_name(),
-                v
-            );
+        chain.push(Cow::Borrowed(super_class.name));
+        next = super_class.super_class_obj_id;
+    }
+    chain
+}
+
+/// The runtime type of the object `id` refers to: its class, a primitive array type, or the
+/// class itself when `id` is a class object.
+pub(crate) fn ref_type_name<'a>(
+    id: Id,
+    obj_id_to_class_obj_id: &dyn ObjClassIndex,
+    classes: &HashMap<Id, EzClass<'a>>,
+    prim_array_obj_id_to_type: &IdDashMap<PrimitiveArrayType>,
+) -> Cow<'a, str> {
+    if let Some(class_obj_id) = obj_id_to_class_obj_id.class_of(id) {
+        Cow::Borrowed(classes.get(&class_obj_id).map(|c| c.name).unwrap_or("(class not found)"))
+    } else if let Some(prim_type) = prim_array_obj_id_to_type.get(&id) {
+        Cow::Borrowed(primitive_array_type_name(*prim_type))
+    } else if let Some(dest_class) = classes.get(&id) {
+        Cow::Owned(format!("class {}", dest_class.name))
+    } else {
+        Cow::Borrowed("type for obj id not found")
+    }
+}
+
+/// Print one field. A reference to a boxed primitive in `boxed` also shows the value it holds.
+pub(crate) fn print_field_val<'a>(
+    out: &mut impl Write,
+    field_val: &FieldValue,
+    field_name: &str,
+    field_type: FieldType,
+    ref_type: &dyn Fn(Id) -> Cow<'a, str>,
+    boxed: &IdMap<FieldValue>,
+    ids: IdFormat,
+) -> Result<(), AnalyzeError> {
+    match field_val {
+        FieldValue::ObjectId(Some(field_ref_id)) => {
+            let type_name = ref_type(*field_ref_id);
+            match boxed.get(field_ref_id) {
+                Some(value) => writeln!(
+                    out,
+                    "  - {} = id {} ({}: {})",
+                    field_name,
+                    ids.show(*field_ref_id),
+                    type_name,
+                    boxed_value_text(value)
+                )?,
+                None => writeln!(out, "  - {} = id {} ({})", field_name, ids.show(*field_ref_id), type_name)?,
+            }
         }
-        FieldValue::Long(v) => {
-            println!(
-                "  - {}: {} = {}",
-                field_name,
-                field_type.java_type_name(),
-                v
-            );
+        FieldValue::ObjectId(None) => {
+            writeln!(out, "  - {} = null", field_name)?;
         }
+        FieldValue::Boolean(v) => writeln!(out, "  - {}: {} = {}", field_name, field_type.java_type_name(), v)?,
+        FieldValue::Char(v) => writeln!(out, "  - {}: {} = {}", field_name, field_type.java_type_name(), v)?,
+        FieldValue::Float(v) => writeln!(out, "  - {}: {} = {}", field_name, field_type.java_type_name(), v)?,
+        FieldValue::Double(v) => writeln!(out, "  - {}: {} = {}", field_name, field_type.java_type_name(), v)?,
+        FieldValue::Byte(v) => writeln!(out, "  - {}: {} = {}", field_name, field_type.java_type_name(), v)?,
+        FieldValue::Short(v) => writeln!(out, "  - {}: {} = {}", field_name, field_type.java_type_name(), v)?,
+        FieldValue::Int(v) => writeln!(out, "  - {}: {} = {}", field_name, field_type.java_type_name(), v)?,
+        FieldValue::Long(v) => writeln!(out, "  - {}: {} = {}", field_name, field_type.java_type_name(), v)?,
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn classes() -> Vec<(Id, &'static str)> {
+        vec![
+            (Id::from(1_u64), "java/lang/String"),
+            (Id::from(2_u64), "java/util/HashMap"),
+            (Id::from(3_u64), "java/util/HashMap$Node"),
+            (Id::from(4_u64), "[Ljava/lang/String;"),
+        ]
+    }
+
+    #[test]
+    fn test_array_elements_truncated_past_cap() {
+        let mut out = Vec::new();
+        let rest = write_capped(0..300, 256, |e| Ok(write!(out, "{}, ", e)?)).unwrap();
+        write_truncation(&mut out, rest, "").unwrap();
+        let text = String::from_utf8(out).unwrap();
+        assert_eq!(rest, 44);
+        assert_eq!(text.matches(", ").count(), 256);
+        assert!(text.starts_with("0, 1, 2, "));
+        assert!(text.ends_with("254, 255, ... (44 more)"), "{}", text);
+    }
+
+    #[test]
+    fn test_array_elements_under_cap_not_truncated() {
+        let mut out = Vec::new();
+        let rest = write_capped(0..3, 256, |e| Ok(write!(out, "{}, ", e)?)).unwrap();
+        write_truncation(&mut out, rest, "").unwrap();
+        assert_eq!(rest, 0);
+        assert_eq!(String::from_utf8(out).unwrap(), "0, 1, 2, ");
+    }
+
+    #[test]
+    fn test_hexdump_short_byte_array() {
+        let lines = hexdump_lines(b"Hello world.\x00\x01\xff\n!");
+        assert_eq!(lines, vec![
+            "00000000  48 65 6c 6c 6f 20 77 6f  72 6c 64 2e 00 01 ff 0a  |Hello world.....|",
+            "00000010  21                                                |!|",
+        ]);
+        assert!(hexdump_lines(&[]).is_empty());
+    }
+
+    #[test]
+    fn test_ascii_and_base64_bytes() {
+        assert_eq!(ascii_text(b"ok\x00\x7f~"), "ok..~");
+        assert_eq!(base64(b""), "");
+        assert_eq!(base64(b"f"), "Zg==");
+        assert_eq!(base64(b"fo"), "Zm8=");
+        assert_eq!(base64(b"foobar"), "Zm9vYmFy");
+    }
+
+    #[test]
+    fn test_object_window_prints_exactly_limit_objects() {
+        // Stand-in for the printing loop: ids of the objects that would be printed
+        let printed = |mut window: ObjectWindow| -> Vec<u32> {
+            let mut printed = Vec::new();
+            for obj in 0..10 {
+                if window.admit() {
+                    printed.push(obj);
+                }
+                if window.is_exhausted() {
+                    break;
+                }
+            }
+            printed
+        };
+        assert_eq!(printed(ObjectWindow::default()).len(), 10);
+        assert_eq!(printed(ObjectWindow { skip: 0, limit: Some(3) }), vec![0, 1, 2]);
+        assert_eq!(printed(ObjectWindow { skip: 4, limit: Some(3) }), vec![4, 5, 6]);
+        assert_eq!(printed(ObjectWindow { skip: 8, limit: Some(5) }), vec![8, 9]);
+        assert_eq!(printed(ObjectWindow { skip: 2, limit: None }), (2..10).collect::<Vec<u32>>());
+        assert!(printed(ObjectWindow { skip: 0, limit: Some(0) }).is_empty());
+    }
+
+    #[test]
+    fn test_object_window_stops_the_scan_at_the_limit() {
+        let mut window = ObjectWindow { skip: 1, limit: Some(2) };
+        assert!(!window.is_exhausted());
+        assert!(!window.admit());
+        assert!(window.admit());
+        assert!(!window.is_exhausted());
+        assert!(window.admit());
+        assert!(window.is_exhausted());
+        assert!(!window.admit());
+    }
+
+    #[test]
+    fn test_exact_filter_selects_single_class() {
+        let filter = ClassFilter::Exact("java.util.HashMap".to_string());
+        let ids = matching_class_ids(classes().into_iter(), &filter);
+        assert_eq!(ids, HashSet::from([Id::from(2_u64)]));
+        // Unrelated instances (String, HashMap$Node) are suppressed
+        assert!(!ids.contains(&Id::from(1_u64)));
+        assert!(!ids.contains(&Id::from(3_u64)));
+    }
+
+    #[test]
+    fn test_exact_filter_accepts_internal_name() {
+        let filter = ClassFilter::Exact("java/lang/String".to_string());
+        let ids = matching_class_ids(classes().into_iter(), &filter);
+        assert_eq!(ids, HashSet::from([Id::from(1_u64)]));
+    }
+
+    #[test]
+    fn test_regex_filter() {
+        let filter = ClassFilter::Regex(Regex::new(r"^java\.util\.HashMap").unwrap());
+        let ids = matching_class_ids(classes().into_iter(), &filter);
+        assert_eq!(ids, HashSet::from([Id::from(2_u64), Id::from(3_u64)]));
+    }
+
+    fn field_output(field_val: FieldValue, field_name: &str, field_type: FieldType) -> String {
+        field_output_with_ids(field_val, field_name, field_type, IdFormat::Decimal)
+    }
+
+    fn field_output_with_ids(field_val: FieldValue, field_name: &str, field_type: FieldType, ids: IdFormat) -> String {
+        let obj_id_to_class_obj_id: IdDashMap<Id> = IdDashMap::default();
+        let prim_array_obj_id_to_type: IdDashMap<PrimitiveArrayType> = IdDashMap::default();
+        prim_array_obj_id_to_type.insert(Id::from(5_u64), PrimitiveArrayType::Byte);
+        let classes: HashMap<Id, EzClass> = HashMap::new();
+
+        let ref_type = |id: Id| ref_type_name(id, &obj_id_to_class_obj_id, &classes, &prim_array_obj_id_to_type);
+        let mut boxed: IdMap<FieldValue> = IdMap::default();
+        boxed.insert(Id::from(7_u64), FieldValue::Int(42));
+
+        let mut out = Vec::new();
+        print_field_val(&mut out, &field_val, field_name, field_type, &ref_type, &boxed, ids).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn test_print_field_val_output() {
+        assert_eq!(field_output(FieldValue::Int(42), "count", FieldType::Int), "  - count: int = 42\n");
+        assert_eq!(field_output(FieldValue::Boolean(true), "done", FieldType::Boolean), "  - done: boolean = true\n");
+        assert_eq!(field_output(FieldValue::ObjectId(None), "next", FieldType::ObjectId), "  - next = null\n");
+        assert_eq!(
+            field_output(FieldValue::ObjectId(Some(Id::from(5_u64))), "value", FieldType::ObjectId),
+            "  - value = id 5 (byte[])\n"
+        );
+        assert_eq!(
+            field_output(FieldValue::ObjectId(Some(Id::from(6_u64))), "value", FieldType::ObjectId),
+            "  - value = id 6 (type for obj id not found)\n"
+        );
+    }
+
+    #[test]
+    fn test_hex_ids() {
+        assert_eq!(IdFormat::Hex.show(Id::from(25789437408_u64)).to_string(), "0x6012b95e0");
+        assert_eq!(IdFormat::Decimal.show(Id::from(25789437408_u64)).to_string(), "25789437408");
+        assert_eq!(
+            field_output_with_ids(FieldValue::ObjectId(Some(Id::from(255_u64))), "value", FieldType::ObjectId, IdFormat::Hex),
+            "  - value = id 0xff (type for obj id not found)\n"
+        );
+        // Primitive field values aren't ids
+        assert_eq!(
+            field_output_with_ids(FieldValue::Int(255), "count", FieldType::Int, IdFormat::Hex),
+            "  - count: int = 255\n"
+        );
+    }
+
+    #[test]
+    fn test_boxed_reference_prints_value() {
+        assert_eq!(
+            field_output(FieldValue::ObjectId(Some(Id::from(7_u64))), "size", FieldType::ObjectId),
+            "  - size = id 7 (type for obj id not found: 42)\n"
+        );
+    }
+
+    #[test]
+    fn test_primitive_array_names_match() {
+        let filter = ClassFilter::Exact("byte[]".to_string());
+        assert!(filter.matches("byte[]"));
+        assert!(!filter.matches("int[]"));
     }
 }
@@ -0,0 +1,135 @@
+// SPDX-License-Identifier: MIT
+// Copyright (c) 2026 Zac Policzer
+
+use std::path::PathBuf;
+use std::sync::Arc;
+use arrow_array::{Array, Int32Array, RecordBatch, StringArray, UInt64Array};
+use arrow_schema::{DataType, Field, Schema};
+use crate::analyzer::HeapDumpAnalyzer;
+use crate::error::AnalyzeError;
+use crate::strings::decode_strings;
+use crate::util::{write_to_parquet, WriterSettings};
+
+pub struct DumpStringsOptions {
+    /// Skip strings shorter than this many UTF-16 code units (Java's `String.length()`).
+    pub min_length: usize,
+    /// Order by text rather than by object id, so duplicates end up adjacent.
+    pub sort_by_value: bool,
+    /// Write `<dir>/strings.parquet` instead of printing.
+    pub parquet_dir: Option<PathBuf>,
+    pub writer: WriterSettings,
+}
+
+/// A decoded String with its Java length.
+struct StringRow {
+    obj_id: u64,
+    length: usize,
+    value: String,
+}
+
+/// Apply the length filter and ordering to decoded strings.
+fn select_strings(mut rows: Vec<StringRow>, options: &DumpStringsOptions) -> Vec<StringRow> {
+    rows.retain(|r| r.length >= options.min_length);
+    if options.sort_by_value {
+        rows.sort_unstable_by(|a, b| a.value.cmp(&b.value).then(a.obj_id.cmp(&b.obj_id)));
+    } else {
+        rows.sort_unstable_by_key(|r| r.obj_id);
+    }
+    rows
+}
+
+/// Build the `strings` batch: obj_id, length, value.
+fn build_strings_batch(rows: &[StringRow]) -> RecordBatch {
+    let schema = Arc::new(Schema::new(vec![
+        Field::new("obj_id", DataType::UInt64, false),
+        Field::new("length", DataType::Int32, false),
+        Field::new("value", DataType::Utf8, false),
+    ]));
+    RecordBatch::try_new(
+        schema,
+        vec![
+            Arc::new(UInt64Array::from_iter_values(rows.iter().map(|r| r.obj_id))) as Arc<dyn Array>,
+            Arc::new(Int32Array::from_iter_values(rows.iter().map(|r| r.length as i32))) as Arc<dyn Array>,
+            Arc::new(StringArray::from_iter_values(rows.iter().map(|r| r.value.as_str()))) as Arc<dyn Array>,
+        ],
+    ).unwrap()
+}
+
+/// Print (or write to parquet) the text of every java.lang.String in the dump, decoded from
+/// its backing char[] or byte[] array.
+pub fn dump_strings(analyzer: &HeapDumpAnalyzer, options: &DumpStringsOptions) -> Result<(), AnalyzeError> {
+    let hprof = analyzer.hprof();
+    let (index, segments) = analyzer.indexed()?;
+
+    let rows: Vec<StringRow> = decode_strings(hprof, index, segments)
+        .into_iter()
+        .map(|(obj_id, decoded)| StringRow {
+            obj_id: obj_id.id(),
+            length: decoded.text.encode_utf16().count(),
+            value: decoded.text,
+        })
+        .collect();
+    let rows = select_strings(rows, options);
+
+    match &options.parquet_dir {
+        Some(dir) => {
+            write_to_parquet(dir, "strings", &[build_strings_batch(&rows)], options.writer)?;
+            println!("Wrote {} strings to {}", rows.len(), dir.join("strings.parquet").display());
+        }
+        None => {
+            for row in rows {
+                // Escape tabs and newlines so each string stays on one line
+                println!("{}\t{}", row.obj_id, row.value.escape_debug());
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arrow_array::cast::AsArray;
+    use arrow_array::types::{Int32Type, UInt64Type};
+    use parquet::basic::Compression;
+
+    fn rows() -> Vec<StringRow> {
+        [(30, "beta"), (10, "alpha"), (20, "a"), (40, "alpha")]
+            .into_iter()
+            .map(|(obj_id, value): (u64, &str)| StringRow {
+                obj_id,
+                length: value.encode_utf16().count(),
+                value: value.to_string(),
+            })
+            .collect()
+    }
+
+    fn options(min_length: usize, sort_by_value: bool) -> DumpStringsOptions {
+        DumpStringsOptions { min_length, sort_by_value, parquet_dir: None, writer: WriterSettings::new(Compression::SNAPPY) }
+    }
+
+    #[test]
+    fn test_select_strings_defaults_to_obj_id_order() {
+        let ids: Vec<u64> = select_strings(rows(), &options(0, false)).iter().map(|r| r.obj_id).collect();
+        assert_eq!(ids, vec![10, 20, 30, 40]);
+    }
+
+    #[test]
+    fn test_select_strings_min_length_and_sort() {
+        let selected = select_strings(rows(), &options(2, true));
+        let got: Vec<(u64, &str)> = selected.iter().map(|r| (r.obj_id, r.value.as_str())).collect();
+        assert_eq!(got, vec![(10, "alpha"), (40, "alpha"), (30, "beta")]);
+    }
+
+    #[test]
+    fn test_build_strings_batch() {
+        let batch = build_strings_batch(&select_strings(rows(), &options(0, false)));
+        assert_eq!(batch.num_rows(), 4);
+        assert_eq!(batch.schema().field(0).name(), "obj_id");
+        assert_eq!(batch.schema().field(1).name(), "length");
+        assert_eq!(batch.schema().field(2).name(), "value");
+        assert_eq!(batch.column(0).as_primitive::<UInt64Type>().value(1), 20);
+        assert_eq!(batch.column(1).as_primitive::<Int32Type>().value(0), 5);
+        assert_eq!(batch.column(2).as_string::<i32>().value(2), "beta");
+    }
+}
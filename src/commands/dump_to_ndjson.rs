@@ -0,0 +1,55 @@
+// SPDX-License-Identifier: MIT
+// Copyright (c) 2026 Zac Policzer
+
+//! `dump-objects-to-ndjson`: every instance and array as one JSON object per line, in the
+//! `dump-objects --format json` layout, for scripts and tools that can't read parquet.
+
+use std::io::Write;
+use jvm_hprof::heap_dump::SubRecord;
+use crate::analyzer::HeapDumpAnalyzer;
+use crate::commands::dump_objects::{ClassFilter, Selection};
+use crate::commands::object_json::write_sub_record_json;
+use crate::edges::instance_field_names;
+use crate::error::AnalyzeError;
+use crate::id_hash::IdMap;
+use crate::progress::Progress;
+use crate::skip_errors::sub_records;
+use crate::strings::decode_strings;
+
+/// Write every instance and array, or those of the classes `filter` matches, to `out` as
+/// they are read, one JSON object per line with every array element. Class records are left
+/// out. Only the index and the decoded Strings are held in memory.
+pub fn dump_objects_to_ndjson(
+    analyzer: &HeapDumpAnalyzer,
+    filter: Option<&ClassFilter>,
+    out: &mut impl Write,
+) -> Result<(), AnalyzeError> {
+    let hprof = analyzer.hprof();
+    let (index, segments) = analyzer.indexed()?;
+    let selection = Selection::new(filter, index);
+
+    // Array elements are written as references, so only String instances need decoding
+    let strings = match selection.strings() {
+        true => decode_strings(hprof, index, segments),
+        false => IdMap::default(),
+    };
+    let field_names = instance_field_names(index);
+
+    let progress = Progress::start("Writing segments", Some(segments.len() as u64));
+    for r in segments {
+        if let Some(p) = &progress {
+            p.advance(1);
+        }
+        let segment = r.as_heap_dump_segment().unwrap()
+            .map_err(|_| AnalyzeError::parse("heap dump segment"))?;
+        for p in sub_records(&index.options, segment.sub_records()) {
+            let s = p?;
+            if !matches!(s, SubRecord::Class(_)) && selection.sub_record(&s) {
+                write_sub_record_json(out, s, hprof, index, &strings, &field_names, usize::MAX)?;
+            }
+        }
+    }
+
+    out.flush()?;
+    Ok(())
+}
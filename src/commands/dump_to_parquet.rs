@@ -1,20 +1,38 @@
 // SPDX-License-Identifier: MIT
 // Copyright (c) 2026 Zac Policzer
 
-use std::collections::HashMap;
-use std::sync::Arc;
-use arrow_array::{Array, BooleanArray, Float32Array, Float64Array, Int16Array, Int32Array, Int64Array, Int8Array, RecordBatch, StringArray, StructArray, UInt16Array, UInt32Array, UInt64Array};
-use arrow_array::builder::{ListBuilder, BooleanBuilder, Int8Builder, UInt16Builder, Int16Builder, Int32Builder, Int64Builder, Float32Builder, Float64Builder, UInt64Builder};
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use arrow_array::{new_null_array, Array, ArrayRef, BooleanArray, Float32Array, Float64Array, Int16Array, Int32Array, Int64Array, Int8Array, RecordBatch, StringArray, StructArray, UInt16Array, UInt32Array, UInt64Array};
+use arrow_array::builder::{ArrayBuilder, BinaryBuilder, ListBuilder, BooleanBuilder, Int8Builder, UInt16Builder, Int16Builder, Int32Builder, Int64Builder, Float32Builder, Float64Builder, StringDictionaryBuilder, StructBuilder, UInt64Builder};
+use arrow_array::types::Int32Type;
 use arrow_schema::{DataType, Field, Schema};
-use dashmap::DashMap;
-use jvm_hprof::{Hprof, Id, Record};
+use jvm_hprof::{Hprof, Id, IdSize, Record, RecordTag};
 use jvm_hprof::heap_dump::{FieldType, FieldValue, PrimitiveArrayType, SubRecord};
-use parquet::arrow::ArrowWriter;
-use parquet::basic::Compression;
-use parquet::file::properties::WriterProperties;
 use rayon::prelude::*;
+use regex::Regex;
+use crate::analyzer::{AnalyzerOptions, HeapDumpAnalyzer};
+use crate::cancel::cancelled;
+use crate::commands::dump_objects::is_object;
+use crate::error::AnalyzeError;
+use crate::gc_roots::{gc_root, GcRoot};
+use crate::graph::{DominatorTree, ObjectGraph, Reachability};
 use crate::hprof_index::HprofIndex;
-use crate::util::generate_schema_from_descriptors;
+use crate::prim_arrays::{list_type, InlineArrays};
+use crate::progress::Progress;
+use crate::skip_errors::sub_records;
+use crate::strings::decode_utf16_string;
+use crate::unresolved::UnresolvedReferences;
+use crate::util::{
+    array_shallow_size, generate_schema_from_descriptors, id_size_bytes, instance_shallow_size, log_file_written,
+    parquet_file_stem, primitive_array_element_size, primitive_array_len, primitive_array_type_name,
+    reference_struct_fields, write_to_parquet, BatchWriter, OutputFormat, ReferenceEncoding, WriterSettings,
+    SCHEMA_JSON_SUFFIX,
+};
+use crate::visit::{visit_segment, SubRecordVisitor};
 
 // ---------------------------------------------------------------------------
 // ExtendedFieldValue & helpers
@@ -23,8 +41,13 @@ use crate::util::generate_schema_from_descriptors;
 #[derive(Debug)]
 enum ExtendedFieldValue {
     FieldValue(FieldValue),
-    /// Any object reference (instance, array, class, or null). Type resolved at build time.
+    /// A reference to an instance, array or class. Type resolved at build time.
     Reference(Id),
+    /// A Java null reference: an Arrow null in reference columns, so it can't be mistaken
+    /// for a reference to an object with id 0, unless nulls are written as id 0.
+    NullReference,
+    /// Padding for a row a column has no value for; written as null.
+    Missing,
 }
 
 // Ensure ExtendedFieldValue can be sent across threads for parallel column building
@@ -35,142 +58,141 @@ unsafe impl Sync for ExtendedFieldValue {}
 /// Reference classification (instance vs prim array) is deferred to build_column
 /// to avoid redundant DashMap lookups during the hot parse loop.
 fn add_instance_values(
-    hprof: &Hprof,
+    id_size: IdSize,
     field_columns: &mut Vec<Vec<ExtendedFieldValue>>,
+    obj_id: Id,
     mut field_val_input: &[u8],
     field_descriptors: &[jvm_hprof::heap_dump::FieldDescriptor],
-) {
+) -> Result<(), AnalyzeError> {
     for (i, fd) in field_descriptors.iter().enumerate() {
         let (input, field_val) = fd
             .field_type()
-            .parse_value(field_val_input, hprof.header().id_size())
-            .unwrap();
+            .parse_value(field_val_input, id_size)
+            .map_err(|_| AnalyzeError::parse(format!("fields of instance {}", obj_id)))?;
         field_val_input = input;
         match field_val {
             FieldValue::ObjectId(Some(field_ref_id)) => {
                 field_columns[i].push(ExtendedFieldValue::Reference(field_ref_id));
             }
             FieldValue::ObjectId(None) => {
-                field_columns[i].push(ExtendedFieldValue::Reference(Id::from(0)));
+                field_columns[i].push(ExtendedFieldValue::NullReference);
             }
             _ => {
                 field_columns[i].push(ExtendedFieldValue::FieldValue(field_val));
             }
         }
     }
+    Ok(())
 }
 
+/// Builds the dictionary-encoded `type` of reference structs.
+type TypeBuilder = StringDictionaryBuilder<Int32Type>;
+
 /// Build an Arrow column from buffered field values, using the schema's declared
-/// DataType to determine the output type.
-fn build_column(field_val_vec: &[ExtendedFieldValue], index: &HprofIndex, expected_type: &DataType) -> Arc<dyn Array> {
-    match expected_type {
+/// DataType to determine the output type. A value whose variant doesn't match the
+/// column type is written as null rather than a made-up default. With `null_as_zero`, null
+/// references are written as id 0 (`{0, "null"}` in a struct column) instead of nulls.
+fn build_column(
+    field_val_vec: &[ExtendedFieldValue],
+    index: &HprofIndex,
+    inline_arrays: &InlineArrays,
+    expected_type: &DataType,
+    null_as_zero: bool,
+) -> Result<Arc<dyn Array>, AnalyzeError> {
+    Ok(match expected_type {
+        DataType::List(item) => {
+            let refs = field_val_vec.iter().map(|v| match v {
+                ExtendedFieldValue::Reference(id) => Some(*id),
+                _ => None,
+            });
+            inline_arrays.list_column(refs, item.data_type())
+        }
         DataType::Struct(_) => {
-            let id_vec = field_val_vec.iter().map(|v| match v {
-                ExtendedFieldValue::Reference(val) => val.id(),
-                _ => 0,
-            }).collect::<Vec<u64>>();
-            let type_vec: Vec<std::borrow::Cow<str>> = field_val_vec.iter().map(|v| match v {
-                ExtendedFieldValue::Reference(val) => {
-                    resolve_ref_type_str(*val, index)
-                },
-                _ => std::borrow::Cow::Borrowed("null"),
-            }).collect();
-            let id_array: Arc<dyn Array> = Arc::new(UInt64Array::from(id_vec));
-            let type_strs: Vec<&str> = type_vec.iter().map(|c| c.as_ref()).collect();
-            let type_array: Arc<dyn Array> = Arc::new(StringArray::from(type_strs));
-            let struct_array = StructArray::from(vec![
-                (Arc::new(Field::new("id", DataType::UInt64, false)), id_array),
-                (Arc::new(Field::new("type", DataType::Utf8, false)), type_array),
-            ]);
-            Arc::new(struct_array)
+            let mut builder = StructBuilder::new(
+                reference_struct_fields(),
+                vec![
+                    Box::new(UInt64Builder::with_capacity(field_val_vec.len())) as Box<dyn ArrayBuilder>,
+                    Box::new(TypeBuilder::new()) as Box<dyn ArrayBuilder>,
+                ],
+            );
+            for v in field_val_vec {
+                // Struct children are non-nullable, so a null slot still needs child values
+                let (id, type_str, valid) = match v {
+                    ExtendedFieldValue::Reference(val) => (val.id(), index.resolve_ref_type(*val), true),
+                    ExtendedFieldValue::NullReference if null_as_zero => (0, Cow::Borrowed("null"), true),
+                    _ => (0, Cow::Borrowed(""), false),
+                };
+                builder.field_builder::<UInt64Builder>(0).unwrap().append_value(id);
+                builder.field_builder::<TypeBuilder>(1).unwrap().append_value(type_str);
+                builder.append(valid);
+            }
+            Arc::new(builder.finish())
         }
         DataType::Int32 => {
-            Arc::new(Int32Array::from(field_val_vec.iter().map(|v| match v {
-                ExtendedFieldValue::FieldValue(FieldValue::Int(val)) => *val,
-                _ => 0,
-            }).collect::<Vec<i32>>()))
+            Arc::new(field_val_vec.iter().map(|v| match v {
+                ExtendedFieldValue::FieldValue(FieldValue::Int(val)) => Some(*val),
+                _ => None,
+            }).collect::<Int32Array>())
         }
         DataType::Int64 => {
-            Arc::new(Int64Array::from(field_val_vec.iter().map(|v| match v {
-                ExtendedFieldValue::FieldValue(FieldValue::Long(val)) => *val,
-                _ => 0,
-            }).collect::<Vec<i64>>()))
+            Arc::new(field_val_vec.iter().map(|v| match v {
+                ExtendedFieldValue::FieldValue(FieldValue::Long(val)) => Some(*val),
+                _ => None,
+            }).collect::<Int64Array>())
         }
         DataType::Boolean => {
-            Arc::new(BooleanArray::from(field_val_vec.iter().map(|v| match v {
-                ExtendedFieldValue::FieldValue(FieldValue::Boolean(val)) => *val,
-                _ => false,
-            }).collect::<Vec<bool>>()))
+            Arc::new(field_val_vec.iter().map(|v| match v {
+                ExtendedFieldValue::FieldValue(FieldValue::Boolean(val)) => Some(*val),
+                _ => None,
+            }).collect::<BooleanArray>())
         }
         DataType::UInt16 => {
-            Arc::new(UInt16Array::from(field_val_vec.iter().map(|v| match v {
-                ExtendedFieldValue::FieldValue(FieldValue::Char(val)) => *val as u16,
-                _ => 0,
-            }).collect::<Vec<u16>>()))
+            Arc::new(field_val_vec.iter().map(|v| match v {
+                ExtendedFieldValue::FieldValue(FieldValue::Char(val)) => Some(*val as u16),
+                _ => None,
+            }).collect::<UInt16Array>())
         }
         DataType::Float32 => {
-            Arc::new(Float32Array::from(field_val_vec.iter().map(|v| match v {
-                ExtendedFieldValue::FieldValue(FieldValue::Float(val)) => *val,
-                _ => 0.0,
-            }).collect::<Vec<f32>>()))
+            Arc::new(field_val_vec.iter().map(|v| match v {
+                ExtendedFieldValue::FieldValue(FieldValue::Float(val)) => Some(*val),
+                _ => None,
+            }).collect::<Float32Array>())
         }
         DataType::Float64 => {
-            Arc::new(Float64Array::from(field_val_vec.iter().map(|v| match v {
-                ExtendedFieldValue::FieldValue(FieldValue::Double(val)) => *val,
-                _ => 0.0,
-            }).collect::<Vec<f64>>()))
+            Arc::new(field_val_vec.iter().map(|v| match v {
+                ExtendedFieldValue::FieldValue(FieldValue::Double(val)) => Some(*val),
+                _ => None,
+            }).collect::<Float64Array>())
         }
         DataType::Int8 => {
-            Arc::new(Int8Array::from(field_val_vec.iter().map(|v| match v {
-                ExtendedFieldValue::FieldValue(FieldValue::Byte(val)) => *val,
-                _ => 0,
-            }).collect::<Vec<i8>>()))
+            Arc::new(field_val_vec.iter().map(|v| match v {
+                ExtendedFieldValue::FieldValue(FieldValue::Byte(val)) => Some(*val),
+                _ => None,
+            }).collect::<Int8Array>())
         }
         DataType::Int16 => {
-            Arc::new(Int16Array::from(field_val_vec.iter().map(|v| match v {
-                ExtendedFieldValue::FieldValue(FieldValue::Short(val)) => *val,
-                _ => 0,
-            }).collect::<Vec<i16>>()))
+            Arc::new(field_val_vec.iter().map(|v| match v {
+                ExtendedFieldValue::FieldValue(FieldValue::Short(val)) => Some(*val),
+                _ => None,
+            }).collect::<Int16Array>())
         }
         DataType::UInt64 => {
-            Arc::new(UInt64Array::from(field_val_vec.iter().map(|v| match v {
-                ExtendedFieldValue::Reference(val) => val.id(),
-                ExtendedFieldValue::FieldValue(FieldValue::ObjectId(val)) => val.map(|v| v.id()).unwrap_or(0),
-                _ => 0,
-            }).collect::<Vec<u64>>()))
+            Arc::new(field_val_vec.iter().map(|v| match v {
+                ExtendedFieldValue::Reference(val) => Some(val.id()),
+                // Robo mode keeps bare ids, with 0 for null
+                ExtendedFieldValue::NullReference => null_as_zero.then_some(0),
+                ExtendedFieldValue::FieldValue(FieldValue::ObjectId(val)) => Some(val.map(|v| v.id()).unwrap_or(0)),
+                _ => None,
+            }).collect::<UInt64Array>())
         }
-        _ => panic!("Unsupported schema data type: {:?}", expected_type),
-    }
+        _ => return Err(output_error(format!("unsupported column type {:?}", expected_type))),
+    })
 }
 
-/// Resolve the type name for an object reference.
-/// Returns a &str where possible to avoid allocation for the common cases
-/// (instance/object array references), falling back to String for rare cases
-/// (primitive array refs, class refs, unresolved).
-fn resolve_ref_type_str<'a>(id: Id, index: &'a HprofIndex) -> std::borrow::Cow<'a, str> {
-    use std::borrow::Cow;
-    if id.id() == 0 {
-        return Cow::Borrowed("null");
-    }
-    // Most common: instance or object array → class name is a &str from the index
-    if let Some(class_obj_id) = index.obj_id_to_class_obj_id.get(&id) {
-        if let Some(c) = index.classes.get(&*class_obj_id) {
-            return Cow::Borrowed(c.name);
-        }
-    }
-    // Primitive array ref (rarer — only when a field points to a prim array)
-    if let Some(pt) = index.prim_array_obj_id_to_type.get(&id) {
-        return Cow::Owned(format!("{}[]", pt.java_type_name()));
-    }
-    // Class reference
-    if let Some(c) = index.classes.get(&id) {
-        return Cow::Owned(format!("class {}", c.name));
-    }
-    Cow::Borrowed("(unresolved)")
-}
-
-fn resolve_ref_type(id: Id, index: &HprofIndex) -> String {
-    resolve_ref_type_str(id, index).into_owned()
+/// A batch that couldn't be put together, reported as an output error rather than a panic
+/// in a worker thread.
+fn output_error(reason: impl Into<String>) -> AnalyzeError {
+    AnalyzeError::Output(std::io::Error::other(reason.into()))
 }
 
 fn format_field_value(fv: &FieldValue) -> (String, String, u64, String) {
@@ -188,6 +210,462 @@ fn format_field_value(fv: &FieldValue) -> (String, String, u64, String) {
     }
 }
 
+// ---------------------------------------------------------------------------
+// Options
+// ---------------------------------------------------------------------------
+
+/// Settings for `dump_objects_to_parquet`, filled in from the CLI.
+pub struct ParquetOptions {
+    pub out_dir: PathBuf,
+    /// Row group bytes the writers may buffer in memory, in total, before flushing to disk.
+    pub writer_buffer_bytes: usize,
+    /// Rayon threads building record batches; file writes run on the writer shards.
+    pub threads: usize,
+    pub writer: WriterSettings,
+    pub robo_mode: bool,
+    /// Write all instances to one `instances.parquet` instead of one file per class.
+    pub single_file: bool,
+    /// `--partition-by class`: write each class's instances to `class=<name>/part-0.parquet`
+    /// rather than `<name>.parquet`, also in single-file mode. Other files stay flat.
+    pub partition_by_class: bool,
+    /// Mark each instance with whether it is reachable from a GC root.
+    pub reachability: bool,
+    /// Only write instances reachable from a GC root, leaving out garbage not yet collected.
+    pub live_only: bool,
+    /// Add each instance's retained size from the dominator tree.
+    pub retained_size: bool,
+    /// Add each instance's shallow size, which its class fixes.
+    pub shallow_size: bool,
+    /// Write fields that only ever hold one primitive array type as lists of its elements.
+    pub inline_primitive_arrays: bool,
+    /// How references are written in default mode.
+    pub references: ReferenceEncoding,
+    /// Write array elements; without them array files only have each array's length.
+    pub array_elements: bool,
+    /// Add a `text` column to the char array file with each array decoded as UTF-16.
+    pub char_array_text: bool,
+    /// Write each byte array's values as one `Binary` value rather than a list of `Int8`.
+    pub binary_byte_arrays: bool,
+    /// Also write the utf8 string table to `_utf8.parquet`.
+    pub dump_utf8: bool,
+    /// Fail instead of warning when a class's instances can't be written as-is.
+    pub strict: bool,
+    /// Fail on the first exported reference whose type can't be resolved, instead of only
+    /// counting it in `ExportSummary::unresolved_references`.
+    pub fail_on_unresolved: bool,
+    /// Only write the first N instances and arrays of the dump.
+    pub sample: Option<usize>,
+    /// Which classes' instances get written.
+    pub classes: ClassSelection,
+    /// Print the totals in the returned `ExportSummary` once the files are closed.
+    pub summary: bool,
+    /// `--objects-per-file`: split each instance file into `.part-<k>` files of at most this
+    /// many rows.
+    pub objects_per_file: Option<usize>,
+}
+
+/// `--include-classes` / `--exclude-classes`: glob patterns matched against dotted class
+/// names, where `*` matches any run of characters (dots included) and `?` any one. A class
+/// is selected if it matches no exclude pattern and, when there are include patterns, at
+/// least one of those. The default selects every class.
+#[derive(Debug, Clone, Default)]
+pub struct ClassSelection {
+    include: Vec<Regex>,
+    exclude: Vec<Regex>,
+}
+
+impl ClassSelection {
+    pub fn new<S: AsRef<str>>(include: &[S], exclude: &[S]) -> Self {
+        let compile = |globs: &[S]| globs.iter().map(|g| glob_regex(g.as_ref())).collect();
+        ClassSelection { include: compile(include), exclude: compile(exclude) }
+    }
+
+    /// `class_name` may be in either internal (`java/lang/String`) or dotted form.
+    pub fn selects(&self, class_name: &str) -> bool {
+        let dotted = class_name.replace('/', ".");
+        !self.exclude.iter().any(|re| re.is_match(&dotted))
+            && (self.include.is_empty() || self.include.iter().any(|re| re.is_match(&dotted)))
+    }
+
+    fn selects_everything(&self) -> bool {
+        self.include.is_empty() && self.exclude.is_empty()
+    }
+}
+
+/// An anchored regex for a glob. Everything but `*` and `?` matches literally.
+fn glob_regex(glob: &str) -> Regex {
+    let mut pattern = String::from("^");
+    for c in glob.replace('/', ".").chars() {
+        match c {
+            '*' => pattern.push_str(".*"),
+            '?' => pattern.push('.'),
+            c => pattern.push_str(&regex::escape(&c.to_string())),
+        }
+    }
+    pattern.push('$');
+    Regex::new(&pattern).expect("escaped glob is a valid regex")
+}
+
+// ---------------------------------------------------------------------------
+// Classes that couldn't be written cleanly
+// ---------------------------------------------------------------------------
+
+/// Why instances of a class were skipped or patched up.
+#[derive(Debug, Clone, PartialEq)]
+enum ClassProblem {
+    /// No class dump record, so no field descriptors and no columns: the instances are skipped.
+    NoClassRecord,
+    /// A class record but no schema, so no file to write the instances to: they are skipped.
+    /// Classes `--include-classes` or `--exclude-classes` leave out have none either, and
+    /// aren't reported.
+    NoSchema,
+    /// Field columns came out with a different length than the number of instances. Short
+    /// columns are padded with nulls and long ones cut to the instance count.
+    LengthMismatch { rows: usize, lengths: Vec<usize> },
+}
+
+/// Problems seen while building batches, gathered across segments so each class is
+/// reported once, after pass 2, in class id order.
+#[derive(Default)]
+struct ClassProblems {
+    classes: Mutex<HashMap<Id, (ClassProblem, u64)>>,
+    unresolved_references: UnresolvedReferences,
+    /// With `fail_on_unresolved`, the unresolved reference of the lowest object id, so the
+    /// error doesn't depend on which segment finished first.
+    unresolved: Mutex<Option<(u64, AnalyzeError)>>,
+}
+
+impl ClassProblems {
+    fn new(fail_on_unresolved: bool) -> Self {
+        ClassProblems { unresolved_references: UnresolvedReferences::new(fail_on_unresolved), ..Default::default() }
+    }
+
+    /// Keep the first problem seen for `class_id`, counting every affected instance.
+    fn record(&self, class_id: Id, problem: ClassProblem, instances: u64) {
+        let mut problems = self.classes.lock().unwrap();
+        problems.entry(class_id).or_insert((problem, 0)).1 += instances;
+    }
+
+    /// Report every reference in `field_columns` whose type can't be resolved, keeping the
+    /// error for the lowest object id if failing on them is enabled.
+    fn check_references(
+        &self,
+        index: &HprofIndex,
+        obj_ids: &[u64],
+        field_columns: &[Vec<ExtendedFieldValue>],
+        schema: &Schema,
+    ) {
+        for (column, field) in field_columns.iter().zip(schema.fields()) {
+            for (obj_id, value) in obj_ids.iter().zip(column) {
+                let ExtendedFieldValue::Reference(id) = value else { continue };
+                if index.is_unresolved_ref(*id) {
+                    self.report_unresolved(*obj_id, field.name(), *id);
+                }
+            }
+        }
+    }
+
+    /// Count a reference from `obj_id` whose type can't be resolved, keeping the error if
+    /// it is the lowest object id failing so far. Elements of an object array are named by
+    /// their position, as `[2]`.
+    fn report_unresolved(&self, obj_id: u64, field_name: &str, id: Id) {
+        if let Err(e) = self.unresolved_references.report(Id::from(obj_id), field_name, id) {
+            let mut unresolved = self.unresolved.lock().unwrap();
+            if unresolved.as_ref().is_none_or(|(first, _)| obj_id < *first) {
+                *unresolved = Some((obj_id, e));
+            }
+        }
+    }
+
+    fn take_unresolved(&self) -> Option<AnalyzeError> {
+        self.unresolved.lock().unwrap().take().map(|(_, e)| e)
+    }
+
+    fn into_sorted(self) -> Vec<(Id, ClassProblem, u64)> {
+        let mut problems: Vec<_> = self.classes.into_inner().unwrap().into_iter()
+            .map(|(class_id, (problem, instances))| (class_id, problem, instances))
+            .collect();
+        problems.sort_unstable_by_key(|(class_id, _, _)| class_id.id());
+        problems
+    }
+}
+
+/// Objects written, summed across segments: instances per class for
+/// `class_histogram.parquet`, and arrays with their shallow bytes for the summary.
+#[derive(Default)]
+struct ObjectCounts {
+    instances: Mutex<HashMap<Id, u64>>,
+    arrays: AtomicU64,
+    array_bytes: AtomicU64,
+}
+
+impl ObjectCounts {
+    fn add(&self, class_id: Id, instances: u64) {
+        *self.instances.lock().unwrap().entry(class_id).or_insert(0) += instances;
+    }
+
+    fn add_arrays(&self, arrays: u64, bytes: u64) {
+        self.arrays.fetch_add(arrays, Ordering::Relaxed);
+        self.array_bytes.fetch_add(bytes, Ordering::Relaxed);
+    }
+}
+
+/// What one `dump_objects_to_parquet` run wrote, printed with `--summary`. Instances and
+/// arrays are the ones written, so `--sample` and `--include-classes` lower them; bytes are
+/// the estimated shallow sizes of those objects.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ExportSummary {
+    pub classes: u64,
+    pub instances: u64,
+    pub arrays: u64,
+    pub shallow_bytes: u64,
+    pub files: u64,
+    /// Exported references whose type couldn't be resolved, written as `(unresolved)`.
+    pub unresolved_references: u64,
+}
+
+impl ExportSummary {
+    pub fn objects(&self) -> u64 {
+        self.instances + self.arrays
+    }
+
+    fn print(&self) {
+        println!("Summary:");
+        println!("  objects:       {}", self.objects());
+        println!("  classes:       {}", self.classes);
+        println!("  instances:     {}", self.instances);
+        println!("  arrays:        {}", self.arrays);
+        println!("  shallow bytes: {}", self.shallow_bytes);
+        println!("  files written: {}", self.files);
+        println!("  unresolved:    {}", self.unresolved_references);
+    }
+}
+
+fn class_problem_message(class_id: Id, class_name: Option<&str>, problem: &ClassProblem, instances: u64) -> String {
+    let class = match class_name {
+        Some(name) => format!("class {} ({})", name, class_id),
+        None => format!("class {}", class_id),
+    };
+    match problem {
+        ClassProblem::NoClassRecord => {
+            format!("skipped {} instances of {}: no class record, so it has no columns", instances, class)
+        }
+        ClassProblem::NoSchema => {
+            format!("skipped {} instances of {}: no schema was generated for it", instances, class)
+        }
+        ClassProblem::LengthMismatch { rows, lengths } => format!(
+            "{}: {} instances but field column lengths {:?}; short columns padded with nulls ({} instances affected)",
+            class, rows, lengths, instances,
+        ),
+    }
+}
+
+/// The one `--strict` error for every class in `problems`, as sorted by `into_sorted`.
+fn class_problems_error(index: &HprofIndex, problems: &[(Id, ClassProblem, u64)]) -> AnalyzeError {
+    AnalyzeError::UnwritableClasses(problems.iter()
+        .map(|(class_id, problem, instances)| {
+            let class_name = index.classes.get(class_id).map(|c| c.name);
+            class_problem_message(*class_id, class_name, problem, *instances)
+        })
+        .collect())
+}
+
+/// Make every field column `rows` long so the batch can be built, padding with nulls or
+/// cutting off extra values. Returns the original lengths if any column needed fixing.
+fn fit_columns(field_columns: &mut [Vec<ExtendedFieldValue>], rows: usize) -> Option<ClassProblem> {
+    let lengths: Vec<usize> = field_columns.iter().map(Vec::len).collect();
+    if lengths.iter().all(|&len| len == rows) {
+        return None;
+    }
+    for column in field_columns.iter_mut() {
+        column.truncate(rows);
+        column.resize_with(rows, || ExtendedFieldValue::Missing);
+    }
+    Some(ClassProblem::LengthMismatch { rows, lengths })
+}
+
+// ---------------------------------------------------------------------------
+// Per-object columns from the reference graph
+// ---------------------------------------------------------------------------
+
+/// Graph analyses and other per-object columns requested for the instance output, written
+/// right after `obj_id`.
+#[derive(Default)]
+struct GraphColumns {
+    reachability: Option<Reachability>,
+    /// Write the `reachable` column; without it `reachability` is only there for `live_only`.
+    reachable_column: bool,
+    /// `--live-only`: leave out instances no GC root reaches.
+    live_only: bool,
+    dominators: Option<DominatorTree>,
+    /// Per class: the shallow size of each of its instances, which the class fixes.
+    shallow_sizes: Option<HashMap<Id, i32>>,
+}
+
+impl GraphColumns {
+    fn fields(&self) -> Vec<Field> {
+        let mut fields = Vec::new();
+        if self.reachable_column {
+            fields.push(Field::new("reachable", DataType::Boolean, false));
+        }
+        if self.dominators.is_some() {
+            // Null for objects not reachable from a GC root
+            fields.push(Field::new("retained_bytes", DataType::UInt64, true));
+        }
+        if self.shallow_sizes.is_some() {
+            fields.push(Field::new("shallow_bytes", DataType::Int32, false));
+        }
+        fields
+    }
+
+    fn columns(&self, class_id: Id, obj_ids: &[u64]) -> Vec<ArrayRef> {
+        let mut columns: Vec<ArrayRef> = Vec::new();
+        if let Some(r) = self.reachability.as_ref().filter(|_| self.reachable_column) {
+            columns.push(Arc::new(BooleanArray::from_iter(obj_ids.iter().map(|id| Some(r.is_reachable(*id))))));
+        }
+        if let Some(d) = &self.dominators {
+            columns.push(Arc::new(UInt64Array::from_iter(obj_ids.iter().map(|id| d.retained_bytes(*id)))));
+        }
+        if let Some(sizes) = &self.shallow_sizes {
+            columns.push(Arc::new(Int32Array::from(vec![sizes[&class_id]; obj_ids.len()])));
+        }
+        columns
+    }
+
+    /// Whether the instance `obj_id` is written at all.
+    fn keeps(&self, obj_id: u64) -> bool {
+        !self.live_only || self.reachability.as_ref().is_some_and(|r| r.is_reachable(obj_id))
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Single-file mode: union schema across all classes
+// ---------------------------------------------------------------------------
+
+const SINGLE_FILE_KEY: &str = "instances";
+
+/// Java type label for a column data type, used to tell apart same-named fields of
+/// different types in the union schema.
+fn java_type_label(data_type: &DataType) -> &'static str {
+    match data_type {
+        DataType::Boolean => "boolean",
+        DataType::UInt16 => "char",
+        DataType::Float32 => "float",
+        DataType::Float64 => "double",
+        DataType::Int8 => "byte",
+        DataType::Int16 => "short",
+        DataType::Int32 => "int",
+        DataType::Int64 => "long",
+        DataType::List(item) => match item.data_type() {
+            DataType::Boolean => "boolean[]",
+            DataType::UInt16 => "char[]",
+            DataType::Float32 => "float[]",
+            DataType::Float64 => "double[]",
+            DataType::Int8 => "byte[]",
+            DataType::Int16 => "short[]",
+            DataType::Int32 => "int[]",
+            _ => "long[]",
+        },
+        _ => "ref",
+    }
+}
+
+/// Superset schema for `--single-file` output: `obj_id`, `class_name`, any graph columns
+/// (`reachable`, `retained_bytes`), then every distinct instance column across all classes,
+/// all nullable so each class's batch can be padded.
+/// A column name used with more than one type (e.g. `value` as both int and reference)
+/// becomes one column per type, named `value:int`, `value:ref`.
+struct UnionSchema {
+    schema: Arc<Schema>,
+    /// Columns ahead of the data columns: obj_id, class_name and the graph columns.
+    leading_columns: usize,
+    /// Per class: for each union data column, the matching column in that class's schema.
+    columns_by_class: HashMap<Id, Vec<Option<usize>>>,
+    /// With `--partition-by class`: keep each class's rows under its own file key, so each
+    /// class gets a partition, rather than sending them all to `instances`.
+    per_class_files: bool,
+}
+
+impl UnionSchema {
+    fn new(schemas: &HashMap<Id, Schema>, graph_fields: Vec<Field>) -> Self {
+        let mut types_by_name: BTreeMap<&str, Vec<&DataType>> = BTreeMap::new();
+        for schema in schemas.values() {
+            for f in schema.fields() {
+                let types = types_by_name.entry(f.name().as_str()).or_default();
+                if !types.contains(&f.data_type()) {
+                    types.push(f.data_type());
+                }
+            }
+        }
+
+        let mut fields = vec![
+            Field::new("obj_id", DataType::UInt64, false),
+            Field::new("class_name", DataType::Utf8, false),
+        ];
+        fields.extend(graph_fields);
+        let leading_columns = fields.len();
+        let mut union_index: HashMap<(&str, &DataType), usize> = HashMap::new();
+        for (name, mut types) in types_by_name {
+            let conflicting = types.len() > 1;
+            types.sort_unstable_by_key(|dt| java_type_label(dt));
+            for dt in types {
+                let column_name = if conflicting {
+                    format!("{}:{}", name, java_type_label(dt))
+                } else {
+                    name.to_string()
+                };
+                union_index.insert((name, dt), fields.len() - leading_columns);
+                fields.push(Field::new(column_name, dt.clone(), true));
+            }
+        }
+
+        let num_data_columns = fields.len() - leading_columns;
+        let columns_by_class = schemas.iter()
+            .map(|(class_id, schema)| {
+                let mut slots = vec![None; num_data_columns];
+                for (i, f) in schema.fields().iter().enumerate() {
+                    slots[union_index[&(f.name().as_str(), f.data_type())]] = Some(i);
+                }
+                (*class_id, slots)
+            })
+            .collect();
+
+        UnionSchema { schema: Arc::new(Schema::new(fields)), leading_columns, columns_by_class, per_class_files: false }
+    }
+
+    fn file_key(&self, class_name: &str, class_id: Id) -> String {
+        match self.per_class_files {
+            true => class_file_key(class_name, class_id),
+            false => SINGLE_FILE_KEY.into(),
+        }
+    }
+
+    /// Lay one class's columns out in union order, null-filling columns the class lacks.
+    fn pad(
+        &self,
+        class_id: Id,
+        class_name: &str,
+        obj_ids: ArrayRef,
+        graph_columns: Vec<ArrayRef>,
+        data_columns: &[ArrayRef],
+    ) -> Result<RecordBatch, AnalyzeError> {
+        let num_rows = obj_ids.len();
+        let mut columns: Vec<ArrayRef> = Vec::with_capacity(self.schema.fields().len());
+        columns.push(obj_ids);
+        columns.push(Arc::new(StringArray::from(vec![class_name; num_rows])));
+        columns.extend(graph_columns);
+        let data_fields = self.schema.fields().iter().skip(self.leading_columns);
+        for (slot, field) in self.columns_by_class[&class_id].iter().zip(data_fields) {
+            columns.push(match slot {
+                Some(i) => data_columns[*i].clone(),
+                None => new_null_array(field.data_type(), num_rows),
+            });
+        }
+        RecordBatch::try_new(self.schema.clone(), columns)
+            .map_err(|e| output_error(format!("padding class '{}' to the union schema failed: {}", class_name, e)))
+    }
+}
+
 // ---------------------------------------------------------------------------
 // Fully parallel segment processing: parse + build RecordBatches in rayon
 // ---------------------------------------------------------------------------
@@ -202,68 +680,176 @@ struct WritableBatch {
 // WritableBatch contains RecordBatch (which is Send+Sync) and Strings/Arc — all Send.
 unsafe impl Send for WritableBatch {}
 
-/// Process a single segment: parse sub-records, build Arrow arrays, and return
-/// ready-to-write RecordBatches. ALL CPU work happens here inside rayon.
-fn process_segment_to_batches<'a>(
-    record: &Record<'a>,
-    hprof: &Hprof,
-    index: &HprofIndex,
-    schemas: &HashMap<Id, Schema>,
-    robo_mode: bool,
-) -> Vec<WritableBatch> {
-    let mut batches = Vec::new();
+/// Build a `_primitive_arrays_<type>` batch (`obj_id`, `values: List<T>`) from buffered
+/// arrays, or None if there are none. Zero-length arrays become empty lists, never nulls.
+macro_rules! build_prim_batch {
+    ($arrays:expr, $name:expr, $inner_type:expr, $builder_type:ident) => {
+        if $arrays.is_empty() {
+            None
+        } else {
+            let mut obj_ids = Vec::with_capacity($arrays.len());
+            let mut list_builder = ListBuilder::new($builder_type::new());
+            for (oid, vals) in &$arrays {
+                obj_ids.push(*oid);
+                for v in vals { list_builder.values().append_value(*v); }
+                list_builder.append(true);
+            }
+            let schema = Arc::new(Schema::new(vec![
+                Field::new("obj_id", DataType::UInt64, false),
+                Field::new("values", DataType::List(Arc::new(Field::new("item", $inner_type, true))), false),
+            ]));
+            let batch = RecordBatch::try_new(
+                schema.clone(),
+                vec![
+                    Arc::new(UInt64Array::from(obj_ids)) as Arc<dyn Array>,
+                    Arc::new(list_builder.finish()) as Arc<dyn Array>,
+                ],
+            ).unwrap();
+            Some(WritableBatch { file_key: $name.into(), schema, batch })
+        }
+    };
+}
+
+/// `_primitive_arrays_byte` with each array's bytes as one `Binary` value.
+fn binary_byte_batch(byte_arrays: Vec<(u64, Vec<i8>)>) -> WritableBatch {
+    let mut obj_ids = Vec::with_capacity(byte_arrays.len());
+    let mut values = BinaryBuilder::new();
+    for (obj_id, bytes) in byte_arrays {
+        obj_ids.push(obj_id);
+        values.append_value(bytes.into_iter().map(|b| b as u8).collect::<Vec<u8>>());
+    }
+    let schema = Arc::new(Schema::new(vec![
+        Field::new("obj_id", DataType::UInt64, false),
+        Field::new("values", DataType::Binary, false),
+    ]));
+    let batch = RecordBatch::try_new(
+        schema.clone(),
+        vec![
+            Arc::new(UInt64Array::from(obj_ids)) as Arc<dyn Array>,
+            Arc::new(values.finish()) as Arc<dyn Array>,
+        ],
+    ).unwrap();
+    WritableBatch { file_key: "_primitive_arrays_byte".into(), schema, batch }
+}
+
+/// `wb` with a `text` column appended, one value per row.
+fn with_text_column(wb: WritableBatch, texts: impl Iterator<Item = String>) -> WritableBatch {
+    let mut fields: Vec<Field> = wb.schema.fields().iter().map(|f| f.as_ref().clone()).collect();
+    fields.push(Field::new("text", DataType::Utf8, false));
+    let schema = Arc::new(Schema::new(fields));
+    let mut columns = wb.batch.columns().to_vec();
+    columns.push(Arc::new(StringArray::from(texts.collect::<Vec<String>>())) as Arc<dyn Array>);
+    let batch = RecordBatch::try_new(schema.clone(), columns).unwrap();
+    WritableBatch { file_key: wb.file_key, schema, batch }
+}
+
+/// File key of a class's instance batches in per-class mode, `<class name>_<class id>`.
+pub(crate) fn class_file_key(class_name: &str, class_id: Id) -> String {
+    format!("{}_{}", class_name, class_id)
+}
 
-    // Temporary per-class accumulators for this segment
-    let mut instances: HashMap<Id, (Vec<u64>, Vec<Vec<ExtendedFieldValue>>)> = HashMap::new();
+/// Which array columns `process_segment_to_batches` writes.
+#[derive(Debug, Clone, Copy)]
+struct ArrayColumns {
+    elements: bool,
+    char_text: bool,
+    byte_binary: bool,
+}
+
+/// A primitive array's elements, or a parse error for the first that doesn't parse.
+fn array_values<T, E>(values: Option<impl Iterator<Item = Result<T, E>>>) -> Result<Vec<T>, AnalyzeError> {
+    values.ok_or_else(|| AnalyzeError::parse("primitive array"))?
+        .collect::<Result<_, _>>()
+        .map_err(|_| AnalyzeError::parse("primitive array element"))
+}
+
+/// One segment's parsed rows, collected sub-record by sub-record for
+/// `process_segment_to_batches` to turn into batches.
+struct SegmentRows<'s, 'i> {
+    index: &'s HprofIndex<'i>,
+    schemas: &'s HashMap<Id, Schema>,
+    graph_columns: &'s GraphColumns,
+    problems: &'s ClassProblems,
+    id_size: IdSize,
+    object_budget: usize,
+    robo_mode: bool,
+    array_elements: bool,
+    id_only: bool,
+    null_as_zero: bool,
+    objects: usize,
+    array_count: u64,
+    array_bytes: u64,
+
+    // Per-class instance accumulators
+    instances: HashMap<Id, (Vec<u64>, Vec<Vec<ExtendedFieldValue>>)>,
+    // Instances skipped for having no schema, recorded as problems once per class
+    without_schema: HashMap<Id, u64>,
 
     // Primitive array accumulators
-    let mut bool_arrays: Vec<(u64, Vec<bool>)> = Vec::new();
-    let mut byte_arrays: Vec<(u64, Vec<i8>)> = Vec::new();
-    let mut char_arrays: Vec<(u64, Vec<u16>)> = Vec::new();
-    let mut short_arrays: Vec<(u64, Vec<i16>)> = Vec::new();
-    let mut int_arrays: Vec<(u64, Vec<i32>)> = Vec::new();
-    let mut long_arrays: Vec<(u64, Vec<i64>)> = Vec::new();
-    let mut float_arrays: Vec<(u64, Vec<f32>)> = Vec::new();
-    let mut double_arrays: Vec<(u64, Vec<f64>)> = Vec::new();
-
-    // Object array accumulators
-    let mut oa_obj_ids: Vec<u64> = Vec::new();
-    let mut oa_class_names: Vec<String> = Vec::new();
-    let mut oa_elements: Vec<Vec<u64>> = Vec::new();
-
-    // GC root accumulators
-    let mut gc_roots: Vec<(String, u64, Option<u32>, Option<u32>)> = Vec::new();
+    bool_arrays: Vec<(u64, Vec<bool>)>,
+    byte_arrays: Vec<(u64, Vec<i8>)>,
+    char_arrays: Vec<(u64, Vec<u16>)>,
+    short_arrays: Vec<(u64, Vec<i16>)>,
+    int_arrays: Vec<(u64, Vec<i32>)>,
+    long_arrays: Vec<(u64, Vec<i64>)>,
+    float_arrays: Vec<(u64, Vec<f32>)>,
+    double_arrays: Vec<(u64, Vec<f64>)>,
+    // Without elements, just (obj id, length) for each array, by file key
+    prim_array_lengths: BTreeMap<String, (Vec<u64>, Vec<i32>)>,
+
+    // Object array accumulators. Robo mode and id-only references keep bare element ids;
+    // otherwise each element is resolved to a {id, type} struct like instance reference fields.
+    oa_obj_ids: Vec<u64>,
+    oa_class_names: Vec<&'i str>,
+    oa_lengths: Vec<i32>,
+    // ListBuilders created and consumed within this task — never sent across threads
+    oa_id_elements: ListBuilder<UInt64Builder>,
+    oa_struct_elements: ListBuilder<StructBuilder>,
+
+    gc_roots: Vec<GcRoot>,
 
     // Object index accumulators (robo mode only)
-    let mut idx_obj_ids: Vec<u64> = Vec::new();
-    let mut idx_type_names: Vec<String> = Vec::new();
+    idx_obj_ids: Vec<u64>,
+    // Borrowed class names for instances and primitive arrays; only object arrays and
+    // classes need a string of their own
+    idx_type_names: Vec<Cow<'i, str>>,
+}
 
-    // --- Parse sub-records ---
-    let segment = record.as_heap_dump_segment().unwrap().unwrap();
-    for p in segment.sub_records() {
-        let s = p.unwrap();
+impl<'a> SubRecordVisitor<'a> for SegmentRows<'_, '_> {
+    fn on_sub_record(&mut self, s: SubRecord<'a>) -> Result<(), AnalyzeError> {
+        if is_object(&s) {
+            if self.objects == self.object_budget {
+                return Ok(());
+            }
+            self.objects += 1;
+        }
         match s {
             SubRecord::Instance(instance) => {
-                if robo_mode {
-                    idx_obj_ids.push(instance.obj_id().id());
-                    idx_type_names.push(
-                        index.classes.get(&instance.class_obj_id())
-                            .map(|c| c.name.to_string())
-                            .unwrap_or_else(|| "(unresolved)".to_string())
-                    );
+                if !self.graph_columns.keeps(instance.obj_id().id()) {
+                    return Ok(());
+                }
+                if self.robo_mode {
+                    self.idx_obj_ids.push(instance.obj_id().id());
+                    self.idx_type_names.push(Cow::Borrowed(
+                        self.index.classes.get(&instance.class_obj_id()).map_or("(unresolved)", |c| c.name)
+                    ));
                 }
 
-                let field_descriptors = match index.class_instance_field_descriptors
+                let field_descriptors = match self.index.class_instance_field_descriptors
                     .get(&instance.class_obj_id())
                 {
                     Some(fd) => fd,
-                    None => continue,
+                    None => {
+                        self.problems.record(instance.class_obj_id(), ClassProblem::NoClassRecord, 1);
+                        return Ok(());
+                    }
                 };
-                if !schemas.contains_key(&instance.class_obj_id()) {
-                    continue;
+                if !self.schemas.contains_key(&instance.class_obj_id()) {
+                    *self.without_schema.entry(instance.class_obj_id()).or_insert(0) += 1;
+                    return Ok(());
                 }
 
-                let entry = instances
+                let entry = self.instances
                     .entry(instance.class_obj_id())
                     .or_insert_with(|| {
                         let columns: Vec<Vec<ExtendedFieldValue>> =
@@ -273,209 +859,319 @@ fn process_segment_to_batches<'a>(
 
                 entry.0.push(instance.obj_id().id());
                 add_instance_values(
-                    hprof, &mut entry.1, instance.fields(), field_descriptors,
-                );
+                    self.id_size, &mut entry.1, instance.obj_id(), instance.fields(), field_descriptors,
+                )?;
             }
             SubRecord::PrimitiveArray(pa) => {
                 let obj_id = pa.obj_id().id();
-                if robo_mode {
-                    idx_obj_ids.push(obj_id);
-                    idx_type_names.push(format!("{}[]", pa.primitive_type().java_type_name()));
+                if self.robo_mode {
+                    self.idx_obj_ids.push(obj_id);
+                    self.idx_type_names.push(Cow::Borrowed(primitive_array_type_name(pa.primitive_type())));
+                }
+                let length = primitive_array_len(&pa);
+                self.array_count += 1;
+                self.array_bytes += array_shallow_size(length, primitive_array_element_size(pa.primitive_type()), self.id_size);
+                if !self.array_elements {
+                    let file_key = format!("_primitive_arrays_{}", pa.primitive_type().java_type_name());
+                    let (obj_ids, lengths) = self.prim_array_lengths.entry(file_key).or_default();
+                    obj_ids.push(obj_id);
+                    lengths.push(length as i32);
+                    return Ok(());
                 }
                 match pa.primitive_type() {
                     PrimitiveArrayType::Boolean => {
-                        bool_arrays.push((obj_id, pa.booleans().unwrap().map(|v| v.unwrap()).collect()));
+                        self.bool_arrays.push((obj_id, array_values(pa.booleans())?));
                     }
                     PrimitiveArrayType::Byte => {
-                        byte_arrays.push((obj_id, pa.bytes().unwrap().map(|v| v.unwrap()).collect()));
+                        self.byte_arrays.push((obj_id, array_values(pa.bytes())?));
                     }
                     PrimitiveArrayType::Char => {
-                        char_arrays.push((obj_id, pa.chars().unwrap().map(|v| v.unwrap() as u16).collect()));
+                        self.char_arrays.push((obj_id, array_values(pa.chars())?.into_iter().map(|v| v as u16).collect()));
                     }
                     PrimitiveArrayType::Short => {
-                        short_arrays.push((obj_id, pa.shorts().unwrap().map(|v| v.unwrap()).collect()));
+                        self.short_arrays.push((obj_id, array_values(pa.shorts())?));
                     }
                     PrimitiveArrayType::Int => {
-                        int_arrays.push((obj_id, pa.ints().unwrap().map(|v| v.unwrap()).collect()));
+                        self.int_arrays.push((obj_id, array_values(pa.ints())?));
                     }
                     PrimitiveArrayType::Long => {
-                        long_arrays.push((obj_id, pa.longs().unwrap().map(|v| v.unwrap()).collect()));
+                        self.long_arrays.push((obj_id, array_values(pa.longs())?));
                     }
                     PrimitiveArrayType::Float => {
-                        float_arrays.push((obj_id, pa.floats().unwrap().map(|v| v.unwrap()).collect()));
+                        self.float_arrays.push((obj_id, array_values(pa.floats())?));
                     }
                     PrimitiveArrayType::Double => {
-                        double_arrays.push((obj_id, pa.doubles().unwrap().map(|v| v.unwrap()).collect()));
+                        self.double_arrays.push((obj_id, array_values(pa.doubles())?));
                     }
                 }
             }
             SubRecord::ObjectArray(oa) => {
-                if robo_mode {
-                    idx_obj_ids.push(oa.obj_id().id());
-                    idx_type_names.push(
-                        index.classes.get(&oa.array_class_obj_id())
+                if self.robo_mode {
+                    self.idx_obj_ids.push(oa.obj_id().id());
+                    self.idx_type_names.push(Cow::Owned(
+                        self.index.classes.get(&oa.array_class_obj_id())
                             .map(|c| format!("{}[]", c.name))
                             .unwrap_or_else(|| "(unresolved)[]".to_string())
-                    );
+                    ));
                 }
-                oa_obj_ids.push(oa.obj_id().id());
-                oa_class_names.push(
-                    index.classes.get(&oa.array_class_obj_id())
-                        .map(|c| c.name.to_string())
-                        .unwrap_or_else(|| "(unresolved)".to_string())
-                );
-                oa_elements.push(
-                    oa.elements(hprof.header().id_size())
-                        .map(|elem| match elem.unwrap() {
-                            Some(id) => id.id(),
-                            None => 0,
-                        })
-                        .collect()
-                );
-            }
-            SubRecord::GcRootUnknown(r) => {
-                gc_roots.push(("Unknown".into(), r.obj_id().id(), None, None));
-            }
-            SubRecord::GcRootThreadObj(r) => {
-                gc_roots.push(("ThreadObj".into(), r.thread_obj_id().map(|id| id.id()).unwrap_or(0), Some(r.thread_serial().num()), None));
-            }
-            SubRecord::GcRootJniGlobal(r) => {
-                gc_roots.push(("JniGlobal".into(), r.obj_id().id(), None, None));
-            }
-            SubRecord::GcRootJniLocalRef(r) => {
-                gc_roots.push(("JniLocal".into(), r.obj_id().id(), Some(r.thread_serial().num()), r.frame_index()));
-            }
-            SubRecord::GcRootJavaStackFrame(r) => {
-                gc_roots.push(("JavaStackFrame".into(), r.obj_id().id(), Some(r.thread_serial().num()), r.frame_index()));
-            }
-            SubRecord::GcRootNativeStack(r) => {
-                gc_roots.push(("NativeStack".into(), r.obj_id().id(), Some(r.thread_serial().num()), None));
-            }
-            SubRecord::GcRootSystemClass(r) => {
-                gc_roots.push(("SystemClass".into(), r.obj_id().id(), None, None));
-            }
-            SubRecord::GcRootThreadBlock(r) => {
-                gc_roots.push(("ThreadBlock".into(), r.obj_id().id(), Some(r.thread_serial().num()), None));
-            }
-            SubRecord::GcRootBusyMonitor(r) => {
-                gc_roots.push(("BusyMonitor".into(), r.obj_id().id(), None, None));
+                self.oa_obj_ids.push(oa.obj_id().id());
+                self.oa_class_names.push(self.index.classes.get(&oa.array_class_obj_id()).map_or("(unresolved)", |c| c.name));
+                let elements = oa.elements(self.id_size).count() as u64;
+                self.array_count += 1;
+                self.array_bytes += array_shallow_size(elements, id_size_bytes(self.id_size), self.id_size);
+                if !self.array_elements {
+                    self.oa_lengths.push(elements as i32);
+                    return Ok(());
+                }
+                let mut length: i32 = 0;
+                for elem in oa.elements(self.id_size) {
+                    // Null elements are encoded as id 0, which resolves to type "null", unless
+                    // they are written as nulls
+                    let elem_id = match elem.map_err(|_| AnalyzeError::parse("object array element"))? {
+                        Some(id) => id.id(),
+                        None => 0,
+                    };
+                    if self.index.is_unresolved_ref(Id::from(elem_id)) {
+                        self.problems.report_unresolved(oa.obj_id().id(), &format!("[{}]", length), Id::from(elem_id));
+                    }
+                    let valid = elem_id != 0 || self.null_as_zero;
+                    if self.id_only {
+                        self.oa_id_elements.values().append_option(valid.then_some(elem_id));
+                    } else {
+                        let elem_type = self.index.resolve_ref_type(Id::from(elem_id));
+                        let elem_struct = self.oa_struct_elements.values();
+                        elem_struct.field_builder::<UInt64Builder>(0).unwrap().append_value(elem_id);
+                        elem_struct.field_builder::<TypeBuilder>(1).unwrap().append_value(elem_type);
+                        elem_struct.append(valid);
+                    }
+                    length += 1;
+                }
+                if self.id_only {
+                    self.oa_id_elements.append(true);
+                } else {
+                    self.oa_struct_elements.append(true);
+                }
+                self.oa_lengths.push(length);
             }
-            SubRecord::Class(c) if robo_mode => {
-                idx_obj_ids.push(c.obj_id().id());
-                idx_type_names.push(
-                    index.classes.get(&c.obj_id())
+            SubRecord::Class(c) if self.robo_mode => {
+                self.idx_obj_ids.push(c.obj_id().id());
+                self.idx_type_names.push(Cow::Owned(
+                    self.index.classes.get(&c.obj_id())
                         .map(|ec| format!("class {}", ec.name))
                         .unwrap_or_else(|| "class (unresolved)".to_string())
-                );
+                ));
+            }
+            other => {
+                if let Some(root) = gc_root(&other) {
+                    self.gc_roots.push(root);
+                }
             }
-            _ => {}
         }
+        Ok(())
+    }
+}
+
+/// Process a single segment: parse sub-records, build Arrow arrays, and return
+/// ready-to-write RecordBatches. ALL CPU work happens here inside rayon. A sub-record
+/// that doesn't parse, or a batch that can't be built, fails the segment.
+fn process_segment_to_batches<'a>(
+    record: &Record<'a>,
+    hprof: &Hprof,
+    index: &HprofIndex,
+    schemas: &HashMap<Id, Schema>,
+    union_schema: Option<&UnionSchema>,
+    graph_columns: &GraphColumns,
+    inline_arrays: &InlineArrays,
+    problems: &ClassProblems,
+    object_counts: &ObjectCounts,
+    object_budget: usize,
+    robo_mode: bool,
+    references: ReferenceEncoding,
+    arrays: ArrayColumns,
+) -> Result<Vec<WritableBatch>, AnalyzeError> {
+    let array_elements = arrays.elements;
+    // Robo mode writes references like id-only, with 0 for null
+    let id_only = robo_mode || references == ReferenceEncoding::IdOnly;
+    let null_as_zero = robo_mode || references == ReferenceEncoding::Struct;
+    let mut batches = Vec::new();
+
+    // --- Parse sub-records ---
+    let mut rows = SegmentRows {
+        index,
+        schemas,
+        graph_columns,
+        problems,
+        id_size: hprof.header().id_size(),
+        object_budget,
+        robo_mode,
+        array_elements,
+        id_only,
+        null_as_zero,
+        objects: 0,
+        array_count: 0,
+        array_bytes: 0,
+        instances: HashMap::new(),
+        without_schema: HashMap::new(),
+        bool_arrays: Vec::new(),
+        byte_arrays: Vec::new(),
+        char_arrays: Vec::new(),
+        short_arrays: Vec::new(),
+        int_arrays: Vec::new(),
+        long_arrays: Vec::new(),
+        float_arrays: Vec::new(),
+        double_arrays: Vec::new(),
+        prim_array_lengths: BTreeMap::new(),
+        oa_obj_ids: Vec::new(),
+        oa_class_names: Vec::new(),
+        oa_lengths: Vec::new(),
+        oa_id_elements: ListBuilder::new(UInt64Builder::new()),
+        oa_struct_elements: ListBuilder::new(StructBuilder::new(
+            reference_struct_fields(),
+            vec![
+                Box::new(UInt64Builder::new()) as Box<dyn ArrayBuilder>,
+                Box::new(TypeBuilder::new()) as Box<dyn ArrayBuilder>,
+            ],
+        )),
+        gc_roots: Vec::new(),
+        idx_obj_ids: Vec::new(),
+        idx_type_names: Vec::new(),
+    };
+    visit_segment(record, &index.options, &mut rows)?;
+    let SegmentRows {
+        array_count, array_bytes, instances, without_schema, bool_arrays, byte_arrays, char_arrays, short_arrays,
+        int_arrays, long_arrays, float_arrays, double_arrays, prim_array_lengths, oa_obj_ids, oa_class_names,
+        oa_lengths, mut oa_id_elements, mut oa_struct_elements, gc_roots, idx_obj_ids, idx_type_names, ..
+    } = rows;
+
+    object_counts.add_arrays(array_count, array_bytes);
+    for (class_id, skipped) in without_schema {
+        problems.record(class_id, ClassProblem::NoSchema, skipped);
     }
 
     // --- Build RecordBatches (all CPU work, still inside rayon task) ---
 
-    // Instance batches per class
-    for (class_id, (obj_ids, field_columns)) in instances {
+    // Instance batches per class, in class name order so single-file rows are reproducible
+    let mut instances: Vec<_> = instances.into_iter().collect();
+    instances.sort_unstable_by_key(|(class_id, _)| {
+        (index.classes.get(class_id).map(|c| c.name), class_id.id())
+    });
+    for (class_id, (obj_ids, mut field_columns)) in instances {
         let schema = match schemas.get(&class_id) {
             Some(s) => s,
             None => continue,
         };
+        if let Some(problem) = fit_columns(&mut field_columns, obj_ids.len()) {
+            problems.record(class_id, problem, obj_ids.len() as u64);
+        }
+        problems.check_references(index, &obj_ids, &field_columns, schema);
+        object_counts.add(class_id, obj_ids.len() as u64);
+
+        let data_columns: Vec<Arc<dyn Array>> = field_columns.iter()
+            .zip(schema.fields().iter())
+            .map(|(col, field)| build_column(col, index, inline_arrays, field.data_type(), null_as_zero))
+            .collect::<Result<_, _>>()?;
+        let object_columns = graph_columns.columns(class_id, &obj_ids);
+
+        if let Some(union_schema) = union_schema {
+            let class_name = index.classes.get(&class_id).map(|c| c.name).unwrap_or("unknown");
+            let batch = union_schema.pad(
+                class_id, class_name, Arc::new(UInt64Array::from(obj_ids)), object_columns, &data_columns,
+            )?;
+            batches.push(WritableBatch {
+                file_key: union_schema.file_key(class_name, class_id),
+                schema: union_schema.schema.clone(),
+                batch,
+            });
+            continue;
+        }
 
         let mut fields = vec![Field::new("obj_id", DataType::UInt64, false)];
+        fields.extend(graph_columns.fields());
         fields.extend(schema.fields().iter().map(|f| f.as_ref().clone()));
         let full_schema = Arc::new(Schema::new(fields));
 
-        let data_columns: Vec<Arc<dyn Array>> = field_columns.iter()
-            .zip(schema.fields().iter())
-            .map(|(col, field)| build_column(col, index, field.data_type()))
-            .collect();
-
-        let mut columns: Vec<Arc<dyn Array>> = Vec::with_capacity(data_columns.len() + 1);
+        let mut columns: Vec<Arc<dyn Array>> = Vec::with_capacity(data_columns.len() + 3);
         columns.push(Arc::new(UInt64Array::from(obj_ids)));
+        columns.extend(object_columns);
         columns.extend(data_columns);
 
+        let class_name = index.classes.get(&class_id).map(|c| c.name).unwrap_or("unknown");
         let batch = RecordBatch::try_new(full_schema.clone(), columns)
-            .unwrap_or_else(|e| {
-                let class_name = index.classes.get(&class_id).map(|c| c.name).unwrap_or("unknown");
-                panic!("RecordBatch creation failed for class '{}': {}", class_name, e);
-            });
+            .map_err(|e| output_error(format!("building the batch for class '{}' failed: {}", class_name, e)))?;
 
-        let class_name = index.classes.get(&class_id).unwrap().name;
-        let file_key = format!("{}_{}", class_name, class_id);
+        let file_key = class_file_key(class_name, class_id);
         batches.push(WritableBatch { file_key, schema: full_schema, batch });
     }
 
     // Object array batch
     if !oa_obj_ids.is_empty() {
-        let schema = Arc::new(Schema::new(vec![
+        let mut fields = vec![
             Field::new("obj_id", DataType::UInt64, false),
             Field::new("class_name", DataType::Utf8, false),
-            Field::new("elements", DataType::List(Arc::new(Field::new("item", DataType::UInt64, true))), false),
-        ]));
-        // ListBuilder created and consumed within this task — never sent across threads
-        let mut list_builder = ListBuilder::new(UInt64Builder::new());
-        for elems in &oa_elements {
-            for e in elems { list_builder.values().append_value(*e); }
-            list_builder.append(true);
+            Field::new("length", DataType::Int32, false),
+        ];
+        let mut columns = vec![
+            Arc::new(UInt64Array::from(oa_obj_ids)) as Arc<dyn Array>,
+            Arc::new(StringArray::from(oa_class_names)) as Arc<dyn Array>,
+            Arc::new(Int32Array::from(oa_lengths)) as Arc<dyn Array>,
+        ];
+        if array_elements {
+            let (element_type, elements): (DataType, Arc<dyn Array>) = if id_only {
+                (DataType::UInt64, Arc::new(oa_id_elements.finish()))
+            } else {
+                (DataType::Struct(reference_struct_fields()), Arc::new(oa_struct_elements.finish()))
+            };
+            fields.push(Field::new("elements", DataType::List(Arc::new(Field::new("item", element_type, true))), false));
+            columns.push(elements);
         }
+        let schema = Arc::new(Schema::new(fields));
+        let batch = RecordBatch::try_new(schema.clone(), columns).unwrap();
+        batches.push(WritableBatch { file_key: "_object_arrays".into(), schema, batch });
+    }
+
+    // Primitive array batches
+    batches.extend(build_prim_batch!(bool_arrays, "_primitive_arrays_boolean", DataType::Boolean, BooleanBuilder));
+    if arrays.byte_binary && !byte_arrays.is_empty() {
+        batches.push(binary_byte_batch(byte_arrays));
+    } else {
+        batches.extend(build_prim_batch!(byte_arrays, "_primitive_arrays_byte", DataType::Int8, Int8Builder));
+    }
+    let char_batch = build_prim_batch!(char_arrays, "_primitive_arrays_char", DataType::UInt16, UInt16Builder);
+    batches.extend(char_batch.map(|wb| match arrays.char_text {
+        true => with_text_column(wb, char_arrays.iter().map(|(_, units)| decode_utf16_string(units))),
+        false => wb,
+    }));
+    batches.extend(build_prim_batch!(short_arrays, "_primitive_arrays_short", DataType::Int16, Int16Builder));
+    batches.extend(build_prim_batch!(int_arrays, "_primitive_arrays_int", DataType::Int32, Int32Builder));
+    batches.extend(build_prim_batch!(long_arrays, "_primitive_arrays_long", DataType::Int64, Int64Builder));
+    batches.extend(build_prim_batch!(float_arrays, "_primitive_arrays_float", DataType::Float32, Float32Builder));
+    batches.extend(build_prim_batch!(double_arrays, "_primitive_arrays_double", DataType::Float64, Float64Builder));
+    for (file_key, (obj_ids, lengths)) in prim_array_lengths {
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("obj_id", DataType::UInt64, false),
+            Field::new("length", DataType::Int32, false),
+        ]));
         let batch = RecordBatch::try_new(
             schema.clone(),
             vec![
-                Arc::new(UInt64Array::from(oa_obj_ids)) as Arc<dyn Array>,
-                Arc::new(StringArray::from(oa_class_names)) as Arc<dyn Array>,
-                Arc::new(list_builder.finish()) as Arc<dyn Array>,
+                Arc::new(UInt64Array::from(obj_ids)) as Arc<dyn Array>,
+                Arc::new(Int32Array::from(lengths)) as Arc<dyn Array>,
             ],
         ).unwrap();
-        batches.push(WritableBatch { file_key: "_object_arrays".into(), schema, batch });
+        batches.push(WritableBatch { file_key, schema, batch });
     }
 
-    // Primitive array batches
-    macro_rules! build_prim_batch {
-        ($arrays:expr, $name:expr, $inner_type:expr, $builder_type:ident) => {
-            if !$arrays.is_empty() {
-                let mut obj_ids = Vec::with_capacity($arrays.len());
-                let mut list_builder = ListBuilder::new($builder_type::new());
-                for (oid, vals) in &$arrays {
-                    obj_ids.push(*oid);
-                    for v in vals { list_builder.values().append_value(*v); }
-                    list_builder.append(true);
-                }
-                let schema = Arc::new(Schema::new(vec![
-                    Field::new("obj_id", DataType::UInt64, false),
-                    Field::new("values", DataType::List(Arc::new(Field::new("item", $inner_type, true))), false),
-                ]));
-                let batch = RecordBatch::try_new(
-                    schema.clone(),
-                    vec![
-                        Arc::new(UInt64Array::from(obj_ids)) as Arc<dyn Array>,
-                        Arc::new(list_builder.finish()) as Arc<dyn Array>,
-                    ],
-                ).unwrap();
-                batches.push(WritableBatch { file_key: $name.into(), schema, batch });
-            }
-        };
-    }
-
-    build_prim_batch!(bool_arrays, "_primitive_arrays_boolean", DataType::Boolean, BooleanBuilder);
-    build_prim_batch!(byte_arrays, "_primitive_arrays_byte", DataType::Int8, Int8Builder);
-    build_prim_batch!(char_arrays, "_primitive_arrays_char", DataType::UInt16, UInt16Builder);
-    build_prim_batch!(short_arrays, "_primitive_arrays_short", DataType::Int16, Int16Builder);
-    build_prim_batch!(int_arrays, "_primitive_arrays_int", DataType::Int32, Int32Builder);
-    build_prim_batch!(long_arrays, "_primitive_arrays_long", DataType::Int64, Int64Builder);
-    build_prim_batch!(float_arrays, "_primitive_arrays_float", DataType::Float32, Float32Builder);
-    build_prim_batch!(double_arrays, "_primitive_arrays_double", DataType::Float64, Float64Builder);
-
     // GC root batch
     if !gc_roots.is_empty() {
         let mut types = Vec::with_capacity(gc_roots.len());
         let mut obj_ids = Vec::with_capacity(gc_roots.len());
         let mut thread_serials = Vec::with_capacity(gc_roots.len());
         let mut frame_indexes = Vec::with_capacity(gc_roots.len());
-        for (rt, oid, ts, fi) in gc_roots {
-            types.push(rt);
-            obj_ids.push(oid);
-            thread_serials.push(ts);
-            frame_indexes.push(fi);
+        for root in gc_roots {
+            types.push(root.root_type);
+            obj_ids.push(root.obj_id);
+            thread_serials.push(root.thread_serial);
+            frame_indexes.push(root.frame_index);
         }
         let schema = Arc::new(Schema::new(vec![
             Field::new("root_type", DataType::Utf8, false),
@@ -505,33 +1201,221 @@ fn process_segment_to_batches<'a>(
             schema.clone(),
             vec![
                 Arc::new(UInt64Array::from(idx_obj_ids)) as Arc<dyn Array>,
-                Arc::new(StringArray::from(idx_type_names)) as Arc<dyn Array>,
+                Arc::new(StringArray::from_iter_values(idx_type_names.iter().map(|name| name.as_ref()))) as Arc<dyn Array>,
             ],
         ).unwrap();
         batches.push(WritableBatch { file_key: "_object_index".into(), schema, batch });
     }
 
-    batches
+    Ok(batches)
 }
 
 // ---------------------------------------------------------------------------
 // Schema generation
 // ---------------------------------------------------------------------------
 
-fn generate_all_schemas(index: &HprofIndex, robo_mode: bool) -> HashMap<Id, Schema> {
+fn generate_all_schemas(
+    index: &HprofIndex,
+    inline_arrays: &InlineArrays,
+    references: ReferenceEncoding,
+) -> HashMap<Id, Schema> {
     index.class_instance_field_descriptors.iter()
         .map(|(class_id, field_descriptors)| {
             let schema = generate_schema_from_descriptors(
                 field_descriptors,
                 &index.utf8,
                 index.class_field_declaring_classes.get(class_id),
-                robo_mode,
+                references,
             );
-            (*class_id, schema)
+            (*class_id, with_inline_arrays(*class_id, schema, inline_arrays))
         })
         .collect()
 }
 
+/// Retype the reference fields of `class_id` that `inline_arrays` inlines as `List<T>`.
+fn with_inline_arrays(class_id: Id, schema: Schema, inline_arrays: &InlineArrays) -> Schema {
+    let fields: Vec<Field> = schema.fields().iter().enumerate()
+        .map(|(i, f)| match inline_arrays.field_type(class_id, i) {
+            Some(pt) => Field::new(f.name(), list_type(pt), true),
+            None => f.as_ref().clone(),
+        })
+        .collect();
+    Schema::new(fields)
+}
+
+// ---------------------------------------------------------------------------
+// Class metadata writer
+// ---------------------------------------------------------------------------
+
+/// One `classes.parquet` row.
+#[derive(Debug, Clone, PartialEq)]
+struct ClassRow<'a> {
+    class_id: u64,
+    class_name: &'a str,
+    /// 0 for java.lang.Object and other roots of the hierarchy.
+    super_class_id: u64,
+    /// Estimated shallow size of one instance, as in the histogram.
+    instance_size: i32,
+    static_field_count: i32,
+    /// Flattened: includes every superclass's instance fields.
+    instance_field_count: i32,
+}
+
+/// Rows for every class in the index, sorted by name then id.
+fn class_rows<'a>(index: &HprofIndex<'a>, id_size: IdSize) -> Vec<ClassRow<'a>> {
+    let mut rows: Vec<ClassRow> = index.classes.iter()
+        .map(|(class_id, c)| {
+            let fds = index.class_instance_field_descriptors.get(class_id).map_or(&[][..], Vec::as_slice);
+            ClassRow {
+                class_id: class_id.id(),
+                class_name: c.name,
+                super_class_id: c.super_class_obj_id.map_or(0, |id| id.id()),
+                instance_size: instance_shallow_size(fds.iter().map(|fd| fd.field_type()), id_size) as i32,
+                static_field_count: c.static_fields.len() as i32,
+                instance_field_count: fds.len() as i32,
+            }
+        })
+        .collect();
+    rows.sort_unstable_by_key(|r| (r.class_name, r.class_id));
+    rows
+}
+
+/// Build the `classes` batch: class_id, class_name, super_class_id, instance_size,
+/// static_field_count, instance_field_count.
+fn build_classes_batch(rows: &[ClassRow]) -> Option<WritableBatch> {
+    if rows.is_empty() {
+        return None;
+    }
+    let schema = Arc::new(Schema::new(vec![
+        Field::new("class_id", DataType::UInt64, false),
+        Field::new("class_name", DataType::Utf8, false),
+        Field::new("super_class_id", DataType::UInt64, false),
+        Field::new("instance_size", DataType::Int32, false),
+        Field::new("static_field_count", DataType::Int32, false),
+        Field::new("instance_field_count", DataType::Int32, false),
+    ]));
+    let batch = RecordBatch::try_new(
+        schema.clone(),
+        vec![
+            Arc::new(UInt64Array::from_iter_values(rows.iter().map(|r| r.class_id))) as Arc<dyn Array>,
+            Arc::new(StringArray::from_iter_values(rows.iter().map(|r| r.class_name))) as Arc<dyn Array>,
+            Arc::new(UInt64Array::from_iter_values(rows.iter().map(|r| r.super_class_id))) as Arc<dyn Array>,
+            Arc::new(Int32Array::from_iter_values(rows.iter().map(|r| r.instance_size))) as Arc<dyn Array>,
+            Arc::new(Int32Array::from_iter_values(rows.iter().map(|r| r.static_field_count))) as Arc<dyn Array>,
+            Arc::new(Int32Array::from_iter_values(rows.iter().map(|r| r.instance_field_count))) as Arc<dyn Array>,
+        ],
+    ).unwrap();
+    Some(WritableBatch { file_key: "classes".into(), schema, batch })
+}
+
+/// One `class_histogram.parquet` row.
+#[derive(Debug, Clone, PartialEq)]
+struct ClassHistogramRow<'a> {
+    class_id: u64,
+    class_name: &'a str,
+    instance_count: u64,
+    total_shallow_bytes: u64,
+    /// Every instance of a class has the same shallow size, so this is also the average.
+    instance_size: u64,
+}
+
+/// Join the per-class instance counts with the class rows' instance sizes, largest total
+/// first and ties by name, like `histogram`. Classes loaded but never instantiated get a
+/// row with no instances; array classes, whose objects aren't instances, get none.
+fn class_histogram_rows<'a>(classes: &[ClassRow<'a>], counts: &HashMap<Id, u64>) -> Vec<ClassHistogramRow<'a>> {
+    let mut rows: Vec<ClassHistogramRow> = classes.iter()
+        .filter(|c| !c.class_name.starts_with('['))
+        .map(|c| {
+            let instance_count = counts.get(&Id::from(c.class_id)).copied().unwrap_or(0);
+            let instance_size = c.instance_size as u64;
+            ClassHistogramRow {
+                class_id: c.class_id,
+                class_name: c.class_name,
+                instance_count,
+                total_shallow_bytes: instance_count * instance_size,
+                instance_size,
+            }
+        })
+        .collect();
+    rows.sort_unstable_by(|a, b| {
+        b.total_shallow_bytes.cmp(&a.total_shallow_bytes).then_with(|| a.class_name.cmp(b.class_name))
+    });
+    rows
+}
+
+/// Build the `class_histogram` batch: class_id, class_name, instance_count,
+/// total_shallow_bytes, avg_shallow_bytes.
+fn build_class_histogram_batch(rows: &[ClassHistogramRow]) -> Option<WritableBatch> {
+    if rows.is_empty() {
+        return None;
+    }
+    let schema = Arc::new(Schema::new(vec![
+        Field::new("class_id", DataType::UInt64, false),
+        Field::new("class_name", DataType::Utf8, false),
+        Field::new("instance_count", DataType::UInt64, false),
+        Field::new("total_shallow_bytes", DataType::UInt64, false),
+        Field::new("avg_shallow_bytes", DataType::Float64, false),
+    ]));
+    let batch = RecordBatch::try_new(
+        schema.clone(),
+        vec![
+            Arc::new(UInt64Array::from_iter_values(rows.iter().map(|r| r.class_id))) as Arc<dyn Array>,
+            Arc::new(StringArray::from_iter_values(rows.iter().map(|r| r.class_name))) as Arc<dyn Array>,
+            Arc::new(UInt64Array::from_iter_values(rows.iter().map(|r| r.instance_count))) as Arc<dyn Array>,
+            Arc::new(UInt64Array::from_iter_values(rows.iter().map(|r| r.total_shallow_bytes))) as Arc<dyn Array>,
+            Arc::new(Float64Array::from_iter_values(rows.iter().map(|r| r.instance_size as f64))) as Arc<dyn Array>,
+        ],
+    ).unwrap();
+    Some(WritableBatch { file_key: "class_histogram".into(), schema, batch })
+}
+
+// ---------------------------------------------------------------------------
+// Utf8 table writer
+// ---------------------------------------------------------------------------
+
+/// Every Utf8 record as `(name_id, text, valid)`, with invalid text decoded as
+/// `options.utf8` says. The index doesn't keep which names were invalid, so this re-reads
+/// the records.
+fn utf8_rows<'a>(hprof: &'a Hprof<'a>, options: &AnalyzerOptions) -> Result<Vec<(u64, &'a str, bool)>, AnalyzeError> {
+    let mut rows = Vec::new();
+    for r in hprof.records_iter() {
+        let r = r.map_err(|_| AnalyzeError::parse("top level record"))?;
+        if r.tag() != RecordTag::Utf8 {
+            continue;
+        }
+        if cancelled(&options.cancel) {
+            break;
+        }
+        let u = r.as_utf_8()
+            .ok_or_else(|| AnalyzeError::parse("utf8 record"))?
+            .map_err(|_| AnalyzeError::parse("utf8 record"))?;
+        rows.push((u.name_id().id(), options.utf8.text(&u)?, u.text_as_str().is_ok()));
+    }
+    rows.sort_unstable_by_key(|(name_id, _, _)| *name_id);
+    Ok(rows)
+}
+
+/// Build the `_utf8` batch: name_id, value, valid.
+fn build_utf8_batch(rows: &[(u64, &str, bool)]) -> Option<WritableBatch> {
+    if rows.is_empty() {
+        return None;
+    }
+    let schema = Arc::new(Schema::new(vec![
+        Field::new("name_id", DataType::UInt64, false),
+        Field::new("value", DataType::Utf8, false),
+        Field::new("valid", DataType::Boolean, false),
+    ]));
+    let batch = RecordBatch::try_new(
+        schema.clone(),
+        vec![
+            Arc::new(UInt64Array::from_iter_values(rows.iter().map(|(id, _, _)| *id))) as Arc<dyn Array>,
+            Arc::new(StringArray::from_iter_values(rows.iter().map(|(_, text, _)| *text))) as Arc<dyn Array>,
+            Arc::new(BooleanArray::from_iter(rows.iter().map(|(_, _, valid)| Some(*valid)))) as Arc<dyn Array>,
+        ],
+    ).unwrap();
+    Some(WritableBatch { file_key: "_utf8".into(), schema, batch })
+}
+
 // ---------------------------------------------------------------------------
 // Static fields writer
 // ---------------------------------------------------------------------------
@@ -545,7 +1429,11 @@ fn build_static_fields_batch(index: &HprofIndex, robo_mode: bool) -> Option<Writ
     let mut ref_ids: Vec<u64> = Vec::new();
     let mut ref_types: Vec<String> = Vec::new();
 
-    for (_, ez_class) in index.classes.iter() {
+    // Rows grouped by class name (declaration order within a class) so reruns diff cleanly
+    let mut classes: Vec<_> = index.classes.values().collect();
+    classes.sort_unstable_by_key(|c| (c.name, c.obj_id.id()));
+
+    for ez_class in classes {
         for sf in &ez_class.static_fields {
             let field_name = index.utf8.get(&sf.name_id())
                 .unwrap_or(&"(missing utf8)");
@@ -561,7 +1449,7 @@ fn build_static_fields_batch(index: &HprofIndex, robo_mode: bool) -> Option<Writ
                 ref_ids.push(rid);
                 if !robo_mode {
                     if rt.is_empty() && rid != 0 {
-                        ref_types.push(resolve_ref_type(Id::from(rid), index));
+                        ref_types.push(index.resolve_ref_type(Id::from(rid)).into_owned());
                     } else {
                         ref_types.push(rt);
                     }
@@ -643,24 +1531,219 @@ fn build_static_fields_batch(index: &HprofIndex, robo_mode: bool) -> Option<Writ
 // ---------------------------------------------------------------------------
 // ShardedWriterPool — lock-free sharded writer pool
 // ---------------------------------------------------------------------------
-// Each shard is a dedicated thread that owns a set of ArrowWriters exclusively.
+// Each shard is a dedicated thread that owns a set of file writers exclusively.
 // Batches are routed to shards by hashing the file_key. No Mutexes needed —
 // each thread is the sole owner of its writers.
 
 use std::collections::hash_map::DefaultHasher;
 use std::hash::{Hash, Hasher};
 
+/// An open file of a shard, with the rows written to it and, for a file key split into
+/// parts by `--objects-per-file`, which part it is.
+struct OpenFile {
+    path: PathBuf,
+    writer: BatchWriter,
+    rows: usize,
+    part: Option<usize>,
+}
+
+/// Row group bytes buffered by every shard of a pool together. Keys are hashed to shards,
+/// so a few shards may hold most of the data while the rest sit idle; sharing one budget
+/// lets the busy ones use all of it rather than a sixteenth each.
+struct BufferBudget {
+    limit: usize,
+    buffered: AtomicUsize,
+    /// Shards holding any buffered bytes.
+    holders: AtomicUsize,
+}
+
+impl BufferBudget {
+    fn new(limit: usize) -> Self {
+        BufferBudget { limit, buffered: AtomicUsize::new(0), holders: AtomicUsize::new(0) }
+    }
+
+    /// Record a shard's buffered bytes going from `before` to `after`. Returns whether it
+    /// should flush: the pool is over budget and the shard holds at least an even share of
+    /// what the holding shards buffer, so small shards don't write tiny row groups while a
+    /// big one keeps its buffer.
+    fn update(&self, before: usize, after: usize) -> bool {
+        match (before, after) {
+            (0, 1..) => { self.holders.fetch_add(1, Ordering::Relaxed); }
+            (1.., 0) => { self.holders.fetch_sub(1, Ordering::Relaxed); }
+            _ => {}
+        }
+        let total = match after >= before {
+            true => self.buffered.fetch_add(after - before, Ordering::Relaxed) + (after - before),
+            false => self.buffered.fetch_sub(before - after, Ordering::Relaxed) - (before - after),
+        };
+        let holders = self.holders.load(Ordering::Relaxed).max(1);
+        total > self.limit && after > 0 && after >= total / holders
+    }
+}
+
+/// Open writers owned by one shard thread. Each parquet writer buffers its current row
+/// group in memory; once the pool's shards together hold more than the budget, a shard
+/// with at least its share of it flushes every one of its row groups to disk. Files stay
+/// open, so each still ends up a single file with one footer, unless its key is capped at a
+/// number of rows per file.
+struct ShardWriters {
+    writers: HashMap<String, OpenFile>,
+    settings: WriterSettings,
+    budget: Arc<BufferBudget>,
+    buffered: usize,
+    /// Parts already closed because they were full.
+    closed: usize,
+}
+
+impl ShardWriters {
+    fn new(settings: WriterSettings, budget: Arc<BufferBudget>) -> Self {
+        ShardWriters { writers: HashMap::new(), settings, budget, buffered: 0, closed: 0 }
+    }
+
+    /// Write `wb`, opening `path(file_key, part)` the first time the key is seen. With
+    /// `rows_per_file`, a full file is closed and the rest of the rows go to the next part,
+    /// which is only opened once there's a row for it.
+    fn write(
+        &mut self,
+        wb: WritableBatch,
+        rows_per_file: Option<usize>,
+        path: impl Fn(&str, Option<usize>) -> Result<PathBuf, AnalyzeError>,
+    ) -> Result<(), AnalyzeError> {
+        let reported = self.buffered;
+        let mut batch = wb.batch;
+        loop {
+            let next_part = match self.writers.get(&wb.file_key) {
+                None => Some(rows_per_file.map(|_| 0)),
+                Some(file) if batch.num_rows() > 0 && rows_per_file.is_some_and(|cap| file.rows >= cap) => {
+                    Some(file.part.map(|part| part + 1))
+                }
+                Some(_) => None,
+            };
+            if let Some(part) = next_part {
+                let file = self.open(&wb.file_key, &wb.schema, part, &path)?;
+                if let Some(full) = self.writers.insert(wb.file_key.clone(), file) {
+                    self.buffered = self.buffered.saturating_sub(full.writer.in_progress_size());
+                    log_file_written(&wb.file_key, &full.path, &full.writer.close()?);
+                    self.closed += 1;
+                }
+            }
+            let file = self.writers.get_mut(&wb.file_key).unwrap();
+
+            let rows = rows_per_file.map_or(batch.num_rows(), |cap| batch.num_rows().min(cap - file.rows));
+            // The writer may close a full row group on its own, so track the change in size
+            let before = file.writer.in_progress_size();
+            file.writer.write(&batch.slice(0, rows))?;
+            file.rows += rows;
+            self.buffered = (self.buffered + file.writer.in_progress_size()).saturating_sub(before);
+            batch = batch.slice(rows, batch.num_rows() - rows);
+            if batch.num_rows() == 0 {
+                break;
+            }
+        }
+
+        if self.budget.update(reported, self.buffered) {
+            self.flush_all()?;
+        }
+        Ok(())
+    }
+
+    fn open(
+        &self,
+        key: &str,
+        schema: &Arc<Schema>,
+        part: Option<usize>,
+        path: impl Fn(&str, Option<usize>) -> Result<PathBuf, AnalyzeError>,
+    ) -> Result<OpenFile, AnalyzeError> {
+        let path = path(key, part)?;
+        let writer = BatchWriter::create(&path, schema.clone(), &self.settings)?;
+        Ok(OpenFile { writer, path, rows: 0, part })
+    }
+
+    fn flush_all(&mut self) -> Result<(), AnalyzeError> {
+        for file in self.writers.values_mut() {
+            file.writer.flush()?;
+        }
+        self.budget.update(self.buffered, 0);
+        self.buffered = 0;
+        Ok(())
+    }
+
+    /// Close every file, returning how many there were, counting full parts closed earlier.
+    fn close(self) -> Result<usize, AnalyzeError> {
+        let files = self.closed + self.writers.len();
+        for (name, file) in self.writers {
+            log_file_written(&name, &file.path, &file.writer.close()?);
+        }
+        Ok(files)
+    }
+}
+
+/// Where each file key's output goes under the output directory. Keys in `partitioned` get
+/// a Hive-style `class=<stem>/part-<n>` directory of their own; everything else is a flat
+/// `<stem>` file. In robo mode `n` is the worker, as in `_chunk<n>`, otherwise it is 0.
+/// Keys in `capped` are split every `objects_per_file` rows into parts, `<stem>.part-<k>`,
+/// or `part-<k>` (`part-<n>-<k>` in robo mode) in a partition directory.
+#[derive(Clone, Default)]
+struct FileLayout {
+    partitioned: Arc<HashSet<String>>,
+    capped: Arc<HashSet<String>>,
+    objects_per_file: Option<usize>,
+}
+
+impl FileLayout {
+    /// The rows each file of `file_key` is capped at, if it's split into parts.
+    fn rows_per_file(&self, file_key: &str) -> Option<usize> {
+        self.objects_per_file.filter(|_| self.capped.contains(file_key))
+    }
+
+    fn path(
+        &self,
+        out_dir: &Path,
+        file_key: &str,
+        worker_id: Option<usize>,
+        part: Option<usize>,
+        extension: &str,
+    ) -> Result<PathBuf, AnalyzeError> {
+        let stem = parquet_file_stem(file_key);
+        if self.partitioned.contains(file_key) {
+            let dir = out_dir.join(format!("class={}", stem));
+            std::fs::create_dir_all(&dir).map_err(|e| AnalyzeError::io(&dir, e))?;
+            let name = match (worker_id, part) {
+                (Some(worker_id), Some(part)) => format!("part-{}-{}", worker_id, part),
+                (worker_id, part) => format!("part-{}", worker_id.or(part).unwrap_or(0)),
+            };
+            return Ok(dir.join(format!("{}.{}", name, extension)));
+        }
+        let name = match worker_id {
+            Some(worker_id) => format!("{}_chunk{}", stem, worker_id),
+            None => stem,
+        };
+        Ok(match part {
+            Some(part) => out_dir.join(format!("{}.part-{}.{}", name, part, extension)),
+            None => out_dir.join(format!("{}.{}", name, extension)),
+        })
+    }
+}
+
 struct ShardedWriterPool {
     senders: Vec<crossbeam_channel::Sender<WritableBatch>>,
-    handles: Vec<std::thread::JoinHandle<()>>,
+    handles: Vec<std::thread::JoinHandle<Result<usize, AnalyzeError>>>,
     robo_mode: bool,
 }
 
 impl ShardedWriterPool {
-    fn new(num_shards: usize, compression: Compression, robo_mode: bool) -> Self {
-        let props = WriterProperties::builder()
-            .set_compression(compression)
-            .build();
+    /// `buffer_bytes` bounds the row groups buffered in memory across all shards, whichever
+    /// of them the file keys are routed to.
+    fn new(
+        num_shards: usize,
+        writer: WriterSettings,
+        robo_mode: bool,
+        out_dir: &Path,
+        layout: FileLayout,
+        buffer_bytes: usize,
+    ) -> Self {
+        let extension = writer.format.extension();
+        let budget = Arc::new(BufferBudget::new(buffer_bytes));
 
         let mut senders = Vec::with_capacity(if robo_mode { 1 } else { num_shards });
         let mut handles = Vec::with_capacity(num_shards);
@@ -670,24 +1753,18 @@ impl ShardedWriterPool {
             let (tx, rx) = crossbeam_channel::unbounded::<WritableBatch>();
             for worker_id in 0..num_shards {
                 let rx = rx.clone();
-                let props = props.clone();
+                let out_dir = out_dir.to_path_buf();
+                let layout = layout.clone();
+                let budget = budget.clone();
                 let handle = std::thread::spawn(move || {
-                    let mut writers: HashMap<String, ArrowWriter<std::fs::File>> = HashMap::new();
-
+                    let mut writers = ShardWriters::new(writer, budget);
                     for wb in rx {
-                        let writer = writers.entry(wb.file_key.clone()).or_insert_with(|| {
-                            let safe_name = wb.file_key.replace("/", ".");
-                            let file = std::fs::File::create(
-                                format!("parquet/{}_chunk{}.parquet", safe_name, worker_id)
-                            ).unwrap();
-                            ArrowWriter::try_new(file, wb.schema.clone(), Some(props.clone())).unwrap()
-                        });
-                        writer.write(&wb.batch).unwrap();
-                    }
-
-                    for (_name, writer) in writers {
-                        writer.close().unwrap();
+                        let rows_per_file = layout.rows_per_file(&wb.file_key);
+                        writers.write(wb, rows_per_file, |key, part| {
+                            layout.path(&out_dir, key, Some(worker_id), part, extension)
+                        })?;
                     }
+                    writers.close()
                 });
                 handles.push(handle);
             }
@@ -696,22 +1773,16 @@ impl ShardedWriterPool {
             // Per-shard channels with hash routing (existing behavior)
             for _ in 0..num_shards {
                 let (tx, rx) = crossbeam_channel::unbounded::<WritableBatch>();
-                let props = props.clone();
+                let out_dir = out_dir.to_path_buf();
+                let layout = layout.clone();
+                let budget = budget.clone();
                 let handle = std::thread::spawn(move || {
-                    let mut writers: HashMap<String, ArrowWriter<std::fs::File>> = HashMap::new();
-
+                    let mut writers = ShardWriters::new(writer, budget);
                     for wb in rx {
-                        let writer = writers.entry(wb.file_key.clone()).or_insert_with(|| {
-                            let safe_name = wb.file_key.replace("/", ".");
-                            let file = std::fs::File::create(format!("parquet/{}.parquet", safe_name)).unwrap();
-                            ArrowWriter::try_new(file, wb.schema.clone(), Some(props.clone())).unwrap()
-                        });
-                        writer.write(&wb.batch).unwrap();
-                    }
-
-                    for (_name, writer) in writers {
-                        writer.close().unwrap();
+                        let rows_per_file = layout.rows_per_file(&wb.file_key);
+                        writers.write(wb, rows_per_file, |key, part| layout.path(&out_dir, key, None, part, extension))?;
                     }
+                    writers.close()
                 });
                 senders.push(tx);
                 handles.push(handle);
@@ -721,26 +1792,31 @@ impl ShardedWriterPool {
         ShardedWriterPool { senders, handles, robo_mode }
     }
 
+    /// Hand `wb` to its shard. A shard that stopped on a write error no longer takes
+    /// batches; its error comes back from `close_all`.
     fn write_batch(&self, wb: WritableBatch) {
-        if self.robo_mode {
+        let sender = if self.robo_mode {
             // MPMC: send to the single shared channel; any idle worker picks it up
-            self.senders[0].send(wb).unwrap();
+            &self.senders[0]
         } else {
             // Hash-route to a specific shard
             let mut hasher = DefaultHasher::new();
             wb.file_key.hash(&mut hasher);
-            let shard = (hasher.finish() as usize) % self.senders.len();
-            self.senders[shard].send(wb).unwrap();
-        }
+            &self.senders[(hasher.finish() as usize) % self.senders.len()]
+        };
+        let _ = sender.send(wb);
     }
 
-    fn close_all(self) {
+    /// Close every shard's files, returning how many were written, or the first write error
+    /// a shard stopped on.
+    fn close_all(self) -> Result<usize, AnalyzeError> {
         // Drop all senders to signal threads to finish
         drop(self.senders);
-        // Wait for all shard threads to close their writers
-        for handle in self.handles {
-            handle.join().unwrap();
-        }
+        // Wait for all shard threads to close their writers, even after one has failed
+        let closed: Vec<Result<usize, AnalyzeError>> = self.handles.into_iter()
+            .map(|handle| handle.join().unwrap())
+            .collect();
+        closed.into_iter().sum()
     }
 }
 
@@ -749,13 +1825,15 @@ impl ShardedWriterPool {
 // ---------------------------------------------------------------------------
 
 /// Write `_class_hierarchy.parquet`: class_obj_id, class_name, super_class_obj_id, super_class_name.
-fn write_class_hierarchy(index: &HprofIndex) {
+fn write_class_hierarchy(index: &HprofIndex, out_dir: &Path, writer: WriterSettings) -> Result<(), AnalyzeError> {
     let mut class_obj_ids: Vec<u64> = Vec::new();
     let mut class_names: Vec<String> = Vec::new();
     let mut super_class_obj_ids: Vec<Option<u64>> = Vec::new();
     let mut super_class_names: Vec<Option<String>> = Vec::new();
 
-    for (class_id, ez_class) in index.classes.iter() {
+    let mut classes: Vec<_> = index.classes.iter().collect();
+    classes.sort_unstable_by_key(|(class_id, c)| (c.name, class_id.id()));
+    for (class_id, ez_class) in classes {
         class_obj_ids.push(class_id.id());
         class_names.push(ez_class.name.to_string());
 
@@ -775,7 +1853,7 @@ fn write_class_hierarchy(index: &HprofIndex) {
     }
 
     if class_obj_ids.is_empty() {
-        return;
+        return Ok(());
     }
 
     let schema = Arc::new(Schema::new(vec![
@@ -795,13 +1873,7 @@ fn write_class_hierarchy(index: &HprofIndex) {
         ],
     ).unwrap();
 
-    let props = WriterProperties::builder()
-        .set_compression(Compression::SNAPPY)
-        .build();
-    let file = std::fs::File::create("parquet/_class_hierarchy.parquet").unwrap();
-    let mut writer = ArrowWriter::try_new(file, schema, Some(props)).unwrap();
-    writer.write(&batch).unwrap();
-    writer.close().unwrap();
+    write_to_parquet(out_dir, "_class_hierarchy", &[batch], writer)
 }
 
 /// Build `_stack_frames` WritableBatch: frame_id, class_name, method_name, method_signature, source_file, line_num.
@@ -871,44 +1943,289 @@ fn build_stack_traces_batch(index: &HprofIndex) -> Option<WritableBatch> {
         for &fid in &st.frame_ids {
             values.append_value(fid);
         }
-        frame_id_lists.append(true);
+        frame_id_lists.append(true);
+    }
+
+    let schema = Arc::new(Schema::new(vec![
+        Field::new("stack_trace_serial", DataType::UInt32, false),
+        Field::new("thread_serial", DataType::UInt32, false),
+        Field::new("frame_ids", DataType::List(Arc::new(Field::new("item", DataType::UInt64, true))), false),
+    ]));
+
+    let batch = RecordBatch::try_new(
+        schema.clone(),
+        vec![
+            Arc::new(UInt32Array::from(trace_serials)) as Arc<dyn Array>,
+            Arc::new(UInt32Array::from(thread_serials)) as Arc<dyn Array>,
+            Arc::new(frame_id_lists.finish()) as Arc<dyn Array>,
+        ],
+    ).unwrap();
+
+    println!("  Built {} stack traces for _stack_traces.parquet", len);
+    Some(WritableBatch { file_key: "_stack_traces".into(), schema, batch })
+}
+
+// ---------------------------------------------------------------------------
+// Main entry point
+// ---------------------------------------------------------------------------
+
+/// Create `out_dir` if needed and remove the `.parquet`, `.arrow`, `.avro` and `.schema.json`
+/// files left by a previous run, whichever format it wrote, including those in `class=` partition directories, which
+/// are removed once empty. Only those are removed — the directory is user-supplied and may
+/// hold other data.
+fn prepare_output_dir(out_dir: &Path) -> std::io::Result<()> {
+    std::fs::create_dir_all(out_dir)?;
+    remove_output_files(out_dir)?;
+    for entry in std::fs::read_dir(out_dir)? {
+        let path = entry?.path();
+        let partition = path.file_name().and_then(|n| n.to_str()).is_some_and(|n| n.starts_with("class="));
+        if partition && path.is_dir() {
+            remove_output_files(&path)?;
+            if std::fs::read_dir(&path)?.next().is_none() {
+                std::fs::remove_dir(&path)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn remove_output_files(dir: &Path) -> std::io::Result<()> {
+    let ours = [OutputFormat::Parquet.extension(), OutputFormat::Ipc.extension(), OutputFormat::Avro.extension()];
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        let sidecar = path.to_str().is_some_and(|p| p.ends_with(SCHEMA_JSON_SUFFIX));
+        if path.is_file() && (sidecar || path.extension().is_some_and(|ext| ours.iter().any(|o| ext == *o))) {
+            std::fs::remove_file(&path)?;
+        }
+    }
+    Ok(())
+}
+
+/// Segments built ahead per compute thread before their batches are handed to the writers.
+const WINDOW_PER_THREAD: usize = 4;
+
+/// Build the batches for every item on a `threads`-wide rayon pool and hand them to the
+/// writer pool in item order, so every file gets its rows in the same order on every run
+/// and for any `threads`. Compute threads run at most `threads * WINDOW_PER_THREAD` items
+/// ahead of the last one handed over, so one slow item only holds up the threads once they
+/// run that far ahead of it, and that many items' batches bound the memory held. Only the
+/// shard threads touch files, so the number of concurrent file writes stays bounded by the
+/// shard count. Once `cancel` is set, nothing more is built or handed over. The first item
+/// in item order that fails to build stops the pass, and its error is returned; the batches
+/// of the items before it have already been handed over.
+fn compute_and_write<T: Sync>(
+    items: &[T],
+    threads: usize,
+    pool: &ShardedWriterPool,
+    cancel: &AtomicBool,
+    process: impl Fn(&T) -> Result<Vec<WritableBatch>, AnalyzeError> + Sync,
+) -> Result<(), AnalyzeError> {
+    let compute_pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(threads)
+        .build()
+        .unwrap();
+    let progress = Progress::start("Writing segments", Some(items.len() as u64));
+    let ahead = threads * WINDOW_PER_THREAD;
+    // One permit per item that may be built but not yet handed over; each handover frees one
+    let (permit_tx, permit_rx) = crossbeam_channel::bounded::<()>(ahead);
+    for _ in 0..ahead {
+        permit_tx.send(()).unwrap();
+    }
+    let (built_tx, built_rx) = crossbeam_channel::bounded::<(usize, Result<Vec<WritableBatch>, AnalyzeError>)>(ahead);
+    let next_item = AtomicUsize::new(0);
+    let mut failed = None;
+
+    compute_pool.in_place_scope(|scope| {
+        for _ in 0..threads {
+            let (permit_rx, built_tx, next_item, process) = (&permit_rx, built_tx.clone(), &next_item, &process);
+            scope.spawn(move |_| {
+                while permit_rx.recv().is_ok() && !cancelled(cancel) {
+                    let i = next_item.fetch_add(1, Ordering::Relaxed);
+                    if i >= items.len() || built_tx.send((i, process(&items[i]))).is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+        // Only the compute threads' senders are left, so the receiver ends if they all stop
+        drop(built_tx);
+
+        // Items are claimed in order, so the next one to hand over is always being built
+        let mut finished: BTreeMap<usize, Result<Vec<WritableBatch>, AnalyzeError>> = BTreeMap::new();
+        let mut next_write = 0;
+        'items: while next_write < items.len() && !cancelled(cancel) {
+            let Ok((i, built)) = built_rx.recv() else {
+                break;
+            };
+            finished.insert(i, built);
+            while let Some(built) = finished.remove(&next_write) {
+                let built = match built {
+                    Ok(built) => built,
+                    Err(e) => {
+                        failed = Some(e);
+                        break 'items;
+                    }
+                };
+                for wb in built {
+                    if cancelled(cancel) {
+                        break;
+                    }
+                    pool.write_batch(wb);
+                }
+                next_write += 1;
+                let _ = permit_tx.send(());
+                if let Some(p) = &progress {
+                    p.advance(1);
+                }
+            }
+        }
+        // Wakes compute threads waiting for a permit, so the scope can end
+        drop(permit_tx);
+        drop(built_rx);
+    });
+    failed.map_or(Ok(()), Err)
+}
+
+/// The segments `--sample` touches, in dump order, each with how many of its objects to
+/// process: all of them, until the sample runs out partway through a segment. Without a
+/// sample every segment is processed in full.
+fn sample_segments<T>(
+    segments: &[T],
+    sample: Option<usize>,
+    count_objects: impl Fn(&T, usize) -> Result<usize, AnalyzeError>,
+) -> Result<Vec<(&T, usize)>, AnalyzeError> {
+    let Some(mut left) = sample else {
+        return Ok(segments.iter().map(|segment| (segment, usize::MAX)).collect());
+    };
+    let mut sampled = Vec::new();
+    for segment in segments {
+        if left == 0 {
+            break;
+        }
+        let budget = count_objects(segment, left)?;
+        sampled.push((segment, budget));
+        left -= budget;
+    }
+    Ok(sampled)
+}
+
+/// Instances and arrays in a heap dump segment, counting no further than `up_to`.
+fn count_objects(index: &HprofIndex, record: &Record, up_to: usize) -> Result<usize, AnalyzeError> {
+    let segment = record.as_heap_dump_segment()
+        .ok_or_else(|| AnalyzeError::parse("heap dump segment"))?
+        .map_err(|_| AnalyzeError::parse("heap dump segment"))?;
+    let mut objects = 0;
+    for sub_record in sub_records(&index.options, segment.sub_records()) {
+        if objects == up_to {
+            break;
+        }
+        if is_object(&sub_record?) {
+            objects += 1;
+        }
     }
+    Ok(objects)
+}
 
-    let schema = Arc::new(Schema::new(vec![
-        Field::new("stack_trace_serial", DataType::UInt32, false),
-        Field::new("thread_serial", DataType::UInt32, false),
-        Field::new("frame_ids", DataType::List(Arc::new(Field::new("item", DataType::UInt64, true))), false),
-    ]));
+/// Shallow size of each class's instances, from its flattened field list.
+fn class_shallow_sizes(index: &HprofIndex, id_size: IdSize) -> HashMap<Id, i32> {
+    index.class_instance_field_descriptors.iter()
+        .map(|(class_id, fds)| {
+            let size = instance_shallow_size(fds.iter().map(|fd| fd.field_type()), id_size);
+            (*class_id, size as i32)
+        })
+        .collect()
+}
 
-    let batch = RecordBatch::try_new(
-        schema.clone(),
-        vec![
-            Arc::new(UInt32Array::from(trace_serials)) as Arc<dyn Array>,
-            Arc::new(UInt32Array::from(thread_serials)) as Arc<dyn Array>,
-            Arc::new(frame_id_lists.finish()) as Arc<dyn Array>,
-        ],
-    ).unwrap();
+/// Build the reference graph once if any graph column was requested, keeping only the
+/// per-object results so the edges are freed before pass 2.
+fn build_graph_columns(hprof: &Hprof, index: &HprofIndex, segments: &[Record], options: &ParquetOptions) -> GraphColumns {
+    use std::time::Instant;
 
-    println!("  Built {} stack traces for _stack_traces.parquet", len);
-    Some(WritableBatch { file_key: "_stack_traces".into(), schema, batch })
+    let shallow_sizes = options.shallow_size.then(|| class_shallow_sizes(index, hprof.header().id_size()));
+    if !options.reachability && !options.live_only && !options.retained_size {
+        return GraphColumns { shallow_sizes, ..GraphColumns::default() };
+    }
+    let t = Instant::now();
+    let graph = ObjectGraph::build(hprof, index, segments);
+    println!("Reference graph built in {:.1}s: {} objects", t.elapsed().as_secs_f64(), graph.node_count());
+
+    let reachability = (options.reachability || options.live_only).then(|| {
+        let reachability = graph.reachability();
+        let (reachable, total) = (reachability.reachable_count(), reachability.total_count());
+        println!("{} of {} objects reachable from GC roots ({} unreachable)", reachable, total, total - reachable);
+        reachability
+    });
+    let dominators = options.retained_size.then(|| {
+        let t = Instant::now();
+        let dominators = graph.dominator_tree();
+        println!("Dominator tree in {:.1}s", t.elapsed().as_secs_f64());
+        dominators
+    });
+    GraphColumns {
+        reachability,
+        reachable_column: options.reachability,
+        live_only: options.live_only,
+        dominators,
+        shallow_sizes,
+    }
 }
 
-// ---------------------------------------------------------------------------
-// Main entry point
-// ---------------------------------------------------------------------------
+/// The instance, array and GC root batches `dump_objects_to_parquet` writes in default mode,
+/// kept in memory instead and grouped by file key. Every record batch of the dump is held at
+/// once, so this needs memory on the order of the parquet output uncompressed.
+#[cfg(feature = "query")]
+pub(crate) fn in_memory_batches(
+    hprof: &Hprof,
+    index: &HprofIndex,
+    segments: &[Record],
+) -> Result<HashMap<String, Vec<RecordBatch>>, AnalyzeError> {
+    let inline_arrays = InlineArrays::default();
+    let schemas = generate_all_schemas(index, &inline_arrays, ReferenceEncoding::default());
+    let graph_columns = GraphColumns::default();
+    let problems = ClassProblems::default();
+    let object_counts = ObjectCounts::default();
+
+    let progress = Progress::start("Building batches", Some(segments.len() as u64));
+    let built: Vec<Vec<WritableBatch>> = segments.par_iter()
+        .map(|record| {
+            let batches = process_segment_to_batches(
+                record, hprof, index, &schemas, None, &graph_columns, &inline_arrays, &problems, &object_counts,
+                usize::MAX, false, ReferenceEncoding::default(), ArrayColumns { elements: true, char_text: false, byte_binary: false },
+            );
+            if let Some(p) = &progress {
+                p.advance(1);
+            }
+            batches
+        })
+        .collect::<Result<_, _>>()?;
+    drop(progress);
+    for (class_id, problem, instances) in &problems.into_sorted() {
+        let class_name = index.classes.get(class_id).map(|c| c.name);
+        eprintln!("warning: {}", class_problem_message(*class_id, class_name, problem, *instances));
+    }
+
+    let mut by_key: HashMap<String, Vec<RecordBatch>> = HashMap::new();
+    for wb in built.into_iter().flatten() {
+        by_key.entry(wb.file_key).or_default().push(wb.batch);
+    }
+    Ok(by_key)
+}
 
-pub fn dump_objects_to_parquet(hprof: &Hprof, _flush_row_threshold: usize, robo_mode: bool) {
+pub fn dump_objects_to_parquet(analyzer: &HeapDumpAnalyzer, options: &ParquetOptions) -> Result<ExportSummary, AnalyzeError> {
     use std::time::Instant;
 
+    let robo_mode = options.robo_mode;
+    let out_dir = options.out_dir.as_path();
+
     // Clean output directory so stale files from previous runs don't persist
-    let _ = std::fs::remove_dir_all("parquet");
-    std::fs::create_dir_all("parquet").unwrap();
+    prepare_output_dir(out_dir).map_err(|e| AnalyzeError::io(out_dir, e))?;
 
     // -----------------------------------------------------------------------
     // Pass 1: Parallel index build + collect segment handles
     // -----------------------------------------------------------------------
     let t0 = Instant::now();
-    let (index, segments) = HprofIndex::build_with_segments(hprof);
+    let hprof = analyzer.hprof();
+    let (index, segments) = analyzer.indexed()?;
     let pass1_dur = t0.elapsed();
 
     println!("Pass 1 complete in {:.1}s: {} classes, {} obj mappings, {} segments",
@@ -919,9 +2236,32 @@ pub fn dump_objects_to_parquet(hprof: &Hprof, _flush_row_threshold: usize, robo_
         println!("Robo mode enabled: bare IDs for references, separate type index files");
     }
 
-    // Generate schemas from field descriptors (no file scan needed)
-    let schemas = generate_all_schemas(&index, robo_mode);
+    let inline_arrays = if options.inline_primitive_arrays {
+        let t = Instant::now();
+        let inline_arrays = InlineArrays::collect(hprof, index, segments);
+        println!("{} fields inlined as primitive array lists in {:.1}s",
+            inline_arrays.fields.len(), t.elapsed().as_secs_f64());
+        inline_arrays
+    } else {
+        InlineArrays::default()
+    };
+
+    // Generate schemas from field descriptors (no file scan needed). Instances of classes
+    // without a schema are skipped, so leaving out unselected classes keeps them unbuffered.
+    let references = if robo_mode { ReferenceEncoding::IdOnly } else { options.references };
+    let mut schemas = generate_all_schemas(index, &inline_arrays, references);
+    if !options.classes.selects_everything() {
+        schemas.retain(|class_id, _| index.classes.get(class_id).is_some_and(|c| options.classes.selects(c.name)));
+    }
     println!("{} schemas generated", schemas.len());
+    let graph_columns = build_graph_columns(hprof, index, segments, options);
+    let union_schema = options.single_file.then(|| UnionSchema {
+        per_class_files: options.partition_by_class,
+        ..UnionSchema::new(&schemas, graph_columns.fields())
+    });
+    if let Some(u) = &union_schema {
+        println!("Single-file mode: {} union columns", u.schema.fields().len());
+    }
 
     // -----------------------------------------------------------------------
     // Pass 2: Parallel compute + sharded lock-free write
@@ -931,37 +2271,98 @@ pub fn dump_objects_to_parquet(hprof: &Hprof, _flush_row_threshold: usize, robo_
     // so there is zero Mutex contention on writers.
     let t1 = Instant::now();
     let num_shards = 16;
-    let pool = ShardedWriterPool::new(num_shards, Compression::SNAPPY, robo_mode);
-
-    // Use a smaller rayon pool for compute so shard threads get more CPU.
-    // Compute only needs ~10s of wall time — 8 threads is plenty.
-    let compute_pool = rayon::ThreadPoolBuilder::new()
-        .num_threads(5)
-        .build()
-        .unwrap();
+    let class_files: Arc<HashSet<String>> = Arc::new(schemas.keys()
+        .filter_map(|class_id| index.classes.get(class_id).map(|c| class_file_key(c.name, *class_id)))
+        .collect());
+    let layout = FileLayout {
+        partitioned: match options.partition_by_class {
+            true => class_files.clone(),
+            false => Arc::default(),
+        },
+        // Instance files only, one per class or the single file
+        capped: Arc::new(class_files.iter().cloned().chain([SINGLE_FILE_KEY.to_string()]).collect()),
+        objects_per_file: options.objects_per_file,
+    };
+    let pool = ShardedWriterPool::new(
+        num_shards, options.writer, robo_mode, out_dir, layout, options.writer_buffer_bytes,
+    );
 
     // Write class hierarchy metadata (tiny — 1,781 rows)
     if robo_mode {
-        write_class_hierarchy(&index);
+        write_class_hierarchy(index, out_dir, options.writer)?;
     }
 
-    compute_pool.install(|| {
-        segments.par_iter().for_each(|record| {
-            let batches = process_segment_to_batches(record, hprof, &index, &schemas, robo_mode);
-            for wb in batches {
-                pool.write_batch(wb);
-            }
-        });
+    let problems = ClassProblems::new(options.fail_on_unresolved);
+    let object_counts = ObjectCounts::default();
+    let sampled = sample_segments(segments, options.sample, |record, up_to| count_objects(index, record, up_to))?;
+    if let Some(n) = options.sample {
+        println!("Sampling the first {} objects from {} of {} segments", n, sampled.len(), segments.len());
+    }
+    let cancel = &analyzer.options().cancel;
+    let written = compute_and_write(&sampled, options.threads, &pool, cancel, |&(record, object_budget)| {
+        process_segment_to_batches(
+            record, hprof, index, &schemas, union_schema.as_ref(), &graph_columns, &inline_arrays, &problems,
+            &object_counts, object_budget, robo_mode, references,
+            ArrayColumns {
+                elements: options.array_elements,
+                char_text: options.char_array_text,
+                byte_binary: options.binary_byte_arrays,
+            },
+        )
+    });
+    if let Err(e) = written {
+        // Close what was written so far, so those files are still readable
+        pool.close_all()?;
+        return Err(e);
+    }
+    if cancelled(cancel) {
+        // Close what was written so far, so those files are still readable
+        pool.close_all()?;
+        return Err(AnalyzeError::Cancelled);
+    }
+    let unresolved = problems.take_unresolved();
+    let unresolved_references = problems.unresolved_references.count();
+    let mut problems = problems.into_sorted();
+    // Classes left out on purpose have no schema either
+    problems.retain(|(class_id, problem, _)| {
+        *problem != ClassProblem::NoSchema || index.classes.get(class_id).is_some_and(|c| options.classes.selects(c.name))
     });
+    if !options.strict {
+        for (class_id, problem, instances) in &problems {
+            let class_name = index.classes.get(class_id).map(|c| c.name);
+            eprintln!("warning: {}", class_problem_message(*class_id, class_name, problem, *instances));
+        }
+    }
 
-    // Write static fields, stack frames, and stack traces through the pool
-    if let Some(sb) = build_static_fields_batch(&index, robo_mode) {
+    // Write class metadata, static fields, stack frames, and stack traces through the pool
+    let classes = class_rows(index, hprof.header().id_size());
+    let histogram = class_histogram_rows(&classes, &object_counts.instances.into_inner().unwrap());
+    let mut summary = ExportSummary {
+        classes: classes.len() as u64,
+        instances: histogram.iter().map(|r| r.instance_count).sum(),
+        arrays: object_counts.arrays.into_inner(),
+        shallow_bytes: histogram.iter().map(|r| r.total_shallow_bytes).sum::<u64>() + object_counts.array_bytes.into_inner(),
+        files: 0,
+        unresolved_references,
+    };
+    if let Some(hb) = build_class_histogram_batch(&histogram) {
+        pool.write_batch(hb);
+    }
+    if let Some(cb) = build_classes_batch(&classes) {
+        pool.write_batch(cb);
+    }
+    if options.dump_utf8 {
+        if let Some(ub) = build_utf8_batch(&utf8_rows(hprof, analyzer.options())?) {
+            pool.write_batch(ub);
+        }
+    }
+    if let Some(sb) = build_static_fields_batch(index, robo_mode) {
         pool.write_batch(sb);
     }
-    if let Some(sf) = build_stack_frames_batch(&index) {
+    if let Some(sf) = build_stack_frames_batch(index) {
         pool.write_batch(sf);
     }
-    if let Some(st) = build_stack_traces_batch(&index) {
+    if let Some(st) = build_stack_traces_batch(index) {
         pool.write_batch(st);
     }
 
@@ -970,15 +2371,63 @@ pub fn dump_objects_to_parquet(hprof: &Hprof, _flush_row_threshold: usize, robo_
 
     // Close all shard threads and their writers
     let t2 = Instant::now();
-    pool.close_all();
+    summary.files = pool.close_all()? as u64 + robo_mode as u64;
     println!("Writers closed in {:.1}s", t2.elapsed().as_secs_f64());
+    if options.summary {
+        summary.print();
+    }
+
+    // Failing on an unresolved reference, like strict mode, still closes the writers first,
+    // so whatever was written is readable
+    if let Some(e) = unresolved {
+        return Err(e);
+    }
+    if options.strict && !problems.is_empty() {
+        return Err(class_problems_error(index, &problems));
+    }
+    Ok(summary)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::hprof_index::{HprofIndex, ResolvedStackFrame, ResolvedStackTrace};
+    use crate::analyzer::AnalyzerOptions;
+    use crate::hprof_index::{HprofIndex, ResolvedStackFrame, ResolvedStackTrace, INVALID_UTF8};
+    use crate::id_hash::IdDashMap;
     use arrow_array::{cast::AsArray, types::UInt64Type};
+    use parquet::basic::Compression;
+    use crate::hprof_builder::{HprofBuilder, Segment, Value, TYPE_INT, TYPE_OBJECT};
+
+    /// `java/lang/Object` (100); `com/example/Point` (200) with `int x, y` and a static;
+    /// `com/example/Line` (400) with `Point a, b`; and `Point[]` (300). Three Points, a Line,
+    /// a bare Object and a `Point[]`.
+    fn shapes_dump() -> Vec<u8> {
+        let mut segment = Segment::default();
+        segment
+            .class(100, 0, 0, &[], &[])
+            .class(200, 100, 8, &[(3, Value::Int(0))], &[(1, TYPE_INT), (2, TYPE_INT)])
+            .class(300, 100, 0, &[], &[])
+            .class(400, 100, 16, &[], &[(4, TYPE_OBJECT), (5, TYPE_OBJECT)]);
+        for point in [1000, 1001, 1002] {
+            segment.instance(point, 200, &[Value::Int(1), Value::Int(2)]);
+        }
+        segment
+            .instance(1100, 400, &[Value::Object(1000), Value::Object(1001)])
+            .instance(1200, 100, &[])
+            .object_array(2000, 300, &[1000, 1002]);
+        HprofBuilder::new()
+            .utf8(1, "x")
+            .utf8(2, "y")
+            .utf8(3, "COUNT")
+            .utf8(4, "a")
+            .utf8(5, "b")
+            .named_class(1, 100, "java/lang/Object")
+            .named_class(2, 200, "com/example/Point")
+            .named_class(3, 300, "[Lcom/example/Point;")
+            .named_class(4, 400, "com/example/Line")
+            .heap_dump_segment(&segment)
+            .finish()
+    }
 
     /// Create a minimal HprofIndex with only stack_frames and stack_traces populated.
     fn make_test_index<'a>(
@@ -989,13 +2438,130 @@ mod tests {
             utf8: HashMap::new(),
             load_classes: HashMap::new(),
             classes: HashMap::new(),
-            obj_id_to_class_obj_id: DashMap::new(),
-            prim_array_obj_id_to_type: DashMap::new(),
+            obj_id_to_class_obj_id: Box::new(IdDashMap::<Id>::default()),
+            prim_array_obj_id_to_type: IdDashMap::default(),
             class_instance_field_descriptors: HashMap::new(),
             class_field_declaring_classes: HashMap::new(),
             stack_frames: frames,
             stack_traces: traces,
+            string_layout: None,
+            options: AnalyzerOptions::default(),
+            skipped_segments: 0,
+        }
+    }
+
+    #[test]
+    fn test_build_static_fields_batch_no_classes_returns_none() {
+        let index = make_test_index(vec![], vec![]);
+        assert!(build_static_fields_batch(&index, false).is_none());
+        assert!(build_static_fields_batch(&index, true).is_none());
+    }
+
+    #[test]
+    fn test_build_classes_batch_one_row_per_class() {
+        let index = make_test_index(vec![], vec![]);
+        assert!(class_rows(&index, IdSize::U64).is_empty());
+        assert!(build_classes_batch(&[]).is_none());
+
+        let dump = shapes_dump();
+        let hprof = jvm_hprof::parse_hprof(&dump[..]).unwrap();
+        let (index, _) = HprofIndex::build_with_options(&hprof, &AnalyzerOptions::default()).unwrap();
+        let rows = class_rows(&index, IdSize::U64);
+        let row = |class_id, class_name, super_class_id, instance_size, static_field_count, instance_field_count| ClassRow {
+            class_id, class_name, super_class_id, instance_size, static_field_count, instance_field_count,
+        };
+        // By name; sizes are the 16-byte header plus the fields, padded to 8
+        assert_eq!(rows, vec![
+            row(300, "[Lcom/example/Point;", 100, 16, 0, 0),
+            row(400, "com/example/Line", 100, 32, 0, 2),
+            row(200, "com/example/Point", 100, 24, 1, 2),
+            row(100, "java/lang/Object", 0, 16, 0, 0),
+        ]);
+
+        let wb = build_classes_batch(&rows).unwrap();
+        assert_eq!(wb.file_key, "classes");
+        let batch = &wb.batch;
+        assert_eq!(batch.num_rows(), 4);
+        let names: Vec<String> = batch.schema().fields().iter().map(|f| f.name().clone()).collect();
+        assert_eq!(names, vec![
+            "class_id", "class_name", "super_class_id", "instance_size", "static_field_count", "instance_field_count",
+        ]);
+
+        // Each superclass id names another row, and the root has 0
+        let ids = batch.column(0).as_primitive::<UInt64Type>();
+        let supers = batch.column(2).as_primitive::<UInt64Type>();
+        assert_eq!(supers.values().to_vec(), vec![100, 100, 100, 0]);
+        for row in 0..3 {
+            assert!(ids.values().contains(&supers.value(row)));
+        }
+        assert_eq!(batch.column(1).as_string::<i32>().value(2), "com/example/Point");
+        assert_eq!(batch.column(4).as_primitive::<arrow_array::types::Int32Type>().value(2), 1);
+    }
+
+    #[test]
+    fn test_class_histogram_counts_and_bytes() {
+        let dump = shapes_dump();
+        let hprof = jvm_hprof::parse_hprof(&dump[..]).unwrap();
+        let (index, segments) = HprofIndex::build_with_options(&hprof, &AnalyzerOptions::default()).unwrap();
+
+        // Instances counted as the export writes them
+        let inline_arrays = InlineArrays::default();
+        let schemas = generate_all_schemas(&index, &inline_arrays, ReferenceEncoding::default());
+        let counts = ObjectCounts::default();
+        for record in &segments {
+            process_segment_to_batches(
+                record, &hprof, &index, &schemas, None, &GraphColumns::default(), &inline_arrays,
+                &ClassProblems::default(), &counts, usize::MAX, false, ReferenceEncoding::default(),
+                ArrayColumns { elements: true, char_text: false, byte_binary: false },
+            ).unwrap();
+        }
+        assert_eq!(counts.arrays.load(Ordering::Relaxed), 1);
+
+        let rows = class_histogram_rows(&class_rows(&index, IdSize::U64), &counts.instances.into_inner().unwrap());
+        let row = |class_id, class_name, instance_count, instance_size: u64| ClassHistogramRow {
+            class_id, class_name, instance_count, total_shallow_bytes: instance_count * instance_size, instance_size,
+        };
+        // The array class gets no row
+        assert_eq!(rows, vec![
+            row(200, "com/example/Point", 3, 24),
+            row(400, "com/example/Line", 1, 32),
+            row(100, "java/lang/Object", 1, 16),
+        ]);
+
+        // The same counts and bytes as the `histogram` command
+        let stats = crate::commands::class_histogram(&hprof, &index, &segments).unwrap();
+        for r in &rows {
+            let stat = stats.iter().find(|s| s.class_name == r.class_name).unwrap();
+            assert_eq!((stat.instance_count, stat.total_shallow_bytes), (r.instance_count, r.total_shallow_bytes));
         }
+
+        let wb = build_class_histogram_batch(&rows).unwrap();
+        assert_eq!(wb.file_key, "class_histogram");
+        let batch = &wb.batch;
+        let names: Vec<String> = batch.schema().fields().iter().map(|f| f.name().clone()).collect();
+        assert_eq!(names, vec!["class_id", "class_name", "instance_count", "total_shallow_bytes", "avg_shallow_bytes"]);
+        assert_eq!(batch.column(0).as_primitive::<UInt64Type>().values().to_vec(), vec![200, 400, 100]);
+        assert_eq!(batch.column(2).as_primitive::<UInt64Type>().values().to_vec(), vec![3, 1, 1]);
+        assert_eq!(batch.column(3).as_primitive::<UInt64Type>().values().to_vec(), vec![72, 32, 16]);
+        let avg = batch.column(4).as_primitive::<arrow_array::types::Float64Type>();
+        assert_eq!(avg.values().to_vec(), vec![24.0, 32.0, 16.0]);
+
+        assert!(build_class_histogram_batch(&[]).is_none());
+    }
+
+    #[test]
+    fn test_build_utf8_batch_marks_invalid_strings() {
+        assert!(build_utf8_batch(&[]).is_none());
+        let wb = build_utf8_batch(&[(1, "java/lang/String", true), (2, INVALID_UTF8, false), (3, "", true)]).unwrap();
+        assert_eq!(wb.file_key, "_utf8");
+        let batch = &wb.batch;
+        assert_eq!(batch.column(0).as_primitive::<UInt64Type>().values().to_vec(), vec![1, 2, 3]);
+        let values = batch.column(1).as_string::<i32>();
+        assert_eq!(values.value(0), "java/lang/String");
+        assert_eq!(values.value(1), "(invalid UTF-8)");
+        assert_eq!(values.value(2), "");
+        let valid: Vec<bool> = batch.column(2).as_boolean().iter().map(|v| v.unwrap()).collect();
+        assert_eq!(valid, vec![true, false, true]);
     }
 
     // -----------------------------------------------------------------------
@@ -1242,10 +2808,657 @@ mod tests {
         }
     }
 
+    // -----------------------------------------------------------------------
+    // Output directory handling
+    // -----------------------------------------------------------------------
+
+    const TEST_WRITER_BUFFER: usize = 64 << 20;
+
+    /// A per-test directory under the system temp dir, removed if left over from a previous run.
+    fn fresh_temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir()
+            .join(format!("heapdumpstardiver-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        dir
+    }
+
+    #[test]
+    fn test_writer_pool_writes_into_out_dir() {
+        let root = fresh_temp_dir("out-dir");
+        let out_dir = root.join("nested").join("parquet");
+        prepare_output_dir(&out_dir).unwrap();
+
+        let frames = vec![ResolvedStackFrame {
+            frame_id: 1, method_name: "m", method_signature: "()V",
+            source_file: "X.java", class_name: "X", line_num: 1,
+        }];
+        let index = make_test_index(frames, vec![]);
+        let pool = ShardedWriterPool::new(
+            2, WriterSettings::new(Compression::SNAPPY), false, &out_dir, FileLayout::default(), TEST_WRITER_BUFFER,
+        );
+        pool.write_batch(build_stack_frames_batch(&index).unwrap());
+        pool.close_all().unwrap();
+
+        assert!(out_dir.join("_stack_frames.parquet").is_file());
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_writer_pool_single_file_per_key_across_batches() {
+        use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+
+        let out_dir = fresh_temp_dir("pool-round-trip");
+        prepare_output_dir(&out_dir).unwrap();
+
+        // Same file_key from several segments must end up as one readable parquet file
+        let pool = ShardedWriterPool::new(
+            4, WriterSettings::new(Compression::SNAPPY), false, &out_dir, FileLayout::default(), TEST_WRITER_BUFFER,
+        );
+        for trace_count in [1_u32, 2, 3] {
+            let traces = (0..trace_count).map(|i| ResolvedStackTrace {
+                stack_trace_serial: i, thread_serial: 1, frame_ids: vec![u64::from(i)],
+            }).collect();
+            let index = make_test_index(vec![], traces);
+            pool.write_batch(build_stack_traces_batch(&index).unwrap());
+        }
+        pool.close_all().unwrap();
+
+        let file = std::fs::File::open(out_dir.join("_stack_traces.parquet")).unwrap();
+        let reader = ParquetRecordBatchReaderBuilder::try_new(file).unwrap().build().unwrap();
+        let rows: usize = reader.map(|b| b.unwrap().num_rows()).sum();
+        assert_eq!(rows, 6);
+        std::fs::remove_dir_all(&out_dir).unwrap();
+    }
+
+    fn id_batch(file_key: &str, start: u64, rows: u64) -> WritableBatch {
+        let schema = Arc::new(Schema::new(vec![Field::new("obj_id", DataType::UInt64, false)]));
+        let batch = RecordBatch::try_new(
+            schema.clone(),
+            vec![Arc::new(UInt64Array::from_iter_values(start..start + rows)) as ArrayRef],
+        ).unwrap();
+        WritableBatch { file_key: file_key.into(), schema, batch }
+    }
+
+    /// Every file in `dir` with its obj_ids in file order.
+    fn ids_by_file(dir: &Path) -> BTreeMap<String, Vec<u64>> {
+        use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+
+        std::fs::read_dir(dir).unwrap()
+            .map(|entry| {
+                let path = entry.unwrap().path();
+                let file = std::fs::File::open(&path).unwrap();
+                let ids: Vec<u64> = ParquetRecordBatchReaderBuilder::try_new(file).unwrap().build().unwrap()
+                    .flat_map(|b| b.unwrap().column(0).as_primitive::<UInt64Type>().values().to_vec())
+                    .collect();
+                (path.file_name().unwrap().to_string_lossy().into_owned(), ids)
+            })
+            .collect()
+    }
+
+    /// Run `compute_and_write` over 40 "segments" that each contribute rows to three classes.
+    fn compute_segments(name: &str, threads: usize) -> BTreeMap<String, Vec<u64>> {
+        let segments: Vec<u64> = (0..40).collect();
+        let process = |segment: &u64| -> Vec<WritableBatch> {
+            (0..3).map(|class| id_batch(&format!("Class{}", class), segment * 1000 + class * 100, 50)).collect()
+        };
+        let out_dir = fresh_temp_dir(name);
+        prepare_output_dir(&out_dir).unwrap();
+        let pool = ShardedWriterPool::new(
+            4, WriterSettings::new(Compression::SNAPPY), false, &out_dir, FileLayout::default(), TEST_WRITER_BUFFER,
+        );
+        compute_and_write(&segments, threads, &pool, &AtomicBool::new(false), |s| Ok(process(s))).unwrap();
+        pool.close_all().unwrap();
+        let ids = ids_by_file(&out_dir);
+        std::fs::remove_dir_all(&out_dir).unwrap();
+        ids
+    }
+
+    #[test]
+    fn test_sample_caps_rows_across_classes() {
+        // 40 segments of 150 objects each, spread over three classes
+        let segments: Vec<u64> = (0..40).collect();
+        let sampled = sample_segments(&segments, Some(420), |_, up_to| Ok(up_to.min(150))).unwrap();
+        assert_eq!(sampled.iter().map(|(_, budget)| *budget).collect::<Vec<_>>(), vec![150, 150, 120]);
+
+        let process = |&(segment, budget): &(&u64, usize)| -> Vec<WritableBatch> {
+            (0..3_u64)
+                .map(|class| (class, budget.saturating_sub(class as usize * 50).min(50)))
+                .filter(|(_, rows)| *rows > 0)
+                .map(|(class, rows)| id_batch(&format!("Class{}", class), segment * 1000 + class * 100, rows as u64))
+                .collect()
+        };
+        let out_dir = fresh_temp_dir("sample");
+        prepare_output_dir(&out_dir).unwrap();
+        let pool = ShardedWriterPool::new(
+            4, WriterSettings::new(Compression::SNAPPY), false, &out_dir, FileLayout::default(), TEST_WRITER_BUFFER,
+        );
+        compute_and_write(&sampled, 4, &pool, &AtomicBool::new(false), |s| Ok(process(s))).unwrap();
+        pool.close_all().unwrap();
+        let ids = ids_by_file(&out_dir);
+        std::fs::remove_dir_all(&out_dir).unwrap();
+        assert_eq!(ids.values().map(Vec::len).sum::<usize>(), 420);
+        assert_eq!(ids["Class2.parquet"].len(), 50 + 50 + 20);
+
+        assert_eq!(sample_segments(&segments, None, |_, _| unreachable!()).unwrap().len(), 40);
+        assert!(sample_segments(&segments, Some(0), |_, up_to| Ok(up_to)).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_compute_stops_at_first_failed_item() {
+        let segments: Vec<u64> = (0..40).collect();
+        let out_dir = fresh_temp_dir("failed");
+        prepare_output_dir(&out_dir).unwrap();
+        let pool = ShardedWriterPool::new(
+            4, WriterSettings::new(Compression::SNAPPY), false, &out_dir, FileLayout::default(), TEST_WRITER_BUFFER,
+        );
+        // Segment 30 fails too, but 10 comes first in item order
+        let err = compute_and_write(&segments, 4, &pool, &AtomicBool::new(false), |&segment| match segment {
+            10 | 30 => Err(AnalyzeError::parse(format!("segment {}", segment))),
+            _ => Ok(vec![id_batch("Class0", segment * 100, 10)]),
+        }).unwrap_err();
+        assert!(matches!(&err, AnalyzeError::Parse(what) if what == "segment 10"), "{:?}", err);
+        pool.close_all().unwrap();
+        // Only the segments before the failed one are written
+        assert_eq!(ids_by_file(&out_dir)["Class0.parquet"].len(), 10 * 10);
+        std::fs::remove_dir_all(&out_dir).unwrap();
+    }
+
+    #[test]
+    fn test_cancelled_compute_builds_nothing() {
+        let segments: Vec<u64> = (0..40).collect();
+        let out_dir = fresh_temp_dir("cancelled");
+        prepare_output_dir(&out_dir).unwrap();
+        let pool = ShardedWriterPool::new(
+            4, WriterSettings::new(Compression::SNAPPY), false, &out_dir, FileLayout::default(), TEST_WRITER_BUFFER,
+        );
+        compute_and_write(&segments, 4, &pool, &AtomicBool::new(true), |_| unreachable!("cancelled before the first window"))
+            .unwrap();
+        pool.close_all().unwrap();
+        assert!(ids_by_file(&out_dir).is_empty());
+        std::fs::remove_dir_all(&out_dir).unwrap();
+    }
+
+    #[test]
+    fn test_parallel_compute_matches_single_threaded() {
+        let single = compute_segments("threads-1", 1);
+        let parallel = compute_segments("threads-8", 8);
+        assert_eq!(single.len(), 3);
+        assert_eq!(single["Class1.parquet"].len(), 40 * 50);
+        assert_eq!(single, parallel);
+    }
+
+    #[test]
+    fn test_output_order_is_deterministic() {
+        let first = compute_segments("order-first", 8);
+        let second = compute_segments("order-second", 8);
+        assert_eq!(first.keys().collect::<Vec<_>>(), vec!["Class0.parquet", "Class1.parquet", "Class2.parquet"]);
+        assert_eq!(first, second);
+        // Rows land in segment order
+        for ids in first.values() {
+            assert!(ids.windows(2).all(|w| w[0] < w[1]));
+        }
+    }
+
+    #[test]
+    fn test_buffer_budget_flushes_the_big_holder() {
+        let budget = BufferBudget::new(100);
+        assert!(!budget.update(0, 60), "under budget");
+        assert!(!budget.update(0, 10));
+        assert!(budget.update(60, 95), "over budget, with more than half of it");
+        assert!(!budget.update(95, 0), "flushed");
+        assert!(!budget.update(10, 20));
+        assert!(budget.update(0, 85));
+        assert!(!budget.update(20, 30), "a small shard keeps its buffer");
+    }
+
+    #[test]
+    fn test_writer_buffer_limit_flushes_row_groups_into_one_file() {
+        use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+
+        let out_dir = fresh_temp_dir("buffer-limit");
+        prepare_output_dir(&out_dir).unwrap();
+
+        // A 1-byte budget flushes after every batch
+        let pool = ShardedWriterPool::new(
+            1, WriterSettings::new(Compression::SNAPPY), false, &out_dir, FileLayout::default(), 1,
+        );
+        for i in 0..5 {
+            pool.write_batch(id_batch("Flushed", i * 100, 100));
+        }
+        pool.close_all().unwrap();
+
+        let file = std::fs::File::open(out_dir.join("Flushed.parquet")).unwrap();
+        let builder = ParquetRecordBatchReaderBuilder::try_new(file).unwrap();
+        assert_eq!(builder.metadata().num_row_groups(), 5);
+        let ids: Vec<u64> = builder.build().unwrap()
+            .flat_map(|b| b.unwrap().column(0).as_primitive::<UInt64Type>().values().to_vec())
+            .collect();
+        assert_eq!(ids, (0..500).collect::<Vec<u64>>());
+        std::fs::remove_dir_all(&out_dir).unwrap();
+    }
+
+    /// Throughput of the open-writer pool against writing each batch with a one-shot
+    /// `write_to_parquet` call, as the export used to. Run with
+    /// `cargo test --release bench_writer_pool -- --ignored --nocapture`.
+    #[test]
+    #[ignore]
+    fn bench_writer_pool_vs_per_call_files() {
+        use std::time::Instant;
+
+        const CLASSES: u64 = 200;
+        const BATCHES_PER_CLASS: u64 = 50;
+        const ROWS: u64 = 10_000;
+        let total_rows = CLASSES * BATCHES_PER_CLASS * ROWS;
+
+        let out_dir = fresh_temp_dir("bench-pool");
+        prepare_output_dir(&out_dir).unwrap();
+        let t = Instant::now();
+        let pool = ShardedWriterPool::new(
+            16, WriterSettings::new(Compression::SNAPPY), false, &out_dir, FileLayout::default(), 512 << 20,
+        );
+        for b in 0..BATCHES_PER_CLASS {
+            for class in 0..CLASSES {
+                pool.write_batch(id_batch(&format!("Class{}", class), b * ROWS, ROWS));
+            }
+        }
+        pool.close_all().unwrap();
+        let pool_secs = t.elapsed().as_secs_f64();
+        std::fs::remove_dir_all(&out_dir).unwrap();
+
+        // One file per call, so each class ends up in BATCHES_PER_CLASS files
+        let out_dir = fresh_temp_dir("bench-per-call");
+        let t = Instant::now();
+        for b in 0..BATCHES_PER_CLASS {
+            for class in 0..CLASSES {
+                let wb = id_batch("unused", b * ROWS, ROWS);
+                write_to_parquet(&out_dir, &format!("Class{}_{}", class, b), &[wb.batch], WriterSettings::new(Compression::SNAPPY)).unwrap();
+            }
+        }
+        let per_call_secs = t.elapsed().as_secs_f64();
+        std::fs::remove_dir_all(&out_dir).unwrap();
+
+        println!("writer pool: {:.2}s ({:.1}M rows/s)", pool_secs, total_rows as f64 / pool_secs / 1e6);
+        println!("per-call files: {:.2}s ({:.1}M rows/s)", per_call_secs, total_rows as f64 / per_call_secs / 1e6);
+    }
+
+    /// `compute_and_write` over segments where every 16th takes 20x as long to build,
+    /// against building them a window at a time and waiting for the whole window, as it
+    /// used to. Run with `cargo test --release bench_compute_and_write -- --ignored --nocapture`.
+    #[test]
+    #[ignore]
+    fn bench_compute_and_write_uneven_segments() {
+        use std::time::{Duration, Instant};
+
+        const THREADS: usize = 8;
+        let segments: Vec<u64> = (0..512).collect();
+        let process = |segment: &u64| -> Vec<WritableBatch> {
+            let slow = segment % 16 == 0;
+            std::thread::sleep(Duration::from_millis(if slow { 20 } else { 1 }));
+            vec![id_batch("Class0", segment * 100, 100)]
+        };
+        let new_pool = |name: &str| {
+            let out_dir = fresh_temp_dir(name);
+            prepare_output_dir(&out_dir).unwrap();
+            let pool = ShardedWriterPool::new(
+                4, WriterSettings::new(Compression::SNAPPY), false, &out_dir, FileLayout::default(), TEST_WRITER_BUFFER,
+            );
+            (out_dir, pool)
+        };
+
+        let (out_dir, pool) = new_pool("bench-pipelined");
+        let t = Instant::now();
+        compute_and_write(&segments, THREADS, &pool, &AtomicBool::new(false), |s| Ok(process(s))).unwrap();
+        pool.close_all().unwrap();
+        let pipelined = t.elapsed().as_secs_f64();
+        std::fs::remove_dir_all(&out_dir).unwrap();
+
+        let (out_dir, pool) = new_pool("bench-windowed");
+        let compute_pool = rayon::ThreadPoolBuilder::new().num_threads(THREADS).build().unwrap();
+        let t = Instant::now();
+        for window in segments.chunks(THREADS * WINDOW_PER_THREAD) {
+            let built: Vec<Vec<WritableBatch>> = compute_pool.install(|| window.par_iter().map(process).collect());
+            built.into_iter().flatten().for_each(|wb| pool.write_batch(wb));
+        }
+        pool.close_all().unwrap();
+        let windowed = t.elapsed().as_secs_f64();
+        std::fs::remove_dir_all(&out_dir).unwrap();
+
+        println!("{} uneven segments on {} threads: pipelined {:.2}s, window barrier {:.2}s",
+            segments.len(), THREADS, pipelined, windowed);
+    }
+
+    #[test]
+    fn test_prepare_output_dir_only_removes_parquet_files() {
+        let out_dir = fresh_temp_dir("prepare");
+        std::fs::create_dir_all(&out_dir).unwrap();
+        std::fs::write(out_dir.join("stale.parquet"), b"old").unwrap();
+        std::fs::write(out_dir.join("notes.txt"), b"keep me").unwrap();
+
+        prepare_output_dir(&out_dir).unwrap();
+
+        assert!(!out_dir.join("stale.parquet").exists());
+        assert!(out_dir.join("notes.txt").is_file());
+        std::fs::remove_dir_all(&out_dir).unwrap();
+    }
+
     // -----------------------------------------------------------------------
     // Integration: both batches built from same index
     // -----------------------------------------------------------------------
 
+    // -----------------------------------------------------------------------
+    // build_column tests
+    // -----------------------------------------------------------------------
+
+    fn mixed_values() -> Vec<ExtendedFieldValue> {
+        vec![
+            ExtendedFieldValue::FieldValue(FieldValue::Int(7)),
+            ExtendedFieldValue::FieldValue(FieldValue::Long(8)),
+            ExtendedFieldValue::Reference(Id::from(0_u64)),
+            ExtendedFieldValue::FieldValue(FieldValue::Boolean(true)),
+        ]
+    }
+
+    #[test]
+    fn test_build_column_mismatched_primitives_are_null() {
+        let index = make_test_index(vec![], vec![]);
+        let values = mixed_values();
+
+        let ints = build_column(&values, &index, &InlineArrays::default(), &DataType::Int32, false).unwrap();
+        let ints = ints.as_primitive::<arrow_array::types::Int32Type>();
+        assert_eq!(ints.value(0), 7);
+        assert!(ints.is_null(1) && ints.is_null(2) && ints.is_null(3));
+        assert_eq!(ints.null_count(), 3);
+
+        let bools = build_column(&values, &index, &InlineArrays::default(), &DataType::Boolean, false).unwrap();
+        let bools = bools.as_boolean();
+        assert!(bools.is_null(0) && bools.is_null(1) && bools.is_null(2));
+        assert!(bools.value(3));
+    }
+
+    #[test]
+    fn test_build_column_mismatched_references_are_null() {
+        let index = make_test_index(vec![], vec![]);
+        let values = mixed_values();
+
+        let refs = build_column(&values, &index, &InlineArrays::default(), &DataType::Struct(reference_struct_fields()), false).unwrap();
+        assert_eq!(refs.null_count(), 3);
+        // Any reference, even to id 0, is a valid struct
+        assert!(refs.is_valid(2));
+        let types = refs.as_struct().column(1).as_dictionary::<Int32Type>();
+        assert_eq!(types.values().as_string::<i32>().value(types.keys().value(2) as usize), "null");
+
+        let ids = build_column(&values, &index, &InlineArrays::default(), &DataType::UInt64, true).unwrap();
+        let ids = ids.as_primitive::<UInt64Type>();
+        assert_eq!(ids.null_count(), 3);
+        assert_eq!(ids.value(2), 0);
+    }
+
+    #[test]
+    fn test_inconsistent_class_is_padded_with_warning() {
+        // Three instances, but the second field only got values for two of them
+        let mut columns = vec![
+            vec![
+                ExtendedFieldValue::FieldValue(FieldValue::Int(1)),
+                ExtendedFieldValue::FieldValue(FieldValue::Int(2)),
+                ExtendedFieldValue::FieldValue(FieldValue::Int(3)),
+            ],
+            vec![
+                ExtendedFieldValue::FieldValue(FieldValue::Long(10)),
+                ExtendedFieldValue::FieldValue(FieldValue::Long(20)),
+            ],
+        ];
+        let problem = fit_columns(&mut columns, 3).unwrap();
+        assert_eq!(problem, ClassProblem::LengthMismatch { rows: 3, lengths: vec![3, 2] });
+        assert!(columns.iter().all(|c| c.len() == 3));
+
+        let index = make_test_index(vec![], vec![]);
+        let longs = build_column(&columns[1], &index, &InlineArrays::default(), &DataType::Int64, false).unwrap();
+        assert_eq!(longs.len(), 3);
+        assert!(longs.is_null(2));
+
+        let problems = ClassProblems::default();
+        problems.record(Id::from(200_u64), problem.clone(), 3);
+        problems.record(Id::from(100_u64), ClassProblem::NoClassRecord, 1);
+        problems.record(Id::from(100_u64), ClassProblem::NoClassRecord, 4);
+        let problems = problems.into_sorted();
+        assert_eq!(problems, vec![
+            (Id::from(100_u64), ClassProblem::NoClassRecord, 5),
+            (Id::from(200_u64), problem.clone(), 3),
+        ]);
+
+        assert_eq!(
+            class_problem_message(Id::from(200_u64), Some("com/example/Broken"), &problem, 3),
+            "class com/example/Broken (200): 3 instances but field column lengths [3, 2]; \
+             short columns padded with nulls (3 instances affected)",
+        );
+        assert_eq!(
+            class_problem_message(Id::from(100_u64), None, &ClassProblem::NoClassRecord, 5),
+            "skipped 5 instances of class 100: no class record, so it has no columns",
+        );
+        assert_eq!(
+            class_problem_message(Id::from(300_u64), None, &ClassProblem::NoSchema, 2),
+            "skipped 2 instances of class 300: no schema was generated for it",
+        );
+
+        // Under --strict, every class is in the one error
+        let AnalyzeError::UnwritableClasses(messages) = class_problems_error(&index, &problems) else { panic!() };
+        assert_eq!(messages, vec![
+            class_problem_message(Id::from(100_u64), None, &ClassProblem::NoClassRecord, 5),
+            class_problem_message(Id::from(200_u64), None, &problem, 3),
+        ]);
+    }
+
+    #[test]
+    fn test_instances_without_schema_are_recorded() {
+        let dump = shapes_dump();
+        let hprof = jvm_hprof::parse_hprof(&dump[..]).unwrap();
+        let (index, segments) = HprofIndex::build_with_options(&hprof, &AnalyzerOptions::default()).unwrap();
+        let inline_arrays = InlineArrays::default();
+        let mut schemas = generate_all_schemas(&index, &inline_arrays, ReferenceEncoding::default());
+        schemas.remove(&Id::from(200_u64));
+
+        let problems = ClassProblems::default();
+        let counts = ObjectCounts::default();
+        let batches = process_segment_to_batches(
+            &segments[0], &hprof, &index, &schemas, None, &GraphColumns::default(), &inline_arrays, &problems,
+            &counts, usize::MAX, false, ReferenceEncoding::default(),
+            ArrayColumns { elements: true, char_text: false, byte_binary: false },
+        ).unwrap();
+        // The three Points are skipped, once per class, and the Line still written
+        assert_eq!(problems.into_sorted(), vec![(Id::from(200_u64), ClassProblem::NoSchema, 3)]);
+        let keys: Vec<&str> = batches.iter().map(|wb| wb.file_key.as_str()).collect();
+        assert!(keys.contains(&class_file_key("com/example/Line", Id::from(400_u64)).as_str()), "{:?}", keys);
+        assert!(!keys.contains(&class_file_key("com/example/Point", Id::from(200_u64)).as_str()), "{:?}", keys);
+        assert_eq!(counts.instances.into_inner().unwrap().get(&Id::from(400_u64)), Some(&1));
+    }
+
+    #[test]
+    fn test_consistent_columns_are_left_alone() {
+        let mut columns: Vec<Vec<ExtendedFieldValue>> = vec![Vec::new(), Vec::new()];
+        assert_eq!(fit_columns(&mut columns, 0), None);
+        let mut columns = vec![vec![ExtendedFieldValue::Reference(Id::from(1_u64))]];
+        assert_eq!(fit_columns(&mut columns, 1), None);
+    }
+
+    #[test]
+    fn test_null_reference_sets_null_bitmap() {
+        let index = make_test_index(vec![], vec![]);
+        let values = vec![
+            ExtendedFieldValue::Reference(Id::from(0_u64)),
+            ExtendedFieldValue::NullReference,
+            ExtendedFieldValue::Reference(Id::from(42_u64)),
+        ];
+
+        let refs = build_column(&values, &index, &InlineArrays::default(), &DataType::Struct(reference_struct_fields()), false).unwrap();
+        assert_eq!(refs.null_count(), 1);
+        assert!(refs.is_valid(0));
+        assert!(refs.is_null(1));
+        assert!(!refs.nulls().unwrap().is_valid(1));
+        assert_eq!(refs.as_struct().column(0).as_primitive::<UInt64Type>().value(2), 42);
+
+        // Robo mode still writes a bare 0
+        let ids = build_column(&values, &index, &InlineArrays::default(), &DataType::UInt64, true).unwrap();
+        let ids = ids.as_primitive::<UInt64Type>();
+        assert_eq!(ids.null_count(), 0);
+        assert_eq!(ids.value(1), 0);
+
+        let lists = build_column(&values, &index, &InlineArrays::default(), &list_type(PrimitiveArrayType::Int), false).unwrap();
+        assert!(lists.is_null(1));
+    }
+
+    #[test]
+    fn test_build_prim_batch_none_when_no_arrays() {
+        let arrays: Vec<(u64, Vec<i32>)> = Vec::new();
+        assert!(build_prim_batch!(arrays, "_primitive_arrays_int", DataType::Int32, Int32Builder).is_none());
+    }
+
+    #[test]
+    fn test_build_prim_batch_empty_array_is_empty_list() {
+        let arrays: Vec<(u64, Vec<i32>)> = vec![(10, vec![1, 2, 3]), (11, vec![]), (12, vec![-4])];
+        let wb = build_prim_batch!(arrays, "_primitive_arrays_int", DataType::Int32, Int32Builder).unwrap();
+        assert_eq!(wb.file_key, "_primitive_arrays_int");
+        assert_eq!(wb.batch.num_rows(), 3);
+
+        let values = wb.batch.column(1).as_list::<i32>();
+        assert_eq!(values.null_count(), 0);
+        assert_eq!(values.value_length(0), 3);
+        assert_eq!(values.value_length(1), 0);
+        let last = values.value(2);
+        assert_eq!(last.as_primitive::<arrow_array::types::Int32Type>().values(), &[-4]);
+    }
+
+    // -----------------------------------------------------------------------
+    // Single-file mode tests
+    // -----------------------------------------------------------------------
+
+    fn test_class_schemas() -> HashMap<Id, Schema> {
+        let ref_type = DataType::Struct(reference_struct_fields());
+        HashMap::from([
+            (Id::from(1_u64), Schema::new(vec![
+                Field::new("count", DataType::Int32, false),
+                Field::new("value", ref_type.clone(), false),
+            ])),
+            (Id::from(2_u64), Schema::new(vec![
+                Field::new("enabled", DataType::Boolean, false),
+                Field::new("value", DataType::Int32, false),
+            ])),
+        ])
+    }
+
+    #[test]
+    fn test_union_schema_columns() {
+        let union = UnionSchema::new(&test_class_schemas(), Vec::new());
+        let names: Vec<&str> = union.schema.fields().iter().map(|f| f.name().as_str()).collect();
+        assert_eq!(names, vec!["obj_id", "class_name", "count", "enabled", "value:int", "value:ref"]);
+        assert!(union.schema.fields().iter().skip(2).all(|f| f.is_nullable()));
+        assert_eq!(union.columns_by_class[&Id::from(1_u64)], vec![Some(0), None, None, Some(1)]);
+        assert_eq!(union.columns_by_class[&Id::from(2_u64)], vec![None, Some(0), Some(1), None]);
+    }
+
+    #[test]
+    fn test_inline_array_field_retyped_as_list() {
+        let mut inline_arrays = InlineArrays::default();
+        inline_arrays.fields.insert((Id::from(1_u64), 1), PrimitiveArrayType::Int);
+        let mut schemas = test_class_schemas();
+        let retyped = with_inline_arrays(Id::from(1_u64), schemas.remove(&Id::from(1_u64)).unwrap(), &inline_arrays);
+        assert_eq!(retyped.field(0).data_type(), &DataType::Int32, false);
+        assert_eq!(retyped.field(1).name(), "value");
+        assert_eq!(retyped.field(1).data_type(), &list_type(PrimitiveArrayType::Int), false);
+
+        // An inlined field sharing a name with a reference field gets its own union column
+        schemas.insert(Id::from(1_u64), retyped);
+        let union = UnionSchema::new(&schemas, Vec::new());
+        let names: Vec<&str> = union.schema.fields().iter().map(|f| f.name().as_str()).collect();
+        assert_eq!(names, vec!["obj_id", "class_name", "count", "enabled", "value:int", "value:int[]"]);
+    }
+
+    #[test]
+    fn test_union_schema_with_reachable_column() {
+        let union = UnionSchema::new(&test_class_schemas(), vec![Field::new("reachable", DataType::Boolean, false)]);
+        let names: Vec<&str> = union.schema.fields().iter().map(|f| f.name().as_str()).collect();
+        assert_eq!(names, vec!["obj_id", "class_name", "reachable", "count", "enabled", "value:int", "value:ref"]);
+
+        let batch = union.pad(
+            Id::from(2_u64), "com/example/B",
+            Arc::new(UInt64Array::from(vec![200_u64])),
+            vec![Arc::new(BooleanArray::from(vec![false]))],
+            &[Arc::new(BooleanArray::from(vec![true])), Arc::new(Int32Array::from(vec![42]))],
+        ).unwrap();
+        assert!(!batch.column_by_name("reachable").unwrap().as_boolean().value(0));
+        assert_eq!(batch.column_by_name("value:int").unwrap().as_primitive::<arrow_array::types::Int32Type>().value(0), 42);
+    }
+
+    #[test]
+    fn test_single_file_two_classes_null_padded() {
+        use arrow_array::DictionaryArray;
+        use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+
+        let union = UnionSchema::new(&test_class_schemas(), Vec::new());
+        let refs = StructArray::from(vec![
+            (Arc::new(Field::new("id", DataType::UInt64, false)),
+             Arc::new(UInt64Array::from(vec![7_u64, 8])) as ArrayRef),
+            (Arc::new(reference_struct_fields()[1].as_ref().clone()),
+             Arc::new(DictionaryArray::<Int32Type>::from_iter(["java/lang/Object", "null"])) as ArrayRef),
+        ]);
+        let a = union.pad(
+            Id::from(1_u64), "com/example/A",
+            Arc::new(UInt64Array::from(vec![100_u64, 101])),
+            Vec::new(),
+            &[Arc::new(Int32Array::from(vec![3, 4])), Arc::new(refs)],
+        ).unwrap();
+        let b = union.pad(
+            Id::from(2_u64), "com/example/B",
+            Arc::new(UInt64Array::from(vec![200_u64])),
+            Vec::new(),
+            &[Arc::new(BooleanArray::from(vec![true])), Arc::new(Int32Array::from(vec![42]))],
+        ).unwrap();
+
+        let out_dir = fresh_temp_dir("single-file");
+        prepare_output_dir(&out_dir).unwrap();
+        let pool = ShardedWriterPool::new(
+            2, WriterSettings::new(Compression::SNAPPY), false, &out_dir, FileLayout::default(), TEST_WRITER_BUFFER,
+        );
+        for batch in [a, b] {
+            pool.write_batch(WritableBatch { file_key: SINGLE_FILE_KEY.into(), schema: union.schema.clone(), batch });
+        }
+        pool.close_all().unwrap();
+
+        let files: Vec<_> = std::fs::read_dir(&out_dir).unwrap().collect();
+        assert_eq!(files.len(), 1);
+
+        let file = std::fs::File::open(out_dir.join("instances.parquet")).unwrap();
+        let reader = ParquetRecordBatchReaderBuilder::try_new(file).unwrap().build().unwrap();
+        let mut seen = 0;
+        for batch in reader.map(|b| b.unwrap()) {
+            let obj_ids = batch.column(0).as_primitive::<UInt64Type>();
+            let class_name = batch.column_by_name("class_name").unwrap().as_string::<i32>();
+            let count = batch.column_by_name("count").unwrap();
+            let enabled = batch.column_by_name("enabled").unwrap();
+            let value_int = batch.column_by_name("value:int").unwrap();
+            let value_ref = batch.column_by_name("value:ref").unwrap();
+            for row in 0..batch.num_rows() {
+                seen += 1;
+                match obj_ids.value(row) {
+                    100 | 101 => {
+                        assert_eq!(class_name.value(row), "com/example/A");
+                        assert!(count.is_valid(row) && value_ref.is_valid(row));
+                        assert!(enabled.is_null(row) && value_int.is_null(row));
+                    }
+                    200 => {
+                        assert_eq!(class_name.value(row), "com/example/B");
+                        assert!(enabled.is_valid(row) && value_int.is_valid(row));
+                        assert!(count.is_null(row) && value_ref.is_null(row));
+                        assert_eq!(value_int.as_primitive::<arrow_array::types::Int32Type>().value(row), 42);
+                    }
+                    other => panic!("unexpected obj_id {}", other),
+                }
+            }
+        }
+        assert_eq!(seen, 3);
+
+        std::fs::remove_dir_all(&out_dir).unwrap();
+    }
+
     #[test]
     fn test_both_batches_from_populated_index() {
         let frames = vec![
@@ -1281,4 +3494,29 @@ mod tests {
             assert!(found, "frame_id {} from trace not found in stack_frames", fid);
         }
     }
+
+    #[test]
+    fn test_class_selection_globs() {
+        let everything = ClassSelection::default();
+        assert!(everything.selects_everything());
+        assert!(everything.selects("java/lang/String"));
+
+        let app = ClassSelection::new(&["com.example.*"], &[]);
+        assert!(app.selects("com/example/Point"));
+        assert!(app.selects("com.example.cache.Entry$Node"));
+        assert!(!app.selects("java/lang/String"));
+        assert!(!app.selects("com/examples/Other"));
+
+        // Excludes win over includes; `?` is one character and `$` is literal
+        let selection = ClassSelection::new(&["com/example/*", "java.util.HashMap"], &["*$?"]);
+        assert!(selection.selects("java.util.HashMap"));
+        assert!(!selection.selects("java.util.HashMap$Node"));
+        assert!(selection.selects("com.example.Point"));
+        assert!(!selection.selects("com.example.Point$1"));
+        assert!(selection.selects("com.example.Point$Builder"));
+
+        let no_jdk = ClassSelection::new(&[] as &[&str], &["java.*", "jdk.*", "sun.*"]);
+        assert!(!no_jdk.selects("jdk/internal/misc/Unsafe"));
+        assert!(no_jdk.selects("com.example.Point"));
+    }
 }
@@ -0,0 +1,61 @@
+// SPDX-License-Identifier: MIT
+// Copyright (c) 2026 Zac Policzer
+
+use std::collections::HashMap;
+use std::io::Write;
+use crate::analyzer::HeapDumpAnalyzer;
+use crate::error::AnalyzeError;
+
+/// Class names with more than one class object, as `(name, class object ids)` with ids
+/// ascending. Most copies first, then by name.
+fn duplicate_class_groups<'a>(classes: impl Iterator<Item = (&'a str, u64)>) -> Vec<(&'a str, Vec<u64>)> {
+    let mut by_name: HashMap<&str, Vec<u64>> = HashMap::new();
+    for (name, class_obj_id) in classes {
+        by_name.entry(name).or_default().push(class_obj_id);
+    }
+    let mut groups: Vec<(&str, Vec<u64>)> = by_name.into_iter()
+        .filter(|(_, ids)| ids.len() > 1)
+        .map(|(name, mut ids)| {
+            ids.sort_unstable();
+            (name, ids)
+        })
+        .collect();
+    groups.sort_unstable_by(|a, b| b.1.len().cmp(&a.1.len()).then(a.0.cmp(b.0)));
+    groups
+}
+
+/// Print every class name loaded more than once, as `count  class_name  class_obj_ids`.
+/// Each copy was defined by a different class loader, so a name with many copies usually
+/// means loaders that should have been collected are still reachable.
+pub fn duplicate_classes(analyzer: &HeapDumpAnalyzer, out: &mut impl Write) -> Result<(), AnalyzeError> {
+    let (index, _) = analyzer.indexed()?;
+
+    let groups = duplicate_class_groups(index.classes.iter().map(|(id, class)| (class.name, id.id())));
+    for (name, ids) in groups {
+        let ids: Vec<String> = ids.iter().map(u64::to_string).collect();
+        writeln!(out, "{}  {}  {}", ids.len(), name, ids.join(", "))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_duplicate_class_groups() {
+        let classes = [
+            ("com/example/Plugin", 30),
+            ("java/lang/Object", 1),
+            ("com/example/Plugin", 10),
+            ("com/example/Handler", 12),
+            ("com/example/Plugin", 20),
+            ("com/example/Handler", 11),
+        ];
+        let groups = duplicate_class_groups(classes.into_iter());
+        assert_eq!(groups, vec![
+            ("com/example/Plugin", vec![10, 20, 30]),
+            ("com/example/Handler", vec![11, 12]),
+        ]);
+    }
+}
@@ -0,0 +1,152 @@
+// SPDX-License-Identifier: MIT
+// Copyright (c) 2026 Zac Policzer
+
+use std::collections::HashMap;
+use crate::analyzer::HeapDumpAnalyzer;
+use crate::error::AnalyzeError;
+use crate::strings::{fold_strings_where, DecodedString};
+
+/// Characters of a duplicated value shown in the report.
+const PREVIEW_CHARS: usize = 80;
+
+/// All Strings sharing one decoded value.
+struct DuplicateGroup {
+    value: String,
+    count: u64,
+    /// Backing array bytes that could be reclaimed by keeping a single copy.
+    wasted_bytes: u64,
+}
+
+/// Decoded strings counted by value: value -> (count, total backing bytes, smallest copy).
+/// One is filled per segment and they are merged, so each distinct value is held once per
+/// segment being read, not once per String.
+#[derive(Default)]
+struct ValueTally(HashMap<String, (u64, u64, u64)>);
+
+impl ValueTally {
+    fn add(&mut self, s: DecodedString) {
+        self.add_copies(s.text, 1, s.backing_array_bytes, s.backing_array_bytes);
+    }
+
+    fn add_copies(&mut self, value: String, count: u64, total_bytes: u64, smallest: u64) {
+        let entry = self.0.entry(value).or_insert((0, 0, u64::MAX));
+        entry.0 += count;
+        entry.1 += total_bytes;
+        entry.2 = entry.2.min(smallest);
+    }
+
+    fn merge(mut self, other: ValueTally) -> ValueTally {
+        for (value, (count, total_bytes, smallest)) in other.0 {
+            self.add_copies(value, count, total_bytes, smallest);
+        }
+        self
+    }
+
+    /// The values seen more than once, most wasteful first.
+    fn duplicate_groups(self) -> Vec<DuplicateGroup> {
+        let mut groups: Vec<DuplicateGroup> = self.0.into_iter()
+            .filter(|(_, (count, _, _))| *count > 1)
+            .map(|(value, (count, total_bytes, smallest))| DuplicateGroup {
+                value,
+                count,
+                // With equal-size copies this is (count - 1) * per-copy size
+                wasted_bytes: total_bytes - smallest,
+            })
+            .collect();
+        groups.sort_unstable_by(|a, b| {
+            b.wasted_bytes.cmp(&a.wasted_bytes).then_with(|| a.value.cmp(&b.value))
+        });
+        groups
+    }
+}
+
+fn preview(value: &str) -> String {
+    let mut preview: String = value.chars().take(PREVIEW_CHARS).collect();
+    if preview.len() < value.len() {
+        preview.push_str("...");
+    }
+    preview.escape_debug().to_string()
+}
+
+/// Print the `top` String values with the most memory spent on duplicate copies, as
+/// `count  wasted_bytes  value_preview`.
+pub fn duplicate_strings(analyzer: &HeapDumpAnalyzer, top: usize) -> Result<(), AnalyzeError> {
+    let hprof = analyzer.hprof();
+    let (index, segments) = analyzer.indexed()?;
+    let tally = fold_strings_where(
+        hprof,
+        index,
+        segments,
+        |_| true,
+        ValueTally::default,
+        |tally, _, s| tally.add(s),
+        ValueTally::merge,
+    );
+
+    for group in tally.duplicate_groups().into_iter().take(top) {
+        println!("{}  {}  \"{}\"", group.count, group.wasted_bytes, preview(&group.value));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decoded(text: &str, backing_array_bytes: u64) -> DecodedString {
+        DecodedString { text: text.to_string(), backing_array_bytes }
+    }
+
+    fn duplicate_groups(strings: Vec<DecodedString>) -> Vec<DuplicateGroup> {
+        let mut tally = ValueTally::default();
+        strings.into_iter().for_each(|s| tally.add(s));
+        tally.duplicate_groups()
+    }
+
+    #[test]
+    fn test_three_identical_strings_waste() {
+        let groups = duplicate_groups(vec![
+            decoded("hello", 24),
+            decoded("hello", 24),
+            decoded("hello", 24),
+            decoded("unique", 24),
+        ]);
+        assert_eq!(groups.len(), 1);
+        assert_eq!(groups[0].value, "hello");
+        assert_eq!(groups[0].count, 3);
+        assert_eq!(groups[0].wasted_bytes, 2 * 24);
+    }
+
+    #[test]
+    fn test_groups_ordered_by_waste() {
+        let groups = duplicate_groups(vec![
+            decoded("small", 24),
+            decoded("small", 24),
+            decoded("big", 1024),
+            decoded("big", 1024),
+        ]);
+        let values: Vec<&str> = groups.iter().map(|g| g.value.as_str()).collect();
+        assert_eq!(values, vec!["big", "small"]);
+    }
+
+    #[test]
+    fn test_segments_merge_by_value() {
+        let mut first = ValueTally::default();
+        first.add(decoded("hello", 24));
+        first.add(decoded("unique", 24));
+        let mut second = ValueTally::default();
+        second.add(decoded("hello", 32));
+
+        let groups = first.merge(second).duplicate_groups();
+        assert_eq!(groups.len(), 1);
+        assert_eq!(groups[0].count, 2);
+        assert_eq!(groups[0].wasted_bytes, 56 - 24);
+    }
+
+    #[test]
+    fn test_preview_truncates() {
+        let long = "x".repeat(200);
+        assert_eq!(preview(&long), format!("{}...", "x".repeat(PREVIEW_CHARS)));
+        assert_eq!(preview("a\tb"), "a\\tb");
+    }
+}
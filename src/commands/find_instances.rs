@@ -0,0 +1,213 @@
+// SPDX-License-Identifier: MIT
+// Copyright (c) 2026 Zac Policzer
+
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::io::Write;
+use jvm_hprof::Id;
+use jvm_hprof::heap_dump::{FieldType, FieldValue, SubRecord};
+use rayon::prelude::*;
+use crate::analyzer::HeapDumpAnalyzer;
+use crate::boxed::decode_boxed;
+use crate::commands::dump_objects::{print_sub_record, ByteArrayFormat, ClassFilter, IdFormat, Referents, TextOptions};
+use crate::error::AnalyzeError;
+use crate::id_hash::IdSet;
+use crate::skip_errors::sub_records;
+use crate::strings::decode_strings_where;
+use crate::unresolved::UnresolvedReferences;
+use crate::util::{disambiguated_field_names, field_base_names};
+
+/// How `find` compares each instance's field with the given value.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Comparison {
+    Eq,
+    Lt,
+    Gt,
+}
+
+pub struct FindOptions {
+    /// Class name, dotted or in internal form. Instances of subclasses aren't included.
+    pub class_name: String,
+    /// Field name as `dump-objects` and the parquet columns name it, `Parent@name` for a
+    /// shadowed superclass field.
+    pub field: String,
+    pub comparison: Comparison,
+    pub value: String,
+    /// Print each match's fields, as `dump-objects` does, instead of just its id.
+    pub print_fields: bool,
+    pub id_format: IdFormat,
+}
+
+/// The value instances are compared with, parsed for the field's type.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Target {
+    Integer(i64),
+    Float(f64),
+    Boolean(bool),
+}
+
+impl Target {
+    fn parse(field_type: FieldType, value: &str, comparison: Comparison) -> Result<Target, AnalyzeError> {
+        let invalid = || AnalyzeError::InvalidArgument(format!("{:?} doesn't parse as the field's type", value));
+        match field_type {
+            FieldType::Boolean if comparison != Comparison::Eq => {
+                Err(AnalyzeError::InvalidArgument("booleans can only be compared with --eq".to_string()))
+            }
+            FieldType::Boolean => value.parse().map(Target::Boolean).map_err(|_| invalid()),
+            FieldType::Float | FieldType::Double => value.parse().map(Target::Float).map_err(|_| invalid()),
+            // A char compares by code unit, so it can be given as the character itself
+            FieldType::Char => {
+                let units: Vec<u16> = value.encode_utf16().collect();
+                match (value.parse::<u16>(), &units[..]) {
+                    (Ok(unit), _) | (Err(_), &[unit]) => Ok(Target::Integer(unit as i64)),
+                    _ => Err(invalid()),
+                }
+            }
+            FieldType::ObjectId => Err(AnalyzeError::InvalidArgument(
+                "the field is a reference; find compares primitive fields".to_string(),
+            )),
+            _ => value.parse().map(Target::Integer).map_err(|_| invalid()),
+        }
+    }
+
+    /// Whether `value` compares with the target as `comparison` asks. NaN matches nothing.
+    fn matches(self, value: &FieldValue, comparison: Comparison) -> bool {
+        let ordering = match (self, value) {
+            (Target::Boolean(b), FieldValue::Boolean(v)) => Some(v.cmp(&b)),
+            (Target::Float(f), FieldValue::Float(v)) => (*v as f64).partial_cmp(&f),
+            (Target::Float(f), FieldValue::Double(v)) => v.partial_cmp(&f),
+            (Target::Integer(i), v) => integer_value(v).map(|v| v.cmp(&i)),
+            _ => None,
+        };
+        ordering == Some(match comparison {
+            Comparison::Eq => Ordering::Equal,
+            Comparison::Lt => Ordering::Less,
+            Comparison::Gt => Ordering::Greater,
+        })
+    }
+}
+
+fn integer_value(value: &FieldValue) -> Option<i64> {
+    match value {
+        FieldValue::Byte(v) => Some(*v as i64),
+        FieldValue::Short(v) => Some(*v as i64),
+        FieldValue::Char(v) => Some(*v as i64),
+        FieldValue::Int(v) => Some(*v as i64),
+        FieldValue::Long(v) => Some(*v),
+        _ => None,
+    }
+}
+
+/// Print every instance of a class whose field compares with a value as asked, in dump
+/// order: one id per line, or each match's fields with `print_fields`. The field is looked
+/// up in the flattened descriptors, so inherited fields work too.
+pub fn find_instances(analyzer: &HeapDumpAnalyzer, options: &FindOptions, out: &mut impl Write) -> Result<(), AnalyzeError> {
+    let hprof = analyzer.hprof();
+    let (index, segments) = analyzer.indexed()?;
+    let id_size = hprof.header().id_size();
+
+    // Per matching class (one per class loader that defined it): the field's position
+    let filter = ClassFilter::Exact(options.class_name.clone());
+    let mut field_positions: HashMap<Id, usize> = HashMap::new();
+    let mut target = None;
+    for (class_id, class) in index.classes.iter().filter(|(_, c)| filter.matches(c.name)) {
+        let descriptors = index.class_instance_field_descriptors.get(class_id).map_or(&[][..], |d| &d[..]);
+        let names = disambiguated_field_names(
+            &field_base_names(descriptors, &index.utf8),
+            index.class_field_declaring_classes.get(class_id),
+        );
+        let position = names.iter().position(|n| *n == options.field).ok_or_else(|| {
+            AnalyzeError::InvalidArgument(format!(
+                "class {} has no field {} (fields: {})",
+                class.name,
+                options.field,
+                names.join(", ")
+            ))
+        })?;
+        target = Some(Target::parse(descriptors[position].field_type(), &options.value, options.comparison)?);
+        field_positions.insert(*class_id, position);
+    }
+    let target = target.ok_or_else(|| AnalyzeError::InvalidArgument(format!("no class named {}", options.class_name)))?;
+
+    let per_segment = segments.par_iter()
+        .map(|record| {
+            let segment = record.as_heap_dump_segment().unwrap()
+                .map_err(|_| AnalyzeError::parse("heap dump segment"))?;
+            let mut matches = Vec::new();
+            for p in sub_records(&index.options, segment.sub_records()) {
+                let SubRecord::Instance(instance) = p? else { continue };
+                let Some(&position) = field_positions.get(&instance.class_obj_id()) else { continue };
+                let mut input = instance.fields();
+                let descriptors = &index.class_instance_field_descriptors[&instance.class_obj_id()];
+                for (i, fd) in descriptors.iter().enumerate().take(position + 1) {
+                    let (rest, value) = fd.field_type().parse_value(input, id_size)
+                        .map_err(|_| AnalyzeError::parse(format!("fields of instance {}", instance.obj_id())))?;
+                    input = rest;
+                    if i == position && target.matches(&value, options.comparison) {
+                        matches.push(instance.obj_id());
+                    }
+                }
+            }
+            Ok(matches)
+        })
+        .collect::<Result<Vec<Vec<Id>>, AnalyzeError>>()?;
+    let matches: Vec<Id> = per_segment.into_iter().flatten().collect();
+
+    if !options.print_fields {
+        for id in matches {
+            writeln!(out, "{}", options.id_format.show(id))?;
+        }
+        return Ok(());
+    }
+
+    let matched: IdSet = matches.into_iter().collect();
+    let strings = decode_strings_where(hprof, index, segments, |id| matched.contains(&id));
+    let boxed = decode_boxed(hprof, index, segments);
+    let text_options = TextOptions {
+        max_array_elements: usize::MAX,
+        ids: options.id_format,
+        decode_char_arrays: false,
+        byte_arrays: ByteArrayFormat::Hex,
+        referents: &Referents::default(),
+        // Labelled, but only dump-objects and the parquet export count them
+        unresolved: &UnresolvedReferences::default(),
+    };
+    for record in segments {
+        let segment = record.as_heap_dump_segment().unwrap()
+            .map_err(|_| AnalyzeError::parse("heap dump segment"))?;
+        for p in sub_records(&index.options, segment.sub_records()) {
+            let s = p?;
+            if matches!(&s, SubRecord::Instance(instance) if matched.contains(&instance.obj_id())) {
+                print_sub_record(out, s, hprof, index, &strings, &boxed, text_options)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_target_comparisons() {
+        let three = Target::parse(FieldType::Int, "3", Comparison::Eq).unwrap();
+        assert!(three.matches(&FieldValue::Int(3), Comparison::Eq));
+        assert!(!three.matches(&FieldValue::Int(4), Comparison::Eq));
+        assert!(three.matches(&FieldValue::Int(2), Comparison::Lt));
+        assert!(three.matches(&FieldValue::Int(4), Comparison::Gt));
+
+        let half = Target::parse(FieldType::Double, "0.5", Comparison::Lt).unwrap();
+        assert!(half.matches(&FieldValue::Double(0.25), Comparison::Lt));
+        assert!(!half.matches(&FieldValue::Double(f64::NAN), Comparison::Lt));
+
+        let yes = Target::parse(FieldType::Boolean, "true", Comparison::Eq).unwrap();
+        assert!(yes.matches(&FieldValue::Boolean(true), Comparison::Eq));
+        assert!(!yes.matches(&FieldValue::Boolean(false), Comparison::Eq));
+
+        assert_eq!(Target::parse(FieldType::Char, "A", Comparison::Eq).unwrap(), Target::Integer(65));
+        assert!(Target::parse(FieldType::Boolean, "true", Comparison::Gt).is_err());
+        assert!(Target::parse(FieldType::Int, "three", Comparison::Eq).is_err());
+        assert!(Target::parse(FieldType::ObjectId, "0", Comparison::Eq).is_err());
+    }
+}
@@ -0,0 +1,107 @@
+// SPDX-License-Identifier: MIT
+// Copyright (c) 2026 Zac Policzer
+
+use std::collections::HashSet;
+use jvm_hprof::Id;
+use crate::analyzer::HeapDumpAnalyzer;
+use crate::edges::{collect_edges, instance_field_names, Edge, RefKind, ReferrerIndex};
+use crate::error::AnalyzeError;
+
+/// One referrer in the printed tree.
+struct ReferrerLine<'i, 'a> {
+    /// 1 for direct referrers of the target, 2 for their referrers, and so on.
+    depth: usize,
+    edge: &'i Edge<'a>,
+    /// The referrer was already listed higher up, so its own referrers aren't repeated.
+    repeated: bool,
+}
+
+/// Referrers of `target` up to `max_depth` levels, depth first in referrer id order. Each
+/// object is expanded at most once, which also stops cycles.
+fn referrer_tree<'i, 'a>(referrers: &'i ReferrerIndex<'a>, target: u64, max_depth: usize) -> Vec<ReferrerLine<'i, 'a>> {
+    let mut seen: HashSet<u64> = HashSet::from([target]);
+    let mut lines = Vec::new();
+    let mut stack: Vec<(usize, &Edge)> = referrers.referrers(target).iter().rev().map(|e| (1, e)).collect();
+    while let Some((depth, edge)) = stack.pop() {
+        let repeated = !seen.insert(edge.from_id);
+        if !repeated && depth < max_depth {
+            stack.extend(referrers.referrers(edge.from_id).iter().rev().map(|e| (depth + 1, e)));
+        }
+        lines.push(ReferrerLine { depth, edge, repeated });
+    }
+    lines
+}
+
+/// How the referrer holds the reference: `.field`, `.FIELD (static)` or `[]`.
+fn describe_field(edge: &Edge) -> String {
+    match edge.kind {
+        RefKind::InstanceField => format!(".{}", edge.field_name),
+        RefKind::StaticField => format!(".{} (static)", edge.field_name),
+        RefKind::ArrayElement => "[]".to_string(),
+    }
+}
+
+/// Print every object that references `id` directly, as `<- referrer_id (type).field`, and
+/// with `depth` > 1 their referrers in turn, indented one level per hop.
+pub fn find_referrers(analyzer: &HeapDumpAnalyzer, id: u64, depth: usize) -> Result<(), AnalyzeError> {
+    let hprof = analyzer.hprof();
+    let (index, segments) = analyzer.indexed()?;
+    if !index.contains_object(Id::from(id)) {
+        return Err(AnalyzeError::UnresolvedRef(id));
+    }
+
+    let field_names = instance_field_names(index);
+    let referrers = ReferrerIndex::new(collect_edges(hprof, index, segments, &field_names)?);
+
+    println!("{} ({})", id, index.resolve_ref_type(Id::from(id)));
+    let lines = referrer_tree(&referrers, id, depth);
+    if lines.is_empty() {
+        println!("  (no referrers)");
+    }
+    for line in lines {
+        println!(
+            "{}<- {} ({}){}{}",
+            "  ".repeat(line.depth),
+            line.edge.from_id,
+            index.resolve_ref_type(Id::from(line.edge.from_id)),
+            describe_field(line.edge),
+            if line.repeated { " (already shown)" } else { "" },
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::edges::tests::tiny_graph_edges;
+
+    fn tree(target: u64, max_depth: usize) -> Vec<(usize, u64, bool)> {
+        let referrers = ReferrerIndex::new(vec![tiny_graph_edges()]);
+        referrer_tree(&referrers, target, max_depth).iter()
+            .map(|l| (l.depth, l.edge.from_id, l.repeated))
+            .collect()
+    }
+
+    #[test]
+    fn test_direct_referrers() {
+        // 2 is held by 1.next and array 10
+        assert_eq!(tree(2, 1), vec![(1, 1, false), (1, 10, false)]);
+    }
+
+    #[test]
+    fn test_transitive_referrers_expand_each_object_once() {
+        // 1 is held by array 10 and class 100; array 10 shows up again as a referrer of 2
+        assert_eq!(
+            tree(2, 3),
+            vec![(1, 1, false), (2, 10, false), (2, 100, false), (1, 10, true)]
+        );
+    }
+
+    #[test]
+    fn test_describe_field() {
+        let edges = tiny_graph_edges();
+        let described: Vec<String> = edges.iter().map(describe_field).collect();
+        assert_eq!(described, vec![".next", "[]", "[]", ".INSTANCE (static)"]);
+    }
+}
@@ -0,0 +1,126 @@
+// SPDX-License-Identifier: MIT
+// Copyright (c) 2026 Zac Policzer
+
+use std::collections::HashMap;
+use jvm_hprof::{Id, Record};
+use jvm_hprof::heap_dump::{
+    GcRootBusyMonitor, GcRootJavaStackFrame, GcRootJniGlobal, GcRootJniLocalRef, GcRootNativeStack,
+    GcRootSystemClass, GcRootThreadBlock, GcRootThreadObj, GcRootUnknown,
+};
+use rayon::prelude::*;
+use crate::analyzer::HeapDumpAnalyzer;
+use crate::error::AnalyzeError;
+use crate::gc_roots::GcRoot;
+use crate::hprof_index::HprofIndex;
+use crate::visit::{visit_segment, SubRecordVisitor};
+
+/// Collects a segment's GC roots in order.
+#[derive(Default)]
+struct RootCollector(Vec<GcRoot>);
+
+impl<'a> SubRecordVisitor<'a> for RootCollector {
+    fn on_gc_root_unknown(&mut self, root: &GcRootUnknown) -> Result<(), AnalyzeError> {
+        self.0.push(root.into());
+        Ok(())
+    }
+    fn on_gc_root_thread_obj(&mut self, root: &GcRootThreadObj) -> Result<(), AnalyzeError> {
+        self.0.push(root.into());
+        Ok(())
+    }
+    fn on_gc_root_jni_global(&mut self, root: &GcRootJniGlobal) -> Result<(), AnalyzeError> {
+        self.0.push(root.into());
+        Ok(())
+    }
+    fn on_gc_root_jni_local_ref(&mut self, root: &GcRootJniLocalRef) -> Result<(), AnalyzeError> {
+        self.0.push(root.into());
+        Ok(())
+    }
+    fn on_gc_root_java_stack_frame(&mut self, root: &GcRootJavaStackFrame) -> Result<(), AnalyzeError> {
+        self.0.push(root.into());
+        Ok(())
+    }
+    fn on_gc_root_native_stack(&mut self, root: &GcRootNativeStack) -> Result<(), AnalyzeError> {
+        self.0.push(root.into());
+        Ok(())
+    }
+    fn on_gc_root_system_class(&mut self, root: &GcRootSystemClass) -> Result<(), AnalyzeError> {
+        self.0.push(root.into());
+        Ok(())
+    }
+    fn on_gc_root_thread_block(&mut self, root: &GcRootThreadBlock) -> Result<(), AnalyzeError> {
+        self.0.push(root.into());
+        Ok(())
+    }
+    fn on_gc_root_busy_monitor(&mut self, root: &GcRootBusyMonitor) -> Result<(), AnalyzeError> {
+        self.0.push(root.into());
+        Ok(())
+    }
+}
+
+/// Per root type counts, largest first (ties by name so output is stable).
+fn count_by_root_type(roots: &[GcRoot]) -> Vec<(&'static str, u64)> {
+    let mut counts: HashMap<&'static str, u64> = HashMap::new();
+    for root in roots {
+        *counts.entry(root.root_type).or_insert(0) += 1;
+    }
+    let mut counts: Vec<(&'static str, u64)> = counts.into_iter().collect();
+    counts.sort_unstable_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+    counts
+}
+
+/// Every GC root in `segments`, in dump order.
+pub(crate) fn collect_gc_roots(index: &HprofIndex, segments: &[Record]) -> Result<Vec<GcRoot>, AnalyzeError> {
+    // Segments collect in order, so roots come back in dump order
+    Ok(segments.par_iter()
+        .map(|record| {
+            let mut roots = RootCollector::default();
+            visit_segment(record, &index.options, &mut roots)?;
+            Ok(roots.0)
+        })
+        .collect::<Result<Vec<Vec<GcRoot>>, AnalyzeError>>()?
+        .into_iter()
+        .flatten()
+        .collect())
+}
+
+/// Print every GC root as `root_type  obj_id  class_name`, then the number of roots of
+/// each type.
+pub fn gc_roots(analyzer: &HeapDumpAnalyzer) -> Result<(), AnalyzeError> {
+    let (index, segments) = analyzer.indexed()?;
+    let roots = collect_gc_roots(index, segments)?;
+
+    for root in &roots {
+        println!("{}  {}  {}", root.root_type, root.obj_id, index.resolve_ref_type(Id::from(root.obj_id)));
+    }
+
+    println!();
+    for (root_type, count) in count_by_root_type(&roots) {
+        println!("{}: {}", root_type, count);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn root(root_type: &'static str, obj_id: u64) -> GcRoot {
+        GcRoot { root_type, obj_id, thread_serial: None, frame_index: None }
+    }
+
+    #[test]
+    fn test_count_by_root_type() {
+        let roots = vec![
+            root("JniGlobal", 1),
+            root("SystemClass", 2),
+            root("JavaStackFrame", 3),
+            root("SystemClass", 4),
+            root("JavaStackFrame", 5),
+            root("SystemClass", 6),
+        ];
+        assert_eq!(
+            count_by_root_type(&roots),
+            vec![("SystemClass", 3), ("JavaStackFrame", 2), ("JniGlobal", 1)]
+        );
+    }
+}
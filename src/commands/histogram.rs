@@ -0,0 +1,283 @@
+// SPDX-License-Identifier: MIT
+// Copyright (c) 2026 Zac Policzer
+
+use std::collections::HashMap;
+use jvm_hprof::{Hprof, Id, IdSize, Record};
+use jvm_hprof::heap_dump::{Instance, ObjectArray, PrimitiveArray, PrimitiveArrayType};
+use rayon::prelude::*;
+use crate::analyzer::HeapDumpAnalyzer;
+use crate::error::AnalyzeError;
+use crate::hprof_index::HprofIndex;
+use crate::visit::{visit_segment, SubRecordVisitor};
+use crate::util::{array_shallow_size, id_size_bytes, instance_shallow_size, primitive_array_element_size, primitive_array_len};
+
+/// Instance count and total estimated shallow size for one class.
+pub struct ClassStat {
+    pub class_name: String,
+    pub instance_count: u64,
+    pub total_shallow_bytes: u64,
+}
+
+const PRIMITIVE_ARRAY_TYPES: [PrimitiveArrayType; 8] = [
+    PrimitiveArrayType::Boolean,
+    PrimitiveArrayType::Char,
+    PrimitiveArrayType::Float,
+    PrimitiveArrayType::Double,
+    PrimitiveArrayType::Byte,
+    PrimitiveArrayType::Short,
+    PrimitiveArrayType::Int,
+    PrimitiveArrayType::Long,
+];
+
+fn primitive_array_slot(primitive_type: PrimitiveArrayType) -> usize {
+    match primitive_type {
+        PrimitiveArrayType::Boolean => 0,
+        PrimitiveArrayType::Char => 1,
+        PrimitiveArrayType::Float => 2,
+        PrimitiveArrayType::Double => 3,
+        PrimitiveArrayType::Byte => 4,
+        PrimitiveArrayType::Short => 5,
+        PrimitiveArrayType::Int => 6,
+        PrimitiveArrayType::Long => 7,
+    }
+}
+
+/// Per-segment (count, bytes) tallies. Instances and object arrays are keyed by class
+/// object id; primitive arrays have no class record and are tallied by element type.
+#[derive(Default)]
+struct SegmentTally {
+    by_class: HashMap<Id, (u64, u64)>,
+    by_primitive_array: [(u64, u64); 8],
+}
+
+impl SegmentTally {
+    fn add(&mut self, class_obj_id: Id, bytes: u64) {
+        let entry = self.by_class.entry(class_obj_id).or_insert((0, 0));
+        entry.0 += 1;
+        entry.1 += bytes;
+    }
+
+    fn merge(mut self, other: SegmentTally) -> SegmentTally {
+        for (class_obj_id, (count, bytes)) in other.by_class {
+            let entry = self.by_class.entry(class_obj_id).or_insert((0, 0));
+            entry.0 += count;
+            entry.1 += bytes;
+        }
+        for (slot, (count, bytes)) in other.by_primitive_array.into_iter().enumerate() {
+            self.by_primitive_array[slot].0 += count;
+            self.by_primitive_array[slot].1 += bytes;
+        }
+        self
+    }
+}
+
+/// A deterministic sample of the dump's heap dump segments: every `step`th one, starting
+/// with the first. Segments that aren't in it are never parsed, which is where the time goes
+/// on a large dump. HotSpot writes segments of a similar size, so totals over the sample are
+/// scaled up by the share of segments read.
+#[derive(Debug, Clone, Copy)]
+struct SegmentSample {
+    step: usize,
+}
+
+impl SegmentSample {
+    /// `fraction` is in (0, 1]. At least one segment is always read.
+    fn new(fraction: f64) -> Self {
+        SegmentSample { step: (1.0 / fraction).round().max(1.0) as usize }
+    }
+
+    fn segments<'r, 'a>(&self, segments: &'r [Record<'a>]) -> Vec<&'r Record<'a>> {
+        segments.iter().step_by(self.step).collect()
+    }
+
+    /// Scale a total over `sampled` of `total` segments up to the whole dump.
+    fn extrapolate(sampled_total: u64, sampled: usize, total: usize) -> u64 {
+        (sampled_total as f64 * total as f64 / sampled.max(1) as f64).round() as u64
+    }
+}
+
+/// Tallies one segment's instances and arrays.
+struct SegmentTallier<'s> {
+    tally: SegmentTally,
+    instance_sizes: &'s HashMap<Id, u64>,
+    id_size: IdSize,
+}
+
+impl<'a> SubRecordVisitor<'a> for SegmentTallier<'_> {
+    fn on_instance(&mut self, instance: &Instance<'a>) -> Result<(), AnalyzeError> {
+        // Classes missing from the index still count, at header size only
+        let bytes = self.instance_sizes.get(&instance.class_obj_id())
+            .copied()
+            .unwrap_or_else(|| instance_shallow_size([], self.id_size));
+        self.tally.add(instance.class_obj_id(), bytes);
+        Ok(())
+    }
+
+    fn on_object_array(&mut self, oa: &ObjectArray<'a>) -> Result<(), AnalyzeError> {
+        let length = oa.elements(self.id_size).count() as u64;
+        self.tally.add(oa.array_class_obj_id(), array_shallow_size(length, id_size_bytes(self.id_size), self.id_size));
+        Ok(())
+    }
+
+    fn on_primitive_array(&mut self, pa: &PrimitiveArray<'a>) -> Result<(), AnalyzeError> {
+        let bytes = array_shallow_size(
+            primitive_array_len(pa),
+            primitive_array_element_size(pa.primitive_type()),
+            self.id_size,
+        );
+        let slot = &mut self.tally.by_primitive_array[primitive_array_slot(pa.primitive_type())];
+        slot.0 += 1;
+        slot.1 += bytes;
+        Ok(())
+    }
+}
+
+fn tally_segment(
+    record: &Record,
+    hprof: &Hprof,
+    index: &HprofIndex,
+    instance_sizes: &HashMap<Id, u64>,
+) -> Result<SegmentTally, AnalyzeError> {
+    let id_size = hprof.header().id_size();
+    let mut tallier = SegmentTallier { tally: SegmentTally::default(), instance_sizes, id_size };
+    visit_segment(record, &index.options, &mut tallier)?;
+    Ok(tallier.tally)
+}
+
+/// Sort descending by total shallow bytes, breaking ties by name so output is stable.
+fn sort_stats(stats: &mut [ClassStat]) {
+    stats.sort_unstable_by(|a, b| {
+        b.total_shallow_bytes.cmp(&a.total_shallow_bytes)
+            .then_with(|| a.class_name.cmp(&b.class_name))
+    });
+}
+
+/// Compute the per-class histogram, largest total shallow size first.
+pub(crate) fn class_histogram(hprof: &Hprof, index: &HprofIndex, segments: &[Record]) -> Result<Vec<ClassStat>, AnalyzeError> {
+    histogram_of(hprof, index, segments, None)
+}
+
+/// Estimate the per-class histogram from a deterministic `fraction` of the heap dump
+/// segments, with the sample's counts and sizes scaled up to all of them. Classes with no
+/// object in the sampled segments are missing, so it suits a first look at the largest
+/// classes.
+pub(crate) fn approximate_class_histogram(
+    hprof: &Hprof,
+    index: &HprofIndex,
+    segments: &[Record],
+    fraction: f64,
+) -> Result<Vec<ClassStat>, AnalyzeError> {
+    histogram_of(hprof, index, segments, Some(SegmentSample::new(fraction)))
+}
+
+fn histogram_of(
+    hprof: &Hprof,
+    index: &HprofIndex,
+    segments: &[Record],
+    sample: Option<SegmentSample>,
+) -> Result<Vec<ClassStat>, AnalyzeError> {
+    let id_size = hprof.header().id_size();
+
+    let instance_sizes: HashMap<Id, u64> = index.class_instance_field_descriptors.iter()
+        .map(|(class_obj_id, fds)| {
+            (*class_obj_id, instance_shallow_size(fds.iter().map(|fd| fd.field_type()), id_size))
+        })
+        .collect();
+
+    let sampled = match sample {
+        Some(sample) => sample.segments(segments),
+        None => segments.iter().collect(),
+    };
+    let tally = sampled.par_iter()
+        .map(|record| tally_segment(record, hprof, index, &instance_sizes))
+        .try_reduce(SegmentTally::default, |a, b| Ok(a.merge(b)))?;
+
+    let mut stats: Vec<ClassStat> = tally.by_class.into_iter()
+        .map(|(class_obj_id, (instance_count, total_shallow_bytes))| ClassStat {
+            class_name: index.classes.get(&class_obj_id)
+                .map(|c| c.name.to_string())
+                .unwrap_or_else(|| format!("(unresolved class {})", class_obj_id.id())),
+            instance_count,
+            total_shallow_bytes,
+        })
+        .collect();
+
+    for primitive_type in PRIMITIVE_ARRAY_TYPES {
+        let (instance_count, total_shallow_bytes) = tally.by_primitive_array[primitive_array_slot(primitive_type)];
+        if instance_count > 0 {
+            stats.push(ClassStat {
+                class_name: format!("{}[]", primitive_type.java_type_name()),
+                instance_count,
+                total_shallow_bytes,
+            });
+        }
+    }
+
+    if sample.is_some() {
+        for stat in &mut stats {
+            stat.instance_count = SegmentSample::extrapolate(stat.instance_count, sampled.len(), segments.len());
+            stat.total_shallow_bytes = SegmentSample::extrapolate(stat.total_shallow_bytes, sampled.len(), segments.len());
+        }
+    }
+    sort_stats(&mut stats);
+    Ok(stats)
+}
+
+/// Print the histogram, or with `sample_fraction` an estimate from that fraction of the
+/// heap dump segments, headed by a line saying so.
+pub fn histogram(analyzer: &HeapDumpAnalyzer, sample_fraction: Option<f64>) -> Result<(), AnalyzeError> {
+    let stats = match sample_fraction {
+        Some(fraction) => {
+            println!("approximate: counts and sizes extrapolated from a {} sample of heap dump segments", fraction);
+            analyzer.approximate_class_histogram(fraction)?
+        }
+        None => analyzer.class_histogram()?,
+    };
+    for stat in stats {
+        println!("{}  {}  {}", stat.instance_count, stat.total_shallow_bytes, stat.class_name);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stat(name: &str, count: u64, bytes: u64) -> ClassStat {
+        ClassStat { class_name: name.to_string(), instance_count: count, total_shallow_bytes: bytes }
+    }
+
+    #[test]
+    fn test_sort_stats_descending_by_bytes_then_name() {
+        let mut stats = vec![
+            stat("b", 1, 16),
+            stat("a", 10, 160),
+            stat("c", 100, 16),
+            stat("d", 2, 1000),
+        ];
+        sort_stats(&mut stats);
+        let names: Vec<&str> = stats.iter().map(|s| s.class_name.as_str()).collect();
+        assert_eq!(names, vec!["d", "a", "b", "c"]);
+    }
+
+    #[test]
+    fn test_segment_sample_steps_and_scales() {
+        assert_eq!(SegmentSample::new(0.25).step, 4);
+        assert_eq!(SegmentSample::new(0.3).step, 3);
+        assert_eq!(SegmentSample::new(1.0).step, 1);
+        assert_eq!(SegmentSample::new(0.0001).step, 10_000);
+        // 2 of 7 segments read: 0 and 4
+        assert_eq!(SegmentSample::extrapolate(10, 2, 7), 35);
+        assert_eq!(SegmentSample::extrapolate(10, 7, 7), 10);
+    }
+
+    #[test]
+    fn test_primitive_array_slots_are_distinct() {
+        let mut seen = [false; 8];
+        for t in PRIMITIVE_ARRAY_TYPES {
+            let slot = primitive_array_slot(t);
+            assert!(!seen[slot]);
+            seen[slot] = true;
+        }
+    }
+}
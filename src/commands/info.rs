@@ -0,0 +1,64 @@
+// SPDX-License-Identifier: MIT
+// Copyright (c) 2026 Zac Policzer
+
+use crate::analyzer::HeapDumpAnalyzer;
+use crate::error::AnalyzeError;
+use crate::util::id_size_bytes;
+
+/// Format milliseconds since the Unix epoch as `YYYY-MM-DD HH:MM:SS.mmm UTC`.
+fn format_utc_millis(millis: u64) -> String {
+    let secs = millis / 1000;
+    let (days, secs_of_day) = (secs / 86_400, secs % 86_400);
+
+    // Civil date from days since 1970-01-01 (Howard Hinnant's days_from_civil inverse)
+    let z = days as i64 + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}:{:02}.{:03} UTC",
+        year, month, day,
+        secs_of_day / 3600, secs_of_day / 60 % 60, secs_of_day % 60, millis % 1000,
+    )
+}
+
+/// Print the header fields, the file size and the number of top-level records.
+pub fn info(analyzer: &HeapDumpAnalyzer, file_size: u64) -> Result<(), AnalyzeError> {
+    let hprof = analyzer.hprof();
+    let header = hprof.header();
+    let label = header.label().map_err(|_| AnalyzeError::parse("hprof format label"))?;
+
+    let mut records = 0u64;
+    for r in hprof.records_iter() {
+        if analyzer.cancelled() {
+            break;
+        }
+        r.map_err(|_| AnalyzeError::parse("top level record"))?;
+        records += 1;
+    }
+
+    println!("Format: {}", label);
+    println!("ID size: {} bytes", id_size_bytes(header.id_size()));
+    println!("Timestamp: {}", format_utc_millis(header.timestamp_millis()));
+    println!("File size: {} bytes ({:.1} MiB)", file_size, file_size as f64 / (1u64 << 20) as f64);
+    println!("Records: {}", records);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_utc_millis() {
+        assert_eq!(format_utc_millis(0), "1970-01-01 00:00:00.000 UTC");
+        assert_eq!(format_utc_millis(951_782_400_000), "2000-02-29 00:00:00.000 UTC");
+        assert_eq!(format_utc_millis(1_700_000_000_123), "2023-11-14 22:13:20.123 UTC");
+    }
+}
@@ -0,0 +1,140 @@
+// SPDX-License-Identifier: MIT
+// Copyright (c) 2026 Zac Policzer
+
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use jvm_hprof::heap_dump::{ObjectArray, PrimitiveArray};
+use jvm_hprof::{Hprof, Id, IdSize, Record};
+use rayon::prelude::*;
+use crate::analyzer::HeapDumpAnalyzer;
+use crate::error::AnalyzeError;
+use crate::hprof_index::HprofIndex;
+use crate::util::{array_shallow_size, id_size_bytes, primitive_array_element_size, primitive_array_len};
+use crate::visit::{visit_segment, SubRecordVisitor};
+
+/// One array and the bytes its elements take.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub(crate) struct ArraySize {
+    // Field order is the ranking: most bytes first, then lowest id
+    pub bytes: u64,
+    pub obj_id: Reverse<u64>,
+    pub length: u64,
+}
+
+impl ArraySize {
+    /// Estimated shallow size: the element bytes plus the array header, padded as
+    /// `histogram` counts arrays.
+    pub(crate) fn shallow_bytes(&self, id_size: IdSize) -> u64 {
+        array_shallow_size(1, self.bytes, id_size)
+    }
+}
+
+/// The `top` largest arrays seen, kept in a min-heap so the smallest is the one replaced.
+struct LargestArrays {
+    top: usize,
+    id_size: IdSize,
+    heap: BinaryHeap<Reverse<ArraySize>>,
+}
+
+impl LargestArrays {
+    fn new(top: usize, id_size: IdSize) -> Self {
+        LargestArrays { top, id_size, heap: BinaryHeap::with_capacity(top + 1) }
+    }
+
+    fn add(&mut self, array: ArraySize) {
+        self.heap.push(Reverse(array));
+        if self.heap.len() > self.top {
+            self.heap.pop();
+        }
+    }
+
+    /// Largest first.
+    fn into_sorted(self) -> Vec<ArraySize> {
+        self.heap.into_sorted_vec().into_iter().map(|Reverse(a)| a).collect()
+    }
+}
+
+impl<'a> SubRecordVisitor<'a> for LargestArrays {
+    fn on_object_array(&mut self, oa: &ObjectArray<'a>) -> Result<(), AnalyzeError> {
+        let length = oa.elements(self.id_size).count() as u64;
+        let bytes = length * id_size_bytes(self.id_size);
+        self.add(ArraySize { bytes, obj_id: Reverse(oa.obj_id().id()), length });
+        Ok(())
+    }
+
+    fn on_primitive_array(&mut self, pa: &PrimitiveArray<'a>) -> Result<(), AnalyzeError> {
+        let length = primitive_array_len(pa);
+        let bytes = length * primitive_array_element_size(pa.primitive_type());
+        self.add(ArraySize { bytes, obj_id: Reverse(pa.obj_id().id()), length });
+        Ok(())
+    }
+}
+
+/// The `top` arrays whose elements take the most bytes, largest first. Bytes are length
+/// times the element size, ids for object arrays, without the array header.
+pub(crate) fn largest_arrays_of(
+    hprof: &Hprof,
+    index: &HprofIndex,
+    segments: &[Record],
+    top: usize,
+) -> Result<Vec<ArraySize>, AnalyzeError> {
+    let id_size = hprof.header().id_size();
+
+    // Each segment keeps its own top N, so only N per segment are merged
+    let per_segment = segments.par_iter()
+        .map(|record| {
+            let mut largest = LargestArrays::new(top, id_size);
+            visit_segment(record, &index.options, &mut largest)?;
+            Ok(largest.into_sorted())
+        })
+        .collect::<Result<Vec<Vec<ArraySize>>, AnalyzeError>>()?;
+    let mut largest = LargestArrays::new(top, id_size);
+    per_segment.into_iter().flatten().for_each(|a| largest.add(a));
+    Ok(largest.into_sorted())
+}
+
+/// Print the `top` arrays whose elements take the most bytes, as
+/// `obj_id  array_type  length  bytes`.
+pub fn largest_arrays(analyzer: &HeapDumpAnalyzer, top: usize) -> Result<(), AnalyzeError> {
+    if top == 0 {
+        return Ok(());
+    }
+    let hprof = analyzer.hprof();
+    let (index, segments) = analyzer.indexed()?;
+
+    println!("obj_id  array_type  length  bytes");
+    for array in largest_arrays_of(hprof, index, segments, top)? {
+        let obj_id = array.obj_id.0;
+        println!("{}  {}  {}  {}", obj_id, index.resolve_ref_type(Id::from(obj_id)), array.length, array.bytes);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn array(obj_id: u64, length: u64, element_size: u64) -> ArraySize {
+        ArraySize { bytes: length * element_size, obj_id: Reverse(obj_id), length }
+    }
+
+    #[test]
+    fn test_largest_arrays_ordered_by_bytes() {
+        let mut largest = LargestArrays::new(3, IdSize::U64);
+        // A long[10] outweighs a byte[50]; equal sizes go by id
+        for a in [array(1, 50, 1), array(2, 10, 8), array(3, 4, 1), array(4, 20, 4), array(5, 80, 1)] {
+            largest.add(a);
+        }
+        let ranked: Vec<(u64, u64)> = largest.into_sorted().into_iter().map(|a| (a.obj_id.0, a.bytes)).collect();
+        assert_eq!(ranked, vec![(2, 80), (4, 80), (5, 80)]);
+    }
+
+    #[test]
+    fn test_largest_arrays_fewer_than_top() {
+        let mut largest = LargestArrays::new(10, IdSize::U32);
+        largest.add(array(7, 1, 4));
+        largest.add(array(8, 3, 4));
+        let ranked: Vec<u64> = largest.into_sorted().into_iter().map(|a| a.obj_id.0).collect();
+        assert_eq!(ranked, vec![8, 7]);
+    }
+}
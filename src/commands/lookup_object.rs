@@ -0,0 +1,114 @@
+// SPDX-License-Identifier: MIT
+// Copyright (c) 2026 Zac Policzer
+
+use std::io::Write;
+use jvm_hprof::{Id, IdSize};
+use jvm_hprof::heap_dump::SubRecord;
+use rayon::prelude::*;
+use crate::analyzer::HeapDumpAnalyzer;
+use crate::boxed::decode_boxed;
+use crate::commands::dump_objects::{print_sub_record, ByteArrayFormat, IdFormat, Referents, TextOptions};
+use crate::error::AnalyzeError;
+use crate::hprof_index::HprofIndex;
+use crate::id_hash::{IdMap, IdSet};
+use crate::skip_errors::sub_records;
+use crate::strings::{decode_strings_where, STRING_CLASS_NAME};
+use crate::unresolved::UnresolvedReferences;
+use crate::util::{array_shallow_size, id_size_bytes, instance_shallow_size, primitive_array_element_size, primitive_array_len};
+
+/// Object id of a class, instance or array sub-record.
+fn sub_record_obj_id(s: &SubRecord) -> Option<Id> {
+    match s {
+        SubRecord::Class(c) => Some(c.obj_id()),
+        SubRecord::Instance(i) => Some(i.obj_id()),
+        SubRecord::ObjectArray(oa) => Some(oa.obj_id()),
+        SubRecord::PrimitiveArray(pa) => Some(pa.obj_id()),
+        _ => None,
+    }
+}
+
+/// Estimated shallow size, or None for class records (their size isn't part of the heap
+/// as the dump models it).
+fn shallow_size(s: &SubRecord, index: &HprofIndex, id_size: IdSize) -> Option<u64> {
+    match s {
+        SubRecord::Instance(i) => {
+            let fds = index.class_instance_field_descriptors.get(&i.class_obj_id())?;
+            Some(instance_shallow_size(fds.iter().map(|fd| fd.field_type()), id_size))
+        }
+        SubRecord::ObjectArray(oa) => {
+            let length = oa.elements(id_size).count() as u64;
+            Some(array_shallow_size(length, id_size_bytes(id_size), id_size))
+        }
+        SubRecord::PrimitiveArray(pa) => Some(array_shallow_size(
+            primitive_array_len(pa),
+            primitive_array_element_size(pa.primitive_type()),
+            id_size,
+        )),
+        _ => None,
+    }
+}
+
+/// Print the single class, instance or array with object id `id`, formatted as in
+/// `dump-objects`, followed by its shallow size.
+pub fn lookup_object(analyzer: &HeapDumpAnalyzer, id: u64, id_format: IdFormat) -> Result<(), AnalyzeError> {
+    let hprof = analyzer.hprof();
+    let (index, segments) = analyzer.indexed()?;
+    let obj_id = Id::from(id);
+    let id_size = hprof.header().id_size();
+
+    // The index already knows every object id, so a miss fails before the segment scan
+    if !index.contains_object(obj_id) {
+        return Err(AnalyzeError::UnresolvedRef(id));
+    }
+
+    let segment = segments.par_iter()
+        .find_any(|r| {
+            r.as_heap_dump_segment().unwrap().unwrap()
+                .sub_records()
+                .any(|p| p.ok().and_then(|s| sub_record_obj_id(&s)) == Some(obj_id))
+        })
+        .ok_or(AnalyzeError::UnresolvedRef(id))?;
+
+    let is_string = index.obj_id_to_class_obj_id.class_of(obj_id)
+        .and_then(|class_obj_id| index.classes.get(&class_obj_id))
+        .is_some_and(|c| c.name == STRING_CLASS_NAME);
+    let boxed = decode_boxed(hprof, index, segments);
+
+    let segment = segment.as_heap_dump_segment().unwrap()
+        .map_err(|_| AnalyzeError::parse("heap dump segment"))?;
+    for p in sub_records(&index.options, segment.sub_records()) {
+        let s = p?;
+        if sub_record_obj_id(&s) != Some(obj_id) {
+            continue;
+        }
+        // Only the Strings that get printed: the object itself, or a String[]'s elements
+        let strings = match &s {
+            SubRecord::Instance(_) if is_string => {
+                decode_strings_where(hprof, index, segments, |string_id| string_id == obj_id)
+            }
+            SubRecord::ObjectArray(oa) => {
+                let elements: IdSet = oa.elements(id_size).filter_map(|e| e.ok().flatten()).collect();
+                decode_strings_where(hprof, index, segments, |string_id| elements.contains(&string_id))
+            }
+            _ => IdMap::default(),
+        };
+        let size = shallow_size(&s, index, id_size);
+        let mut out = std::io::stdout().lock();
+        let options = TextOptions {
+            max_array_elements: usize::MAX,
+            ids: id_format,
+            decode_char_arrays: false,
+            byte_arrays: ByteArrayFormat::Hex,
+            referents: &Referents::default(),
+            // Labelled, but only dump-objects and the parquet export count them
+            unresolved: &UnresolvedReferences::default(),
+        };
+        print_sub_record(&mut out, s, hprof, index, &strings, &boxed, options)?;
+        if let Some(size) = size {
+            writeln!(out, "  shallow size: {} bytes", size)?;
+        }
+        return Ok(());
+    }
+
+    Err(AnalyzeError::UnresolvedRef(id))
+}
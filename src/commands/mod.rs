@@ -2,10 +2,54 @@
 // Copyright (c) 2026 Zac Policzer
 
 mod count_records;
+mod diff;
+mod dump_classes;
 mod dump_objects;
+mod dump_strings;
+mod dump_to_ndjson;
 mod dump_to_parquet;
+mod duplicate_classes;
+mod duplicate_strings;
+mod find_instances;
+mod find_referrers;
+mod gc_roots;
+mod histogram;
+mod info;
+mod largest_arrays;
+mod lookup_object;
+mod monitors;
+mod object_json;
+#[cfg(feature = "query")]
+mod query;
+mod reference_edges;
+mod retained_size;
+mod stack_traces;
+mod top_consumers;
 
-pub use count_records::count_records;
-pub use dump_objects::dump_objects;
-pub use dump_to_parquet::dump_objects_to_parquet;
+pub use count_records::{count_records, record_totals, RecordSort, RecordTotals};
+pub(crate) use count_records::tally;
+pub use diff::{diff, histogram_diff, ClassDelta};
+pub use dump_classes::dump_classes;
+pub use dump_objects::{dump_objects, ByteArrayFormat, ClassFilter, DumpObjectsOptions, FormattedId, IdFormat, ObjectFormat, ObjectWindow};
+pub use dump_strings::{dump_strings, DumpStringsOptions};
+pub use dump_to_ndjson::dump_objects_to_ndjson;
+pub use dump_to_parquet::{dump_objects_to_parquet, ClassSelection, ExportSummary, ParquetOptions};
+pub use duplicate_classes::duplicate_classes;
+pub use duplicate_strings::duplicate_strings;
+pub use find_instances::{find_instances, Comparison, FindOptions};
+pub use find_referrers::find_referrers;
+pub use gc_roots::gc_roots;
+pub(crate) use gc_roots::collect_gc_roots;
+pub use histogram::{histogram, ClassStat};
+pub(crate) use histogram::{approximate_class_histogram, class_histogram};
+pub use info::info;
+pub use largest_arrays::largest_arrays;
+pub use lookup_object::lookup_object;
+pub use monitors::monitors;
+#[cfg(feature = "query")]
+pub use query::query;
+pub use reference_edges::reference_edges;
+pub use retained_size::{retained_by_package, retained_size};
+pub use stack_traces::stack_traces;
+pub use top_consumers::top_consumers;
 
@@ -0,0 +1,101 @@
+// SPDX-License-Identifier: MIT
+// Copyright (c) 2026 Zac Policzer
+
+use std::collections::HashMap;
+use std::io::Write;
+use jvm_hprof::Id;
+use crate::analyzer::HeapDumpAnalyzer;
+use crate::commands::collect_gc_roots;
+use crate::error::AnalyzeError;
+use crate::gc_roots::GcRoot;
+
+/// An object with a BusyMonitor root, and the serials of the threads that other roots tie
+/// it to (a Java frame, JNI local, native stack or thread block root), in serial order.
+/// The dump doesn't record a monitor's owner, but a thread that holds a lock almost always
+/// has the locked object on its stack, so these are the candidates.
+#[derive(Debug, PartialEq, Eq)]
+struct Monitor {
+    obj_id: u64,
+    thread_serials: Vec<u32>,
+}
+
+/// The monitored objects among `roots`, in dump order, each listed once.
+fn monitors_from_roots(roots: &[GcRoot]) -> Vec<Monitor> {
+    let mut threads_by_obj: HashMap<u64, Vec<u32>> = HashMap::new();
+    for root in roots.iter().filter(|r| r.root_type != "ThreadObj") {
+        if let Some(serial) = root.thread_serial {
+            threads_by_obj.entry(root.obj_id).or_default().push(serial);
+        }
+    }
+    let mut monitors: Vec<Monitor> = Vec::new();
+    for root in roots.iter().filter(|r| r.root_type == "BusyMonitor") {
+        if monitors.iter().any(|m| m.obj_id == root.obj_id) {
+            continue;
+        }
+        let mut thread_serials = threads_by_obj.get(&root.obj_id).cloned().unwrap_or_default();
+        thread_serials.sort_unstable();
+        thread_serials.dedup();
+        monitors.push(Monitor { obj_id: root.obj_id, thread_serials });
+    }
+    monitors
+}
+
+/// Print every object with a monitor held on it as `id <obj_id>: <class>`, then a line per
+/// thread whose stack refers to it, with the thread object when a ThreadObj root gives one.
+pub fn monitors(analyzer: &HeapDumpAnalyzer, out: &mut impl Write) -> Result<(), AnalyzeError> {
+    let (index, segments) = analyzer.indexed()?;
+    let roots = collect_gc_roots(index, segments)?;
+    let thread_objs: HashMap<u32, u64> = roots.iter()
+        .filter(|r| r.root_type == "ThreadObj" && r.obj_id != 0)
+        .filter_map(|r| r.thread_serial.map(|serial| (serial, r.obj_id)))
+        .collect();
+
+    let monitors = monitors_from_roots(&roots);
+    for monitor in &monitors {
+        writeln!(out, "id {}: {}", monitor.obj_id, index.resolve_ref_type(Id::from(monitor.obj_id)))?;
+        if monitor.thread_serials.is_empty() {
+            writeln!(out, "  (owner unknown)")?;
+        }
+        for serial in &monitor.thread_serials {
+            match thread_objs.get(serial) {
+                Some(&thread_id) => writeln!(
+                    out,
+                    "  on the stack of thread {} (id {}: {})",
+                    serial,
+                    thread_id,
+                    index.resolve_ref_type(Id::from(thread_id))
+                )?,
+                None => writeln!(out, "  on the stack of thread {}", serial)?,
+            }
+        }
+    }
+    writeln!(out, "\n{} monitored objects", monitors.len())?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn root(root_type: &'static str, obj_id: u64, thread_serial: Option<u32>) -> GcRoot {
+        GcRoot { root_type, obj_id, thread_serial, frame_index: None }
+    }
+
+    #[test]
+    fn test_monitors_from_roots_find_candidate_threads() {
+        let roots = vec![
+            root("BusyMonitor", 10, None),
+            root("JavaStackFrame", 10, Some(2)),
+            root("ThreadObj", 10, Some(9)),
+            root("BusyMonitor", 20, None),
+            root("JniLocal", 10, Some(1)),
+            root("JavaStackFrame", 10, Some(2)),
+            root("BusyMonitor", 10, None),
+            root("JavaStackFrame", 30, Some(3)),
+        ];
+        assert_eq!(monitors_from_roots(&roots), vec![
+            Monitor { obj_id: 10, thread_serials: vec![1, 2] },
+            Monitor { obj_id: 20, thread_serials: vec![] },
+        ]);
+    }
+}
@@ -0,0 +1,245 @@
+// SPDX-License-Identifier: MIT
+// Copyright (c) 2026 Zac Policzer
+
+//! `dump-objects --format json`: one JSON object per line for every class, instance and
+//! array, written straight to the output as each record is read.
+
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::io::Write;
+use jvm_hprof::{Hprof, Id};
+use jvm_hprof::heap_dump::{FieldValue, PrimitiveArrayType, SubRecord};
+use crate::commands::dump_objects::write_capped;
+use crate::error::AnalyzeError;
+use crate::hprof_index::HprofIndex;
+use crate::id_hash::IdMap;
+use crate::json::write_json_str;
+use crate::strings::DecodedString;
+
+/// JSON has no NaN or infinities, so those are written as strings.
+fn write_json_float(out: &mut impl Write, v: f64) -> Result<(), AnalyzeError> {
+    if v.is_finite() {
+        write!(out, "{}", v)?;
+    } else {
+        write_json_str(out, &v.to_string())?;
+    }
+    Ok(())
+}
+
+/// `{"id": ..., "type": ...}` for a reference, or `null`.
+fn write_json_ref<'r>(out: &mut impl Write, id: Option<Id>, ref_type: &impl Fn(Id) -> Cow<'r, str>) -> Result<(), AnalyzeError> {
+    match id {
+        Some(id) => {
+            write!(out, "{{\"id\":{},\"type\":", id)?;
+            write_json_str(out, &ref_type(id))?;
+            write!(out, "}}")?;
+        }
+        None => write!(out, "null")?,
+    }
+    Ok(())
+}
+
+fn write_json_value<'r>(out: &mut impl Write, value: &FieldValue, ref_type: &impl Fn(Id) -> Cow<'r, str>) -> Result<(), AnalyzeError> {
+    match value {
+        FieldValue::ObjectId(id) => write_json_ref(out, *id, ref_type)?,
+        FieldValue::Boolean(v) => write!(out, "{}", v)?,
+        FieldValue::Char(v) => write!(out, "{}", v)?,
+        FieldValue::Float(v) => write_json_float(out, f64::from(*v))?,
+        FieldValue::Double(v) => write_json_float(out, *v)?,
+        FieldValue::Byte(v) => write!(out, "{}", v)?,
+        FieldValue::Short(v) => write!(out, "{}", v)?,
+        FieldValue::Int(v) => write!(out, "{}", v)?,
+        FieldValue::Long(v) => write!(out, "{}", v)?,
+    }
+    Ok(())
+}
+
+/// `"<key>":{"name": value, ...}`.
+fn write_json_fields<'f, 'r>(
+    out: &mut impl Write,
+    key: &str,
+    fields: impl Iterator<Item = Result<(&'f str, FieldValue), AnalyzeError>>,
+    ref_type: &impl Fn(Id) -> Cow<'r, str>,
+) -> Result<(), AnalyzeError> {
+    write!(out, "\"{}\":{{", key)?;
+    for (i, field) in fields.enumerate() {
+        let (name, value) = field?;
+        if i > 0 {
+            write!(out, ",")?;
+        }
+        write_json_str(out, name)?;
+        write!(out, ":")?;
+        write_json_value(out, &value, ref_type)?;
+    }
+    write!(out, "}}")?;
+    Ok(())
+}
+
+/// `"elements":[...],"length":n`, with `"more":n` when `max_elements` cut the list short.
+fn write_json_elements<T>(
+    out: &mut impl Write,
+    elements: impl Iterator<Item = T>,
+    max_elements: usize,
+    mut write_one: impl FnMut(&mut dyn Write, T) -> Result<(), AnalyzeError>,
+) -> Result<(), AnalyzeError> {
+    write!(out, "\"elements\":[")?;
+    let mut shown = 0;
+    let rest = write_capped(elements, max_elements, |e| {
+        if shown > 0 {
+            write!(out, ",")?;
+        }
+        shown += 1;
+        write_one(out, e)
+    })?;
+    write!(out, "],\"length\":{}", shown + rest)?;
+    if rest > 0 {
+        write!(out, ",\"more\":{}", rest)?;
+    }
+    Ok(())
+}
+
+/// Write one class, instance or array record as a single line of JSON. `field_names` are
+/// the disambiguated instance field names, as in the parquet columns, so a shadowed
+/// superclass field doesn't repeat a key. Other sub-records write nothing.
+pub(crate) fn write_sub_record_json(
+    out: &mut impl Write,
+    s: SubRecord,
+    hprof: &Hprof,
+    index: &HprofIndex,
+    strings: &IdMap<DecodedString>,
+    field_names: &HashMap<Id, Vec<String>>,
+    max_array_elements: usize,
+) -> Result<(), AnalyzeError> {
+    let id_size = hprof.header().id_size();
+    let ref_type = |id: Id| index.resolve_ref_type(id);
+
+    match s {
+        SubRecord::Class(class) => {
+            let mc = index.classes.get(&class.obj_id())
+                .ok_or(AnalyzeError::MissingClass { class_obj_id: class.obj_id().id(), obj_id: None })?;
+            write!(out, "{{\"id\":{},\"kind\":\"class\",\"class\":", class.obj_id())?;
+            write_json_str(out, mc.name)?;
+            write!(out, ",")?;
+            let statics = mc.static_fields.iter().map(|sf| {
+                Ok((index.utf8.get(&sf.name_id()).copied().unwrap_or("(missing utf8)"), sf.value()))
+            });
+            write_json_fields(out, "static_fields", statics, &ref_type)?;
+            writeln!(out, "}}")?;
+        }
+        SubRecord::Instance(instance) => {
+            let missing_class = || AnalyzeError::MissingClass {
+                class_obj_id: instance.class_obj_id().id(),
+                obj_id: Some(instance.obj_id().id()),
+            };
+            let mc = index.classes.get(&instance.class_obj_id()).ok_or_else(missing_class)?;
+            let fds = index.class_instance_field_descriptors.get(&instance.class_obj_id()).ok_or_else(missing_class)?;
+            let names = field_names.get(&instance.class_obj_id()).ok_or_else(missing_class)?;
+
+            write!(out, "{{\"id\":{},\"kind\":\"instance\",\"class\":", instance.obj_id())?;
+            write_json_str(out, mc.name)?;
+            if let Some(decoded) = strings.get(&instance.obj_id()) {
+                write!(out, ",\"value\":")?;
+                write_json_str(out, &decoded.text)?;
+            }
+            write!(out, ",")?;
+            let mut input: &[u8] = instance.fields();
+            let fields = fds.iter().zip(names).map(|(fd, name)| {
+                let (rest, value) = fd.field_type().parse_value(input, id_size)
+                    .map_err(|_| AnalyzeError::parse(format!("fields of instance {}", instance.obj_id())))?;
+                input = rest;
+                Ok((name.as_str(), value))
+            });
+            write_json_fields(out, "fields", fields, &ref_type)?;
+            writeln!(out, "}}")?;
+        }
+        SubRecord::ObjectArray(oa) => {
+            let mc = index.classes.get(&oa.array_class_obj_id())
+                .ok_or(AnalyzeError::MissingClass {
+                    class_obj_id: oa.array_class_obj_id().id(),
+                    obj_id: Some(oa.obj_id().id()),
+                })?;
+            write!(out, "{{\"id\":{},\"kind\":\"object_array\",\"class\":", oa.obj_id())?;
+            write_json_str(out, mc.name)?;
+            write!(out, ",")?;
+            write_json_elements(out, oa.elements(id_size), max_array_elements, |out, e| {
+                let id = e.map_err(|_| AnalyzeError::parse(format!("elements of array {}", oa.obj_id())))?;
+                write_json_ref(out, id, &ref_type)
+            })?;
+            writeln!(out, "}}")?;
+        }
+        SubRecord::PrimitiveArray(pa) => {
+            write!(
+                out,
+                "{{\"id\":{},\"kind\":\"primitive_array\",\"class\":\"{}[]\",",
+                pa.obj_id(),
+                pa.primitive_type().java_type_name()
+            )?;
+            let elements_err = || AnalyzeError::parse(format!("elements of array {}", pa.obj_id()));
+            macro_rules! elements {
+                ($iter:expr, |$out:ident, $v:ident| $write:expr) => {
+                    write_json_elements(out, $iter, max_array_elements, |$out, e| {
+                        let $v = e.map_err(|_| elements_err())?;
+                        $write
+                    })?
+                };
+            }
+            match pa.primitive_type() {
+                PrimitiveArrayType::Boolean => elements!(pa.booleans().unwrap(), |out, v| Ok(write!(out, "{}", v)?)),
+                PrimitiveArrayType::Char => elements!(pa.chars().unwrap(), |out, v| Ok(write!(out, "{}", v)?)),
+                PrimitiveArrayType::Float => elements!(pa.floats().unwrap(), |out, v| write_json_float(out, f64::from(v))),
+                PrimitiveArrayType::Double => elements!(pa.doubles().unwrap(), |out, v| write_json_float(out, v)),
+                PrimitiveArrayType::Byte => elements!(pa.bytes().unwrap(), |out, v| Ok(write!(out, "{}", v)?)),
+                PrimitiveArrayType::Short => elements!(pa.shorts().unwrap(), |out, v| Ok(write!(out, "{}", v)?)),
+                PrimitiveArrayType::Int => elements!(pa.ints().unwrap(), |out, v| Ok(write!(out, "{}", v)?)),
+                PrimitiveArrayType::Long => elements!(pa.longs().unwrap(), |out, v| Ok(write!(out, "{}", v)?)),
+            }
+            writeln!(out, "}}")?;
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ref_type(id: Id) -> Cow<'static, str> {
+        match id.id() {
+            501 => Cow::Borrowed("com/example/Point"),
+            _ => Cow::Borrowed("(unresolved)"),
+        }
+    }
+
+    #[test]
+    fn test_instance_fields_are_valid_json() {
+        let fields = vec![
+            ("x", FieldValue::Int(7)),
+            ("visible", FieldValue::Boolean(true)),
+            ("next", FieldValue::ObjectId(Some(Id::from(501_u64)))),
+            ("prev", FieldValue::ObjectId(None)),
+            ("big", FieldValue::Long(-3)),
+            ("ratio", FieldValue::Double(0.5)),
+            ("bad", FieldValue::Float(f32::NAN)),
+            ("say \"hi\"\n", FieldValue::Char(65)),
+        ];
+        let mut out = Vec::new();
+        write_json_fields(&mut out, "fields", fields.into_iter().map(Ok), &ref_type).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "\"fields\":{\"x\":7,\"visible\":true,\"next\":{\"id\":501,\"type\":\"com/example/Point\"},\
+             \"prev\":null,\"big\":-3,\"ratio\":0.5,\"bad\":\"NaN\",\"say \\\"hi\\\"\\n\":65}",
+        );
+    }
+
+    #[test]
+    fn test_json_elements_respect_the_cap() {
+        let mut out = Vec::new();
+        write_json_elements(&mut out, 0..5, 3, |out, v| Ok(write!(out, "{}", v)?)).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "\"elements\":[0,1,2],\"length\":5,\"more\":2");
+
+        let mut out = Vec::new();
+        write_json_elements(&mut out, 0..0, 3, |out, v: i32| Ok(write!(out, "{}", v)?)).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "\"elements\":[],\"length\":0");
+    }
+}
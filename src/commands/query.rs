@@ -0,0 +1,149 @@
+// SPDX-License-Identifier: MIT
+// Copyright (c) 2026 Zac Policzer
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use arrow_array::RecordBatch;
+use datafusion::arrow::util::pretty::pretty_format_batches;
+use datafusion::datasource::MemTable;
+use datafusion::prelude::{SessionConfig, SessionContext};
+use crate::analyzer::HeapDumpAnalyzer;
+use crate::commands::dump_to_parquet::{class_file_key, in_memory_batches};
+use crate::error::AnalyzeError;
+
+/// Table name for a class: its name with every character other than `A-Z a-z 0-9` turned
+/// into `_`, so `java/util/HashMap$Node` is `java_util_HashMap_Node`.
+fn table_name(class_name: &str) -> String {
+    class_name.chars().map(|c| if c.is_ascii_alphanumeric() { c } else { '_' }).collect()
+}
+
+/// Table names for every class with instances, keyed by the classes' file keys. Classes
+/// whose names sanitize to the same table, e.g. one class loaded by two class loaders, or to
+/// one of the `reserved` names of the array and GC root tables, get their class id appended.
+fn class_tables<'a>(classes: impl Iterator<Item = (String, &'a str)>, reserved: &[String]) -> HashMap<String, String> {
+    let classes: Vec<(String, String)> = classes.map(|(key, name)| (key, table_name(name))).collect();
+    let mut uses: HashMap<&str, usize> = reserved.iter().map(|table| (table.as_str(), 1)).collect();
+    for (_, table) in &classes {
+        *uses.entry(table.as_str()).or_default() += 1;
+    }
+    classes.iter()
+        .map(|(key, table)| {
+            let table = if uses[table.as_str()] > 1 {
+                // The file key ends in `_<class id>`
+                format!("{}_{}", table, key.rsplit('_').next().unwrap())
+            } else {
+                table.clone()
+            };
+            (key.clone(), table)
+        })
+        .collect()
+}
+
+/// A session with each `(table, batches)` registered as an in-memory table. Identifiers keep
+/// their case, so class and field names can be written unquoted as they are in Java.
+fn session(tables: impl IntoIterator<Item = (String, Vec<RecordBatch>)>) -> Result<SessionContext, AnalyzeError> {
+    let config = SessionConfig::new().set_bool("datafusion.sql_parser.enable_ident_normalization", false);
+    let ctx = SessionContext::new_with_config(config);
+    for (table, batches) in tables {
+        let Some(schema) = batches.first().map(RecordBatch::schema) else { continue };
+        let mem_table = MemTable::try_new(schema, vec![batches]).map_err(|e| AnalyzeError::Query(e.to_string()))?;
+        ctx.register_table(table.as_str(), Arc::new(mem_table)).map_err(|e| AnalyzeError::Query(e.to_string()))?;
+    }
+    Ok(ctx)
+}
+
+fn run_sql(ctx: &SessionContext, sql: &str) -> Result<Vec<RecordBatch>, AnalyzeError> {
+    let runtime = tokio::runtime::Builder::new_multi_thread().enable_all().build()
+        .map_err(|e| AnalyzeError::Query(e.to_string()))?;
+    runtime.block_on(async {
+        ctx.sql(sql).await?.collect().await
+    }).map_err(|e| AnalyzeError::Query(e.to_string()))
+}
+
+/// Run `sql` over the dump's objects without writing any files. Every class with instances
+/// is a table of the same columns as its `dump-objects-to-parquet` file, named by
+/// `table_name`; arrays and GC roots are `object_arrays`, `primitive_arrays_<type>` and
+/// `gc_roots`. All tables are built in memory before the query runs.
+pub fn query(analyzer: &HeapDumpAnalyzer, sql: &str) -> Result<(), AnalyzeError> {
+    let hprof = analyzer.hprof();
+    let (index, segments) = analyzer.indexed()?;
+    let mut batches = in_memory_batches(hprof, index, segments)?;
+
+    let class_keys: Vec<(String, &str)> = index.classes.iter()
+        .map(|(class_id, c)| (class_file_key(c.name, *class_id), c.name))
+        .filter(|(key, _)| batches.contains_key(key))
+        .collect();
+    let mut class_batches = Vec::new();
+    for (key, _) in &class_keys {
+        class_batches.push((key.clone(), batches.remove(key).unwrap()));
+    }
+    // What's left are the `_object_arrays`-style keys, whose tables classes must not take
+    let other_tables: Vec<(String, Vec<RecordBatch>)> = batches.into_iter()
+        .map(|(key, b)| (key.trim_start_matches('_').to_string(), b))
+        .collect();
+    let reserved: Vec<String> = other_tables.iter().map(|(table, _)| table.clone()).collect();
+    let class_tables = class_tables(class_keys.into_iter(), &reserved);
+    let mut tables: Vec<(String, Vec<RecordBatch>)> = class_batches.into_iter()
+        .map(|(key, b)| (class_tables[&key].clone(), b))
+        .collect();
+    tables.extend(other_tables);
+
+    let ctx = session(tables)?;
+    let results = run_sql(&ctx, sql)?;
+    println!("{}", pretty_format_batches(&results).map_err(|e| AnalyzeError::Query(e.to_string()))?);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arrow_array::cast::AsArray;
+    use arrow_array::types::Int64Type;
+    use arrow_array::{Int32Array, UInt64Array};
+    use arrow_schema::{DataType, Field, Schema};
+
+    fn instances_batch(obj_ids: Vec<u64>, sizes: Vec<i32>) -> RecordBatch {
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("obj_id", DataType::UInt64, false),
+            Field::new("size", DataType::Int32, true),
+        ]));
+        RecordBatch::try_new(schema, vec![Arc::new(UInt64Array::from(obj_ids)), Arc::new(Int32Array::from(sizes))]).unwrap()
+    }
+
+    #[test]
+    fn test_count_over_registered_instances() {
+        let batches = vec![instances_batch(vec![1, 2, 3], vec![0, 5, 7]), instances_batch(vec![4], vec![9])];
+        let ctx = session([("java_util_HashMap".to_string(), batches)]).unwrap();
+
+        let results = run_sql(&ctx, "SELECT count(*) FROM java_util_HashMap").unwrap();
+        assert_eq!(results[0].column(0).as_primitive::<Int64Type>().value(0), 4);
+        let results = run_sql(&ctx, "SELECT count(*) FROM java_util_HashMap WHERE size > 4").unwrap();
+        assert_eq!(results[0].column(0).as_primitive::<Int64Type>().value(0), 3);
+    }
+
+    #[test]
+    fn test_unknown_table_is_a_query_error() {
+        let ctx = session(std::iter::empty()).unwrap();
+        assert!(matches!(run_sql(&ctx, "SELECT * FROM missing"), Err(AnalyzeError::Query(_))));
+    }
+
+    #[test]
+    fn test_table_names() {
+        assert_eq!(table_name("java/util/HashMap$Node"), "java_util_HashMap_Node");
+        assert_eq!(table_name("[Ljava/lang/String;"), "_Ljava_lang_String_");
+
+        let tables = class_tables([
+            ("a/B_10".to_string(), "a/B"),
+            ("a/B_11".to_string(), "a/B"),
+            ("a$B_12".to_string(), "a$B"),
+            ("c/D_13".to_string(), "c/D"),
+            ("gc_roots_14".to_string(), "gc_roots"),
+        ].into_iter(), &["gc_roots".to_string()]);
+        assert_eq!(tables["a/B_10"], "a_B_10");
+        assert_eq!(tables["a/B_11"], "a_B_11");
+        assert_eq!(tables["a$B_12"], "a_B_12");
+        assert_eq!(tables["c/D_13"], "c_D");
+        // A class can't take the GC roots' table
+        assert_eq!(tables["gc_roots_14"], "gc_roots_14");
+    }
+}
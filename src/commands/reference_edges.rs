@@ -0,0 +1,80 @@
+// SPDX-License-Identifier: MIT
+// Copyright (c) 2026 Zac Policzer
+
+use std::path::Path;
+use std::sync::Arc;
+use arrow_array::{Array, RecordBatch, StringArray, UInt64Array};
+use arrow_schema::{DataType, Field, Schema};
+use rayon::prelude::*;
+use crate::analyzer::HeapDumpAnalyzer;
+use crate::edges::{collect_edges, instance_field_names, Edge};
+use crate::error::AnalyzeError;
+use crate::util::{write_to_parquet, WriterSettings};
+
+/// Build the `edges` batch: from_id, to_id, field_name, ref_kind.
+fn build_edges_batch(edges: &[Edge]) -> RecordBatch {
+    let schema = Arc::new(Schema::new(vec![
+        Field::new("from_id", DataType::UInt64, false),
+        Field::new("to_id", DataType::UInt64, false),
+        Field::new("field_name", DataType::Utf8, false),
+        Field::new("ref_kind", DataType::Utf8, false),
+    ]));
+    RecordBatch::try_new(
+        schema,
+        vec![
+            Arc::new(UInt64Array::from_iter_values(edges.iter().map(|e| e.from_id))) as Arc<dyn Array>,
+            Arc::new(UInt64Array::from_iter_values(edges.iter().map(|e| e.to_id))) as Arc<dyn Array>,
+            Arc::new(StringArray::from_iter_values(edges.iter().map(|e| e.field_name))) as Arc<dyn Array>,
+            Arc::new(StringArray::from_iter_values(edges.iter().map(|e| e.kind.as_str()))) as Arc<dyn Array>,
+        ],
+    ).unwrap()
+}
+
+/// Print (or write to `<dir>/edges.parquet`) every non-null outbound reference in the dump:
+/// instance reference fields, object array elements and class static reference fields.
+pub fn reference_edges(analyzer: &HeapDumpAnalyzer, parquet_dir: Option<&Path>, writer: WriterSettings) -> Result<(), AnalyzeError> {
+    let hprof = analyzer.hprof();
+    let (index, segments) = analyzer.indexed()?;
+
+    let field_names = instance_field_names(index);
+    let parts = collect_edges(hprof, index, segments, &field_names)?;
+
+    match parquet_dir {
+        Some(dir) => {
+            let batches: Vec<RecordBatch> = parts.par_iter()
+                .filter(|edges| !edges.is_empty())
+                .map(|edges| build_edges_batch(edges))
+                .collect();
+            let count: usize = batches.iter().map(|b| b.num_rows()).sum();
+            write_to_parquet(dir, "edges", &batches, writer)?;
+            println!("Wrote {} edges to {}", count, dir.join("edges.parquet").display());
+        }
+        None => {
+            for edge in parts.iter().flatten() {
+                println!("{}\t{}\t{}\t{}", edge.from_id, edge.to_id, edge.field_name, edge.kind.as_str());
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::edges::tests::tiny_graph_edges;
+    use arrow_array::cast::AsArray;
+    use arrow_array::types::UInt64Type;
+
+    #[test]
+    fn test_build_edges_batch() {
+        let batch = build_edges_batch(&tiny_graph_edges());
+        assert_eq!(batch.num_rows(), 4);
+        let names: Vec<String> = batch.schema().fields().iter().map(|f| f.name().clone()).collect();
+        assert_eq!(names, vec!["from_id", "to_id", "field_name", "ref_kind"]);
+        assert_eq!(batch.column(0).as_primitive::<UInt64Type>().value(3), 100);
+        assert_eq!(batch.column(2).as_string::<i32>().value(0), "next");
+        assert_eq!(batch.column(2).as_string::<i32>().value(1), "");
+        assert_eq!(batch.column(3).as_string::<i32>().value(1), "array_element");
+        assert_eq!(batch.column(3).as_string::<i32>().value(3), "static_field");
+    }
+}
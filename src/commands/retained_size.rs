@@ -0,0 +1,104 @@
+// SPDX-License-Identifier: MIT
+// Copyright (c) 2026 Zac Policzer
+
+use std::collections::HashMap;
+use jvm_hprof::Id;
+use crate::analyzer::HeapDumpAnalyzer;
+use crate::error::AnalyzeError;
+use crate::graph::ObjectGraph;
+
+/// Print the `top` objects retaining the most memory, as
+/// `retained_bytes  shallow_bytes  obj_id  type`.
+pub fn retained_size(analyzer: &HeapDumpAnalyzer, top: usize) -> Result<(), AnalyzeError> {
+    let hprof = analyzer.hprof();
+    let (index, segments) = analyzer.indexed()?;
+    let graph = ObjectGraph::build(hprof, index, segments);
+    let dominators = graph.dominator_tree();
+
+    for (obj_id, retained) in dominators.largest(top) {
+        let shallow = graph.node(obj_id).map(|n| graph.shallow_size(n)).unwrap_or(0);
+        println!("{}  {}  {}  {}", retained, shallow, obj_id, index.resolve_ref_type(Id::from(obj_id)));
+    }
+    Ok(())
+}
+
+const PRIMITIVE_ARRAYS: &str = "(primitive arrays)";
+const DEFAULT_PACKAGE: &str = "(default package)";
+
+/// The first `depth` package segments of a JVM class name, dotted: `com/example/cache/Entry`
+/// at depth 2 is `com.example`. Object arrays count towards their element class's package.
+fn package_prefix(class_name: &str, depth: usize) -> &str {
+    let name = class_name.trim_start_matches('[');
+    if name.len() == 1 && name != class_name {
+        return PRIMITIVE_ARRAYS;
+    }
+    let name = name.strip_prefix('L').filter(|_| name != class_name).unwrap_or(name);
+    let package_len = match name.rfind('/') {
+        Some(i) => i,
+        None => return DEFAULT_PACKAGE,
+    };
+    let end = name[..package_len].match_indices('/').nth(depth.saturating_sub(1)).map_or(package_len, |(i, _)| i);
+    &name[..end]
+}
+
+/// Print the memory each package retains, largest first, as `retained_bytes  package`.
+/// Objects are grouped by the first `depth` segments of their class's package; a group's
+/// total counts each object once, so objects retained by another in the same package don't
+/// add to it again.
+pub fn retained_by_package(analyzer: &HeapDumpAnalyzer, depth: usize) -> Result<(), AnalyzeError> {
+    let hprof = analyzer.hprof();
+    let (index, segments) = analyzer.indexed()?;
+    let graph = ObjectGraph::build(hprof, index, segments);
+    let dominators = graph.dominator_tree();
+
+    // Group numbers per package, and per class so objects don't re-derive their package
+    let mut packages: Vec<String> = vec![PRIMITIVE_ARRAYS.to_string()];
+    let mut package_groups: HashMap<String, usize> = HashMap::from([(PRIMITIVE_ARRAYS.to_string(), 0)]);
+    let class_groups: HashMap<Id, usize> = index.classes.iter()
+        .map(|(class_id, class)| {
+            let package = package_prefix(class.name, depth).replace('/', ".");
+            let group = *package_groups.entry(package.clone()).or_insert_with(|| {
+                packages.push(package);
+                packages.len() - 1
+            });
+            (*class_id, group)
+        })
+        .collect();
+    let group_of = |obj_id: u64| {
+        let id = Id::from(obj_id);
+        match index.obj_id_to_class_obj_id.class_of(id) {
+            Some(class_id) => class_groups.get(&class_id).copied(),
+            None if index.prim_array_obj_id_to_type.contains_key(&id) => Some(0),
+            // A class object, retaining its static fields
+            None => class_groups.get(&id).copied(),
+        }
+    };
+
+    let totals = dominators.retained_by_group(packages.len(), group_of);
+    let mut rows: Vec<(u64, &str)> = totals.into_iter()
+        .zip(packages.iter().map(String::as_str))
+        .filter(|(retained, _)| *retained > 0)
+        .collect();
+    rows.sort_unstable_by(|a, b| b.0.cmp(&a.0).then(a.1.cmp(b.1)));
+    for (retained, package) in rows {
+        println!("{}  {}", retained, package);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_package_prefix() {
+        assert_eq!(package_prefix("com/example/cache/Entry", 2), "com/example");
+        assert_eq!(package_prefix("com/example/cache/Entry", 3), "com/example/cache");
+        assert_eq!(package_prefix("com/example/cache/Entry", 9), "com/example/cache");
+        assert_eq!(package_prefix("[[Lcom/example/cache/Entry;", 1), "com");
+        assert_eq!(package_prefix("[J", 2), PRIMITIVE_ARRAYS);
+        assert_eq!(package_prefix("Main", 2), DEFAULT_PACKAGE);
+        // A top-level class whose name starts with L isn't an array
+        assert_eq!(package_prefix("Lib/Util", 2), "Lib");
+    }
+}
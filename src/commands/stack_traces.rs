@@ -0,0 +1,102 @@
+// SPDX-License-Identifier: MIT
+// Copyright (c) 2026 Zac Policzer
+
+use std::collections::HashMap;
+use std::io::Write;
+use jvm_hprof::Id;
+use jvm_hprof::heap_dump::GcRootThreadObj;
+use rayon::prelude::*;
+use crate::analyzer::HeapDumpAnalyzer;
+use crate::error::AnalyzeError;
+use crate::hprof_index::ResolvedStackFrame;
+use crate::visit::{visit_segment, SubRecordVisitor};
+
+/// Collects a segment's thread object roots as (thread serial, thread object id).
+#[derive(Default)]
+struct ThreadCollector(Vec<(u32, u64)>);
+
+impl<'a> SubRecordVisitor<'a> for ThreadCollector {
+    fn on_gc_root_thread_obj(&mut self, root: &GcRootThreadObj) -> Result<(), AnalyzeError> {
+        self.0.push((root.thread_serial().num(), root.thread_obj_id().map(|id| id.id()).unwrap_or(0)));
+        Ok(())
+    }
+}
+
+/// A frame the way a Java stack trace prints it: `at com.example.Foo.bar(Foo.java:12)`.
+fn frame_text(frame: &ResolvedStackFrame) -> String {
+    let location = match frame.line_num {
+        n if n > 0 => format!("{}:{}", frame.source_file, n),
+        -2 => "Compiled method".to_string(),
+        -3 => "Native Method".to_string(),
+        _ => frame.source_file.to_string(),
+    };
+    format!("at {}.{}({})", frame.class_name.replace('/', "."), frame.method_name, location)
+}
+
+/// Print every stack trace in serial order: a `trace <serial>` line naming its thread, and
+/// the thread object when a ThreadObj root gives one, then a line per frame, innermost first.
+pub fn stack_traces(analyzer: &HeapDumpAnalyzer, out: &mut impl Write) -> Result<(), AnalyzeError> {
+    let (index, segments) = analyzer.indexed()?;
+
+    let thread_objs: HashMap<u32, u64> = segments.par_iter()
+        .map(|record| {
+            let mut threads = ThreadCollector::default();
+            visit_segment(record, &index.options, &mut threads)?;
+            Ok(threads.0)
+        })
+        .collect::<Result<Vec<Vec<(u32, u64)>>, AnalyzeError>>()?
+        .into_iter()
+        .flatten()
+        .collect();
+    let frames: HashMap<u64, &ResolvedStackFrame> = index.stack_frames.iter().map(|f| (f.frame_id, f)).collect();
+
+    let mut traces: Vec<_> = index.stack_traces.iter().collect();
+    traces.sort_unstable_by_key(|t| t.stack_trace_serial);
+    for trace in traces {
+        match thread_objs.get(&trace.thread_serial).filter(|id| **id != 0) {
+            Some(&thread_id) => writeln!(
+                out,
+                "trace {} (thread {}, id {}: {})",
+                trace.stack_trace_serial,
+                trace.thread_serial,
+                thread_id,
+                index.resolve_ref_type(Id::from(thread_id))
+            )?,
+            None => writeln!(out, "trace {} (thread {})", trace.stack_trace_serial, trace.thread_serial)?,
+        }
+        if trace.frame_ids.is_empty() {
+            writeln!(out, "  (no frames)")?;
+        }
+        for frame_id in &trace.frame_ids {
+            match frames.get(frame_id) {
+                Some(frame) => writeln!(out, "  {}", frame_text(frame))?,
+                None => writeln!(out, "  at (unknown frame {})", frame_id)?,
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(source_file: &'static str, line_num: i32) -> ResolvedStackFrame<'static> {
+        ResolvedStackFrame {
+            frame_id: 1,
+            method_name: "run",
+            method_signature: "()V",
+            source_file,
+            class_name: "com/example/Worker$Task",
+            line_num,
+        }
+    }
+
+    #[test]
+    fn test_frame_text() {
+        assert_eq!(frame_text(&frame("Worker.java", 42)), "at com.example.Worker$Task.run(Worker.java:42)");
+        assert_eq!(frame_text(&frame("Worker.java", -1)), "at com.example.Worker$Task.run(Worker.java)");
+        assert_eq!(frame_text(&frame("Worker.java", -2)), "at com.example.Worker$Task.run(Compiled method)");
+        assert_eq!(frame_text(&frame("Worker.java", -3)), "at com.example.Worker$Task.run(Native Method)");
+    }
+}
@@ -0,0 +1,111 @@
+// SPDX-License-Identifier: MIT
+// Copyright (c) 2026 Zac Policzer
+
+use jvm_hprof::{Id, IdSize};
+use crate::analyzer::HeapDumpAnalyzer;
+use crate::commands::histogram::{class_histogram, ClassStat};
+use crate::commands::largest_arrays::{largest_arrays_of, ArraySize};
+use crate::error::AnalyzeError;
+
+/// One row of `top-consumers`: all of a class's instances together, or a single array.
+#[derive(Debug, PartialEq)]
+enum Consumer {
+    Class { name: String, instance_count: u64, bytes: u64 },
+    Array { obj_id: u64, array_type: String, length: u64, bytes: u64 },
+}
+
+impl Consumer {
+    fn bytes(&self) -> u64 {
+        match self {
+            Consumer::Class { bytes, .. } | Consumer::Array { bytes, .. } => *bytes,
+        }
+    }
+}
+
+/// Merge the class histogram with the largest arrays into the `top` biggest consumers, most
+/// bytes first. Both are ranked by estimated shallow size, so an array's header counts as it
+/// does in a class's total. Array classes are left out of the class rows: their arrays are
+/// ranked one by one instead, and counting them twice would crowd out everything else.
+fn rank_consumers(
+    classes: Vec<ClassStat>,
+    arrays: Vec<ArraySize>,
+    array_type: impl Fn(u64) -> String,
+    id_size: IdSize,
+    top: usize,
+) -> Vec<Consumer> {
+    let mut consumers: Vec<Consumer> = classes.into_iter()
+        .filter(|c| !c.class_name.starts_with('[') && !c.class_name.ends_with("[]"))
+        .map(|c| Consumer::Class { name: c.class_name, instance_count: c.instance_count, bytes: c.total_shallow_bytes })
+        .chain(arrays.into_iter().map(|a| Consumer::Array {
+            obj_id: a.obj_id.0,
+            array_type: array_type(a.obj_id.0),
+            length: a.length,
+            bytes: a.shallow_bytes(id_size),
+        }))
+        .collect();
+    // Stable, so equal sizes keep classes before arrays, each in their own command's order
+    consumers.sort_by(|a, b| b.bytes().cmp(&a.bytes()));
+    consumers.truncate(top);
+    consumers
+}
+
+/// Print the `top` largest memory consumers, classes by total instance shallow size (as in
+/// `histogram`) and single arrays by their own shallow size, in one list of
+/// `kind  shallow_bytes  consumer` rows.
+pub fn top_consumers(analyzer: &HeapDumpAnalyzer, top: usize) -> Result<(), AnalyzeError> {
+    if top == 0 {
+        return Ok(());
+    }
+    let hprof = analyzer.hprof();
+    let (index, segments) = analyzer.indexed()?;
+    let classes = class_histogram(hprof, index, segments)?;
+    let arrays = largest_arrays_of(hprof, index, segments, top)?;
+
+    println!("kind  shallow_bytes  consumer");
+    let array_type = |id| index.resolve_ref_type(Id::from(id)).to_string();
+    for consumer in rank_consumers(classes, arrays, array_type, hprof.header().id_size(), top) {
+        match consumer {
+            Consumer::Class { name, instance_count, bytes } => {
+                println!("class  {}  {} ({} instances)", bytes, name, instance_count)
+            }
+            Consumer::Array { obj_id, array_type, length, bytes } => {
+                println!("array  {}  {} id {} (length {})", bytes, array_type, obj_id, length)
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cmp::Reverse;
+    use super::*;
+
+    fn class(name: &str, bytes: u64) -> ClassStat {
+        ClassStat { class_name: name.to_string(), instance_count: bytes / 16, total_shallow_bytes: bytes }
+    }
+
+    #[test]
+    fn test_consumers_ranked_by_bytes_across_kinds() {
+        let classes = vec![
+            class("java/lang/String", 4096),
+            class("[Ljava/lang/Object;", 100_000),
+            class("byte[]", 100_000),
+            class("java/util/HashMap$Node", 512),
+        ];
+        let arrays = vec![
+            ArraySize { bytes: 65536, obj_id: Reverse(7), length: 65536 },
+            ArraySize { bytes: 1024, obj_id: Reverse(8), length: 128 },
+        ];
+        let array_type = |id| if id == 7 { "byte[]" } else { "long[]" }.to_string();
+        let ranked = rank_consumers(classes, arrays, array_type, IdSize::U64, 10);
+        let bytes: Vec<u64> = ranked.iter().map(Consumer::bytes).collect();
+        // Arrays carry their 20-byte header, padded to 8
+        assert_eq!(bytes, vec![65560, 4096, 1048, 512], "array classes are counted by their arrays");
+        assert_eq!(ranked[0], Consumer::Array { obj_id: 7, array_type: "byte[]".to_string(), length: 65536, bytes: 65560 });
+        assert!(matches!(&ranked[1], Consumer::Class { name, .. } if name == "java/lang/String"));
+
+        let ranked = rank_consumers(vec![class("java/lang/String", 4096)], Vec::new(), |_| String::new(), IdSize::U64, 0);
+        assert!(ranked.is_empty());
+    }
+}
@@ -0,0 +1,211 @@
+// SPDX-License-Identifier: MIT
+// Copyright (c) 2026 Zac Policzer
+
+use std::collections::HashMap;
+use jvm_hprof::{Hprof, Id, Record};
+use jvm_hprof::heap_dump::{FieldValue, SubRecord};
+use rayon::prelude::*;
+use crate::error::AnalyzeError;
+use crate::hprof_index::HprofIndex;
+use crate::skip_errors::sub_records;
+use crate::util::{disambiguated_field_names, field_base_names};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RefKind {
+    InstanceField,
+    ArrayElement,
+    StaticField,
+}
+
+impl RefKind {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            RefKind::InstanceField => "instance_field",
+            RefKind::ArrayElement => "array_element",
+            RefKind::StaticField => "static_field",
+        }
+    }
+}
+
+/// One non-null outbound reference.
+pub struct Edge<'a> {
+    pub from_id: u64,
+    pub to_id: u64,
+    /// Column name of the field as in the instance parquet files; empty for array elements.
+    pub field_name: &'a str,
+    pub kind: RefKind,
+}
+
+/// Push an edge for every non-null reference among `fields`.
+fn push_field_edges<'a>(
+    edges: &mut Vec<Edge<'a>>,
+    from_id: u64,
+    fields: impl IntoIterator<Item = (&'a str, FieldValue)>,
+    kind: RefKind,
+) {
+    for (field_name, value) in fields {
+        if let FieldValue::ObjectId(Some(to)) = value {
+            edges.push(Edge { from_id, to_id: to.id(), field_name, kind });
+        }
+    }
+}
+
+/// Push an edge for every non-null element of an object array.
+fn push_array_edges(edges: &mut Vec<Edge<'_>>, from_id: u64, elements: impl IntoIterator<Item = Option<Id>>) {
+    for to in elements.into_iter().flatten() {
+        edges.push(Edge { from_id, to_id: to.id(), field_name: "", kind: RefKind::ArrayElement });
+    }
+}
+
+/// Per class, the name of each instance field in descriptor order. These are the instance
+/// parquet column names, so edges join back onto them.
+pub fn instance_field_names(index: &HprofIndex) -> HashMap<Id, Vec<String>> {
+    index.class_instance_field_descriptors.iter()
+        .map(|(class_id, fds)| {
+            let base_names = field_base_names(fds, &index.utf8);
+            let names = disambiguated_field_names(&base_names, index.class_field_declaring_classes.get(class_id));
+            (*class_id, names)
+        })
+        .collect()
+}
+
+/// Instance field and object array element edges in one heap dump segment.
+fn segment_edges<'a>(
+    record: &Record,
+    hprof: &Hprof,
+    index: &HprofIndex,
+    field_names: &'a HashMap<Id, Vec<String>>,
+) -> Result<Vec<Edge<'a>>, AnalyzeError> {
+    let id_size = hprof.header().id_size();
+    let segment = record.as_heap_dump_segment().unwrap()
+        .map_err(|_| AnalyzeError::parse("heap dump segment"))?;
+    let mut edges = Vec::new();
+    for p in sub_records(&index.options, segment.sub_records()) {
+        match p? {
+            SubRecord::Instance(instance) => {
+                let class_id = instance.class_obj_id();
+                let (Some(fds), Some(names)) = (
+                    index.class_instance_field_descriptors.get(&class_id),
+                    field_names.get(&class_id),
+                ) else {
+                    continue;
+                };
+                let mut input: &[u8] = instance.fields();
+                let mut values = Vec::with_capacity(fds.len());
+                for (fd, name) in fds.iter().zip(names) {
+                    let (rest, value) = fd.field_type().parse_value(input, id_size)
+                        .map_err(|_| AnalyzeError::parse("instance field value"))?;
+                    input = rest;
+                    values.push((name.as_str(), value));
+                }
+                push_field_edges(&mut edges, instance.obj_id().id(), values, RefKind::InstanceField);
+            }
+            SubRecord::ObjectArray(oa) => {
+                let elements = oa.elements(id_size)
+                    .collect::<Result<Vec<Option<Id>>, _>>()
+                    .map_err(|_| AnalyzeError::parse("object array element"))?;
+                push_array_edges(&mut edges, oa.obj_id().id(), elements);
+            }
+            _ => {}
+        }
+    }
+    Ok(edges)
+}
+
+/// Static reference field edges of every class, ordered by class name.
+fn static_field_edges<'a>(index: &'a HprofIndex) -> Vec<Edge<'a>> {
+    let mut classes: Vec<_> = index.classes.values().collect();
+    classes.sort_unstable_by_key(|c| (c.name, c.obj_id.id()));
+
+    let mut edges = Vec::new();
+    for class in classes {
+        let fields = class.static_fields.iter().map(|sf| {
+            let name = index.utf8.get(&sf.name_id()).copied().unwrap_or("(missing utf8)");
+            (name, sf.value())
+        });
+        push_field_edges(&mut edges, class.obj_id.id(), fields, RefKind::StaticField);
+    }
+    edges
+}
+
+/// Every non-null outbound reference in the dump: one part per heap dump segment, in dump
+/// order, then a final part with the class static field edges.
+pub fn collect_edges<'a>(
+    hprof: &Hprof,
+    index: &'a HprofIndex,
+    segments: &[Record],
+    field_names: &'a HashMap<Id, Vec<String>>,
+) -> Result<Vec<Vec<Edge<'a>>>, AnalyzeError> {
+    let mut parts = segments.par_iter()
+        .map(|record| segment_edges(record, hprof, index, field_names))
+        .collect::<Result<Vec<Vec<Edge>>, AnalyzeError>>()?;
+    parts.push(static_field_edges(index));
+    Ok(parts)
+}
+
+/// Reverse adjacency: edges sorted by target, so an object's referrers are one contiguous
+/// slice found by binary search.
+pub struct ReferrerIndex<'a> {
+    edges: Vec<Edge<'a>>,
+}
+
+impl<'a> ReferrerIndex<'a> {
+    pub fn new(parts: Vec<Vec<Edge<'a>>>) -> Self {
+        let mut edges: Vec<Edge<'a>> = parts.into_iter().flatten().collect();
+        edges.par_sort_unstable_by_key(|e| (e.to_id, e.from_id));
+        ReferrerIndex { edges }
+    }
+
+    /// Edges pointing at `to_id`, ordered by referrer id.
+    pub fn referrers(&self, to_id: u64) -> &[Edge<'a>] {
+        let start = self.edges.partition_point(|e| e.to_id < to_id);
+        let end = start + self.edges[start..].partition_point(|e| e.to_id == to_id);
+        &self.edges[start..end]
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    fn obj(id: u64) -> FieldValue {
+        FieldValue::ObjectId(Some(Id::from(id)))
+    }
+
+    /// Node 1 { next: 2, prev: null, count: 3 }, array 10 [1, null, 2], class 100 { INSTANCE: 1 }
+    pub(crate) fn tiny_graph_edges() -> Vec<Edge<'static>> {
+        let mut edges = Vec::new();
+        push_field_edges(
+            &mut edges,
+            1,
+            vec![("next", obj(2)), ("prev", FieldValue::ObjectId(None)), ("count", FieldValue::Int(3))],
+            RefKind::InstanceField,
+        );
+        push_array_edges(&mut edges, 10, vec![Some(Id::from(1_u64)), None, Some(Id::from(2_u64))]);
+        push_field_edges(&mut edges, 100, vec![("INSTANCE", obj(1))], RefKind::StaticField);
+        edges
+    }
+
+    #[test]
+    fn test_tiny_graph_edge_counts() {
+        let edges = tiny_graph_edges();
+        let count = |kind| edges.iter().filter(|e| e.kind == kind).count();
+        assert_eq!(edges.len(), 4);
+        assert_eq!(count(RefKind::InstanceField), 1);
+        assert_eq!(count(RefKind::ArrayElement), 2);
+        assert_eq!(count(RefKind::StaticField), 1);
+
+        let pairs: Vec<(u64, u64)> = edges.iter().map(|e| (e.from_id, e.to_id)).collect();
+        assert_eq!(pairs, vec![(1, 2), (10, 1), (10, 2), (100, 1)]);
+    }
+
+    #[test]
+    fn test_referrer_index() {
+        let index = ReferrerIndex::new(vec![tiny_graph_edges()]);
+        let from_ids = |to_id| index.referrers(to_id).iter().map(|e| e.from_id).collect::<Vec<u64>>();
+        assert_eq!(from_ids(1), vec![10, 100]);
+        assert_eq!(from_ids(2), vec![1, 10]);
+        assert!(from_ids(10).is_empty());
+        assert!(from_ids(999).is_empty());
+    }
+}
@@ -0,0 +1,150 @@
+// SPDX-License-Identifier: MIT
+// Copyright (c) 2026 Zac Policzer
+
+use std::fmt;
+use std::path::PathBuf;
+
+/// Errors surfaced to the CLI instead of panicking.
+#[derive(Debug)]
+pub enum AnalyzeError {
+    Io { path: PathBuf, source: std::io::Error },
+    /// The hprof data could not be parsed. Only our own description is kept: jvm-hprof's
+    /// nom errors carry the remaining input, which can be most of a multi-GB dump.
+    Parse(String),
+    /// Sub-record `position` of heap dump segment `segment` didn't parse. `offset` is where
+    /// in the file the parser stopped, when it could tell, and `reason` is nom's error kind.
+    BadSubRecord { segment: usize, position: usize, offset: Option<u64>, reason: String },
+    /// The input doesn't start with a valid HPROF header, e.g. a truncated or unrelated file.
+    NotHprof { path: PathBuf, reason: String },
+    /// A record refers to a class object id that has no class dump record.
+    MissingClass { class_obj_id: u64, obj_id: Option<u64> },
+    /// An object id that must resolve (not just best-effort type naming) isn't in the dump.
+    UnresolvedRef(u64),
+    /// With `--fail-on-unresolved`, a field refers to an object whose type can't be resolved.
+    /// For an object array element, `field_name` is its position, as in `[2]`.
+    UnresolvedField { obj_id: u64, field_name: String, ref_id: u64 },
+    /// Writing command output failed, e.g. a closed pipe or a full disk.
+    Output(std::io::Error),
+    /// A `query` SQL statement could not be planned or run.
+    Query(String),
+    /// With `--strict`, the parquet export skipped or padded the instances of these classes;
+    /// one message per class.
+    UnwritableClasses(Vec<String>),
+    /// A command argument doesn't fit the dump, e.g. a field its class doesn't have.
+    InvalidArgument(String),
+    /// `--timeout` passed, or `AnalyzerOptions::cancel` was set, before the dump was fully read.
+    Cancelled,
+}
+
+impl AnalyzeError {
+    pub fn io(path: impl Into<PathBuf>, source: std::io::Error) -> Self {
+        AnalyzeError::Io { path: path.into(), source }
+    }
+
+    pub fn parse(what: impl Into<String>) -> Self {
+        AnalyzeError::Parse(what.into())
+    }
+}
+
+impl fmt::Display for AnalyzeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AnalyzeError::Io { path, source } => write!(f, "{}: {}", path.display(), source),
+            AnalyzeError::Parse(what) => write!(f, "could not parse {}", what),
+            AnalyzeError::BadSubRecord { segment, position, offset: Some(offset), reason } => write!(
+                f,
+                "could not parse sub-record {} of heap dump segment {} at byte {}: {}",
+                position, segment, offset, reason
+            ),
+            AnalyzeError::BadSubRecord { segment, position, offset: None, reason } => write!(
+                f,
+                "could not parse sub-record {} of heap dump segment {}: {}",
+                position, segment, reason
+            ),
+            AnalyzeError::NotHprof { path, reason } => {
+                write!(f, "{} is not a valid hprof file ({})", path.display(), reason)
+            }
+            AnalyzeError::MissingClass { class_obj_id, obj_id: Some(obj_id) } => {
+                write!(f, "could not find class {} for object {}", class_obj_id, obj_id)
+            }
+            AnalyzeError::MissingClass { class_obj_id, obj_id: None } => {
+                write!(f, "could not find class {}", class_obj_id)
+            }
+            AnalyzeError::UnresolvedRef(id) => write!(f, "object id {} not found in the heap dump", id),
+            AnalyzeError::UnresolvedField { obj_id, field_name, ref_id } => write!(
+                f,
+                "field {} of object {} refers to {}, whose type could not be resolved",
+                field_name, obj_id, ref_id
+            ),
+            AnalyzeError::Output(source) => write!(f, "could not write output: {}", source),
+            AnalyzeError::Query(reason) => write!(f, "query failed: {}", reason),
+            AnalyzeError::UnwritableClasses(messages) => write!(
+                f,
+                "{} classes could not be written as-is: {}",
+                messages.len(),
+                messages.join("; ")
+            ),
+            AnalyzeError::InvalidArgument(reason) => write!(f, "{}", reason),
+            AnalyzeError::Cancelled => write!(f, "cancelled before the dump was fully read; output is partial"),
+        }
+    }
+}
+
+impl std::error::Error for AnalyzeError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            AnalyzeError::Io { source, .. } | AnalyzeError::Output(source) => Some(source),
+            _ => None,
+        }
+    }
+}
+
+/// Lets `writeln!(out, ...)?` report write failures. File errors should still go through
+/// `AnalyzeError::io` so the message names the path.
+impl From<std::io::Error> for AnalyzeError {
+    fn from(source: std::io::Error) -> Self {
+        AnalyzeError::Output(source)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_display() {
+        let e = AnalyzeError::io("heap.hprof", std::io::Error::from(std::io::ErrorKind::NotFound));
+        assert!(e.to_string().starts_with("heap.hprof: "));
+        assert!(std::error::Error::source(&e).is_some());
+
+        assert_eq!(AnalyzeError::parse("hprof header").to_string(), "could not parse hprof header");
+        assert_eq!(
+            AnalyzeError::MissingClass { class_obj_id: 7, obj_id: Some(9) }.to_string(),
+            "could not find class 7 for object 9"
+        );
+        assert_eq!(
+            AnalyzeError::MissingClass { class_obj_id: 7, obj_id: None }.to_string(),
+            "could not find class 7"
+        );
+
+        assert_eq!(
+            AnalyzeError::UnresolvedField { obj_id: 9, field_name: "next".to_string(), ref_id: 12 }.to_string(),
+            "field next of object 9 refers to 12, whose type could not be resolved"
+        );
+
+        assert_eq!(
+            AnalyzeError::BadSubRecord { segment: 2, position: 1, offset: Some(700), reason: "End of file".to_string() }
+                .to_string(),
+            "could not parse sub-record 1 of heap dump segment 2 at byte 700: End of file"
+        );
+
+        assert_eq!(
+            AnalyzeError::UnwritableClasses(vec!["class 7: padded".to_string(), "class 9: skipped".to_string()])
+                .to_string(),
+            "2 classes could not be written as-is: class 7: padded; class 9: skipped"
+        );
+
+        let e: AnalyzeError = std::io::Error::from(std::io::ErrorKind::BrokenPipe).into();
+        assert!(e.to_string().starts_with("could not write output: "));
+    }
+}
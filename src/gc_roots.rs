@@ -0,0 +1,97 @@
+// SPDX-License-Identifier: MIT
+// Copyright (c) 2026 Zac Policzer
+
+use jvm_hprof::heap_dump::{
+    GcRootBusyMonitor, GcRootJavaStackFrame, GcRootJniGlobal, GcRootJniLocalRef, GcRootNativeStack,
+    GcRootSystemClass, GcRootThreadBlock, GcRootThreadObj, GcRootUnknown, SubRecord,
+};
+
+/// One GC root sub-record, flattened to the fields shared across root types.
+pub struct GcRoot {
+    pub root_type: &'static str,
+    /// Rooted object id; 0 for a thread root without a thread object.
+    pub obj_id: u64,
+    pub thread_serial: Option<u32>,
+    pub frame_index: Option<u32>,
+}
+
+impl GcRoot {
+    fn new(root_type: &'static str, obj_id: u64, thread_serial: Option<u32>, frame_index: Option<u32>) -> Self {
+        GcRoot { root_type, obj_id, thread_serial, frame_index }
+    }
+}
+
+impl From<&GcRootUnknown> for GcRoot {
+    fn from(r: &GcRootUnknown) -> Self {
+        GcRoot::new("Unknown", r.obj_id().id(), None, None)
+    }
+}
+
+impl From<&GcRootThreadObj> for GcRoot {
+    fn from(r: &GcRootThreadObj) -> Self {
+        GcRoot::new(
+            "ThreadObj",
+            r.thread_obj_id().map(|id| id.id()).unwrap_or(0),
+            Some(r.thread_serial().num()),
+            None,
+        )
+    }
+}
+
+impl From<&GcRootJniGlobal> for GcRoot {
+    fn from(r: &GcRootJniGlobal) -> Self {
+        GcRoot::new("JniGlobal", r.obj_id().id(), None, None)
+    }
+}
+
+impl From<&GcRootJniLocalRef> for GcRoot {
+    fn from(r: &GcRootJniLocalRef) -> Self {
+        GcRoot::new("JniLocal", r.obj_id().id(), Some(r.thread_serial().num()), r.frame_index())
+    }
+}
+
+impl From<&GcRootJavaStackFrame> for GcRoot {
+    fn from(r: &GcRootJavaStackFrame) -> Self {
+        GcRoot::new("JavaStackFrame", r.obj_id().id(), Some(r.thread_serial().num()), r.frame_index())
+    }
+}
+
+impl From<&GcRootNativeStack> for GcRoot {
+    fn from(r: &GcRootNativeStack) -> Self {
+        GcRoot::new("NativeStack", r.obj_id().id(), Some(r.thread_serial().num()), None)
+    }
+}
+
+impl From<&GcRootSystemClass> for GcRoot {
+    fn from(r: &GcRootSystemClass) -> Self {
+        GcRoot::new("SystemClass", r.obj_id().id(), None, None)
+    }
+}
+
+impl From<&GcRootThreadBlock> for GcRoot {
+    fn from(r: &GcRootThreadBlock) -> Self {
+        GcRoot::new("ThreadBlock", r.obj_id().id(), Some(r.thread_serial().num()), None)
+    }
+}
+
+impl From<&GcRootBusyMonitor> for GcRoot {
+    fn from(r: &GcRootBusyMonitor) -> Self {
+        GcRoot::new("BusyMonitor", r.obj_id().id(), None, None)
+    }
+}
+
+/// Extract the GC root from `s`, or None if it isn't a root sub-record.
+pub fn gc_root(s: &SubRecord) -> Option<GcRoot> {
+    Some(match s {
+        SubRecord::GcRootUnknown(r) => r.into(),
+        SubRecord::GcRootThreadObj(r) => r.into(),
+        SubRecord::GcRootJniGlobal(r) => r.into(),
+        SubRecord::GcRootJniLocalRef(r) => r.into(),
+        SubRecord::GcRootJavaStackFrame(r) => r.into(),
+        SubRecord::GcRootNativeStack(r) => r.into(),
+        SubRecord::GcRootSystemClass(r) => r.into(),
+        SubRecord::GcRootThreadBlock(r) => r.into(),
+        SubRecord::GcRootBusyMonitor(r) => r.into(),
+        _ => return None,
+    })
+}
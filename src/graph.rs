@@ -0,0 +1,625 @@
+// SPDX-License-Identifier: MIT
+// Copyright (c) 2026 Zac Policzer
+
+use std::sync::Arc;
+use jvm_hprof::{Hprof, Id, Record};
+use jvm_hprof::heap_dump::{FieldValue, SubRecord};
+use rayon::prelude::*;
+use crate::gc_roots::gc_root;
+use crate::hprof_index::HprofIndex;
+use crate::skip_errors::sub_records;
+use crate::util::{array_shallow_size, id_size_bytes, instance_shallow_size, primitive_array_element_size, primitive_array_len};
+
+// ---------------------------------------------------------------------------
+// ObjectGraph — compact outbound-reference graph over every object in the dump
+// ---------------------------------------------------------------------------
+// Object ids are interned as positions in one sorted Vec<u64>, and edges are stored CSR
+// style (one offsets array, one flat targets array of u32 node indices). That keeps a
+// 200M-object, 1B-reference heap at roughly 1.6GB ids + 4GB edges, with no per-node
+// allocation. Looking a reference up is a binary search in the sorted ids.
+
+/// Fixed-size bit set over graph node indices.
+pub struct NodeSet {
+    words: Vec<u64>,
+}
+
+impl NodeSet {
+    pub fn new(len: usize) -> Self {
+        NodeSet { words: vec![0; len.div_ceil(64)] }
+    }
+
+    /// Insert `node`, returning true if it wasn't already present.
+    pub fn insert(&mut self, node: u32) -> bool {
+        let (word, bit) = (node as usize / 64, 1_u64 << (node % 64));
+        let absent = self.words[word] & bit == 0;
+        self.words[word] |= bit;
+        absent
+    }
+
+    pub fn contains(&self, node: u32) -> bool {
+        self.words[node as usize / 64] & (1_u64 << (node % 64)) != 0
+    }
+
+    pub fn count(&self) -> usize {
+        self.words.iter().map(|w| w.count_ones() as usize).sum()
+    }
+}
+
+/// Outbound edges found in one heap dump segment, CSR-style but keyed by source node.
+#[derive(Default)]
+struct SegmentEdges {
+    sources: Vec<u32>,
+    /// `targets[ends[i-1]..ends[i]]` are the successors of `sources[i]`
+    ends: Vec<usize>,
+    targets: Vec<u32>,
+    roots: Vec<u32>,
+    /// `(node, shallow size)` of every instance and array in the segment.
+    sizes: Vec<(u32, u64)>,
+}
+
+impl SegmentEdges {
+    /// Close off the targets pushed since the previous node as the successors of `source`.
+    fn finish_node(&mut self, source: u32) {
+        self.sources.push(source);
+        self.ends.push(self.targets.len());
+    }
+
+    fn nodes(&self) -> impl Iterator<Item = (u32, &[u32])> + '_ {
+        let starts = std::iter::once(0).chain(self.ends.iter().copied());
+        self.sources.iter()
+            .zip(starts.zip(self.ends.iter().copied()))
+            .map(|(&source, (start, end))| (source, &self.targets[start..end]))
+    }
+}
+
+pub struct ObjectGraph {
+    /// Sorted object ids; a node's index is its position here. Shared with the analysis
+    /// results so they can outlive the edges.
+    ids: Arc<Vec<u64>>,
+    /// `targets[offsets[n]..offsets[n + 1]]` are the successors of node `n`.
+    offsets: Vec<u64>,
+    targets: Vec<u32>,
+    roots: Vec<u32>,
+    /// Estimated shallow size per node; 0 for classes.
+    shallow_sizes: Vec<u64>,
+}
+
+impl ObjectGraph {
+    /// Build the reference graph: instance fields and the instance's class, object array
+    /// elements and the array's class, and class static fields. GC roots come from the
+    /// root sub-records. References to ids with no record in the dump are dropped.
+    pub fn build(hprof: &Hprof, index: &HprofIndex, segments: &[Record]) -> Self {
+        let mut ids: Vec<u64> = Vec::with_capacity(
+            index.obj_id_to_class_obj_id.len() + index.prim_array_obj_id_to_type.len() + index.classes.len(),
+        );
+        ids.extend(index.obj_id_to_class_obj_id.obj_ids().map(|id| id.id()));
+        ids.extend(index.prim_array_obj_id_to_type.iter().map(|e| e.key().id()));
+        ids.extend(index.classes.keys().map(|id| id.id()));
+        ids.par_sort_unstable();
+        ids.dedup();
+        assert!(ids.len() <= u32::MAX as usize, "too many objects for u32 node indices");
+
+        let mut parts: Vec<SegmentEdges> = segments.par_iter()
+            .map(|record| segment_edges(record, hprof, index, &ids))
+            .collect();
+        parts.push(static_field_edges(index, &ids));
+
+        Self::from_segment_edges(ids, parts)
+    }
+
+    fn from_segment_edges(ids: Vec<u64>, parts: Vec<SegmentEdges>) -> Self {
+        let n = ids.len();
+        let mut degree = vec![0_u64; n];
+        for part in &parts {
+            for (source, successors) in part.nodes() {
+                degree[source as usize] += successors.len() as u64;
+            }
+        }
+
+        let mut offsets = Vec::with_capacity(n + 1);
+        offsets.push(0_u64);
+        for d in &degree {
+            offsets.push(offsets.last().unwrap() + d);
+        }
+        drop(degree);
+
+        let mut cursor: Vec<u64> = offsets[..n].to_vec();
+        let mut targets = vec![0_u32; *offsets.last().unwrap() as usize];
+        let mut roots = Vec::new();
+        let mut shallow_sizes = vec![0_u64; n];
+        for part in parts {
+            for (source, successors) in part.nodes() {
+                let start = cursor[source as usize] as usize;
+                targets[start..start + successors.len()].copy_from_slice(successors);
+                cursor[source as usize] += successors.len() as u64;
+            }
+            roots.extend(part.roots);
+            for (node, size) in part.sizes {
+                shallow_sizes[node as usize] = size;
+            }
+        }
+        roots.sort_unstable();
+        roots.dedup();
+
+        ObjectGraph { ids: Arc::new(ids), offsets, targets, roots, shallow_sizes }
+    }
+
+    pub fn node_count(&self) -> usize {
+        self.ids.len()
+    }
+
+    /// Node index for an object id, if the dump has a record for it.
+    pub fn node(&self, obj_id: u64) -> Option<u32> {
+        node_of(&self.ids, obj_id)
+    }
+
+    pub fn shallow_size(&self, node: u32) -> u64 {
+        self.shallow_sizes[node as usize]
+    }
+
+    pub fn successors(&self, node: u32) -> &[u32] {
+        let start = self.offsets[node as usize] as usize;
+        let end = self.offsets[node as usize + 1] as usize;
+        &self.targets[start..end]
+    }
+
+    /// Every node reachable from a GC root (breadth-first).
+    pub fn reachable(&self) -> NodeSet {
+        let mut seen = NodeSet::new(self.node_count());
+        let mut queue: std::collections::VecDeque<u32> = std::collections::VecDeque::new();
+        for &root in &self.roots {
+            if seen.insert(root) {
+                queue.push_back(root);
+            }
+        }
+        while let Some(node) = queue.pop_front() {
+            for &next in self.successors(node) {
+                if seen.insert(next) {
+                    queue.push_back(next);
+                }
+            }
+        }
+        seen
+    }
+
+    /// Reachability queryable by object id, without holding on to the edges.
+    pub fn reachability(&self) -> Reachability {
+        Reachability { ids: self.ids.clone(), reachable: self.reachable() }
+    }
+
+    /// Nodes reachable from a GC root in DFS postorder, starting from a super-root whose
+    /// successors are the GC roots. Returns the order and each node's position in it
+    /// (`UNVISITED` for unreachable nodes).
+    fn postorder(&self) -> (Vec<u32>, Vec<u32>) {
+        let mut position = vec![UNVISITED; self.node_count()];
+        let mut order: Vec<u32> = Vec::new();
+        let mut seen = NodeSet::new(self.node_count());
+        // (node, index of the next successor to visit)
+        let mut stack: Vec<(u32, usize)> = Vec::new();
+        for &root in &self.roots {
+            if !seen.insert(root) {
+                continue;
+            }
+            stack.push((root, 0));
+            while let Some((node, next)) = stack.last_mut() {
+                let successors = self.successors(*node);
+                if let Some(&succ) = successors.get(*next) {
+                    *next += 1;
+                    if seen.insert(succ) {
+                        stack.push((succ, 0));
+                    }
+                } else {
+                    position[*node as usize] = order.len() as u32;
+                    order.push(*node);
+                    stack.pop();
+                }
+            }
+        }
+        (order, position)
+    }
+
+    /// Dominator tree of the reachable graph, rooted at a super-root joined to every GC
+    /// root, with retained sizes. Uses the iterative algorithm of Cooper, Harvey and Kennedy
+    /// ("A Simple, Fast Dominance Algorithm") over postorder numbers, so every array is
+    /// indexed by integer and the only per-edge allocation is the predecessor list.
+    pub fn dominator_tree(&self) -> DominatorTree {
+        let (order, position) = self.postorder();
+        let m = order.len();
+        let super_root = m as u32;
+
+        // Predecessors in postorder numbering, CSR style; the super-root precedes each root
+        let mut pred_offsets = vec![0_u64; m + 2];
+        for &node in &order {
+            for &succ in self.successors(node) {
+                pred_offsets[position[succ as usize] as usize + 1] += 1;
+            }
+        }
+        for &root in &self.roots {
+            pred_offsets[position[root as usize] as usize + 1] += 1;
+        }
+        let mut total = 0;
+        for offset in pred_offsets.iter_mut() {
+            total += *offset;
+            *offset = total;
+        }
+        let mut cursor = pred_offsets.clone();
+        let mut preds = vec![0_u32; pred_offsets[m + 1] as usize];
+        let mut push_pred = |to: u32, from: u32| {
+            preds[cursor[to as usize] as usize] = from;
+            cursor[to as usize] += 1;
+        };
+        for (po, &node) in order.iter().enumerate() {
+            for &succ in self.successors(node) {
+                push_pred(position[succ as usize], po as u32);
+            }
+        }
+        for &root in &self.roots {
+            push_pred(position[root as usize], super_root);
+        }
+
+        let mut idom = vec![UNVISITED; m + 1];
+        idom[m] = super_root;
+        let intersect = |idom: &[u32], mut a: u32, mut b: u32| {
+            while a != b {
+                while a < b {
+                    a = idom[a as usize];
+                }
+                while b < a {
+                    b = idom[b as usize];
+                }
+            }
+            a
+        };
+        let mut changed = true;
+        while changed {
+            changed = false;
+            // Reverse postorder, skipping the super-root
+            for b in (0..m).rev() {
+                let mut new_idom = UNVISITED;
+                for &p in &preds[pred_offsets[b] as usize..pred_offsets[b + 1] as usize] {
+                    if idom[p as usize] == UNVISITED {
+                        continue;
+                    }
+                    new_idom = if new_idom == UNVISITED { p } else { intersect(&idom, p, new_idom) };
+                }
+                if idom[b] != new_idom {
+                    idom[b] = new_idom;
+                    changed = true;
+                }
+            }
+        }
+
+        // A dominator always comes later in postorder, so one forward pass sums subtrees
+        let mut retained: Vec<u64> = order.iter().map(|&node| self.shallow_size(node)).collect();
+        retained.push(0);
+        for (b, &d) in idom[..m].iter().enumerate() {
+            retained[d as usize] += retained[b];
+        }
+
+        let mut node_idom = vec![UNVISITED; self.node_count()];
+        let mut node_retained = vec![0_u64; self.node_count()];
+        for (po, &node) in order.iter().enumerate() {
+            node_idom[node as usize] = match idom[po] {
+                d if d == super_root => SUPER_ROOT,
+                d => order[d as usize],
+            };
+            node_retained[node as usize] = retained[po];
+        }
+        DominatorTree { ids: self.ids.clone(), idom: node_idom, retained: node_retained }
+    }
+}
+
+/// Postorder position / dominator of a node not reachable from any GC root.
+const UNVISITED: u32 = u32::MAX;
+/// Dominator of a node dominated only by the synthetic super-root.
+const SUPER_ROOT: u32 = u32::MAX - 1;
+
+fn node_of(ids: &[u64], obj_id: u64) -> Option<u32> {
+    ids.binary_search(&obj_id).ok().map(|i| i as u32)
+}
+
+fn segment_edges(record: &Record, hprof: &Hprof, index: &HprofIndex, ids: &[u64]) -> SegmentEdges {
+    let id_size = hprof.header().id_size();
+    let node = |id: Id| node_of(ids, id.id());
+    let mut edges = SegmentEdges::default();
+
+    let segment = record.as_heap_dump_segment().unwrap().unwrap();
+    for p in sub_records(&index.options, segment.sub_records()) {
+        let s = p.unwrap();
+        match &s {
+            SubRecord::Instance(instance) => {
+                let Some(source) = node(instance.obj_id()) else { continue };
+                edges.targets.extend(node(instance.class_obj_id()));
+                if let Some(fds) = index.class_instance_field_descriptors.get(&instance.class_obj_id()) {
+                    edges.sizes.push((source, instance_shallow_size(fds.iter().map(|fd| fd.field_type()), id_size)));
+                    let mut input: &[u8] = instance.fields();
+                    for fd in fds {
+                        let (rest, val) = fd.field_type().parse_value(input, id_size).unwrap();
+                        input = rest;
+                        if let FieldValue::ObjectId(Some(id)) = val {
+                            edges.targets.extend(node(id));
+                        }
+                    }
+                }
+                edges.finish_node(source);
+            }
+            SubRecord::ObjectArray(oa) => {
+                let Some(source) = node(oa.obj_id()) else { continue };
+                edges.targets.extend(node(oa.array_class_obj_id()));
+                let mut length = 0;
+                for elem in oa.elements(id_size) {
+                    length += 1;
+                    if let Some(id) = elem.unwrap() {
+                        edges.targets.extend(node(id));
+                    }
+                }
+                edges.sizes.push((source, array_shallow_size(length, id_size_bytes(id_size), id_size)));
+                edges.finish_node(source);
+            }
+            SubRecord::PrimitiveArray(pa) => {
+                let Some(source) = node(pa.obj_id()) else { continue };
+                let element_size = primitive_array_element_size(pa.primitive_type());
+                edges.sizes.push((source, array_shallow_size(primitive_array_len(pa), element_size, id_size)));
+            }
+            _ => {
+                if let Some(root) = gc_root(&s) {
+                    edges.roots.extend(node_of(ids, root.obj_id));
+                }
+            }
+        }
+    }
+    edges
+}
+
+fn static_field_edges(index: &HprofIndex, ids: &[u64]) -> SegmentEdges {
+    let mut edges = SegmentEdges::default();
+    for (class_id, class) in &index.classes {
+        let Some(source) = node_of(ids, class_id.id()) else { continue };
+        for sf in &class.static_fields {
+            if let FieldValue::ObjectId(Some(id)) = sf.value() {
+                edges.targets.extend(node_of(ids, id.id()));
+            }
+        }
+        edges.finish_node(source);
+    }
+    edges
+}
+
+/// Which objects are reachable from a GC root, queryable by object id.
+pub struct Reachability {
+    ids: Arc<Vec<u64>>,
+    reachable: NodeSet,
+}
+
+impl Reachability {
+    pub fn is_reachable(&self, obj_id: u64) -> bool {
+        node_of(&self.ids, obj_id).is_some_and(|n| self.reachable.contains(n))
+    }
+
+    pub fn reachable_count(&self) -> usize {
+        self.reachable.count()
+    }
+
+    pub fn total_count(&self) -> usize {
+        self.ids.len()
+    }
+}
+
+/// Immediate dominators and retained sizes, queryable by object id.
+pub struct DominatorTree {
+    ids: Arc<Vec<u64>>,
+    /// Per node: its immediate dominator's node, `SUPER_ROOT`, or `UNVISITED` if unreachable.
+    idom: Vec<u32>,
+    /// Per node: shallow size of the node plus everything it dominates.
+    retained: Vec<u64>,
+}
+
+impl DominatorTree {
+    /// Retained size of an object, or None if it isn't reachable from a GC root.
+    pub fn retained_bytes(&self, obj_id: u64) -> Option<u64> {
+        let node = node_of(&self.ids, obj_id)? as usize;
+        (self.idom[node] != UNVISITED).then(|| self.retained[node])
+    }
+
+    /// The object that every path from the GC roots to `obj_id` passes through last, or
+    /// None if it is unreachable or only dominated by the roots as a whole.
+    pub fn immediate_dominator(&self, obj_id: u64) -> Option<u64> {
+        let node = node_of(&self.ids, obj_id)? as usize;
+        match self.idom[node] {
+            UNVISITED | SUPER_ROOT => None,
+            d => Some(self.ids[d as usize]),
+        }
+    }
+
+    /// Retained bytes per group, for `groups` groups numbered from 0 and `group_of` giving
+    /// each object's group (None leaves it out). An object counts towards its group unless
+    /// another object of the same group dominates it, since that one's retained size already
+    /// includes it: a group's total is what would be freed if every object in it went.
+    pub fn retained_by_group(&self, groups: usize, group_of: impl Fn(u64) -> Option<usize>) -> Vec<u64> {
+        let n = self.ids.len();
+        // Dominator tree children, CSR style like the graph's edges
+        let mut roots = Vec::new();
+        let mut offsets = vec![0_u32; n + 1];
+        for (node, &d) in self.idom.iter().enumerate() {
+            match d {
+                UNVISITED => {}
+                SUPER_ROOT => roots.push(node as u32),
+                d => offsets[d as usize + 1] += 1,
+            }
+        }
+        for i in 0..n {
+            offsets[i + 1] += offsets[i];
+        }
+        let mut next = offsets.clone();
+        let mut children = vec![0_u32; offsets[n] as usize];
+        for (node, &d) in self.idom.iter().enumerate() {
+            if d != UNVISITED && d != SUPER_ROOT {
+                children[next[d as usize] as usize] = node as u32;
+                next[d as usize] += 1;
+            }
+        }
+
+        // Walk the tree keeping, per group, how many of the current node's dominators are in it
+        let mut totals = vec![0_u64; groups];
+        let mut on_path = vec![0_u32; groups];
+        let mut stack: Vec<(u32, bool)> = roots.into_iter().map(|r| (r, false)).collect();
+        while let Some((node, leaving)) = stack.pop() {
+            let group = group_of(self.ids[node as usize]);
+            if leaving {
+                if let Some(g) = group {
+                    on_path[g] -= 1;
+                }
+                continue;
+            }
+            if let Some(g) = group {
+                if on_path[g] == 0 {
+                    totals[g] += self.retained[node as usize];
+                }
+                on_path[g] += 1;
+            }
+            stack.push((node, true));
+            let (start, end) = (offsets[node as usize] as usize, offsets[node as usize + 1] as usize);
+            stack.extend(children[start..end].iter().map(|c| (*c, false)));
+        }
+        totals
+    }
+
+    /// The `n` reachable objects with the largest retained size, as `(obj_id, retained)`.
+    pub fn largest(&self, n: usize) -> Vec<(u64, u64)> {
+        let mut nodes: Vec<usize> = (0..self.ids.len()).filter(|&i| self.idom[i] != UNVISITED).collect();
+        let by_size = |a: &usize, b: &usize| self.retained[*b].cmp(&self.retained[*a]).then(a.cmp(b));
+        if nodes.len() > n {
+            nodes.select_nth_unstable_by(n, by_size);
+            nodes.truncate(n);
+        }
+        nodes.sort_unstable_by(by_size);
+        nodes.into_iter().map(|i| (self.ids[i], self.retained[i])).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Graph from `(source, successors)` adjacency over `ids`, rooted at `roots`.
+    pub(crate) fn test_graph(ids: &[u64], adjacency: &[(u64, &[u64])], roots: &[u64]) -> ObjectGraph {
+        let mut ids = ids.to_vec();
+        ids.sort_unstable();
+        let mut edges = SegmentEdges::default();
+        for (source, successors) in adjacency {
+            edges.targets.extend(successors.iter().filter_map(|id| node_of(&ids, *id)));
+            edges.finish_node(node_of(&ids, *source).unwrap());
+        }
+        edges.roots = roots.iter().filter_map(|id| node_of(&ids, *id)).collect();
+        ObjectGraph::from_segment_edges(ids, vec![edges])
+    }
+
+    #[test]
+    fn test_node_set() {
+        let mut set = NodeSet::new(130);
+        assert!(set.insert(0));
+        assert!(set.insert(129));
+        assert!(!set.insert(129));
+        assert!(set.contains(129) && !set.contains(64));
+        assert_eq!(set.count(), 2);
+    }
+
+    #[test]
+    fn test_csr_adjacency() {
+        let graph = test_graph(&[30, 10, 20], &[(10, &[20, 30]), (20, &[30])], &[10]);
+        let n = |id| graph.node(id).unwrap();
+        assert_eq!(graph.node_count(), 3);
+        assert_eq!(graph.successors(n(10)), &[n(20), n(30)]);
+        assert_eq!(graph.successors(n(20)), &[n(30)]);
+        assert!(graph.successors(n(30)).is_empty());
+        assert_eq!(graph.node(15), None);
+    }
+
+    #[test]
+    fn test_reachable_and_orphan() {
+        // root 10 -> 20; 30 is referenced by nothing
+        let graph = test_graph(&[10, 20, 30], &[(10, &[20])], &[10]);
+        let reachability = graph.reachability();
+        assert!(reachability.is_reachable(10));
+        assert!(reachability.is_reachable(20));
+        assert!(!reachability.is_reachable(30));
+        assert_eq!(reachability.reachable_count(), 2);
+        assert_eq!(reachability.total_count(), 3);
+    }
+
+    #[test]
+    fn test_reachability_handles_cycles() {
+        let graph = test_graph(&[1, 2, 3, 4], &[(1, &[2]), (2, &[3]), (3, &[1]), (4, &[1])], &[2]);
+        let reachability = graph.reachability();
+        assert!(reachability.is_reachable(1) && reachability.is_reachable(3));
+        assert!(!reachability.is_reachable(4));
+    }
+
+    /// root R -> A, B; A -> C; B -> C; C -> D; D -> A, plus an orphan O. Every object is
+    /// 10 bytes, so R retains everything reachable.
+    fn diamond_graph() -> ObjectGraph {
+        let mut graph = test_graph(
+            &[1, 2, 3, 4, 5, 6],
+            &[(1, &[2, 3]), (2, &[4]), (3, &[4]), (4, &[5]), (5, &[2])],
+            &[1],
+        );
+        graph.shallow_sizes = vec![10; 6];
+        graph
+    }
+
+    #[test]
+    fn test_dominator_tree() {
+        let (r, a, b, c, d, orphan) = (1, 2, 3, 4, 5, 6);
+        let tree = diamond_graph().dominator_tree();
+        assert_eq!(tree.immediate_dominator(r), None);
+        assert_eq!(tree.immediate_dominator(a), Some(r));
+        assert_eq!(tree.immediate_dominator(b), Some(r));
+        // Reachable through both A and B, so only R dominates C
+        assert_eq!(tree.immediate_dominator(c), Some(r));
+        assert_eq!(tree.immediate_dominator(d), Some(c));
+
+        assert_eq!(tree.retained_bytes(d), Some(10));
+        assert_eq!(tree.retained_bytes(c), Some(20));
+        assert_eq!(tree.retained_bytes(a), Some(10));
+        assert_eq!(tree.retained_bytes(r), Some(50));
+        assert_eq!(tree.retained_bytes(orphan), None);
+        assert_eq!(tree.largest(2), vec![(r, 50), (c, 20)]);
+    }
+
+    #[test]
+    fn test_retained_by_group() {
+        let (r, a, b, c, d) = (1, 2, 3, 4, 5);
+        let tree = diamond_graph().dominator_tree();
+        // Classes: X = {R}, Y = {A, B}, Z = {C, D}; the orphan has no class
+        let class_of = |id: u64| match id {
+            id if id == r => Some(0),
+            id if id == a || id == b => Some(1),
+            id if id == c || id == d => Some(2),
+            _ => None,
+        };
+        // D is inside C's retained size, so Z is C's 20 bytes, not 30
+        let per_class = tree.retained_by_group(3, class_of);
+        assert_eq!(per_class, vec![50, 20, 20]);
+
+        // Y and Z in one package: neither dominates the other, so their totals add up
+        let package = tree.retained_by_group(2, |id| class_of(id).map(|class| [0, 1, 1][class]));
+        assert_eq!(package, vec![per_class[0], per_class[1] + per_class[2]]);
+
+        // X and Z in one package: R dominates C, so C isn't counted again
+        let package = tree.retained_by_group(2, |id| class_of(id).map(|class| [0, 1, 0][class]));
+        assert_eq!(package, vec![50, 20]);
+    }
+
+    #[test]
+    fn test_object_shared_by_two_roots_is_dominated_by_neither() {
+        // roots 1 and 2 both -> 3 -> 4
+        let mut graph = test_graph(&[1, 2, 3, 4], &[(1, &[3]), (2, &[3]), (3, &[4])], &[1, 2]);
+        graph.shallow_sizes = vec![1, 2, 4, 8];
+        let tree = graph.dominator_tree();
+        assert_eq!(tree.immediate_dominator(3), None);
+        assert_eq!(tree.immediate_dominator(4), Some(3));
+        assert_eq!(tree.retained_bytes(1), Some(1));
+        assert_eq!(tree.retained_bytes(2), Some(2));
+        assert_eq!(tree.retained_bytes(3), Some(12));
+    }
+}
@@ -1,11 +1,22 @@
 // SPDX-License-Identifier: MIT
 // Copyright (c) 2026 Zac Policzer
 
+use std::borrow::Cow;
 use std::collections::HashMap;
+use std::hash::BuildHasher;
 use dashmap::DashMap;
-use jvm_hprof::{Hprof, Id, LineNum, LoadClass, Record, RecordTag, EzClass, build_type_hierarchy_field_descriptors};
+use jvm_hprof::{Hprof, Id, LineNum, LoadClass, Record, RecordTag, EzClass, Utf8, build_type_hierarchy_field_descriptors};
 use jvm_hprof::heap_dump::{FieldDescriptor, PrimitiveArrayType, SubRecord};
 use rayon::prelude::*;
+use crate::analyzer::AnalyzerOptions;
+use crate::cancel::cancelled;
+use crate::error::AnalyzeError;
+use crate::id_hash::{IdBuildHasher, IdDashMap};
+use crate::obj_class_index::{ObjClassIndex, SortedObjClassesBuilder};
+use crate::progress::Progress;
+use crate::skip_errors::bad_sub_record;
+use crate::strings::StringLayout;
+use crate::util::{field_base_names, primitive_array_type_name};
 
 /// Resolved stack frame with string names (not raw IDs).
 /// Borrows from the HPROF's UTF8 string table to avoid allocations.
@@ -25,33 +36,122 @@ pub(crate) struct ResolvedStackTrace {
     pub frame_ids: Vec<u64>,
 }
 
+/// Stands in for the text of a Utf8 record whose bytes aren't valid UTF-8.
+pub(crate) const INVALID_UTF8: &str = "(invalid UTF-8)";
+
+/// What a Utf8 record whose bytes aren't valid UTF-8 becomes (`--utf8`).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Utf8Mode {
+    /// Fail with an error naming the record.
+    Strict,
+    /// Keep the valid parts, with U+FFFD for each invalid sequence, so differently broken
+    /// names usually stay distinct.
+    Lossy,
+    /// `(invalid UTF-8)` for the whole name, whatever the bytes.
+    #[default]
+    Placeholder,
+}
+
+impl Utf8Mode {
+    /// The text of `u` under this mode.
+    pub(crate) fn text<'a>(self, u: &Utf8<'a>) -> Result<&'a str, AnalyzeError> {
+        match (u.text_as_str(), self) {
+            (Ok(s), _) => Ok(s),
+            (Err(_), Utf8Mode::Strict) => Err(AnalyzeError::parse(format!(
+                "utf8 record {}: not valid UTF-8 (--utf8 lossy or placeholder reads it anyway)",
+                u.name_id()
+            ))),
+            // Invalid names are rare and have to live as long as the dump's borrowed names,
+            // so the repaired copies are leaked rather than owned by the index
+            (Err(_), Utf8Mode::Lossy) => Ok(String::from_utf8_lossy(u.text()).into_owned().leak()),
+            (Err(_), Utf8Mode::Placeholder) => Ok(INVALID_UTF8),
+        }
+    }
+}
+
+/// Everything the commands need from a first pass over the dump: the utf8 and class tables,
+/// flattened field descriptors, and obj id -> type maps. Built once by `build_with_options`,
+/// which also hands back the heap dump segment records so second passes iterate those
+/// directly instead of re-walking every top level record.
 pub(crate) struct HprofIndex<'a> {
     pub utf8: HashMap<Id, &'a str>,
     pub load_classes: HashMap<Id, LoadClass>,
     pub classes: HashMap<Id, EzClass<'a>>,
-    pub obj_id_to_class_obj_id: DashMap<Id, Id>,
-    pub prim_array_obj_id_to_type: DashMap<Id, PrimitiveArrayType>,
+    pub obj_id_to_class_obj_id: Box<dyn ObjClassIndex>,
+    pub prim_array_obj_id_to_type: IdDashMap<PrimitiveArrayType>,
     pub class_instance_field_descriptors: HashMap<Id, Vec<FieldDescriptor>>,
     /// For each class, the declaring class name for each field descriptor (parallel to class_instance_field_descriptors)
     pub class_field_declaring_classes: HashMap<Id, Vec<&'a str>>,
     pub stack_frames: Vec<ResolvedStackFrame<'a>>,
     pub stack_traces: Vec<ResolvedStackTrace>,
+    /// How java.lang.String keeps its text in this dump, if it has that class.
+    pub string_layout: Option<StringLayout>,
+    /// What it was built with, so second passes stop when the build would have.
+    pub options: AnalyzerOptions,
+    /// Segments cut short at a sub-record that didn't parse, under `options.skip_errors`.
+    pub skipped_segments: u64,
 }
 
 impl<'a> HprofIndex<'a> {
-    pub fn build(hprof: &'a Hprof<'a>) -> Self {
-        let (index, _) = Self::build_with_segments(hprof);
-        index
+    /// Whether the dump has an instance, array or class record with this object id.
+    pub fn contains_object(&self, id: Id) -> bool {
+        self.obj_id_to_class_obj_id.class_of(id).is_some()
+            || self.prim_array_obj_id_to_type.contains_key(&id)
+            || self.classes.contains_key(&id)
+    }
+
+    /// Whether `resolve_ref_type` can't name the type of non-null `id`: there is no object
+    /// with that id, or it's an instance of a class without a class record.
+    pub fn is_unresolved_ref(&self, id: Id) -> bool {
+        id.id() != 0
+            && !self.obj_id_to_class_obj_id.class_of(id).is_some_and(|c| self.classes.contains_key(&c))
+            && !self.prim_array_obj_id_to_type.contains_key(&id)
+            && !self.classes.contains_key(&id)
+    }
+
+    /// Resolve the type name for an object reference.
+    /// Returns a &str where possible to avoid allocation for the common cases
+    /// (instance, object array and primitive array references), falling back to String
+    /// only for class refs.
+    pub fn resolve_ref_type(&self, id: Id) -> Cow<'_, str> {
+        if id.id() == 0 {
+            return Cow::Borrowed("null");
+        }
+        // Most common: instance or object array → class name is a &str from the index
+        if let Some(class_obj_id) = self.obj_id_to_class_obj_id.class_of(id) {
+            if let Some(c) = self.classes.get(&class_obj_id) {
+                return Cow::Borrowed(c.name);
+            }
+        }
+        // Primitive array ref (rarer — only when a field points to a prim array)
+        if let Some(pt) = self.prim_array_obj_id_to_type.get(&id) {
+            return Cow::Borrowed(primitive_array_type_name(*pt));
+        }
+        // Class reference
+        if let Some(c) = self.classes.get(&id) {
+            return Cow::Owned(format!("class {}", c.name));
+        }
+        Cow::Borrowed("(unresolved)")
     }
 
     /// Build the index with parallel segment processing, also returning segment
-    /// Record handles for later use in Pass 2.
+    /// Record handles for later use in Pass 2. The obj id -> class map is stored and
+    /// invalid names decoded as `options` asks.
     ///
     /// Phase 1a: Quick sequential scan — collect UTF8, LoadClass, and segment
     ///           Record handles. No sub-record parsing (fast).
     /// Phase 1b: Parallel sub-record processing via rayon — inserts directly
     ///           into shared DashMaps (no merge step needed).
-    pub fn build_with_segments(hprof: &'a Hprof<'a>) -> (Self, Vec<Record<'a>>) {
+    pub fn build_with_options(hprof: &'a Hprof<'a>, options: &AnalyzerOptions) -> Result<(Self, Vec<Record<'a>>), AnalyzeError> {
+        Self::build_with_hasher::<IdBuildHasher>(hprof, options)
+    }
+
+    /// `build_with_options`, hashing the in-memory obj id -> class map with `S`, so the
+    /// hasher benchmark can time a whole index pass with each. The other maps keep `IdHasher`.
+    pub(crate) fn build_with_hasher<S: BuildHasher + Clone + Default + Send + Sync + 'static>(
+        hprof: &'a Hprof<'a>,
+        options: &AnalyzerOptions,
+    ) -> Result<(Self, Vec<Record<'a>>), AnalyzeError> {
         use std::time::Instant;
 
         // Phase 1a: Quick sequential scan of top-level records.
@@ -65,24 +165,39 @@ impl<'a> HprofIndex<'a> {
         // serial → class_obj_id mapping for resolving StackFrame.class_serial → class name
         let mut class_serial_to_obj_id: HashMap<u32, Id> = HashMap::new();
 
-        for r in hprof.records_iter().map(|r| r.unwrap()) {
+        let record_progress = Progress::start("Indexing records", None);
+        for r in hprof.records_iter() {
+            if cancelled(&options.cancel) {
+                break;
+            }
+            let r = r.map_err(|_| AnalyzeError::parse("top level record"))?;
+            if let Some(p) = &record_progress {
+                p.advance(1);
+            }
             match r.tag() {
                 RecordTag::Utf8 => {
-                    let u = r.as_utf_8().unwrap().unwrap();
-                    let s = u.text_as_str().unwrap_or("(invalid UTF-8)");
-                    utf8.insert(u.name_id(), s);
+                    let u = r.as_utf_8()
+                        .ok_or_else(|| AnalyzeError::parse("utf8 record"))?
+                        .map_err(|_| AnalyzeError::parse("utf8 record"))?;
+                    utf8.insert(u.name_id(), options.utf8.text(&u)?);
                 }
                 RecordTag::LoadClass => {
-                    let lc = r.as_load_class().unwrap().unwrap();
+                    let lc = r.as_load_class()
+                        .ok_or_else(|| AnalyzeError::parse("load class record"))?
+                        .map_err(|_| AnalyzeError::parse("load class record"))?;
                     class_serial_to_obj_id.insert(lc.class_serial().num(), lc.class_obj_id());
                     load_classes.insert(lc.class_obj_id(), lc);
                 }
                 RecordTag::StackFrame => {
-                    let sf = r.as_stack_frame().unwrap().unwrap();
+                    let sf = r.as_stack_frame()
+                        .ok_or_else(|| AnalyzeError::parse("stack frame record"))?
+                        .map_err(|_| AnalyzeError::parse("stack frame record"))?;
                     raw_stack_frames.push(sf);
                 }
                 RecordTag::StackTrace => {
-                    let st = r.as_stack_trace().unwrap().unwrap();
+                    let st = r.as_stack_trace()
+                        .ok_or_else(|| AnalyzeError::parse("stack trace record"))?
+                        .map_err(|_| AnalyzeError::parse("stack trace record"))?;
                     raw_stack_traces.push(st);
                 }
                 RecordTag::HeapDump | RecordTag::HeapDumpSegment => {
@@ -119,15 +234,18 @@ impl<'a> HprofIndex<'a> {
 
         // Stack traces are typically few (hundreds) with trivial per-item work — sequential is faster
         let stack_traces: Vec<ResolvedStackTrace> = raw_stack_traces.iter().map(|st| {
-            ResolvedStackTrace {
+            Ok(ResolvedStackTrace {
                 stack_trace_serial: st.stack_trace_serial().num(),
                 thread_serial: st.thread_serial().num(),
-                frame_ids: st.frame_ids().map(|id| id.unwrap().id()).collect(),
-            }
-        }).collect();
+                frame_ids: st.frame_ids()
+                    .map(|id| id.map(|id| id.id()).map_err(|_| AnalyzeError::parse("stack trace frame id")))
+                    .collect::<Result<_, _>>()?,
+            })
+        }).collect::<Result<_, AnalyzeError>>()?;
 
+        drop(record_progress);
         let phase1a_dur = t0.elapsed();
-        println!("  Phase 1a (sequential scan): {:.1}s — {} utf8, {} load_classes, {} segments, {} stack_frames, {} stack_traces",
+        eprintln!("  Phase 1a (sequential scan): {:.1}s — {} utf8, {} load_classes, {} segments, {} stack_frames, {} stack_traces",
             phase1a_dur.as_secs_f64(), utf8.len(), load_classes.len(), segments.len(),
             stack_frames.len(), stack_traces.len());
 
@@ -135,17 +253,55 @@ impl<'a> HprofIndex<'a> {
         // Shared concurrent maps — rayon threads insert directly, no merge needed.
         // Pre-size to reduce rehashing. Typical heap: ~200M instances, ~85M prim arrays.
         let t1 = Instant::now();
-        let obj_id_to_class_obj_id: DashMap<Id, Id> = DashMap::with_capacity(200_000_000);
-        let prim_array_obj_id_to_type: DashMap<Id, PrimitiveArrayType> = DashMap::with_capacity(100_000_000);
+        // Off-heap, each segment's pairs are sorted and spilled to disk as one run instead.
+        let off_heap = match &options.off_heap_dir {
+            Some(dir) => Some((dir, SortedObjClassesBuilder::new(dir).map_err(|e| AnalyzeError::io(dir, e))?)),
+            None => None,
+        };
+        let in_memory_capacity = if off_heap.is_some() { 0 } else { 200_000_000 };
+        let in_memory: DashMap<Id, Id, S> = DashMap::with_capacity_and_hasher(in_memory_capacity, S::default());
+        // In the same proportion, so off-heap, where memory is what's short, the primitive
+        // array map isn't pre-sized either and only grows as far as the dump needs
+        let prim_array_obj_id_to_type: IdDashMap<PrimitiveArrayType> =
+            IdDashMap::with_capacity_and_hasher(in_memory_capacity / 2, IdBuildHasher::default());
         // Classes are small (thousands, not millions), so thread-local + merge is fine.
         let classes_partial: std::sync::Mutex<HashMap<Id, EzClass<'a>>> = std::sync::Mutex::new(HashMap::new());
 
-        segments.par_iter().for_each(|r| {
+        // The label is the dump's first bytes, so a parse error's position in the file is
+        // its remaining input's address less this one
+        let dump_start = hprof.header().label().ok().map(|l| l.as_ptr() as usize);
+        let skipped_segments = std::sync::atomic::AtomicU64::new(0);
+
+        let segment_progress = Progress::start("Indexing segments", Some(segments.len() as u64));
+        segments.par_iter().enumerate().try_for_each(|(segment_num, r)| {
             let mut local_classes = HashMap::new();
+            let mut local_obj_classes: Vec<(u64, u64)> = Vec::new();
+            let mut add_obj_class = |obj_id: Id, class_obj_id: Id| {
+                if off_heap.is_some() {
+                    local_obj_classes.push((obj_id.id(), class_obj_id.id()));
+                } else {
+                    in_memory.insert(obj_id, class_obj_id);
+                }
+            };
 
-            let segment = r.as_heap_dump_segment().unwrap().unwrap();
-            for p in segment.sub_records() {
-                let s = p.unwrap();
+            let segment = r.as_heap_dump_segment().unwrap()
+                .map_err(|_| AnalyzeError::parse("heap dump segment"))?;
+            for (position, p) in segment.sub_records().enumerate() {
+                if cancelled(&options.cancel) {
+                    break;
+                }
+                let s = match p {
+                    Ok(s) => s,
+                    Err(e) => {
+                        let e = bad_sub_record(dump_start, segment_num, position, &e);
+                        if !options.skip_errors {
+                            return Err(e);
+                        }
+                        skipped_segments.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+                        eprintln!("warning: {}, skipping the rest of the segment", e);
+                        break;
+                    }
+                };
                 match s {
                     SubRecord::Class(c) => {
                         local_classes.insert(
@@ -154,12 +310,10 @@ impl<'a> HprofIndex<'a> {
                         );
                     }
                     SubRecord::Instance(instance) => {
-                        obj_id_to_class_obj_id
-                            .insert(instance.obj_id(), instance.class_obj_id());
+                        add_obj_class(instance.obj_id(), instance.class_obj_id());
                     }
                     SubRecord::ObjectArray(obj_array) => {
-                        obj_id_to_class_obj_id
-                            .insert(obj_array.obj_id(), obj_array.array_class_obj_id());
+                        add_obj_class(obj_array.obj_id(), obj_array.array_class_obj_id());
                     }
                     SubRecord::PrimitiveArray(pa) => {
                         prim_array_obj_id_to_type
@@ -172,35 +326,38 @@ impl<'a> HprofIndex<'a> {
             if !local_classes.is_empty() {
                 classes_partial.lock().unwrap().extend(local_classes);
             }
-        });
+            if let Some((dir, builder)) = &off_heap {
+                builder.add_run(local_obj_classes).map_err(|e| AnalyzeError::io(*dir, e))?;
+            }
+            if let Some(p) = &segment_progress {
+                p.advance(1);
+            }
+            Ok(())
+        })?;
+        drop(segment_progress);
+
+        let obj_id_to_class_obj_id: Box<dyn ObjClassIndex> = match off_heap {
+            Some((dir, builder)) => Box::new(builder.finish().map_err(|e| AnalyzeError::io(dir, e))?),
+            None => Box::new(in_memory),
+        };
 
         let classes = classes_partial.into_inner().unwrap();
         let phase1b_dur = t1.elapsed();
-        println!("  Phase 1b (parallel index + DashMap): {:.1}s — {} classes, {} obj mappings, {} prim mappings",
+        eprintln!("  Phase 1b (parallel index + DashMap): {:.1}s — {} classes, {} obj mappings, {} prim mappings",
             phase1b_dur.as_secs_f64(), classes.len(), obj_id_to_class_obj_id.len(), prim_array_obj_id_to_type.len());
 
-        // Finalize: build field descriptors and declaring class maps
+        // Finalize: build field descriptors and declaring class maps. The declaring classes
+        // come first, since they fail on a missing superclass that the field descriptors
+        // would panic on.
         let t2 = Instant::now();
+        let class_field_declaring_classes = field_declaring_classes(&classes)?;
         let class_instance_field_descriptors = build_type_hierarchy_field_descriptors(&classes);
-
-        let mut class_field_declaring_classes: HashMap<Id, Vec<&str>> = HashMap::new();
-        for (id, mc) in &classes {
-            let mut declaring_classes = Vec::new();
-            for _ in &mc.instance_field_descriptors {
-                declaring_classes.push(mc.name);
-            }
-            let mut opt_scid = mc.super_class_obj_id;
-            while let Some(scid) = opt_scid {
-                let sc = classes.get(&scid).expect("Could not find superclass");
-                for _ in &sc.instance_field_descriptors {
-                    declaring_classes.push(sc.name);
-                }
-                opt_scid = sc.super_class_obj_id;
-            }
-            class_field_declaring_classes.insert(*id, declaring_classes);
-        }
+        let string_layout = StringLayout::detect(
+            classes.iter().map(|(id, c)| (*id, c.name)),
+            |id| class_instance_field_descriptors.get(&id).map(|fds| field_base_names(fds, &utf8)),
+        );
         let finalize_dur = t2.elapsed();
-        println!("  Phase 1c (finalize): {:.1}s", finalize_dur.as_secs_f64());
+        eprintln!("  Phase 1c (finalize): {:.1}s", finalize_dur.as_secs_f64());
 
         let index = HprofIndex {
             utf8,
@@ -212,14 +369,41 @@ impl<'a> HprofIndex<'a> {
             class_field_declaring_classes,
             stack_frames,
             stack_traces,
+            string_layout,
+            options: options.clone(),
+            skipped_segments: skipped_segments.into_inner(),
         };
-        (index, segments)
+        Ok((index, segments))
+    }
+}
+
+/// For each class, the class declaring each of its instance fields, in the order of
+/// `build_type_hierarchy_field_descriptors`: its own fields, then each superclass's. A
+/// superclass without a class dump record is `AnalyzeError::MissingClass`.
+fn field_declaring_classes<'a>(classes: &HashMap<Id, EzClass<'a>>) -> Result<HashMap<Id, Vec<&'a str>>, AnalyzeError> {
+    let mut class_field_declaring_classes = HashMap::new();
+    for (id, mc) in classes {
+        let mut declaring_classes = Vec::new();
+        for _ in &mc.instance_field_descriptors {
+            declaring_classes.push(mc.name);
+        }
+        let mut opt_scid = mc.super_class_obj_id;
+        while let Some(scid) = opt_scid {
+            let sc = classes.get(&scid).ok_or(AnalyzeError::MissingClass { class_obj_id: scid.id(), obj_id: None })?;
+            for _ in &sc.instance_field_descriptors {
+                declaring_classes.push(sc.name);
+            }
+            opt_scid = sc.super_class_obj_id;
+        }
+        class_field_declaring_classes.insert(*id, declaring_classes);
     }
+    Ok(class_field_declaring_classes)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::hprof_builder::{HprofBuilder, Segment, Value, TYPE_INT};
 
     #[test]
     fn test_resolved_stack_frame_fields() {
@@ -280,4 +464,59 @@ mod tests {
         };
         assert!(trace.frame_ids.is_empty());
     }
+
+    /// Class 100 `com/example/Point` with one `int x`, instance 500 of it and int[] 700.
+    fn point_dump() -> Vec<u8> {
+        let mut segment = Segment::default();
+        segment
+            .class(100, 0, 4, &[], &[(2, TYPE_INT)])
+            .instance(500, 100, &[Value::Int(7)])
+            .int_array(700, &[9]);
+        HprofBuilder::new()
+            .utf8(2, "x")
+            .named_class(1, 100, "com/example/Point")
+            .heap_dump_segment(&segment)
+            .finish()
+    }
+
+    #[test]
+    fn test_index_maps_for_fixture() {
+        let dump = point_dump();
+        let hprof = jvm_hprof::parse_hprof(&dump[..]).unwrap();
+        let (index, segments) = HprofIndex::build_with_options(&hprof, &AnalyzerOptions::default()).unwrap();
+        let (class_id, instance_id, array_id) = (Id::from(100_u64), Id::from(500_u64), Id::from(700_u64));
+
+        assert_eq!(segments.len(), 1);
+        // named_class gives the name utf8 id 10_000 + the class id
+        assert_eq!(index.utf8.get(&Id::from(10_100_u64)), Some(&"com/example/Point"));
+        assert_eq!(index.utf8.get(&Id::from(2_u64)), Some(&"x"));
+        assert!(index.load_classes.contains_key(&class_id));
+        assert_eq!(index.classes[&class_id].name, "com/example/Point");
+        assert_eq!(index.class_instance_field_descriptors[&class_id].len(), 1);
+        assert_eq!(index.class_field_declaring_classes[&class_id], vec!["com/example/Point"]);
+
+        assert_eq!(index.obj_id_to_class_obj_id.class_of(instance_id), Some(class_id));
+        assert_eq!(index.obj_id_to_class_obj_id.len(), 1);
+        assert!(matches!(index.prim_array_obj_id_to_type.get(&array_id).as_deref(), Some(PrimitiveArrayType::Int)));
+        assert_eq!(index.resolve_ref_type(instance_id), "com/example/Point");
+        assert_eq!(index.resolve_ref_type(array_id), "int[]");
+        assert!(index.contains_object(class_id) && !index.contains_object(Id::from(501_u64)));
+        assert!(!index.is_unresolved_ref(instance_id) && !index.is_unresolved_ref(Id::from(0_u64)));
+        assert!(index.is_unresolved_ref(Id::from(501_u64)));
+    }
+
+    #[test]
+    fn test_missing_superclass_is_an_error() {
+        // Point (100) extends class 50, which has no class dump record
+        let mut segment = Segment::default();
+        segment.class(100, 50, 4, &[], &[(2, TYPE_INT)]);
+        let dump = HprofBuilder::new()
+            .utf8(2, "x")
+            .named_class(1, 100, "com/example/Point")
+            .heap_dump_segment(&segment)
+            .finish();
+        let hprof = jvm_hprof::parse_hprof(&dump[..]).unwrap();
+        let err = HprofIndex::build_with_options(&hprof, &AnalyzerOptions::default()).err().unwrap();
+        assert!(matches!(err, AnalyzeError::MissingClass { class_obj_id: 50, obj_id: None }), "{}", err);
+    }
 }
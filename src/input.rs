@@ -0,0 +1,300 @@
+// SPDX-License-Identifier: MIT
+// Copyright (c) 2026 Zac Policzer
+
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read};
+use std::path::{Path, PathBuf};
+use flate2::read::MultiGzDecoder;
+use memmap2::{Mmap, MmapOptions};
+use xz2::read::XzDecoder;
+use crate::error::AnalyzeError;
+
+const GZIP_MAGIC: &[u8] = &[0x1f, 0x8b];
+const XZ_MAGIC: &[u8] = &[0xfd, b'7', b'z', b'X', b'Z', 0x00];
+/// `-f -` reads the dump from stdin.
+const STDIN_PATH: &str = "-";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum DumpCompression {
+    Gzip,
+    Xz,
+}
+
+impl DumpCompression {
+    fn from_magic(head: &[u8]) -> Option<Self> {
+        if head.starts_with(GZIP_MAGIC) {
+            Some(DumpCompression::Gzip)
+        } else if head.starts_with(XZ_MAGIC) {
+            Some(DumpCompression::Xz)
+        } else {
+            None
+        }
+    }
+
+    fn from_extension(path: &Path) -> Option<Self> {
+        match path.extension()?.to_str()? {
+            "gz" => Some(DumpCompression::Gzip),
+            "xz" => Some(DumpCompression::Xz),
+            _ => None,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            DumpCompression::Gzip => "gzip",
+            DumpCompression::Xz => "xz",
+        }
+    }
+
+    fn decoder<'a>(self, input: impl Read + 'a) -> Box<dyn Read + 'a> {
+        match self {
+            DumpCompression::Gzip => Box::new(MultiGzDecoder::new(input)),
+            DumpCompression::Xz => Box::new(XzDecoder::new_multi_decoder(input)),
+        }
+    }
+}
+
+/// Where a compressed dump is decompressed to: the system temp dir, named after the input
+/// so concurrent runs on different dumps don't collide.
+fn decompressed_path(path: &Path) -> PathBuf {
+    let stem = path.file_stem().map_or("heap".into(), |s| s.to_string_lossy());
+    std::env::temp_dir().join(format!("heapdumpstardiver-{}-{}", std::process::id(), stem))
+}
+
+/// Copy `reader` into a temp file named after `path` and map that. The file is unlinked once
+/// mapped, so on Unix the space is released when the process exits. `read_failed` describes
+/// an error reading `reader`.
+fn map_via_temp_file(
+    path: &Path,
+    mut reader: impl Read,
+    read_failed: impl FnOnce(io::Error) -> AnalyzeError,
+) -> Result<Mmap, AnalyzeError> {
+    let out_path = decompressed_path(path);
+    let out = File::create(&out_path).map_err(|e| AnalyzeError::io(&out_path, e))?;
+    let copied = io::copy(&mut reader, &mut BufWriter::new(&out));
+    let mapped = copied
+        .map_err(read_failed)
+        .and_then(|_| unsafe { MmapOptions::new().map(&out) }.map_err(|e| AnalyzeError::io(&out_path, e)));
+    let _ = std::fs::remove_file(&out_path);
+    mapped
+}
+
+/// Decompress `path` into a temp file and map that.
+fn map_decompressed(path: &Path, file: File, compression: DumpCompression) -> Result<Mmap, AnalyzeError> {
+    eprintln!("Decompressing {} ({}) to {}", path.display(), compression.name(), decompressed_path(path).display());
+    map_via_temp_file(path, compression.decoder(BufReader::new(file)), |e| AnalyzeError::NotHprof {
+        path: path.to_path_buf(),
+        reason: format!("{} decompression failed: {}", compression.name(), e),
+    })
+}
+
+/// Copy a dump streamed on `input` into a temp file and map that, since a pipe can't be
+/// mapped. With no file name to go by, a gzip or xz stream is recognised by its magic bytes
+/// and decompressed on the way.
+fn map_stream(mut input: impl Read) -> Result<Mmap, AnalyzeError> {
+    // Names the temp file and errors
+    let path = Path::new("stdin");
+    let mut head = Vec::with_capacity(XZ_MAGIC.len());
+    input.by_ref().take(XZ_MAGIC.len() as u64).read_to_end(&mut head).map_err(|e| AnalyzeError::io(path, e))?;
+    if head.is_empty() {
+        return Err(AnalyzeError::NotHprof { path: path.to_path_buf(), reason: "it is empty".to_string() });
+    }
+
+    let stream = io::Cursor::new(head.clone()).chain(input);
+    match DumpCompression::from_magic(&head) {
+        Some(compression) => {
+            eprintln!("Decompressing stdin ({}) to {}", compression.name(), decompressed_path(path).display());
+            map_via_temp_file(path, compression.decoder(BufReader::new(stream)), |e| AnalyzeError::NotHprof {
+                path: path.to_path_buf(),
+                reason: format!("{} decompression failed: {}", compression.name(), e),
+            })
+        }
+        None => map_via_temp_file(path, stream, |e| AnalyzeError::io(path, e)),
+    }
+}
+
+/// Tell the kernel the map will be read front to back, so it reads ahead aggressively
+/// instead of faulting pages in a few at a time. It's only a hint, so a failure is ignored.
+fn advise_sequential(map: &Mmap) {
+    #[cfg(unix)]
+    if let Err(e) = map.advise(memmap2::Advice::Sequential) {
+        log::debug!("madvise(MADV_SEQUENTIAL) failed: {}", e);
+    }
+    #[cfg(not(unix))]
+    let _ = map;
+}
+
+/// Map a heap dump into memory. `.gz` and `.xz` files (or any gzip/xz file when
+/// `force_decompress` is set) are decompressed to a temp file first, since `parse_hprof`
+/// needs the whole dump as one slice. A `path` of `-` reads the dump from stdin. Every pass
+/// scans the dump in order, so the map is advised as sequential on Unix.
+pub fn map_heap_dump(path: &Path, force_decompress: bool) -> Result<Mmap, AnalyzeError> {
+    let map = map_dump(path, force_decompress)?;
+    advise_sequential(&map);
+    Ok(map)
+}
+
+fn map_dump(path: &Path, force_decompress: bool) -> Result<Mmap, AnalyzeError> {
+    if path == Path::new(STDIN_PATH) {
+        return map_stream(io::stdin().lock());
+    }
+    let mut file = File::open(path).map_err(|e| AnalyzeError::io(path, e))?;
+
+    // Mapping an empty file fails with an unhelpful OS error
+    let file_len = file.metadata().map_err(|e| AnalyzeError::io(path, e))?.len();
+    if file_len == 0 {
+        return Err(AnalyzeError::NotHprof { path: path.to_path_buf(), reason: "file is empty".to_string() });
+    }
+
+    let mut head = [0u8; 6];
+    let head_len = file.read(&mut head).map_err(|e| AnalyzeError::io(path, e))?;
+    let magic = DumpCompression::from_magic(&head[..head_len]);
+    let compression = DumpCompression::from_extension(path).or(magic.filter(|_| force_decompress));
+
+    match compression {
+        Some(compression) => {
+            let file = File::open(path).map_err(|e| AnalyzeError::io(path, e))?;
+            map_decompressed(path, file, compression)
+        }
+        None if force_decompress => Err(AnalyzeError::NotHprof {
+            path: path.to_path_buf(),
+            reason: "--decompress given but the file is neither gzip nor xz".to_string(),
+        }),
+        None => match magic {
+            Some(found) => Err(AnalyzeError::NotHprof {
+                path: path.to_path_buf(),
+                reason: format!("file is {}-compressed; pass --decompress", found.name()),
+            }),
+            None => unsafe { MmapOptions::new().map(&file) }.map_err(|e| AnalyzeError::io(path, e)),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    const DUMP: &[u8] = b"JAVA PROFILE 1.0.2\0not really a heap dump, but enough bytes to round trip";
+
+    fn temp_file(name: &str, contents: &[u8]) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("heapdumpstardiver-input-{}-{}", std::process::id(), name));
+        std::fs::write(&path, contents).unwrap();
+        path
+    }
+
+    fn gzip(bytes: &[u8]) -> Vec<u8> {
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder.write_all(bytes).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    fn xz(bytes: &[u8]) -> Vec<u8> {
+        let mut encoder = xz2::write::XzEncoder::new(Vec::new(), 6);
+        encoder.write_all(bytes).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    #[test]
+    fn test_detect_compression() {
+        assert_eq!(DumpCompression::from_magic(&gzip(DUMP)), Some(DumpCompression::Gzip));
+        assert_eq!(DumpCompression::from_magic(&xz(DUMP)), Some(DumpCompression::Xz));
+        assert_eq!(DumpCompression::from_magic(DUMP), None);
+        assert_eq!(DumpCompression::from_extension(Path::new("heap.hprof.gz")), Some(DumpCompression::Gzip));
+        assert_eq!(DumpCompression::from_extension(Path::new("heap.hprof.xz")), Some(DumpCompression::Xz));
+        assert_eq!(DumpCompression::from_extension(Path::new("heap.hprof")), None);
+    }
+
+    #[test]
+    fn test_map_heap_dump_decompresses_by_extension() {
+        for (name, bytes) in [("a.hprof.gz", gzip(DUMP)), ("b.hprof.xz", xz(DUMP)), ("c.hprof", DUMP.to_vec())] {
+            let path = temp_file(name, &bytes);
+            assert_eq!(&map_heap_dump(&path, false).unwrap()[..], DUMP, "{}", name);
+            std::fs::remove_file(&path).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_map_heap_dump_needs_decompress_without_extension() {
+        let path = temp_file("renamed.hprof", &gzip(DUMP));
+        let err = map_heap_dump(&path, false).unwrap_err().to_string();
+        assert!(err.ends_with("(file is gzip-compressed; pass --decompress)"), "{}", err);
+        assert_eq!(&map_heap_dump(&path, true).unwrap()[..], DUMP);
+        std::fs::remove_file(&path).unwrap();
+
+        let plain = temp_file("plain.hprof", DUMP);
+        assert!(map_heap_dump(&plain, true).unwrap_err().to_string().contains("neither gzip nor xz"));
+        std::fs::remove_file(&plain).unwrap();
+    }
+
+    #[test]
+    fn test_map_stream_plain_and_compressed() {
+        assert_eq!(&map_stream(DUMP).unwrap()[..], DUMP);
+        assert_eq!(&map_stream(&gzip(DUMP)[..]).unwrap()[..], DUMP);
+        assert_eq!(&map_stream(&xz(DUMP)[..]).unwrap()[..], DUMP);
+        // Shorter than the magic bytes still round trips
+        assert_eq!(&map_stream(&b"JA"[..]).unwrap()[..], b"JA");
+    }
+
+    #[test]
+    fn test_map_stream_rejects_empty_stdin() {
+        let err = map_stream(&b""[..]).unwrap_err().to_string();
+        assert_eq!(err, "stdin is not a valid hprof file (it is empty)");
+    }
+
+    /// Drop `file`'s pages from the page cache, so the next read of it comes from disk.
+    /// Whether that worked; pages another process has mapped stay.
+    #[cfg(target_os = "linux")]
+    fn evict_page_cache(file: &File) -> bool {
+        use std::os::unix::io::AsRawFd;
+        unsafe { libc::posix_fadvise(file.as_raw_fd(), 0, 0, libc::POSIX_FADV_DONTNEED) == 0 }
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    fn evict_page_cache(_file: &File) -> bool {
+        false
+    }
+
+    /// Time a full read of the dump at `$HDSD_BENCH_DUMP` without and then with the
+    /// sequential hint, each from a cold page cache. Off Linux the cache can't be dropped
+    /// from here, so the second read is likely served from memory. Run with
+    /// `HDSD_BENCH_DUMP=heap.hprof cargo test --release bench_sequential_advice -- --ignored --nocapture`.
+    #[test]
+    #[ignore]
+    fn bench_sequential_advice() {
+        use std::time::Instant;
+
+        let path = PathBuf::from(std::env::var("HDSD_BENCH_DUMP").expect("set HDSD_BENCH_DUMP to a heap dump"));
+        for advise in [false, true] {
+            let file = File::open(&path).unwrap();
+            if !evict_page_cache(&file) {
+                println!("could not drop {} from the page cache; this read may not touch the disk", path.display());
+            }
+            let map = unsafe { MmapOptions::new().map(&file) }.unwrap();
+            if advise {
+                advise_sequential(&map);
+            }
+
+            let t = Instant::now();
+            // Touch one byte per page, the way a scan faults the file in
+            let checksum: u64 = map.iter().step_by(4096).map(|&b| u64::from(b)).sum();
+            let secs = t.elapsed().as_secs_f64();
+            println!(
+                "{} advice: read {:.1} GiB in {:.2}s ({:.0} MiB/s, checksum {})",
+                if advise { "sequential" } else { "no" },
+                map.len() as f64 / (1u64 << 30) as f64,
+                secs,
+                map.len() as f64 / (1u64 << 20) as f64 / secs,
+                checksum,
+            );
+        }
+    }
+
+    #[test]
+    fn test_map_heap_dump_rejects_empty_file() {
+        let path = temp_file("empty.hprof", b"");
+        let err = map_heap_dump(&path, false).unwrap_err().to_string();
+        assert!(err.ends_with("is not a valid hprof file (file is empty)"), "{}", err);
+        std::fs::remove_file(&path).unwrap();
+    }
+}
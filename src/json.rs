@@ -0,0 +1,37 @@
+// SPDX-License-Identifier: MIT
+// Copyright (c) 2026 Zac Policzer
+
+//! JSON string literals, shared by the JSON object output and the schema sidecar files.
+
+use std::io::Write;
+use crate::error::AnalyzeError;
+
+/// Write `s` as a JSON string literal.
+pub(crate) fn write_json_str(out: &mut impl Write, s: &str) -> Result<(), AnalyzeError> {
+    write!(out, "\"")?;
+    for c in s.chars() {
+        match c {
+            '"' => write!(out, "\\\"")?,
+            '\\' => write!(out, "\\\\")?,
+            '\n' => write!(out, "\\n")?,
+            '\r' => write!(out, "\\r")?,
+            '\t' => write!(out, "\\t")?,
+            c if (c as u32) < 0x20 => write!(out, "\\u{:04x}", c as u32)?,
+            c => write!(out, "{}", c)?,
+        }
+    }
+    write!(out, "\"")?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_json_str_escapes_control_characters() {
+        let mut out = Vec::new();
+        write_json_str(&mut out, "a\\b\t\u{1}é").unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "\"a\\\\b\\t\\u0001é\"");
+    }
+}
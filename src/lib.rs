@@ -0,0 +1,43 @@
+// SPDX-License-Identifier: MIT
+// Copyright (c) 2026 Zac Policzer
+
+//! Heap dump analysis as a library. [`HeapDumpAnalyzer`] wraps a parsed hprof file and
+//! answers queries from one shared index; [`commands`] holds the CLI subcommands, which
+//! print their results.
+
+mod analyzer;
+mod avro;
+mod boxed;
+mod cancel;
+pub mod commands;
+mod edges;
+mod error;
+mod gc_roots;
+mod graph;
+mod hprof_index;
+mod id_hash;
+mod input;
+mod json;
+mod obj_class_index;
+mod prim_arrays;
+mod progress;
+mod skip_errors;
+mod strings;
+mod unresolved;
+mod util;
+mod visit;
+
+// The integration tests' dump builder, for unit tests that need a small dump in memory
+#[cfg(test)]
+#[path = "../tests/support/hprof_builder.rs"]
+mod hprof_builder;
+
+pub use analyzer::{AnalyzerOptions, HeapDumpAnalyzer, ResolvedInstance, ResolvedValue};
+pub use cancel::cancel_after;
+pub use commands::{record_totals, ClassStat, RecordTotals};
+pub use error::AnalyzeError;
+pub use hprof_index::Utf8Mode;
+pub use input::map_heap_dump;
+pub use progress::enable_progress;
+pub use util::{check_hprof_header, parse_compression, OutputFormat, ReferenceEncoding, WriterSettings, COMPRESSION_CODECS};
+pub use visit::{visit_segment, visit_subrecords, SubRecordVisitor};
@@ -1,36 +1,609 @@
 // SPDX-License-Identifier: MIT
 // Copyright (c) 2026 Zac Policzer
 
-mod commands;
-mod hprof_index;
-mod util;
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::AtomicBool;
+use std::sync::Arc;
+use std::time::Duration;
+use jvm_hprof::{parse_hprof, Hprof};
+use heap_dump_star_diver::{
+    cancel_after, check_hprof_header, commands, enable_progress,
+    map_heap_dump, parse_compression, AnalyzeError,
+    AnalyzerOptions, HeapDumpAnalyzer, OutputFormat, ReferenceEncoding, Utf8Mode, WriterSettings, COMPRESSION_CODECS,
+};
 
-use std::fs;
-use jvm_hprof::parse_hprof;
+/// Exit status when `--timeout` cut the run short, as `timeout(1)` uses.
+const TIMED_OUT_STATUS: i32 = 124;
 
 fn main() {
-    let app = clap::Command::new("Analyze Hprof")
+    if let Err(e) = run() {
+        eprintln!("error: {}", e);
+        if let AnalyzeError::BadSubRecord { .. } = e {
+            eprintln!("--skip-errors reads past it, skipping the rest of that segment");
+        }
+        let status = match e {
+            AnalyzeError::Cancelled => TIMED_OUT_STATUS,
+            _ => 1,
+        };
+        std::process::exit(status);
+    }
+}
+
+/// The full command line definition, shared by argument parsing and `completions`.
+fn build_cli() -> clap::Command {
+    let cli = clap::Command::new("Analyze Hprof")
         .arg(
             clap::Arg::new("file")
                 .short('f')
                 .long("file")
-                .required(true)
                 .value_name("FILE")
-                .help("Heap dump file to read"),
+                .help("Heap dump file to read, or - for stdin; .gz and .xz files are decompressed to a temp file first"),
+        )
+        .arg(
+            clap::Arg::new("verbose")
+                .short('v')
+                .long("verbose")
+                .global(true)
+                .action(clap::ArgAction::SetTrue)
+                .help("Log debug detail to stderr, such as every parquet file written with its rows and size. RUST_LOG overrides this"),
+        )
+        .arg(
+            clap::Arg::new("progress")
+                .long("progress")
+                .global(true)
+                .action(clap::ArgAction::SetTrue)
+                .help("Show a progress counter on stderr while indexing and writing: records, then segments with a percentage"),
+        )
+        .arg(
+            clap::Arg::new("skip-errors")
+                .long("skip-errors")
+                .global(true)
+                .action(clap::ArgAction::SetTrue)
+                .help("Warn about and skip past sub-records that don't parse, e.g. in a dump cut short by a JVM crash"),
+        )
+        .arg(
+            clap::Arg::new("fail-on-unresolved")
+                .long("fail-on-unresolved")
+                .global(true)
+                .action(clap::ArgAction::SetTrue)
+                .help("Fail on the first reference whose type can't be resolved (dump-objects, dump-objects-to-parquet) instead of labeling and counting it"),
+        )
+        .arg(
+            clap::Arg::new("sample")
+                .long("sample")
+                .global(true)
+                .value_name("N")
+                .value_parser(clap::value_parser!(usize))
+                .help("Only process the first N instances and arrays (dump-objects, dump-objects-to-parquet). The class index is still built in full"),
+        )
+        .arg(
+            clap::Arg::new("id-format")
+                .long("id-format")
+                .global(true)
+                .value_name("FORMAT")
+                .value_parser(["dec", "hex"])
+                .default_value("dec")
+                .help("Print object ids in decimal or as 0x-prefixed hex (dump-objects, lookup-object). JSON and parquet ids stay numeric"),
+        )
+        .arg(
+            clap::Arg::new("utf8")
+                .long("utf8")
+                .global(true)
+                .value_name("MODE")
+                .value_parser(["strict", "lossy", "placeholder"])
+                .default_value("placeholder")
+                .help("Names that aren't valid UTF-8 (dump-objects, dump-objects-to-parquet): fail, replace the bad bytes with U+FFFD, or show as \"(invalid UTF-8)\""),
+        )
+        .arg(
+            clap::Arg::new("timeout")
+                .long("timeout")
+                .global(true)
+                .value_name("SECONDS")
+                .value_parser(clap::value_parser!(u64))
+                .help("Stop reading the dump after this long, close the files written so far and exit with status 124"),
+        )
+        .arg(
+            clap::Arg::new("decompress")
+                .long("decompress")
+                .action(clap::ArgAction::SetTrue)
+                .help("Decompress a gzip or xz file whatever its extension"),
+        )
+        .arg(
+            clap::Arg::new("compression")
+                .long("compression")
+                .global(true)
+                .value_name("CODEC")
+                .value_parser(COMPRESSION_CODECS)
+                .default_value("snappy")
+                .help("Codec for every parquet file written"),
+        )
+        .arg(
+            clap::Arg::new("compression-level")
+                .long("compression-level")
+                .global(true)
+                .value_name("LEVEL")
+                .value_parser(clap::value_parser!(u32))
+                .help("Compression level for gzip (0-10) or zstd (1-22)"),
+        )
+        .arg(
+            clap::Arg::new("row-group-size")
+                .long("row-group-size")
+                // dump-objects-to-parquet's old --flush-rows, which never did anything
+                .alias("flush-rows")
+                .global(true)
+                .value_name("ROWS")
+                .value_parser(clap::value_parser!(u64).range(1..))
+                .help("Most rows per parquet row group (default 1048576)"),
+        )
+        .arg(
+            clap::Arg::new("bloom-filter")
+                .long("bloom-filter")
+                .global(true)
+                .action(clap::ArgAction::SetTrue)
+                .help("Write parquet bloom filters on object id columns, for fast lookups by id"),
+        )
+        .arg(
+            clap::Arg::new("emit-schema-json")
+                .long("emit-schema-json")
+                .global(true)
+                .action(clap::ArgAction::SetTrue)
+                .help("Also write each output file's schema (field names, Arrow types, nullability) to <name>.schema.json"),
         )
         .subcommand(clap::Command::new("dump-objects")
-            .about("Display Object (and other associated) heap dump subrecords to stdout"))
+            .about("Display Object (and other associated) heap dump subrecords to stdout")
+            .arg(
+                clap::Arg::new("class")
+                    .long("class")
+                    .value_name("NAME")
+                    .conflicts_with("class-regex")
+                    .help("Only print instances, arrays and the class record of this fully-qualified class (e.g. java.lang.String)"),
+            )
+            .arg(
+                clap::Arg::new("class-regex")
+                    .long("class-regex")
+                    .value_name("REGEX")
+                    .value_parser(|s: &str| regex::Regex::new(s).map_err(|e| e.to_string()))
+                    .help("Only print classes whose fully-qualified name matches this regex. The full index pass still runs first"),
+            )
+            .arg(
+                clap::Arg::new("limit")
+                    .long("limit")
+                    .value_name("N")
+                    .value_parser(clap::value_parser!(usize))
+                    .help("Stop after printing N objects (classes, instances and arrays that pass the filter)"),
+            )
+            .arg(
+                clap::Arg::new("skip")
+                    .long("skip")
+                    .value_name("M")
+                    .value_parser(clap::value_parser!(usize))
+                    .default_value("0")
+                    .help("Skip the first M matching objects before printing"),
+            )
+            .arg(
+                clap::Arg::new("max-array-elements")
+                    .long("max-array-elements")
+                    .value_name("K")
+                    .value_parser(clap::value_parser!(usize))
+                    .default_value("256")
+                    .help("Print at most K elements of each array, then `... (<n> more)`. 0 prints every element"),
+            )
+            .arg(
+                clap::Arg::new("format")
+                    .long("format")
+                    .value_name("FORMAT")
+                    .value_parser(["text", "json"])
+                    .default_value("text")
+                    .help("Print objects as indented text or as one JSON object per line"),
+            )
+            .arg(
+                clap::Arg::new("decode-char-arrays")
+                    .long("decode-char-arrays")
+                    .action(clap::ArgAction::SetTrue)
+                    .help("Print each char[] as the string it spells instead of its UTF-16 code units (text format)"),
+            )
+            .arg(
+                clap::Arg::new("byte-array-format")
+                    .long("byte-array-format")
+                    .value_name("FORMAT")
+                    .value_parser(["hex", "hexdump", "ascii", "base64"])
+                    .default_value("hex")
+                    .help("Print byte[] as hex bytes, hexdump -C lines, an ASCII string or base64 (text format)"),
+            )
+            .arg(
+                clap::Arg::new("expand-depth")
+                    .long("expand-depth")
+                    .value_name("K")
+                    .value_parser(clap::value_parser!(usize))
+                    .default_value("0")
+                    .help("Also print the fields of referenced instances, nested up to K levels. Each object is opened once per printed instance, so cycles terminate (text format)"),
+            ))
+        .subcommand(clap::Command::new("dump-objects-to-ndjson")
+            .about("Write every instance and array as one JSON object per line, to a file or stdout")
+            .arg(
+                clap::Arg::new("class")
+                    .long("class")
+                    .value_name("NAME")
+                    .conflicts_with("class-regex")
+                    .help("Only write instances and arrays of this fully-qualified class (e.g. java.lang.String)"),
+            )
+            .arg(
+                clap::Arg::new("class-regex")
+                    .long("class-regex")
+                    .value_name("REGEX")
+                    .value_parser(|s: &str| regex::Regex::new(s).map_err(|e| e.to_string()))
+                    .help("Only write instances and arrays of classes whose fully-qualified name matches this regex"),
+            )
+            .arg(
+                clap::Arg::new("out")
+                    .long("out")
+                    .value_name("FILE")
+                    .value_parser(clap::value_parser!(PathBuf))
+                    .help("File to write (truncated if it exists) instead of stdout"),
+            ))
+        .subcommand(clap::Command::new("dump-strings")
+            .about("Display the text of every java.lang.String as `obj_id<TAB>value`")
+            .arg(
+                clap::Arg::new("min-length")
+                    .long("min-length")
+                    .value_name("N")
+                    .value_parser(clap::value_parser!(usize))
+                    .default_value("0")
+                    .help("Only include strings with at least N characters"),
+            )
+            .arg(
+                clap::Arg::new("sort")
+                    .long("sort")
+                    .action(clap::ArgAction::SetTrue)
+                    .help("Sort by string value (duplicates end up adjacent) instead of object id"),
+            )
+            .arg(
+                clap::Arg::new("to-parquet")
+                    .long("to-parquet")
+                    .value_name("DIR")
+                    .num_args(0..=1)
+                    .default_missing_value("parquet")
+                    .value_parser(clap::value_parser!(PathBuf))
+                    .help("Write DIR/strings.parquet (obj_id, length, value) instead of printing. DIR defaults to parquet"),
+            ))
+        .subcommand(clap::Command::new("duplicate-strings")
+            .about("Display the String values wasting the most memory on duplicate copies")
+            .arg(
+                clap::Arg::new("top")
+                    .long("top")
+                    .value_name("N")
+                    .value_parser(clap::value_parser!(usize))
+                    .default_value("20")
+                    .help("Number of values to report"),
+            ))
+        .subcommand(clap::Command::new("dump-classes")
+            .about("Display every class with its id, superclasses, instance field layout and static field values")
+            .arg(
+                clap::Arg::new("class-regex")
+                    .long("class-regex")
+                    .value_name("REGEX")
+                    .value_parser(|s: &str| regex::Regex::new(s).map_err(|e| e.to_string()))
+                    .help("Only print classes whose fully-qualified name matches this regex"),
+            ))
+        .subcommand(clap::Command::new("duplicate-classes")
+            .about("Display class names loaded by more than one class loader, a sign of a classloader leak"))
+        .subcommand(clap::Command::new("gc-roots")
+            .about("Display every GC root with its rooted object's type, then counts per root type"))
+        .subcommand(clap::Command::new("monitors")
+            .about("Display every object with a monitor held on it and the threads whose stacks refer to it"))
+        .subcommand(clap::Command::new("stack-traces")
+            .about("Display every stack trace with its frames and the thread object it belongs to"))
         .subcommand(clap::Command::new("count-records")
-            .about("Display the number of each of the top level hprof record types"))
+            .about("Display the number and total byte size of each of the top level hprof record types")
+            .arg(
+                clap::Arg::new("sort-by")
+                    .long("sort-by")
+                    .value_name("COLUMN")
+                    .value_parser(["count", "bytes"])
+                    .default_value("count")
+                    .help("Sort record types by count or by total bytes, largest first"),
+            ))
+        .subcommand(clap::Command::new("diff")
+            .about("Compare the class histogram of -f against a baseline dump, classes that grew most first")
+            .arg(
+                clap::Arg::new("baseline")
+                    .long("baseline")
+                    .required(true)
+                    .value_name("FILE")
+                    .help("Earlier heap dump to compare against; compressed dumps are handled as for -f"),
+            ))
+        .subcommand(clap::Command::new("info")
+            .about("Display the header (format, id size, timestamp), file size and record count"))
+        .subcommand(clap::Command::new("lookup-object")
+            .about("Display a single instance, array or class by object id, with its shallow size")
+            .arg(
+                clap::Arg::new("id")
+                    .long("id")
+                    .required(true)
+                    .value_name("N")
+                    .value_parser(clap::value_parser!(u64))
+                    .help("Object id to look up, as printed by dump-objects or found in the parquet output"),
+            ))
+        .subcommands(query_command())
+        .subcommand(clap::Command::new("reference-edges")
+            .about("Display every outbound object reference as `from_id<TAB>to_id<TAB>field_name<TAB>ref_kind`")
+            .arg(
+                clap::Arg::new("to-parquet")
+                    .long("to-parquet")
+                    .value_name("DIR")
+                    .num_args(0..=1)
+                    .default_missing_value("parquet")
+                    .value_parser(clap::value_parser!(PathBuf))
+                    .help("Write DIR/edges.parquet (from_id, to_id, field_name, ref_kind) instead of printing. DIR defaults to parquet"),
+            ))
+        .subcommand(clap::Command::new("find")
+            .about("Display the instances of a class whose primitive field equals, or is below or above, a value")
+            .arg(
+                clap::Arg::new("class")
+                    .long("class")
+                    .required(true)
+                    .value_name("NAME")
+                    .help("Class name, e.g. com.example.Order; instances of subclasses aren't searched"),
+            )
+            .arg(
+                clap::Arg::new("field")
+                    .long("field")
+                    .required(true)
+                    .value_name("NAME")
+                    .help("Field to compare, inherited fields included; Parent@name for a shadowed one"),
+            )
+            .arg(
+                clap::Arg::new("eq")
+                    .long("eq")
+                    .value_name("VALUE")
+                    .allow_negative_numbers(true)
+                    .help("Match instances whose field equals VALUE"),
+            )
+            .arg(
+                clap::Arg::new("lt")
+                    .long("lt")
+                    .value_name("VALUE")
+                    .allow_negative_numbers(true)
+                    .help("Match instances whose field is below VALUE"),
+            )
+            .arg(
+                clap::Arg::new("gt")
+                    .long("gt")
+                    .value_name("VALUE")
+                    .allow_negative_numbers(true)
+                    .help("Match instances whose field is above VALUE"),
+            )
+            .group(clap::ArgGroup::new("comparison").args(["eq", "lt", "gt"]).required(true))
+            .arg(
+                clap::Arg::new("print-fields")
+                    .long("print-fields")
+                    .action(clap::ArgAction::SetTrue)
+                    .help("Print each match's fields as dump-objects does, not just its id"),
+            ))
+        .subcommand(clap::Command::new("find-referrers")
+            .about("Display every object directly referencing an object id, with the referrer's type and field")
+            .arg(
+                clap::Arg::new("id")
+                    .long("id")
+                    .required(true)
+                    .value_name("N")
+                    .value_parser(clap::value_parser!(u64))
+                    .help("Object id whose referrers to find"),
+            )
+            .arg(
+                clap::Arg::new("depth")
+                    .long("depth")
+                    .value_name("K")
+                    .value_parser(clap::value_parser!(u32).range(1..))
+                    .default_value("1")
+                    .help("Also walk referrers of referrers, up to K levels. Each object is expanded once, so cycles terminate"),
+            ))
+        .subcommand(clap::Command::new("retained-size")
+            .about("Display the objects retaining the most memory, from the dominator tree of the reachable heap")
+            .arg(
+                clap::Arg::new("top")
+                    .long("top")
+                    .value_name("N")
+                    .value_parser(clap::value_parser!(usize))
+                    .default_value("20")
+                    .help("Number of objects to report"),
+            ))
+        .subcommand(clap::Command::new("retained-by-package")
+            .about("Display the memory each package retains, from the dominator tree of the reachable heap")
+            .arg(
+                clap::Arg::new("depth")
+                    .long("depth")
+                    .value_name("D")
+                    .value_parser(clap::value_parser!(u32).range(1..))
+                    .default_value("2")
+                    .help("Group classes by the first D segments of their package, e.g. com.example at 2"),
+            ))
+        .subcommand(clap::Command::new("largest-arrays")
+            .about("Display the arrays whose elements take the most bytes")
+            .arg(
+                clap::Arg::new("top")
+                    .long("top")
+                    .value_name("N")
+                    .value_parser(clap::value_parser!(usize))
+                    .default_value("20")
+                    .help("Number of arrays to report"),
+            ))
+        .subcommand(clap::Command::new("top-consumers")
+            .about("Display the biggest memory consumers by shallow size, classes and single arrays ranked together")
+            .arg(
+                clap::Arg::new("top")
+                    .long("top")
+                    .value_name("N")
+                    .value_parser(clap::value_parser!(usize))
+                    .default_value("20")
+                    .help("Number of classes and arrays to report"),
+            ))
+        .subcommand(clap::Command::new("histogram")
+            .about("Display instance count and total shallow size per class, largest first")
+            .arg(
+                clap::Arg::new("approximate")
+                    .long("approximate")
+                    .action(clap::ArgAction::SetTrue)
+                    .help("Estimate from a deterministic sample of heap dump segments instead of tallying every one"),
+            )
+            .arg(
+                clap::Arg::new("sample-fraction")
+                    .long("sample-fraction")
+                    .value_name("F")
+                    .value_parser(parse_sample_fraction)
+                    .default_value("0.01")
+                    .requires("approximate")
+                    .help("Fraction of heap dump segments --approximate reads, in (0, 1]"),
+            ))
         .subcommand(clap::Command::new("dump-objects-to-parquet")
             .about("Parses and dumps objects in the heap dump to parquet files")
             .arg(
-                clap::Arg::new("flush-rows")
-                    .long("flush-rows")
+                clap::Arg::new("threads")
+                    .long("threads")
                     .value_name("N")
+                    .value_parser(clap::value_parser!(u32).range(1..))
+                    .help("Threads building record batches (default: available parallelism). File writes use a fixed set of writer threads"),
+            )
+            .arg(
+                clap::Arg::new("writer-buffer-mb")
+                    .long("writer-buffer-mb")
+                    .value_name("MB")
                     .value_parser(clap::value_parser!(usize))
-                    .default_value("500000")
-                    .help("Number of rows to accumulate before flushing to disk (lower = less memory)"),
+                    .default_value("512")
+                    .help("Memory the open parquet writers may buffer in total before flushing row groups to disk"),
+            )
+            .arg(
+                clap::Arg::new("out-dir")
+                    .long("out-dir")
+                    .value_name("DIR")
+                    .value_parser(clap::value_parser!(PathBuf))
+                    .default_value("parquet")
+                    .help("Directory to write parquet files into (created if missing)"),
+            )
+            .arg(
+                clap::Arg::new("format")
+                    .long("format")
+                    .value_name("FORMAT")
+                    .value_parser(["parquet", "ipc", "avro"])
+                    .default_value("parquet")
+                    .help("Write .parquet files, or Arrow IPC (.arrow) or Avro (.avro) files with the same batches and no parquet settings applied"),
+            )
+            .arg(
+                clap::Arg::new("single-file")
+                    .long("single-file")
+                    .action(clap::ArgAction::SetTrue)
+                    .help("Write all instances to one instances.parquet with a class_name column, instead of one file per class"),
+            )
+            .arg(
+                clap::Arg::new("partition-by")
+                    .long("partition-by")
+                    .value_name("COLUMN")
+                    .value_parser(["class"])
+                    .help("Write instances Hive-style as class=<name>/part-0.parquet, one directory per class, instead of flat files"),
+            )
+            .arg(
+                clap::Arg::new("objects-per-file")
+                    .long("objects-per-file")
+                    .value_name("N")
+                    .value_parser(clap::value_parser!(u64).range(1..))
+                    .help("Split each instance file into <name>.part-0.parquet, <name>.part-1.parquet, ... of at most N rows each"),
+            )
+            .arg(
+                clap::Arg::new("reachability")
+                    .long("reachability")
+                    .action(clap::ArgAction::SetTrue)
+                    .help("Add a reachable column marking instances reachable from a GC root (builds the full reference graph)"),
+            )
+            .arg(
+                clap::Arg::new("live-only")
+                    .long("live-only")
+                    .action(clap::ArgAction::SetTrue)
+                    .help("Skip instances no GC root reaches, so the output is only live memory (builds the full reference graph)"),
+            )
+            .arg(
+                clap::Arg::new("retained-size")
+                    .long("retained-size")
+                    .action(clap::ArgAction::SetTrue)
+                    .help("Add a retained_bytes column from the dominator tree (builds the full reference graph)"),
+            )
+            .arg(
+                clap::Arg::new("with-shallow-size")
+                    .long("with-shallow-size")
+                    .action(clap::ArgAction::SetTrue)
+                    .help("Add a shallow_bytes column with each instance's estimated shallow size, header included"),
+            )
+            .arg(
+                clap::Arg::new("inline-primitive-arrays")
+                    .long("inline-primitive-arrays")
+                    .action(clap::ArgAction::SetTrue)
+                    .help("Write fields that only hold one primitive array type as lists of the array's elements (extra pass, holds those arrays in memory)"),
+            )
+            .arg(
+                clap::Arg::new("reference-encoding")
+                    .long("reference-encoding")
+                    .value_name("ENCODING")
+                    .value_parser(["struct", "nullable-struct", "id-only"])
+                    .default_value("nullable-struct")
+                    .help("Write references as {id, type} structs with {0, \"null\"} for null, the same structs with Arrow nulls, or bare nullable ids"),
+            )
+            .arg(
+                clap::Arg::new("no-array-elements")
+                    .long("no-array-elements")
+                    .action(clap::ArgAction::SetTrue)
+                    .help("Write only each array's obj_id, class and length, not its elements: much smaller and faster"),
+            )
+            .arg(
+                clap::Arg::new("decode-char-arrays")
+                    .long("decode-char-arrays")
+                    .action(clap::ArgAction::SetTrue)
+                    .help("Add a text column to _primitive_arrays_char.parquet with each array decoded as UTF-16"),
+            )
+            .arg(
+                clap::Arg::new("binary-byte-arrays")
+                    .long("binary-byte-arrays")
+                    .action(clap::ArgAction::SetTrue)
+                    .help("Write byte[] values as one Binary value per array instead of a list of bytes"),
+            )
+            .arg(
+                clap::Arg::new("dump-utf8")
+                    .long("dump-utf8")
+                    .action(clap::ArgAction::SetTrue)
+                    .help("Also write the utf8 string table (name_id, value, valid) to _utf8.parquet"),
+            )
+            .arg(
+                clap::Arg::new("strict")
+                    .long("strict")
+                    .action(clap::ArgAction::SetTrue)
+                    .help("Exit with an error instead of a warning when a class's instances can't be written as-is"),
+            )
+            .arg(
+                clap::Arg::new("include-classes")
+                    .long("include-classes")
+                    .value_name("GLOBS")
+                    .value_delimiter(',')
+                    .action(clap::ArgAction::Append)
+                    .help("Only write instances of classes matching one of these comma-separated globs, e.g. 'com.example.*'"),
+            )
+            .arg(
+                clap::Arg::new("exclude-classes")
+                    .long("exclude-classes")
+                    .value_name("GLOBS")
+                    .value_delimiter(',')
+                    .action(clap::ArgAction::Append)
+                    .help("Skip instances of classes matching any of these comma-separated globs; wins over --include-classes"),
+            )
+            .arg(
+                clap::Arg::new("off-heap")
+                    .long("off-heap")
+                    .action(clap::ArgAction::SetTrue)
+                    .help("Keep the object -> class index in a memory-mapped temp file instead of RAM. Slower, for very large dumps"),
+            )
+            .arg(
+                clap::Arg::new("summary")
+                    .long("summary")
+                    .action(clap::ArgAction::SetTrue)
+                    .help("Print totals at the end: objects, classes, instances, arrays, shallow bytes and files written"),
             )
             .arg(
                 clap::Arg::new("robo-mode")
@@ -38,25 +611,359 @@ fn main() {
                     .action(clap::ArgAction::SetTrue)
                     .help("LLM-optimized output: bare IDs for references, separate type index file. Faster parsing."),
             )
-        );
-    let matches = app.get_matches();
+        )
+        .subcommand(clap::Command::new("completions")
+            .about("Print a shell completion script to stdout")
+            .hide(true)
+            .arg(
+                clap::Arg::new("shell")
+                    .required(true)
+                    .value_name("SHELL")
+                    .value_parser(clap::value_parser!(clap_complete::Shell))
+                    .help("Shell to generate completions for"),
+            ));
+    // The parquet export with `--format avro` fixed, under the name Avro users look for
+    let avro = cli.find_subcommand("dump-objects-to-parquet").unwrap().clone()
+        .name("dump-objects-to-avro")
+        .about("Write instances and arrays to per-class Avro object container files (.avro)")
+        .mut_arg("format", |arg| arg.value_parser(["avro"]).default_value("avro").hide(true))
+        .mut_arg("out-dir", |arg| arg.default_value("avro"));
+    cli.subcommand(avro)
+}
+
+/// `query`, if built with the `query` feature, which brings in DataFusion.
+fn query_command() -> Option<clap::Command> {
+    if !cfg!(feature = "query") {
+        return None;
+    }
+    Some(clap::Command::new("query")
+        .about("Run SQL over the dump's objects in memory, with one table per class")
+        .arg(
+            clap::Arg::new("sql")
+                .long("sql")
+                .required(true)
+                .value_name("SQL")
+                .help("Statement to run, e.g. \"SELECT count(*) FROM java_util_HashMap\". Table and column names are case sensitive"),
+        ))
+}
 
-    let file_path = matches.get_one::<String>("file").expect("file must be specified");
+/// Parse `--sample-fraction`: a fraction in (0, 1].
+fn parse_sample_fraction(s: &str) -> Result<f64, String> {
+    match s.parse::<f64>() {
+        Ok(f) if f > 0.0 && f <= 1.0 => Ok(f),
+        _ => Err(format!("{} is not a fraction in (0, 1]", s)),
+    }
+}
 
-    let file = fs::File::open(file_path).unwrap_or_else(|_| panic!("Could not open file at path: {}", file_path));
+/// Check the header of the dump mapped from `path` and parse it.
+fn parse_dump<'a>(path: &str, bytes: &'a [u8]) -> Result<Hprof<'a>, AnalyzeError> {
+    check_hprof_header(Path::new(path), bytes)?;
+    parse_hprof(bytes).map_err(|_| AnalyzeError::NotHprof {
+        path: path.into(),
+        reason: "header could not be parsed".to_string(),
+    })
+}
 
-    let memmap = unsafe { memmap::MmapOptions::new().map(&file) }.unwrap();
+fn run() -> Result<(), AnalyzeError> {
+    let mut app = build_cli();
+    let matches = app.get_matches_mut();
 
-    let hprof = parse_hprof(&memmap[..]).unwrap();
+    // Needs no heap dump, so it's handled before --file is looked at
+    if let Some(("completions", sub_matches)) = matches.subcommand() {
+        let shell = *sub_matches.get_one::<clap_complete::Shell>("shell").unwrap();
+        clap_complete::generate(shell, &mut build_cli(), env!("CARGO_BIN_NAME"), &mut std::io::stdout());
+        return Ok(());
+    }
 
-    matches.subcommand().map(|(subcommand, sub_matches)| match subcommand {
-        "dump-objects" => commands::dump_objects(&hprof),
-        "count-records" => commands::count_records(&hprof),
-        "dump-objects-to-parquet" => {
-            let flush_rows = *sub_matches.get_one::<usize>("flush-rows").unwrap();
-            let robo_mode = sub_matches.get_flag("robo-mode");
-            commands::dump_objects_to_parquet(&hprof, flush_rows, robo_mode)
-        }
-        _ => panic!("Unknown subcommand"),
+    let default_filter = if matches.get_flag("verbose") { "heap_dump_star_diver=debug" } else { "warn" };
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or(default_filter)).init();
+    if matches.get_flag("progress") {
+        enable_progress();
+    }
+    let fail_on_unresolved = matches.get_flag("fail-on-unresolved");
+    // Started before the dump is opened, so time spent decompressing it counts too
+    let cancel = Arc::new(AtomicBool::new(false));
+    if let Some(seconds) = matches.get_one::<u64>("timeout") {
+        cancel_after(&cancel, Duration::from_secs(*seconds));
+    }
+
+    let compression = parse_compression(
+        matches.get_one::<String>("compression").unwrap(),
+        matches.get_one::<u32>("compression-level").copied(),
+    ).unwrap_or_else(|e| app.error(clap::error::ErrorKind::ValueValidation, e).exit());
+    let writer = WriterSettings {
+        format: OutputFormat::Parquet,
+        compression,
+        max_row_group_size: matches.get_one::<u64>("row-group-size").map(|n| *n as usize),
+        bloom_filter: matches.get_flag("bloom-filter"),
+        emit_schema_json: matches.get_flag("emit-schema-json"),
+    };
+
+    // Required for every subcommand but `completions`, so clap can't enforce it
+    let file_path = matches.get_one::<String>("file").unwrap_or_else(|| {
+        app.error(clap::error::ErrorKind::MissingRequiredArgument, "--file <FILE> is required").exit()
     });
+
+    let memmap = map_heap_dump(Path::new(file_path), matches.get_flag("decompress"))?;
+
+    let hprof = parse_dump(file_path, &memmap[..])?;
+
+    let id_format = match matches.get_one::<String>("id-format").unwrap().as_str() {
+        "hex" => commands::IdFormat::Hex,
+        _ => commands::IdFormat::Decimal,
+    };
+    let utf8 = match matches.get_one::<String>("utf8").unwrap().as_str() {
+        "strict" => Utf8Mode::Strict,
+        "lossy" => Utf8Mode::Lossy,
+        _ => Utf8Mode::Placeholder,
+    };
+    // `--off-heap` is an export option, but it decides how the shared index is stored
+    let off_heap = matches.subcommand()
+        .is_some_and(|(_, sub_matches)| matches!(sub_matches.try_get_one::<bool>("off-heap"), Ok(Some(&true))));
+    let analyzer = HeapDumpAnalyzer::with_options(&hprof, AnalyzerOptions {
+        off_heap_dir: off_heap.then(std::env::temp_dir),
+        utf8,
+        cancel,
+        skip_errors: matches.get_flag("skip-errors"),
+    });
+
+    let result = match matches.subcommand() {
+        Some(("dump-objects", sub_matches)) => {
+            let filter = sub_matches.get_one::<String>("class")
+                .map(|name| commands::ClassFilter::Exact(name.clone()))
+                .or_else(|| sub_matches.get_one::<regex::Regex>("class-regex")
+                    .map(|re| commands::ClassFilter::Regex(re.clone())));
+            let window = commands::ObjectWindow {
+                skip: *sub_matches.get_one::<usize>("skip").unwrap(),
+                limit: sub_matches.get_one::<usize>("limit").copied(),
+            };
+            let max_array_elements = match *sub_matches.get_one::<usize>("max-array-elements").unwrap() {
+                0 => usize::MAX,
+                k => k,
+            };
+            let format = match sub_matches.get_one::<String>("format").unwrap().as_str() {
+                "json" => commands::ObjectFormat::Json,
+                _ => commands::ObjectFormat::Text,
+            };
+            let options = commands::DumpObjectsOptions {
+                filter,
+                window,
+                sample: matches.get_one::<usize>("sample").copied(),
+                max_array_elements,
+                format,
+                id_format,
+                decode_char_arrays: sub_matches.get_flag("decode-char-arrays"),
+                byte_array_format: match sub_matches.get_one::<String>("byte-array-format").unwrap().as_str() {
+                    "hexdump" => commands::ByteArrayFormat::Hexdump,
+                    "ascii" => commands::ByteArrayFormat::Ascii,
+                    "base64" => commands::ByteArrayFormat::Base64,
+                    _ => commands::ByteArrayFormat::Hex,
+                },
+                expand_depth: *sub_matches.get_one::<usize>("expand-depth").unwrap(),
+                fail_on_unresolved,
+            };
+            let mut out = BufWriter::new(std::io::stdout().lock());
+            commands::dump_objects(&analyzer, &options, &mut out).map(warn_unresolved)
+        }
+        Some(("dump-objects-to-ndjson", sub_matches)) => {
+            let filter = sub_matches.get_one::<String>("class")
+                .map(|name| commands::ClassFilter::Exact(name.clone()))
+                .or_else(|| sub_matches.get_one::<regex::Regex>("class-regex")
+                    .map(|re| commands::ClassFilter::Regex(re.clone())));
+            match sub_matches.get_one::<PathBuf>("out") {
+                Some(path) => std::fs::File::create(path)
+                    .map_err(|e| AnalyzeError::io(path, e))
+                    .and_then(|file| {
+                        commands::dump_objects_to_ndjson(&analyzer, filter.as_ref(), &mut BufWriter::new(file))
+                    }),
+                None => {
+                    let mut out = BufWriter::new(std::io::stdout().lock());
+                    commands::dump_objects_to_ndjson(&analyzer, filter.as_ref(), &mut out)
+                }
+            }
+        }
+        Some(("dump-strings", sub_matches)) => {
+            let options = commands::DumpStringsOptions {
+                min_length: *sub_matches.get_one::<usize>("min-length").unwrap(),
+                sort_by_value: sub_matches.get_flag("sort"),
+                parquet_dir: sub_matches.get_one::<PathBuf>("to-parquet").cloned(),
+                writer,
+            };
+            commands::dump_strings(&analyzer, &options)
+        }
+        Some(("duplicate-strings", sub_matches)) => {
+            commands::duplicate_strings(&analyzer, *sub_matches.get_one::<usize>("top").unwrap())
+        }
+        Some(("dump-classes", sub_matches)) => {
+            let filter = sub_matches.get_one::<regex::Regex>("class-regex")
+                .map(|re| commands::ClassFilter::Regex(re.clone()));
+            let mut out = BufWriter::new(std::io::stdout().lock());
+            commands::dump_classes(&analyzer, filter.as_ref(), id_format, &mut out)
+        }
+        Some(("duplicate-classes", _)) => {
+            let mut out = BufWriter::new(std::io::stdout().lock());
+            commands::duplicate_classes(&analyzer, &mut out)
+        }
+        Some(("gc-roots", _)) => commands::gc_roots(&analyzer),
+        Some(("monitors", _)) => {
+            let mut out = BufWriter::new(std::io::stdout().lock());
+            commands::monitors(&analyzer, &mut out)
+        }
+        Some(("stack-traces", _)) => {
+            let mut out = BufWriter::new(std::io::stdout().lock());
+            commands::stack_traces(&analyzer, &mut out)
+        }
+        Some(("count-records", sub_matches)) => {
+            let sort_by = match sub_matches.get_one::<String>("sort-by").unwrap().as_str() {
+                "bytes" => commands::RecordSort::Bytes,
+                _ => commands::RecordSort::Count,
+            };
+            commands::count_records(&analyzer, &memmap[..], sort_by)
+        }
+        Some(("diff", sub_matches)) => {
+            let baseline_path = sub_matches.get_one::<String>("baseline").unwrap();
+            let baseline_memmap = map_heap_dump(Path::new(baseline_path), matches.get_flag("decompress"))?;
+            let baseline = parse_dump(baseline_path, &baseline_memmap[..])?;
+            let baseline = HeapDumpAnalyzer::with_options(&baseline, analyzer.options().clone());
+            commands::diff(&baseline, &analyzer)
+        }
+        Some(("info", _)) => commands::info(&analyzer, memmap.len() as u64),
+        Some(("histogram", sub_matches)) => {
+            let sample_fraction = sub_matches.get_flag("approximate")
+                .then(|| *sub_matches.get_one::<f64>("sample-fraction").unwrap());
+            commands::histogram(&analyzer, sample_fraction)
+        }
+        Some(("lookup-object", sub_matches)) => {
+            commands::lookup_object(&analyzer, *sub_matches.get_one::<u64>("id").unwrap(), id_format)
+        }
+        #[cfg(feature = "query")]
+        Some(("query", sub_matches)) => commands::query(&analyzer, sub_matches.get_one::<String>("sql").unwrap()),
+        Some(("find", sub_matches)) => {
+            let (comparison, value) = [
+                ("eq", commands::Comparison::Eq),
+                ("lt", commands::Comparison::Lt),
+                ("gt", commands::Comparison::Gt),
+            ]
+                .into_iter()
+                .find_map(|(arg, comparison)| sub_matches.get_one::<String>(arg).map(|v| (comparison, v.clone())))
+                .unwrap();
+            let options = commands::FindOptions {
+                class_name: sub_matches.get_one::<String>("class").unwrap().clone(),
+                field: sub_matches.get_one::<String>("field").unwrap().clone(),
+                comparison,
+                value,
+                print_fields: sub_matches.get_flag("print-fields"),
+                id_format,
+            };
+            let mut out = BufWriter::new(std::io::stdout().lock());
+            commands::find_instances(&analyzer, &options, &mut out)
+        }
+        Some(("find-referrers", sub_matches)) => commands::find_referrers(
+            &analyzer,
+            *sub_matches.get_one::<u64>("id").unwrap(),
+            *sub_matches.get_one::<u32>("depth").unwrap() as usize,
+        ),
+        Some(("largest-arrays", sub_matches)) => {
+            commands::largest_arrays(&analyzer, *sub_matches.get_one::<usize>("top").unwrap())
+        }
+        Some(("top-consumers", sub_matches)) => {
+            commands::top_consumers(&analyzer, *sub_matches.get_one::<usize>("top").unwrap())
+        }
+        Some(("retained-size", sub_matches)) => {
+            commands::retained_size(&analyzer, *sub_matches.get_one::<usize>("top").unwrap())
+        }
+        Some(("retained-by-package", sub_matches)) => {
+            commands::retained_by_package(&analyzer, *sub_matches.get_one::<u32>("depth").unwrap() as usize)
+        }
+        Some(("reference-edges", sub_matches)) => {
+            commands::reference_edges(&analyzer, sub_matches.get_one::<PathBuf>("to-parquet").map(PathBuf::as_path), writer)
+        }
+        Some(("dump-objects-to-parquet" | "dump-objects-to-avro", sub_matches)) => {
+            let globs = |name: &str| -> Vec<String> {
+                sub_matches.get_many::<String>(name).map(|g| g.cloned().collect()).unwrap_or_default()
+            };
+            let options = commands::ParquetOptions {
+                out_dir: sub_matches.get_one::<PathBuf>("out-dir").unwrap().clone(),
+                writer_buffer_bytes: *sub_matches.get_one::<usize>("writer-buffer-mb").unwrap() << 20,
+                threads: sub_matches.get_one::<u32>("threads")
+                    .map(|n| *n as usize)
+                    .unwrap_or_else(|| std::thread::available_parallelism().map_or(1, |n| n.get())),
+                writer: WriterSettings {
+                    format: match sub_matches.get_one::<String>("format").unwrap().as_str() {
+                        "ipc" => OutputFormat::Ipc,
+                        "avro" => OutputFormat::Avro,
+                        _ => OutputFormat::Parquet,
+                    },
+                    ..writer
+                },
+                robo_mode: sub_matches.get_flag("robo-mode"),
+                single_file: sub_matches.get_flag("single-file"),
+                partition_by_class: sub_matches.get_one::<String>("partition-by").is_some_and(|p| p == "class"),
+                reachability: sub_matches.get_flag("reachability"),
+                live_only: sub_matches.get_flag("live-only"),
+                retained_size: sub_matches.get_flag("retained-size"),
+                shallow_size: sub_matches.get_flag("with-shallow-size"),
+                inline_primitive_arrays: sub_matches.get_flag("inline-primitive-arrays"),
+                references: match sub_matches.get_one::<String>("reference-encoding").unwrap().as_str() {
+                    "struct" => ReferenceEncoding::Struct,
+                    "id-only" => ReferenceEncoding::IdOnly,
+                    _ => ReferenceEncoding::NullableStruct,
+                },
+                array_elements: !sub_matches.get_flag("no-array-elements"),
+                char_array_text: sub_matches.get_flag("decode-char-arrays"),
+                binary_byte_arrays: sub_matches.get_flag("binary-byte-arrays"),
+                dump_utf8: sub_matches.get_flag("dump-utf8"),
+                strict: sub_matches.get_flag("strict"),
+                fail_on_unresolved,
+                sample: matches.get_one::<usize>("sample").copied(),
+                classes: commands::ClassSelection::new(&globs("include-classes"), &globs("exclude-classes")),
+                summary: sub_matches.get_flag("summary"),
+                objects_per_file: sub_matches.get_one::<u64>("objects-per-file").map(|n| *n as usize),
+            };
+            commands::dump_objects_to_parquet(&analyzer, &options)
+                .map(|summary| warn_unresolved(summary.unresolved_references))
+        }
+        Some(("completions", _)) => unreachable!("completions returns before the dump is opened"),
+        Some((other, _)) => unreachable!("clap rejects unknown subcommand {}", other),
+        None => Ok(()),
+    };
+    let skipped = analyzer.skipped_segments();
+    if skipped > 0 {
+        eprintln!("warning: {} heap dump segment(s) had an unreadable sub-record and were cut short; output is partial", skipped);
+    }
+    // Commands other than the parquet export finish quietly with what they read
+    match result {
+        Ok(()) if analyzer.cancelled() => Err(AnalyzeError::Cancelled),
+        result => result,
+    }
+}
+
+/// Close a `dump-objects` or export run that labeled references it couldn't give a type.
+fn warn_unresolved(unresolved: u64) {
+    if unresolved > 0 {
+        eprintln!("warning: {} reference(s) to objects whose type could not be resolved (--fail-on-unresolved makes this an error)", unresolved);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cli_definition_is_valid() {
+        build_cli().debug_assert();
+    }
+
+    #[test]
+    fn test_completions_need_no_file() {
+        let matches = build_cli().try_get_matches_from(["hdsd", "completions", "bash"]).unwrap();
+        let (name, sub_matches) = matches.subcommand().unwrap();
+        assert_eq!(name, "completions");
+        assert_eq!(sub_matches.get_one::<clap_complete::Shell>("shell"), Some(&clap_complete::Shell::Bash));
+
+        let mut script = Vec::new();
+        clap_complete::generate(clap_complete::Shell::Bash, &mut build_cli(), "HeapDumpStarDiver", &mut script);
+        let script = String::from_utf8(script).unwrap();
+        assert!(script.contains("dump-objects-to-parquet"));
+    }
 }
@@ -0,0 +1,274 @@
+// SPDX-License-Identifier: MIT
+// Copyright (c) 2026 Zac Policzer
+
+//! The obj id -> class obj id map for every instance and object array. It is by far the
+//! largest part of the index (one entry per object), so besides the in-memory map there is
+//! an off-heap backend: a sorted array of id pairs in a memory-mapped temp file, whose
+//! pages the kernel can drop and re-read instead of counting against the heap.
+
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::fs::File;
+use std::hash::BuildHasher;
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use jvm_hprof::Id;
+use memmap2::{Mmap, MmapOptions};
+use dashmap::DashMap;
+
+/// Object id -> class object id lookups, whichever backend holds them.
+pub(crate) trait ObjClassIndex: Send + Sync {
+    fn class_of(&self, obj_id: Id) -> Option<Id>;
+    fn len(&self) -> usize;
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+    /// Every object id in the index, in no particular order.
+    fn obj_ids(&self) -> Box<dyn Iterator<Item = Id> + '_>;
+}
+
+impl<S: BuildHasher + Clone + Send + Sync> ObjClassIndex for DashMap<Id, Id, S> {
+    fn class_of(&self, obj_id: Id) -> Option<Id> {
+        self.get(&obj_id).map(|class_obj_id| *class_obj_id)
+    }
+
+    fn len(&self) -> usize {
+        DashMap::len(self)
+    }
+
+    fn obj_ids(&self) -> Box<dyn Iterator<Item = Id> + '_> {
+        Box::new(self.iter().map(|e| *e.key()))
+    }
+}
+
+/// Bytes per `(obj_id, class_obj_id)` pair: two little-endian u64s.
+const PAIR_BYTES: usize = 16;
+
+fn read_u64(bytes: &[u8], at: usize) -> u64 {
+    u64::from_le_bytes(bytes[at..at + 8].try_into().unwrap())
+}
+
+/// Pairs sorted by obj id in a memory-mapped file; lookups binary search the mapping.
+pub(crate) struct SortedObjClasses {
+    /// None when there are no pairs, since an empty file can't be mapped.
+    map: Option<Mmap>,
+    /// The file, to remove once it's unmapped where it can't be unlinked while mapped.
+    #[cfg(not(unix))]
+    path: PathBuf,
+}
+
+#[cfg(not(unix))]
+impl Drop for SortedObjClasses {
+    fn drop(&mut self) {
+        self.map = None;
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+impl SortedObjClasses {
+    fn bytes(&self) -> &[u8] {
+        self.map.as_deref().unwrap_or(&[])
+    }
+
+    fn obj_id_at(&self, i: usize) -> u64 {
+        read_u64(self.bytes(), i * PAIR_BYTES)
+    }
+}
+
+impl ObjClassIndex for SortedObjClasses {
+    fn class_of(&self, obj_id: Id) -> Option<Id> {
+        let target = obj_id.id();
+        let (mut lo, mut hi) = (0, self.len());
+        while lo < hi {
+            let mid = lo + (hi - lo) / 2;
+            match self.obj_id_at(mid).cmp(&target) {
+                std::cmp::Ordering::Less => lo = mid + 1,
+                std::cmp::Ordering::Greater => hi = mid,
+                std::cmp::Ordering::Equal => return Some(Id::from(read_u64(self.bytes(), mid * PAIR_BYTES + 8))),
+            }
+        }
+        None
+    }
+
+    fn len(&self) -> usize {
+        self.bytes().len() / PAIR_BYTES
+    }
+
+    fn obj_ids(&self) -> Box<dyn Iterator<Item = Id> + '_> {
+        Box::new((0..self.len()).map(|i| Id::from(self.obj_id_at(i))))
+    }
+}
+
+fn map_file(file: &File, len: u64) -> io::Result<Option<Mmap>> {
+    if len == 0 {
+        return Ok(None);
+    }
+    unsafe { MmapOptions::new().map(file) }.map(Some)
+}
+
+/// Unlink a temp file that is still mapped, so its space is released as soon as the
+/// mapping goes, however the process ends. Only Unix allows that; elsewhere the file is
+/// removed after it's unmapped instead.
+fn unlink_mapped(path: &Path) {
+    if cfg!(unix) {
+        let _ = std::fs::remove_file(path);
+    }
+}
+
+/// Collects sorted runs of pairs (one per heap dump segment) from any thread into a temp
+/// file, then merges them into one `SortedObjClasses`. Only one run per thread is ever held
+/// in memory.
+pub(crate) struct SortedObjClassesBuilder {
+    dir: PathBuf,
+    runs: Mutex<(BufWriter<File>, Vec<(usize, usize)>)>,
+}
+
+impl SortedObjClassesBuilder {
+    fn path(dir: &Path, suffix: &str) -> PathBuf {
+        dir.join(format!("heapdumpstardiver-{}-obj-classes.{}", std::process::id(), suffix))
+    }
+
+    /// Read as well as write, so the file can be mapped once written.
+    fn create_rw(path: &Path) -> io::Result<File> {
+        File::options().read(true).write(true).create(true).truncate(true).open(path)
+    }
+
+    pub fn new(dir: &Path) -> io::Result<Self> {
+        let runs = Self::create_rw(&Self::path(dir, "runs"))?;
+        Ok(SortedObjClassesBuilder { dir: dir.to_path_buf(), runs: Mutex::new((BufWriter::new(runs), Vec::new())) })
+    }
+
+    pub fn add_run(&self, mut pairs: Vec<(u64, u64)>) -> io::Result<()> {
+        if pairs.is_empty() {
+            return Ok(());
+        }
+        pairs.sort_unstable();
+        let mut bytes = Vec::with_capacity(pairs.len() * PAIR_BYTES);
+        for (obj_id, class_obj_id) in &pairs {
+            bytes.extend_from_slice(&obj_id.to_le_bytes());
+            bytes.extend_from_slice(&class_obj_id.to_le_bytes());
+        }
+        let mut runs = self.runs.lock().unwrap();
+        let start = runs.1.last().map_or(0, |(start, len)| start + len);
+        runs.0.write_all(&bytes)?;
+        runs.1.push((start, pairs.len()));
+        Ok(())
+    }
+
+    /// K-way merge the runs into the final sorted file. On Unix both temp files are unlinked
+    /// once mapped, so their space is released when the index is dropped even if the
+    /// process is killed. Elsewhere the runs file is removed after the merge and the sorted
+    /// file when the index is dropped.
+    pub fn finish(self) -> io::Result<SortedObjClasses> {
+        let (writer, runs) = self.runs.into_inner().unwrap();
+        let runs_file = writer.into_inner().map_err(|e| e.into_error())?;
+        let total: usize = runs.iter().map(|(_, len)| len).sum();
+        let runs_path = Self::path(&self.dir, "runs");
+        let runs_map = map_file(&runs_file, (total * PAIR_BYTES) as u64);
+        unlink_mapped(&runs_path);
+        let runs_map = runs_map?;
+        let run_bytes = runs_map.as_deref().unwrap_or(&[]);
+
+        let sorted_path = Self::path(&self.dir, "sorted");
+        let sorted = Self::create_rw(&sorted_path)?;
+        let mut out = BufWriter::new(&sorted);
+        // (obj id, run, position in run), smallest obj id first
+        let mut heads: BinaryHeap<Reverse<(u64, usize, usize)>> = runs.iter().enumerate()
+            .map(|(run, (start, _))| Reverse((read_u64(run_bytes, start * PAIR_BYTES), run, 0)))
+            .collect();
+        while let Some(Reverse((_, run, pos))) = heads.pop() {
+            let (start, len) = runs[run];
+            let at = (start + pos) * PAIR_BYTES;
+            out.write_all(&run_bytes[at..at + PAIR_BYTES])?;
+            if pos + 1 < len {
+                heads.push(Reverse((read_u64(run_bytes, at + PAIR_BYTES), run, pos + 1)));
+            }
+        }
+        out.flush()?;
+        drop(out);
+        #[cfg(not(unix))]
+        {
+            drop((runs_map, runs_file));
+            let _ = std::fs::remove_file(&runs_path);
+        }
+
+        let map = map_file(&sorted, (total * PAIR_BYTES) as u64);
+        unlink_mapped(&sorted_path);
+        Ok(SortedObjClasses {
+            map: map?,
+            #[cfg(not(unix))]
+            path: sorted_path,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::id_hash::IdDashMap;
+
+    /// Pairs split across several unsorted runs, as segments would produce them.
+    fn runs() -> Vec<Vec<(u64, u64)>> {
+        vec![
+            vec![(0x1040, 7), (0x1000, 7), (0x2000, 9)],
+            vec![],
+            vec![(0x1008, 9), (0x3000, 7)],
+            (0..1000).map(|i| (0x10_0000 + i * 24, 11 + i % 3)).rev().collect(),
+        ]
+    }
+
+    fn both_backends(name: &str) -> (IdDashMap<Id>, SortedObjClasses) {
+        let in_memory = IdDashMap::default();
+        let dir = std::env::temp_dir().join(format!("heapdumpstardiver-obj-classes-{}-{}", name, std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let builder = SortedObjClassesBuilder::new(&dir).unwrap();
+        for run in runs() {
+            for (obj_id, class_obj_id) in &run {
+                in_memory.insert(Id::from(*obj_id), Id::from(*class_obj_id));
+            }
+            builder.add_run(run).unwrap();
+        }
+        let off_heap = builder.finish().unwrap();
+        // On Unix temp files are unlinked as soon as they're mapped
+        if cfg!(unix) {
+            assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 0);
+            std::fs::remove_dir(&dir).unwrap();
+        }
+        (in_memory, off_heap)
+    }
+
+    #[test]
+    fn test_backends_agree() {
+        let (in_memory, off_heap) = both_backends("agree");
+        let backends: [&dyn ObjClassIndex; 2] = [&in_memory, &off_heap];
+        for index in backends {
+            assert_eq!(index.len(), 1005);
+            assert_eq!(index.class_of(Id::from(0x1008)), Some(Id::from(9)));
+            assert_eq!(index.class_of(Id::from(0x3000)), Some(Id::from(7)));
+            assert_eq!(index.class_of(Id::from(0x10_0000 + 999 * 24)), Some(Id::from(11)));
+            assert_eq!(index.class_of(Id::from(0x1001)), None);
+            assert_eq!(index.class_of(Id::from(0)), None);
+            assert_eq!(index.class_of(Id::from(u64::MAX)), None);
+        }
+        for run in runs() {
+            for (obj_id, _) in run {
+                assert_eq!(in_memory.class_of(Id::from(obj_id)), off_heap.class_of(Id::from(obj_id)));
+            }
+        }
+
+        let mut in_memory_ids: Vec<u64> = in_memory.obj_ids().map(|id| id.id()).collect();
+        in_memory_ids.sort_unstable();
+        let off_heap_ids: Vec<u64> = off_heap.obj_ids().map(|id| id.id()).collect();
+        assert_eq!(in_memory_ids, off_heap_ids);
+    }
+
+    #[test]
+    fn test_empty_off_heap_index() {
+        let dir = std::env::temp_dir();
+        let off_heap = SortedObjClassesBuilder::new(&dir).unwrap().finish().unwrap();
+        assert!(off_heap.is_empty());
+        assert_eq!(off_heap.class_of(Id::from(8)), None);
+        assert_eq!(off_heap.obj_ids().count(), 0);
+    }
+}
@@ -0,0 +1,252 @@
+// SPDX-License-Identifier: MIT
+// Copyright (c) 2026 Zac Policzer
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use arrow_array::ArrayRef;
+use arrow_array::builder::{BooleanBuilder, Float32Builder, Float64Builder, Int16Builder, Int32Builder, Int64Builder, Int8Builder, ListBuilder, UInt16Builder};
+use arrow_schema::{DataType, Field};
+use jvm_hprof::{Hprof, Id, Record};
+use jvm_hprof::heap_dump::{FieldValue, PrimitiveArray, PrimitiveArrayType, SubRecord};
+use rayon::prelude::*;
+use crate::hprof_index::HprofIndex;
+use crate::id_hash::{IdMap, IdSet};
+use crate::skip_errors::sub_records;
+
+/// The elements of one primitive array.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ArrayValues {
+    Boolean(Vec<bool>),
+    Byte(Vec<i8>),
+    Char(Vec<u16>),
+    Short(Vec<i16>),
+    Int(Vec<i32>),
+    Long(Vec<i64>),
+    Float(Vec<f32>),
+    Double(Vec<f64>),
+}
+
+impl ArrayValues {
+    fn read(pa: &PrimitiveArray) -> Self {
+        match pa.primitive_type() {
+            PrimitiveArrayType::Boolean => ArrayValues::Boolean(pa.booleans().unwrap().map(|v| v.unwrap()).collect()),
+            PrimitiveArrayType::Byte => ArrayValues::Byte(pa.bytes().unwrap().map(|v| v.unwrap()).collect()),
+            PrimitiveArrayType::Char => ArrayValues::Char(pa.chars().unwrap().map(|v| v.unwrap() as u16).collect()),
+            PrimitiveArrayType::Short => ArrayValues::Short(pa.shorts().unwrap().map(|v| v.unwrap()).collect()),
+            PrimitiveArrayType::Int => ArrayValues::Int(pa.ints().unwrap().map(|v| v.unwrap()).collect()),
+            PrimitiveArrayType::Long => ArrayValues::Long(pa.longs().unwrap().map(|v| v.unwrap()).collect()),
+            PrimitiveArrayType::Float => ArrayValues::Float(pa.floats().unwrap().map(|v| v.unwrap()).collect()),
+            PrimitiveArrayType::Double => ArrayValues::Double(pa.doubles().unwrap().map(|v| v.unwrap()).collect()),
+        }
+    }
+}
+
+/// Arrow element type of a `List` column holding arrays of `primitive_type`, matching the
+/// `values` column of the `_primitive_arrays_<type>` files.
+pub fn element_type(primitive_type: PrimitiveArrayType) -> DataType {
+    match primitive_type {
+        PrimitiveArrayType::Boolean => DataType::Boolean,
+        PrimitiveArrayType::Byte => DataType::Int8,
+        PrimitiveArrayType::Char => DataType::UInt16,
+        PrimitiveArrayType::Short => DataType::Int16,
+        PrimitiveArrayType::Int => DataType::Int32,
+        PrimitiveArrayType::Long => DataType::Int64,
+        PrimitiveArrayType::Float => DataType::Float32,
+        PrimitiveArrayType::Double => DataType::Float64,
+    }
+}
+
+pub fn list_type(primitive_type: PrimitiveArrayType) -> DataType {
+    DataType::List(Arc::new(Field::new("item", element_type(primitive_type), true)))
+}
+
+/// What one instance field was seen pointing at across the dump.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum FieldTarget {
+    Array(PrimitiveArrayType),
+    /// Some value was an instance, an object array, a class, an unresolved id or a second
+    /// primitive array type.
+    Other,
+}
+
+impl FieldTarget {
+    fn merge(self, other: FieldTarget) -> FieldTarget {
+        if self == other { self } else { FieldTarget::Other }
+    }
+}
+
+fn merge_targets(
+    mut into: HashMap<(Id, usize), FieldTarget>,
+    from: HashMap<(Id, usize), FieldTarget>,
+) -> HashMap<(Id, usize), FieldTarget> {
+    for (field, target) in from {
+        into.entry(field).and_modify(|t| *t = t.merge(target)).or_insert(target);
+    }
+    into
+}
+
+/// Instance fields whose every non-null value is a primitive array of one type, with the
+/// contents of the arrays they point to, so those fields can be written as lists of the
+/// actual elements rather than `{id, type}` references.
+#[derive(Default)]
+pub struct InlineArrays {
+    /// (class obj id, flattened field index) -> element type.
+    pub fields: HashMap<(Id, usize), PrimitiveArrayType>,
+    values: IdMap<ArrayValues>,
+}
+
+impl InlineArrays {
+    /// Two parallel passes over the segments, like `decode_strings`: the first classifies
+    /// every reference field and notes the arrays it points to, the second copies the
+    /// elements of the arrays held by fields that stayed primitive-array-only.
+    pub fn collect(hprof: &Hprof, index: &HprofIndex, segments: &[Record]) -> Self {
+        let id_size = hprof.header().id_size();
+
+        // Pass 1: per field, what its values point to, and which arrays each field holds
+        let (targets, refs) = segments.par_iter()
+            .map(|record| {
+                let mut targets: HashMap<(Id, usize), FieldTarget> = HashMap::new();
+                let mut refs: Vec<((Id, usize), Id)> = Vec::new();
+                let segment = record.as_heap_dump_segment().unwrap().unwrap();
+                for p in sub_records(&index.options, segment.sub_records()) {
+                    let SubRecord::Instance(instance) = p.unwrap() else { continue };
+                    let class_id = instance.class_obj_id();
+                    let Some(fds) = index.class_instance_field_descriptors.get(&class_id) else { continue };
+                    let mut input: &[u8] = instance.fields();
+                    for (i, fd) in fds.iter().enumerate() {
+                        let (rest, value) = fd.field_type().parse_value(input, id_size).unwrap();
+                        input = rest;
+                        let FieldValue::ObjectId(Some(ref_id)) = value else { continue };
+                        let target = match index.prim_array_obj_id_to_type.get(&ref_id) {
+                            Some(pt) => {
+                                refs.push(((class_id, i), ref_id));
+                                FieldTarget::Array(*pt)
+                            }
+                            None => FieldTarget::Other,
+                        };
+                        targets.entry((class_id, i)).and_modify(|t| *t = t.merge(target)).or_insert(target);
+                    }
+                }
+                (targets, refs)
+            })
+            .reduce(
+                || (HashMap::new(), Vec::new()),
+                |(targets, mut refs), (more_targets, more_refs)| {
+                    refs.extend(more_refs);
+                    (merge_targets(targets, more_targets), refs)
+                },
+            );
+
+        let fields: HashMap<(Id, usize), PrimitiveArrayType> = targets.into_iter()
+            .filter_map(|(field, target)| match target {
+                FieldTarget::Array(pt) => Some((field, pt)),
+                FieldTarget::Other => None,
+            })
+            .collect();
+        let wanted: IdSet = refs.into_iter()
+            .filter(|(field, _)| fields.contains_key(field))
+            .map(|(_, array_id)| array_id)
+            .collect();
+
+        // Pass 2: contents of the wanted arrays
+        let values: IdMap<ArrayValues> = segments.par_iter().flat_map_iter(|record| {
+            let mut found = Vec::new();
+            let segment = record.as_heap_dump_segment().unwrap().unwrap();
+            for p in sub_records(&index.options, segment.sub_records()) {
+                if let SubRecord::PrimitiveArray(pa) = p.unwrap() {
+                    if wanted.contains(&pa.obj_id()) {
+                        found.push((pa.obj_id(), ArrayValues::read(&pa)));
+                    }
+                }
+            }
+            found
+        }).collect();
+
+        InlineArrays { fields, values }
+    }
+
+    /// Element type of `field_index` of `class_id` if that field is inlined.
+    pub fn field_type(&self, class_id: Id, field_index: usize) -> Option<PrimitiveArrayType> {
+        self.fields.get(&(class_id, field_index)).copied()
+    }
+
+    /// Build a `List<element>` column with one row per reference: the referenced array's
+    /// elements, or a null list for a null reference or an array that isn't in the dump.
+    pub fn list_column(&self, refs: impl Iterator<Item = Option<Id>>, element: &DataType) -> ArrayRef {
+        macro_rules! build_list {
+            ($variant:ident, $builder_type:ident) => {{
+                let mut builder = ListBuilder::new($builder_type::new());
+                for ref_id in refs {
+                    match ref_id.and_then(|id| self.values.get(&id)) {
+                        Some(ArrayValues::$variant(elements)) => {
+                            builder.values().append_slice(elements);
+                            builder.append(true);
+                        }
+                        _ => builder.append(false),
+                    }
+                }
+                Arc::new(builder.finish()) as ArrayRef
+            }};
+        }
+        match element {
+            DataType::Boolean => build_list!(Boolean, BooleanBuilder),
+            DataType::Int8 => build_list!(Byte, Int8Builder),
+            DataType::UInt16 => build_list!(Char, UInt16Builder),
+            DataType::Int16 => build_list!(Short, Int16Builder),
+            DataType::Int32 => build_list!(Int, Int32Builder),
+            DataType::Int64 => build_list!(Long, Int64Builder),
+            DataType::Float32 => build_list!(Float, Float32Builder),
+            DataType::Float64 => build_list!(Double, Float64Builder),
+            other => panic!("Unsupported primitive array element type: {:?}", other),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arrow_array::Array;
+    use arrow_array::cast::AsArray;
+    use arrow_array::types::Int32Type;
+
+    #[test]
+    fn test_field_targets_merge() {
+        let int_array = FieldTarget::Array(PrimitiveArrayType::Int);
+        assert_eq!(int_array.merge(int_array), int_array);
+        assert_eq!(int_array.merge(FieldTarget::Array(PrimitiveArrayType::Long)), FieldTarget::Other);
+        assert_eq!(int_array.merge(FieldTarget::Other), FieldTarget::Other);
+
+        let field = (Id::from(100_u64), 0);
+        let seg_a = HashMap::from([(field, int_array)]);
+        let seg_b = HashMap::from([(field, FieldTarget::Other), ((Id::from(100_u64), 1), int_array)]);
+        let merged = merge_targets(seg_a, seg_b);
+        assert_eq!(merged[&field], FieldTarget::Other);
+        assert_eq!(merged[&(Id::from(100_u64), 1)], int_array);
+    }
+
+    #[test]
+    fn test_int_array_field_becomes_list_of_ints() {
+        // An object with an int[] field: two instances point at real arrays, one at null,
+        // one at an array id that isn't in the dump
+        let inline = InlineArrays {
+            fields: HashMap::from([((Id::from(100_u64), 0), PrimitiveArrayType::Int)]),
+            values: IdMap::from_iter([
+                (Id::from(10_u64), ArrayValues::Int(vec![1, 2, 3])),
+                (Id::from(11_u64), ArrayValues::Int(vec![])),
+            ]),
+        };
+        assert_eq!(inline.field_type(Id::from(100_u64), 0), Some(PrimitiveArrayType::Int));
+        assert_eq!(inline.field_type(Id::from(100_u64), 1), None);
+
+        let refs = vec![Some(Id::from(10_u64)), None, Some(Id::from(11_u64)), Some(Id::from(12_u64))];
+        let column = inline.list_column(refs.into_iter(), &element_type(PrimitiveArrayType::Int));
+        assert_eq!(column.data_type(), &list_type(PrimitiveArrayType::Int));
+        let list = column.as_list::<i32>();
+        assert_eq!(list.len(), 4);
+        assert_eq!(list.value(0).as_primitive::<Int32Type>().values().to_vec(), vec![1, 2, 3]);
+        assert!(list.is_null(1));
+        assert!(list.is_valid(2));
+        assert_eq!(list.value(2).len(), 0);
+        assert!(list.is_null(3));
+    }
+}
@@ -0,0 +1,94 @@
+// SPDX-License-Identifier: MIT
+// Copyright (c) 2026 Zac Policzer
+
+//! `--progress`: a throttled counter on stderr for the long passes over a dump.
+
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// At most this often a progress line is redrawn.
+const REPORT_INTERVAL: Duration = Duration::from_millis(500);
+/// Without a total, items are cheap records, so the clock is only read every this many.
+const UNCOUNTED_CHECK_EVERY: u64 = 4096;
+
+/// Report progress on stderr from here on. Off by default, so library callers and parsed
+/// output aren't interleaved with it.
+pub fn enable_progress() {
+    ENABLED.store(true, Ordering::Relaxed);
+}
+
+/// Counts items done for one pass, shared across the threads doing it. The final count is
+/// reported when it's dropped.
+pub(crate) struct Progress {
+    label: &'static str,
+    total: Option<u64>,
+    done: AtomicU64,
+    last_report: Mutex<Instant>,
+}
+
+impl Progress {
+    /// `None` unless progress is enabled, so a disabled pass costs one branch per update.
+    pub(crate) fn start(label: &'static str, total: Option<u64>) -> Option<Progress> {
+        ENABLED.load(Ordering::Relaxed).then(|| Progress {
+            label,
+            total,
+            done: AtomicU64::new(0),
+            last_report: Mutex::new(Instant::now()),
+        })
+    }
+
+    pub(crate) fn advance(&self, n: u64) {
+        let done = self.done.fetch_add(n, Ordering::Relaxed) + n;
+        if self.total.is_none() && (done - n) / UNCOUNTED_CHECK_EVERY == done / UNCOUNTED_CHECK_EVERY {
+            return;
+        }
+        // try_lock: a worker never waits for another one to finish reporting
+        if let Ok(mut last) = self.last_report.try_lock() {
+            if last.elapsed() >= REPORT_INTERVAL {
+                *last = Instant::now();
+                eprint!("\r{}", self.line(done));
+            }
+        }
+    }
+
+    fn line(&self, done: u64) -> String {
+        match self.total {
+            Some(total) if total > 0 => {
+                format!("{}: {}/{} ({:.0}%)", self.label, done, total, done as f64 * 100.0 / total as f64)
+            }
+            _ => format!("{}: {}", self.label, done),
+        }
+    }
+}
+
+impl Drop for Progress {
+    fn drop(&mut self) {
+        eprintln!("\r{}", self.line(*self.done.get_mut()));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn progress(total: Option<u64>) -> Progress {
+        Progress { label: "Indexing", total, done: AtomicU64::new(0), last_report: Mutex::new(Instant::now()) }
+    }
+
+    #[test]
+    fn test_progress_line() {
+        let with_total = progress(Some(8));
+        with_total.advance(1);
+        with_total.advance(1);
+        assert_eq!(with_total.line(with_total.done.load(Ordering::Relaxed)), "Indexing: 2/8 (25%)");
+        assert_eq!(with_total.line(8), "Indexing: 8/8 (100%)");
+
+        let without_total = progress(None);
+        without_total.advance(5000);
+        assert_eq!(without_total.line(without_total.done.load(Ordering::Relaxed)), "Indexing: 5000");
+        assert_eq!(progress(Some(0)).line(0), "Indexing: 0");
+    }
+}
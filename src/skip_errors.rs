@@ -0,0 +1,53 @@
+// SPDX-License-Identifier: MIT
+// Copyright (c) 2026 Zac Policzer
+
+//! `--skip-errors`: read what parses of a partly corrupt dump instead of stopping at the
+//! first bad sub-record. A sub-record's length comes from its own contents, so nothing after
+//! a bad one in the same segment can be trusted; the rest of that segment is skipped and
+//! reading carries on with the next segment, whose length is in its record header.
+
+use jvm_hprof::heap_dump::SubRecord;
+use nom::error::ErrorKind;
+use crate::analyzer::AnalyzerOptions;
+use crate::cancel::cancelled;
+use crate::error::AnalyzeError;
+
+/// What jvm-hprof's sub-record parser fails with: nom's error, holding the input it
+/// stopped at.
+pub(crate) type SubRecordError<'a> = nom::Err<(&'a [u8], ErrorKind)>;
+
+/// Sub-record `position` of heap dump segment `segment` didn't parse. `dump_start` is the
+/// address of the dump's first byte, if known, so the point the parser stopped at can be
+/// given as a byte offset from the start of the file. Only nom's error kind is kept, not
+/// the remaining input it carries.
+pub(crate) fn bad_sub_record(
+    dump_start: Option<usize>,
+    segment: usize,
+    position: usize,
+    e: &SubRecordError,
+) -> AnalyzeError {
+    let (offset, reason) = match e {
+        nom::Err::Error((rest, kind)) | nom::Err::Failure((rest, kind)) => (
+            dump_start.and_then(|start| (rest.as_ptr() as usize).checked_sub(start)).map(|o| o as u64),
+            kind.description().to_string(),
+        ),
+        nom::Err::Incomplete(_) => (None, "segment ends partway through it".to_string()),
+    };
+    AnalyzeError::BadSubRecord { segment, position, offset, reason }
+}
+
+/// `sub_records` with errors as `AnalyzeError`s, ending quietly at the first bad one when
+/// `options.skip_errors` is set. The index build has already reported it. Once
+/// `options.cancel` is set, it ends before the next sub-record.
+pub(crate) fn sub_records<'a, E>(
+    options: &AnalyzerOptions,
+    sub_records: impl Iterator<Item = Result<SubRecord<'a>, E>>,
+) -> impl Iterator<Item = Result<SubRecord<'a>, AnalyzeError>> {
+    let skip = options.skip_errors;
+    let cancel = options.cancel.clone();
+    sub_records.take_while(move |_| !cancelled(&cancel)).map_while(move |p| match p {
+        Ok(s) => Some(Ok(s)),
+        Err(_) if skip => None,
+        Err(_) => Some(Err(AnalyzeError::parse("heap dump sub-record"))),
+    })
+}
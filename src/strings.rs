@@ -0,0 +1,285 @@
+// SPDX-License-Identifier: MIT
+// Copyright (c) 2026 Zac Policzer
+
+use jvm_hprof::{Hprof, Id, Record};
+use jvm_hprof::heap_dump::{FieldValue, PrimitiveArrayType, SubRecord};
+use rayon::prelude::*;
+use crate::hprof_index::HprofIndex;
+use crate::id_hash::IdMap;
+use crate::skip_errors::sub_records;
+use crate::util::array_shallow_size;
+
+pub const STRING_CLASS_NAME: &str = "java/lang/String";
+
+// java.lang.String.coder values on JDK 9+ compact strings
+const CODER_LATIN1: i8 = 0;
+const CODER_UTF16: i8 = 1;
+
+/// The text of a java.lang.String instance, reconstructed from its backing array.
+pub struct DecodedString {
+    pub text: String,
+    /// Estimated shallow size of the backing byte[]/char[], header included.
+    pub backing_array_bytes: u64,
+}
+
+/// Raw backing array contents, before each owning String's coder is applied.
+enum BackingArray {
+    Bytes(Vec<u8>),
+    Chars(Vec<u16>),
+}
+
+/// Decode a JDK 9+ compact string `byte[]`. Latin-1 maps each byte to one code point;
+/// UTF-16 packs each code unit into two bytes in the dumping JVM's native order, which we
+/// assume is little-endian (x86/aarch64). Without a coder field the runtime predates compact
+/// strings or isn't HotSpot, so the bytes are read as UTF-8.
+pub fn decode_compact_string(bytes: &[u8], coder: Option<i8>) -> String {
+    match coder {
+        Some(CODER_LATIN1) => bytes.iter().map(|&b| b as char).collect(),
+        Some(CODER_UTF16) => {
+            let units: Vec<u16> = bytes.chunks_exact(2)
+                .map(|pair| u16::from_le_bytes([pair[0], pair[1]]))
+                .collect();
+            String::from_utf16_lossy(&units)
+        }
+        _ => String::from_utf8_lossy(bytes).into_owned(),
+    }
+}
+
+/// Decode a JDK 8 style `char[]` string value.
+pub fn decode_utf16_string(units: &[u16]) -> String {
+    String::from_utf16_lossy(units)
+}
+
+/// How this dump's java.lang.String keeps its text, told apart by the class's fields rather
+/// than by each String's backing array.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum StringEncoding {
+    /// JDK 8 and earlier: `value` is a `char[]` of UTF-16 code units.
+    Utf16Chars,
+    /// JDK 9+ compact strings: `value` is a `byte[]` decoded by the `coder` field at this
+    /// position in the flattened instance field list.
+    Compact { coder_field: usize },
+}
+
+/// Layout of java.lang.String in this dump: its class and the position of `value` in the
+/// flattened instance field list. Detected once per dump when the index is built.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct StringLayout {
+    pub string_class_obj_id: Id,
+    pub value_field: usize,
+    pub encoding: StringEncoding,
+}
+
+impl StringLayout {
+    /// The layout of the `java/lang/String` class among `classes`, or None if there is no
+    /// such class or it has no `value` field.
+    pub(crate) fn detect<'a>(
+        classes: impl IntoIterator<Item = (Id, &'a str)>,
+        field_names: impl Fn(Id) -> Option<Vec<&'a str>>,
+    ) -> Option<StringLayout> {
+        let (string_class_obj_id, _) = classes.into_iter().find(|(_, name)| *name == STRING_CLASS_NAME)?;
+        let names = field_names(string_class_obj_id)?;
+        let position = |wanted: &str| names.iter().position(|n| *n == wanted);
+        Some(StringLayout {
+            string_class_obj_id,
+            value_field: position("value")?,
+            encoding: match position("coder") {
+                Some(coder_field) => StringEncoding::Compact { coder_field },
+                None => StringEncoding::Utf16Chars,
+            },
+        })
+    }
+
+    /// Whether a String's backing array of this type holds its text under this layout.
+    fn backed_by(&self, array_type: PrimitiveArrayType) -> bool {
+        match self.encoding {
+            StringEncoding::Utf16Chars => matches!(array_type, PrimitiveArrayType::Char),
+            StringEncoding::Compact { .. } => matches!(array_type, PrimitiveArrayType::Byte),
+        }
+    }
+}
+
+/// Decode every java.lang.String in the dump, keyed by the String's object id.
+///
+/// Two parallel passes over the segments: the first reads each String's `value` reference and
+/// `coder`, the second pulls the referenced arrays, `char[]` or `byte[]` as the index's
+/// `StringLayout` says. Strings whose `value` is null, missing from the dump or an array of
+/// the other type are omitted.
+pub fn decode_strings(hprof: &Hprof, index: &HprofIndex, segments: &[Record]) -> IdMap<DecodedString> {
+    decode_strings_where(hprof, index, segments, |_| true)
+}
+
+/// Like `decode_strings`, but only for Strings whose object id satisfies `keep`.
+pub fn decode_strings_where(
+    hprof: &Hprof,
+    index: &HprofIndex,
+    segments: &[Record],
+    keep: impl Fn(Id) -> bool + Sync,
+) -> IdMap<DecodedString> {
+    fold_strings_where(
+        hprof,
+        index,
+        segments,
+        keep,
+        IdMap::default,
+        |strings, obj_id, decoded| {
+            strings.insert(obj_id, decoded);
+        },
+        |mut a, b| {
+            a.extend(b);
+            a
+        },
+    )
+}
+
+/// The passes of `decode_strings_where`, but each String is handed to `fold` as soon as its
+/// backing array is read, into one `A` per segment from `init`, and those are combined with
+/// `merge`. Only what the folds keep outlives its segment, where `decode_strings_where` holds
+/// every String's text at once.
+pub(crate) fn fold_strings_where<A: Send>(
+    hprof: &Hprof,
+    index: &HprofIndex,
+    segments: &[Record],
+    keep: impl Fn(Id) -> bool + Sync,
+    init: impl Fn() -> A + Sync + Send,
+    fold: impl Fn(&mut A, Id, DecodedString) + Sync + Send,
+    merge: impl Fn(A, A) -> A + Sync + Send,
+) -> A {
+    let layout = match index.string_layout {
+        Some(l) => l,
+        None => return init(),
+    };
+    let coder_field = match layout.encoding {
+        StringEncoding::Compact { coder_field } => Some(coder_field),
+        StringEncoding::Utf16Chars => None,
+    };
+    let fds = &index.class_instance_field_descriptors[&layout.string_class_obj_id];
+    let id_size = hprof.header().id_size();
+
+    // Pass 1: String obj id -> (value array id, coder)
+    let strings: Vec<(Id, Id, Option<i8>)> = segments.par_iter().flat_map_iter(|record| {
+        let mut found = Vec::new();
+        let segment = record.as_heap_dump_segment().unwrap().unwrap();
+        for p in sub_records(&index.options, segment.sub_records()) {
+            if let SubRecord::Instance(instance) = p.unwrap() {
+                if instance.class_obj_id() != layout.string_class_obj_id || !keep(instance.obj_id()) {
+                    continue;
+                }
+                let mut value_id = None;
+                let mut coder = None;
+                let mut input: &[u8] = instance.fields();
+                for (i, fd) in fds.iter().enumerate() {
+                    let (rest, val) = fd.field_type().parse_value(input, id_size).unwrap();
+                    input = rest;
+                    match val {
+                        FieldValue::ObjectId(id) if i == layout.value_field => value_id = id,
+                        FieldValue::Byte(b) if Some(i) == coder_field => coder = Some(b),
+                        _ => {}
+                    }
+                }
+                if let Some(value_id) = value_id {
+                    found.push((instance.obj_id(), value_id, coder));
+                }
+            }
+        }
+        found
+    }).collect();
+
+    // Several Strings can share one backing array, e.g. after `new String(other)`
+    let mut owners: IdMap<Vec<(Id, Option<i8>)>> = IdMap::default();
+    for (obj_id, value_id, coder) in strings {
+        owners.entry(value_id).or_default().push((obj_id, coder));
+    }
+
+    // Pass 2: decode each referenced array for its Strings as it is read
+    segments.par_iter().map(|record| {
+        let mut acc = init();
+        let segment = record.as_heap_dump_segment().unwrap().unwrap();
+        for p in sub_records(&index.options, segment.sub_records()) {
+            if let SubRecord::PrimitiveArray(pa) = p.unwrap() {
+                let Some(owners) = owners.get(&pa.obj_id()) else {
+                    continue;
+                };
+                if !layout.backed_by(pa.primitive_type()) {
+                    continue;
+                }
+                let array = match pa.primitive_type() {
+                    PrimitiveArrayType::Byte => BackingArray::Bytes(pa.bytes().unwrap().map(|v| v.unwrap() as u8).collect()),
+                    PrimitiveArrayType::Char => BackingArray::Chars(pa.chars().unwrap().map(|v| v.unwrap() as u16).collect()),
+                    _ => continue,
+                };
+                for (obj_id, coder) in owners {
+                    let decoded = match &array {
+                        BackingArray::Bytes(bytes) => DecodedString {
+                            text: decode_compact_string(bytes, *coder),
+                            backing_array_bytes: array_shallow_size(bytes.len() as u64, 1, id_size),
+                        },
+                        BackingArray::Chars(units) => DecodedString {
+                            text: decode_utf16_string(units),
+                            backing_array_bytes: array_shallow_size(units.len() as u64, 2, id_size),
+                        },
+                    };
+                    fold(&mut acc, *obj_id, decoded);
+                }
+            }
+        }
+        acc
+    }).reduce(&init, &merge)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn detect(fields: &[&'static str]) -> Option<StringLayout> {
+        let classes = [(Id::from(1), "java/lang/Object"), (Id::from(2), STRING_CLASS_NAME)];
+        StringLayout::detect(classes, |id| (id == Id::from(2)).then(|| fields.to_vec()))
+    }
+
+    #[test]
+    fn test_detect_string_layout() {
+        let compact = detect(&["value", "coder", "hash", "hashIsZero"]).unwrap();
+        assert_eq!(compact.string_class_obj_id, Id::from(2));
+        assert_eq!(compact.value_field, 0);
+        assert_eq!(compact.encoding, StringEncoding::Compact { coder_field: 1 });
+        assert!(compact.backed_by(PrimitiveArrayType::Byte));
+        assert!(!compact.backed_by(PrimitiveArrayType::Char));
+
+        let jdk8 = detect(&["hash", "value"]).unwrap();
+        assert_eq!(jdk8.value_field, 1);
+        assert_eq!(jdk8.encoding, StringEncoding::Utf16Chars);
+        assert!(jdk8.backed_by(PrimitiveArrayType::Char));
+        assert!(!jdk8.backed_by(PrimitiveArrayType::Byte));
+
+        assert_eq!(detect(&["hash"]), None);
+    }
+
+    #[test]
+    fn test_decode_latin1() {
+        let bytes = [b'c', b'a', b'f', 0xE9];
+        assert_eq!(decode_compact_string(&bytes, Some(CODER_LATIN1)), "café");
+    }
+
+    #[test]
+    fn test_decode_compact_utf16() {
+        // "h€" little-endian: 0x0068 0x20AC
+        let bytes = [0x68, 0x00, 0xAC, 0x20];
+        assert_eq!(decode_compact_string(&bytes, Some(CODER_UTF16)), "h€");
+    }
+
+    #[test]
+    fn test_decode_without_coder_is_utf8() {
+        assert_eq!(decode_compact_string("häh".as_bytes(), None), "häh");
+    }
+
+    #[test]
+    fn test_decode_char_array() {
+        let units: Vec<u16> = "snow ☃ 😀".encode_utf16().collect();
+        assert_eq!(decode_utf16_string(&units), "snow ☃ 😀");
+    }
+
+    #[test]
+    fn test_decode_unpaired_surrogate_is_lossy() {
+        assert_eq!(decode_utf16_string(&[0x61, 0xD800]), "a\u{FFFD}");
+    }
+}
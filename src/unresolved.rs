@@ -0,0 +1,45 @@
+// SPDX-License-Identifier: MIT
+// Copyright (c) 2026 Zac Policzer
+
+//! References whose type can't be resolved, such as `(class not found)` in `dump-objects` or
+//! `(unresolved)` in a parquet reference struct. Each command run counts its own and returns
+//! the count; `fail_on_unresolved` in its options makes the first one an error instead, for
+//! validation runs.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use jvm_hprof::Id;
+use crate::error::AnalyzeError;
+
+/// The unresolved references one run printed or exported, each time it was printed or
+/// exported. Shared by the segments a run processes in parallel.
+#[derive(Debug, Default)]
+pub(crate) struct UnresolvedReferences {
+    fail: bool,
+    count: AtomicU64,
+}
+
+impl UnresolvedReferences {
+    /// Count them, and fail on them if `fail` is set.
+    pub fn new(fail: bool) -> Self {
+        UnresolvedReferences { fail, count: AtomicU64::new(0) }
+    }
+
+    /// Field `field_name` of object `obj_id` refers to `ref_id`, whose type can't be
+    /// resolved; for an array element, `field_name` is its position, as in `[2]`. Counted,
+    /// and an error for the caller to stop at if failing on unresolved references.
+    pub fn report(&self, obj_id: Id, field_name: &str, ref_id: Id) -> Result<(), AnalyzeError> {
+        self.count.fetch_add(1, Ordering::Relaxed);
+        match self.fail {
+            true => Err(AnalyzeError::UnresolvedField {
+                obj_id: obj_id.id(),
+                field_name: field_name.to_string(),
+                ref_id: ref_id.id(),
+            }),
+            false => Ok(()),
+        }
+    }
+
+    pub fn count(&self) -> u64 {
+        self.count.load(Ordering::Relaxed)
+    }
+}
@@ -2,48 +2,375 @@
 // Copyright (c) 2026 Zac Policzer
 
 use std::collections;
+use std::fs::File;
+use std::io::Write;
+use std::path::{Path, PathBuf};
 use arrow_array::RecordBatch;
-use arrow_schema::{DataType, Field, Fields, Schema};
-use jvm_hprof::{Hprof, Id};
-use jvm_hprof::heap_dump::{FieldDescriptor, FieldType, FieldValue};
+use arrow_ipc::writer::FileWriter;
+use arrow_schema::{DataType, Field, Fields, Schema, SchemaRef};
+use jvm_hprof::{Hprof, Id, IdSize};
+use jvm_hprof::heap_dump::{FieldDescriptor, FieldType, FieldValue, PrimitiveArray, PrimitiveArrayType};
 use parquet::arrow::ArrowWriter;
-use parquet::basic::Compression;
-use parquet::file::properties::WriterProperties;
-
-// TODO: This opens and flushes a file with every call to this function.  This is not efficient.
-// the writer itself has a notion of how much memory it's taking up.  What we could do is keep
-// an array of open writers, and when the cumulative size of the memory getting used by these writers
-// reaches some threshhold, we could them flush them all and then start buffering again.
-// For MVP this 'seems' to be fast enough, but it's an easy opportunity to speed things up in exchange
-// for using more memory.
-pub fn write_to_parquet(filename_prefix: &str, batch: RecordBatch) {
-    let filename_prefix = filename_prefix.replace("/", ".");
-    
-    // We need to open the file if it exists, or create it if it doesn't
-    let file = std::fs::OpenOptions::new()
-        .write(true)
-        .create(true)
-        .append(true)
-        .open(format!("parquet/{}.parquet", filename_prefix))
-        .unwrap();
-    
-    // let file = std::fs::File::create(format!("parquet/{}.parquet", filename_prefix)).unwrap();
-    // let file = std::fs::File::create(format!("{}.parquet", filename_prefix)).unwrap();
-
-    // WriterProperties can be used to set Parquet file options
-    let props = WriterProperties::builder()
-        .set_compression(Compression::SNAPPY)// TODO: experiment with Gzip
-        .build();
-    
-    let mut writer = ArrowWriter::try_new(file, batch.schema(), Some(props)).unwrap();
-
-    writer.write(&batch).unwrap();
+use parquet::basic::{Compression, GzipLevel, ZstdLevel};
+use parquet::file::properties::{EnabledStatistics, WriterProperties};
+use parquet::schema::types::ColumnPath;
+use crate::avro::AvroFileWriter;
+use crate::error::AnalyzeError;
+use crate::json::write_json_str;
+
+/// Top-level columns holding object ids, the ones `--bloom-filter` covers. The `id` inside
+/// a reference struct isn't listed: its path is `<field>.id`, different for every class.
+pub const OBJECT_ID_COLUMNS: [&str; 4] = ["obj_id", "from_id", "to_id", "ref_id"];
+
+/// Ending of the file `--emit-schema-json` writes next to each output file.
+pub const SCHEMA_JSON_SUFFIX: &str = ".schema.json";
+
+/// Which kind of file the batches are written to.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OutputFormat {
+    #[default]
+    Parquet,
+    /// Arrow IPC files (Feather v2), for readers that take Arrow as-is and would rather not
+    /// pay for the parquet encode and decode. The parquet settings don't apply.
+    Ipc,
+    /// Avro object container files, one data block per batch, for Kafka and Hadoop tools.
+    /// The parquet settings don't apply and blocks aren't compressed.
+    Avro,
+}
+
+impl OutputFormat {
+    pub fn extension(self) -> &'static str {
+        match self {
+            OutputFormat::Parquet => "parquet",
+            OutputFormat::Ipc => "arrow",
+            OutputFormat::Avro => "avro",
+        }
+    }
+}
+
+/// How every parquet file is written: `--compression`, `--row-group-size`, `--bloom-filter`
+/// and `--emit-schema-json`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WriterSettings {
+    pub format: OutputFormat,
+    pub compression: Compression,
+    /// Rows per row group, or the parquet crate's default (1M rows) when None.
+    pub max_row_group_size: Option<usize>,
+    /// Write a bloom filter for each of `OBJECT_ID_COLUMNS` a file has.
+    pub bloom_filter: bool,
+    /// Also write each file's schema to a `<name>.schema.json` next to it.
+    pub emit_schema_json: bool,
+}
+
+impl WriterSettings {
+    pub fn new(compression: Compression) -> Self {
+        WriterSettings {
+            format: OutputFormat::Parquet,
+            compression,
+            max_row_group_size: None,
+            bloom_filter: false,
+            emit_schema_json: false,
+        }
+    }
+
+    pub fn properties(&self) -> WriterProperties {
+        // Page-level min/max statistics are what lets a reader skip row groups by id range.
+        // They're the parquet crate's default, but set here so they don't silently go away.
+        let mut builder = WriterProperties::builder()
+            .set_compression(self.compression)
+            .set_statistics_enabled(EnabledStatistics::Page);
+        if let Some(rows) = self.max_row_group_size {
+            builder = builder.set_max_row_group_size(rows);
+        }
+        if self.bloom_filter {
+            for column in OBJECT_ID_COLUMNS {
+                builder = builder.set_column_bloom_filter_enabled(ColumnPath::from(column), true);
+            }
+        }
+        builder.build()
+    }
+}
+
+/// One open output file, in whichever format the settings ask for.
+pub(crate) struct BatchWriter {
+    output: FormatWriter,
+    path: PathBuf,
+    columns: usize,
+}
+
+enum FormatWriter {
+    Parquet(ArrowWriter<File>),
+    Ipc { writer: FileWriter<File>, rows: usize },
+    Avro(AvroFileWriter),
+}
+
+/// What a closed output file holds, as its writer reports it.
+pub(crate) struct WrittenFile {
+    pub rows: i64,
+    pub columns: usize,
+    pub bytes: u64,
+}
+
+/// A parquet or Arrow writer error on `path`, reported as the I/O error it usually is.
+fn write_failed(path: &Path, e: impl Into<Box<dyn std::error::Error + Send + Sync>>) -> AnalyzeError {
+    AnalyzeError::io(path, std::io::Error::other(e))
+}
+
+impl BatchWriter {
+    pub(crate) fn create(path: &Path, schema: SchemaRef, settings: &WriterSettings) -> Result<Self, AnalyzeError> {
+        if settings.emit_schema_json {
+            let sidecar = path.with_extension(&SCHEMA_JSON_SUFFIX[1..]);
+            std::fs::write(&sidecar, schema_json(&schema)).map_err(|e| AnalyzeError::io(&sidecar, e))?;
+        }
+        let file = File::create(path).map_err(|e| AnalyzeError::io(path, e))?;
+        let columns = schema.fields().len();
+        let output = match settings.format {
+            OutputFormat::Parquet => FormatWriter::Parquet(
+                ArrowWriter::try_new(file, schema, Some(settings.properties())).map_err(|e| write_failed(path, e))?,
+            ),
+            OutputFormat::Ipc => FormatWriter::Ipc {
+                writer: FileWriter::try_new(file, &schema).map_err(|e| write_failed(path, e))?,
+                rows: 0,
+            },
+            OutputFormat::Avro => {
+                let name = path.file_stem().map_or("row".into(), |stem| stem.to_string_lossy());
+                FormatWriter::Avro(AvroFileWriter::create(file, &name, &schema).map_err(|e| AnalyzeError::io(path, e))?)
+            }
+        };
+        Ok(BatchWriter { output, path: path.to_path_buf(), columns })
+    }
+
+    pub(crate) fn write(&mut self, batch: &RecordBatch) -> Result<(), AnalyzeError> {
+        match &mut self.output {
+            FormatWriter::Parquet(writer) => writer.write(batch).map_err(|e| write_failed(&self.path, e))?,
+            FormatWriter::Ipc { writer, rows } => {
+                writer.write(batch).map_err(|e| write_failed(&self.path, e))?;
+                *rows += batch.num_rows();
+            }
+            FormatWriter::Avro(writer) => writer.write(batch).map_err(|e| AnalyzeError::io(&self.path, e))?,
+        }
+        Ok(())
+    }
+
+    /// Bytes held in memory for the current row group. IPC and Avro batches go straight to
+    /// the file.
+    pub(crate) fn in_progress_size(&self) -> usize {
+        match &self.output {
+            FormatWriter::Parquet(writer) => writer.in_progress_size(),
+            FormatWriter::Ipc { .. } | FormatWriter::Avro(_) => 0,
+        }
+    }
+
+    pub(crate) fn flush(&mut self) -> Result<(), AnalyzeError> {
+        if let FormatWriter::Parquet(writer) = &mut self.output {
+            writer.flush().map_err(|e| write_failed(&self.path, e))?;
+        }
+        Ok(())
+    }
+
+    /// Write the footer and report what the file holds.
+    pub(crate) fn close(self) -> Result<WrittenFile, AnalyzeError> {
+        let path = self.path;
+        let (rows, bytes) = match self.output {
+            FormatWriter::Parquet(mut writer) => {
+                let rows = writer.finish().map_err(|e| write_failed(&path, e))?.num_rows;
+                (rows, writer.bytes_written() as u64)
+            }
+            FormatWriter::Ipc { mut writer, rows } => {
+                writer.finish().map_err(|e| write_failed(&path, e))?;
+                (rows as i64, writer.get_ref().metadata().map_or(0, |m| m.len()))
+            }
+            FormatWriter::Avro(writer) => (writer.rows as i64, writer.bytes),
+        };
+        Ok(WrittenFile { rows, columns: self.columns, bytes })
+    }
+}
+
+/// `{"fields": [...]}` with each field's `name`, Arrow `type` and `nullable`. Struct fields
+/// list their children under `fields` and list fields their element under `item`, so the
+/// shape can be read without parsing Arrow's type names.
+pub(crate) fn schema_json(schema: &Schema) -> String {
+    let mut out = Vec::new();
+    write!(out, "{{\"fields\": ").unwrap();
+    write_fields_json(&mut out, schema.fields());
+    writeln!(out, "}}").unwrap();
+    String::from_utf8(out).unwrap()
+}
+
+fn write_fields_json(out: &mut Vec<u8>, fields: &Fields) {
+    write!(out, "[").unwrap();
+    for (i, field) in fields.iter().enumerate() {
+        if i > 0 {
+            write!(out, ", ").unwrap();
+        }
+        write_field_json(out, field);
+    }
+    write!(out, "]").unwrap();
+}
+
+fn write_field_json(out: &mut Vec<u8>, field: &Field) {
+    write!(out, "{{\"name\": ").unwrap();
+    write_json_str(out, field.name()).unwrap();
+    let type_name = match field.data_type() {
+        DataType::Struct(_) => "Struct".to_string(),
+        DataType::List(_) => "List".to_string(),
+        other => format!("{:?}", other),
+    };
+    write!(out, ", \"type\": ").unwrap();
+    write_json_str(out, &type_name).unwrap();
+    write!(out, ", \"nullable\": {}", field.is_nullable()).unwrap();
+    match field.data_type() {
+        DataType::Struct(children) => {
+            write!(out, ", \"fields\": ").unwrap();
+            write_fields_json(out, children);
+        }
+        DataType::List(item) => {
+            write!(out, ", \"item\": ").unwrap();
+            write_field_json(out, item);
+        }
+        _ => {}
+    }
+    write!(out, "}}").unwrap();
+}
+
+/// Log one finished output file; `--verbose` shows these.
+pub(crate) fn log_file_written(file_key: &str, path: &Path, file: &WrittenFile) {
+    log::info!(
+        "wrote {} to {}: {} rows, {} columns, {} bytes",
+        file_key, path.display(), file.rows, file.columns, file.bytes,
+    );
+}
+
+// TODO: Each call opens, writes and closes a whole file, so every batch destined for
+// the file has to be handed over at once. The writer pool in dump_to_parquet keeps writers
+// open across segments instead; this helper is for small one-shot files.
+/// Write `batches` to `<out_dir>/<filename_prefix>.parquet` as a single parquet file, or
+/// to `.arrow` or `.avro` when `writer.format` is IPC or Avro.
+/// The file is truncated rather than appended to: appending another ArrowWriter's output
+/// leaves several footers in one file, which Arrow/DuckDB/Spark all reject.
+pub fn write_to_parquet(
+    out_dir: &Path,
+    filename_prefix: &str,
+    batches: &[RecordBatch],
+    writer: WriterSettings,
+) -> Result<(), AnalyzeError> {
+    let filename_prefix = parquet_file_stem(filename_prefix);
+
+    let schema = match batches.first() {
+        Some(batch) => batch.schema(),
+        None => return Ok(()),
+    };
+
+    std::fs::create_dir_all(out_dir).map_err(|e| AnalyzeError::io(out_dir, e))?;
+
+    let path = out_dir.join(format!("{}.{}", filename_prefix, writer.format.extension()));
+    let mut writer = BatchWriter::create(&path, schema, &writer)?;
+
+    for batch in batches {
+        writer.write(batch)?;
+    }
 
     // writer must be closed to write footer
-    writer.close().unwrap();
-    if filename_prefix == "sun.nio.fs.UnixPath" {
-        println!("Writing to file: {}", filename_prefix);
+    log_file_written(&filename_prefix, &path, &writer.close()?);
+    Ok(())
+}
+
+/// Device names Windows reserves whatever the extension, compared case-insensitively.
+const WINDOWS_RESERVED_NAMES: [&str; 22] = [
+    "CON", "PRN", "AUX", "NUL",
+    "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8", "COM9",
+    "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+
+/// File name (without `.parquet`) for an output file key such as `java/util/HashMap$Node_123`.
+/// Package separators become `.` so names stay readable; every other byte outside
+/// `[A-Za-z0-9_-]`, including `.`, `$`, `[`, `;` and `~` itself, is written as `~XX` hex.
+/// That keeps the mapping reversible, so two keys never share a file, and the names safe
+/// to glob in a shell. A stem that Windows would read as a device name (`CON`, `nul.x`)
+/// has its first letter escaped too.
+pub fn parquet_file_stem(file_key: &str) -> String {
+    let mut stem = String::with_capacity(file_key.len());
+    for &b in file_key.as_bytes() {
+        match b {
+            b'/' => stem.push('.'),
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'_' | b'-' => stem.push(b as char),
+            _ => stem.push_str(&format!("~{:02X}", b)),
+        }
+    }
+    let device = stem.split('.').next().unwrap_or("");
+    if WINDOWS_RESERVED_NAMES.iter().any(|r| r.eq_ignore_ascii_case(device)) {
+        stem.replace_range(..1, &format!("~{:02X}", stem.as_bytes()[0]));
     }
+    stem
+}
+
+/// Codec names accepted by `--compression`.
+pub const COMPRESSION_CODECS: [&str; 5] = ["none", "snappy", "gzip", "zstd", "lz4"];
+
+/// Parse a `--compression` codec name and optional `--compression-level`. Levels apply to
+/// gzip (0-10) and zstd (1-22) only.
+pub fn parse_compression(codec: &str, level: Option<u32>) -> Result<Compression, String> {
+    let compression = match (codec, level) {
+        ("none", None) => Compression::UNCOMPRESSED,
+        ("snappy", None) => Compression::SNAPPY,
+        ("lz4", None) => Compression::LZ4_RAW,
+        ("gzip", level) => Compression::GZIP(match level {
+            Some(l) => GzipLevel::try_new(l).map_err(|e| format!("invalid gzip level {}: {}", l, e))?,
+            None => GzipLevel::default(),
+        }),
+        ("zstd", level) => Compression::ZSTD(match level {
+            Some(l) => ZstdLevel::try_new(l as i32).map_err(|e| format!("invalid zstd level {}: {}", l, e))?,
+            None => ZstdLevel::default(),
+        }),
+        (codec, Some(_)) if COMPRESSION_CODECS.contains(&codec) => {
+            return Err(format!("--compression-level only applies to gzip and zstd, not {}", codec));
+        }
+        (codec, _) => {
+            return Err(format!("unknown compression codec '{}' (expected one of {})", codec, COMPRESSION_CODECS.join(", ")));
+        }
+    };
+    Ok(compression)
+}
+
+/// Check the fixed HPROF header (`JAVA PROFILE <version>\0`, id size, timestamp) so a
+/// wrong or truncated file gets a clear error instead of a nom error from `parse_hprof`.
+pub fn check_hprof_header(path: &Path, bytes: &[u8]) -> Result<(), AnalyzeError> {
+    const MAGIC: &[u8] = b"JAVA PROFILE ";
+    let not_hprof = |reason: String| AnalyzeError::NotHprof { path: path.to_path_buf(), reason };
+    if !bytes.starts_with(MAGIC) {
+        return Err(not_hprof("expected magic 'JAVA PROFILE 1.0.x'".to_string()));
+    }
+    let label_end = bytes.iter().take(64).position(|&b| b == 0)
+        .ok_or_else(|| not_hprof("format label is not terminated".to_string()))?;
+    let rest = &bytes[label_end + 1..];
+    if rest.len() < 12 {
+        return Err(not_hprof("header is truncated".to_string()));
+    }
+    match u32::from_be_bytes([rest[0], rest[1], rest[2], rest[3]]) {
+        4 | 8 => Ok(()),
+        other => Err(not_hprof(format!("unsupported id size {}", other))),
+    }
+}
+
+/// Bytes of the 9-byte prefix (tag, micros, body length) framing every top-level record.
+const RECORD_PREFIX_BYTES: usize = 9;
+
+/// Total size of each top-level record, prefix included, in file order, read from the
+/// length in its framing. `bytes` must start with a header `check_hprof_header` accepts;
+/// iteration stops at the first record that runs past the end of the file.
+pub fn record_sizes(bytes: &[u8]) -> impl Iterator<Item = u64> + '_ {
+    let label_end = bytes.iter().position(|&b| b == 0).unwrap_or(bytes.len());
+    let mut at = label_end + 1 + 12;
+    std::iter::from_fn(move || {
+        let prefix = bytes.get(at..at + RECORD_PREFIX_BYTES)?;
+        let body_len = u32::from_be_bytes([prefix[5], prefix[6], prefix[7], prefix[8]]) as usize;
+        let size = RECORD_PREFIX_BYTES + body_len;
+        if at + size > bytes.len() {
+            return None;
+        }
+        at += size;
+        Some(size as u64)
+    })
 }
 
 const MISSING_UTF8: &str = "(missing utf8)";
@@ -57,70 +384,46 @@ pub fn generate_schema_from_type(
     mut field_val_input: &[u8],
     utf8: &collections::HashMap<Id, &str>,
     declaring_classes: Option<&Vec<&str>>,
+    references: ReferenceEncoding,
 ) -> Schema
 {
+    let field_names = disambiguated_field_names(&field_base_names(field_descriptors, utf8), declaring_classes);
     let mut field_vec: Vec<Field> = vec![];
-    let mut name_counts: collections::HashMap<&str, usize> = collections::HashMap::new();
-    for (i, fd) in field_descriptors.iter().enumerate() {
+    for (fd, field_name) in field_descriptors.iter().zip(field_names) {
         let (input, field_val) = fd
             .field_type()
             .parse_value(field_val_input, hprof.header().id_size())
             .unwrap();
         field_val_input = input;
-        let base_name: &str = utf8.get(&fd.name_id()).unwrap_or_else(|| &MISSING_UTF8);
-        let count = name_counts.entry(base_name).or_insert(0);
-        let field_name = if *count == 0 {
-            base_name.to_string()
-        } else {
-            // Prefix with declaring class short name for disambiguation
-            let class_prefix = declaring_classes
-                .and_then(|dc| dc.get(i))
-                .map(|c| c.rsplit('/').next().unwrap_or(c))
-                .unwrap_or("unknown");
-            format!("{}@{}", class_prefix, base_name)
-        };
-        *count += 1;
         match field_val {
-            FieldValue::ObjectId(Some(_)) => {
-                // All reference types (instance, primitive array, class, unresolvable)
-                // use the same schema: Struct{id, type}
-                field_vec.push(Field::new(field_name, DataType::Struct(
-                    Fields::from(vec![
-                        Field::new("id", DataType::UInt64, false),
-                        Field::new("type", DataType::Utf8, false)])
-                ), false));
-            }
-            FieldValue::ObjectId(None) => {
-                field_vec.push(Field::new(field_name, DataType::Struct(
-                    Fields::from(vec![
-                        Field::new("id", DataType::UInt64, false),
-                        Field::new("type", DataType::Utf8, false)])
-                ), false));
-                // field_vec.push(Field::new(field_name, DataType::Null, true));
+            FieldValue::ObjectId(_) => {
+                // All reference types (instance, primitive array, class, unresolvable, null)
+                // use the same schema: Struct{id, type} or a bare id
+                field_vec.push(Field::new(field_name, references.data_type(), true));
             }
-            FieldValue::Boolean(v) => {
-                field_vec.push(Field::new(field_name, DataType::Boolean, false));
+            FieldValue::Boolean(_) => {
+                field_vec.push(Field::new(field_name, DataType::Boolean, true));
             }
-            FieldValue::Char(v) => {
-                field_vec.push(Field::new(field_name, DataType::UInt16, false));
+            FieldValue::Char(_) => {
+                field_vec.push(Field::new(field_name, DataType::UInt16, true));
             }
-            FieldValue::Float(v) => {
-                field_vec.push(Field::new(field_name, DataType::Float32, false));
+            FieldValue::Float(_) => {
+                field_vec.push(Field::new(field_name, DataType::Float32, true));
             }
-            FieldValue::Double(v) => {
-                field_vec.push(Field::new(field_name, DataType::Float64, false));
+            FieldValue::Double(_) => {
+                field_vec.push(Field::new(field_name, DataType::Float64, true));
             }
-            FieldValue::Byte(v) => {
-                field_vec.push(Field::new(field_name, DataType::Int8, false));
+            FieldValue::Byte(_) => {
+                field_vec.push(Field::new(field_name, DataType::Int8, true));
             }
-            FieldValue::Short(v) => {
-                field_vec.push(Field::new(field_name, DataType::Int16, false));
+            FieldValue::Short(_) => {
+                field_vec.push(Field::new(field_name, DataType::Int16, true));
             }
-            FieldValue::Int(v) => {
-                field_vec.push(Field::new(field_name, DataType::Int32, false));
+            FieldValue::Int(_) => {
+                field_vec.push(Field::new(field_name, DataType::Int32, true));
             }
-            FieldValue::Long(v) => {
-                field_vec.push(Field::new(field_name, DataType::Int64, false));
+            FieldValue::Long(_) => {
+                field_vec.push(Field::new(field_name, DataType::Int64, true));
             }
         }
     }
@@ -128,37 +431,97 @@ pub fn generate_schema_from_type(
     Schema::new(field_vec)
 }
 
-/// Generate an Arrow schema from field descriptors and their types alone.
-/// No instance data required — the type mapping is deterministic from FieldType.
-pub fn generate_schema_from_descriptors(
+/// The utf8 name of each field descriptor, in descriptor order.
+pub fn field_base_names<'a>(
     field_descriptors: &[FieldDescriptor],
-    utf8: &collections::HashMap<Id, &str>,
-    declaring_classes: Option<&Vec<&str>>,
-    robo_mode: bool,
-) -> Schema {
-    let ref_struct_type = DataType::Struct(Fields::from(vec![
-        Field::new("id", DataType::UInt64, false),
-        Field::new("type", DataType::Utf8, false),
-    ]));
+    utf8: &collections::HashMap<Id, &'a str>,
+) -> Vec<&'a str> {
+    field_descriptors.iter()
+        .map(|fd| utf8.get(&fd.name_id()).copied().unwrap_or(MISSING_UTF8))
+        .collect()
+}
 
-    let mut field_vec: Vec<Field> = vec![];
-    let mut name_counts: collections::HashMap<&str, usize> = collections::HashMap::new();
-    for (i, fd) in field_descriptors.iter().enumerate() {
-        let base_name: &str = utf8.get(&fd.name_id()).unwrap_or_else(|| &MISSING_UTF8);
-        let count = name_counts.entry(base_name).or_insert(0);
-        let field_name = if *count == 0 {
-            base_name.to_string()
-        } else {
+/// Column names for a flattened field descriptor list (subclass fields first, then each
+/// superclass in turn). The first field with a given name keeps it; a later field with the
+/// same name — a superclass field shadowed by a subclass — is prefixed with its declaring
+/// class's short name, e.g. `Parent@count`. Names are guaranteed unique, with a numeric
+/// suffix as a last resort when two declaring classes share a short name.
+pub fn disambiguated_field_names(
+    base_names: &[&str],
+    declaring_classes: Option<&Vec<&str>>,
+) -> Vec<String> {
+    let mut taken: collections::HashSet<String> = collections::HashSet::new();
+    let mut names = Vec::with_capacity(base_names.len());
+    for (i, base_name) in base_names.iter().enumerate() {
+        let candidate = if taken.contains(*base_name) {
+            // Prefix with declaring class short name for disambiguation
             let class_prefix = declaring_classes
                 .and_then(|dc| dc.get(i))
                 .map(|c| c.rsplit('/').next().unwrap_or(c))
                 .unwrap_or("unknown");
             format!("{}@{}", class_prefix, base_name)
+        } else {
+            base_name.to_string()
         };
-        *count += 1;
+        let mut name = candidate.clone();
+        let mut suffix = 2;
+        while taken.contains(&name) {
+            name = format!("{}_{}", candidate, suffix);
+            suffix += 1;
+        }
+        taken.insert(name.clone());
+        names.push(name);
+    }
+    names
+}
 
+/// Child fields of the `{id, type}` struct used for object references in default mode.
+/// `type` is dictionary encoded: a dump has millions of references but only as many types
+/// as classes, so a batch holds each type name once instead of once per reference.
+pub fn reference_struct_fields() -> Fields {
+    Fields::from(vec![
+        Field::new("id", DataType::UInt64, false),
+        Field::new("type", DataType::Dictionary(Box::new(DataType::Int32), Box::new(DataType::Utf8)), false),
+    ])
+}
+
+/// How object references are written in the parquet export's default mode. Robo mode
+/// always writes bare ids, with 0 for null.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ReferenceEncoding {
+    /// `{id, type}` structs; a null reference is `{id: 0, type: "null"}`, so no column holds nulls.
+    Struct,
+    /// `{id, type}` structs; a null reference is an Arrow null.
+    #[default]
+    NullableStruct,
+    /// Bare `UInt64` ids without the resolved type; a null reference is an Arrow null.
+    IdOnly,
+}
+
+impl ReferenceEncoding {
+    /// The Arrow type of a reference column or object array element.
+    pub fn data_type(self) -> DataType {
+        match self {
+            ReferenceEncoding::IdOnly => DataType::UInt64,
+            _ => DataType::Struct(reference_struct_fields()),
+        }
+    }
+}
+
+/// Generate an Arrow schema from field descriptors and their types alone.
+/// No instance data required — the type mapping is deterministic from FieldType.
+pub fn generate_schema_from_descriptors(
+    field_descriptors: &[FieldDescriptor],
+    utf8: &collections::HashMap<Id, &str>,
+    declaring_classes: Option<&Vec<&str>>,
+    references: ReferenceEncoding,
+) -> Schema {
+
+    let field_names = disambiguated_field_names(&field_base_names(field_descriptors, utf8), declaring_classes);
+    let mut field_vec: Vec<Field> = vec![];
+    for (fd, field_name) in field_descriptors.iter().zip(field_names) {
         let data_type = match fd.field_type() {
-            FieldType::ObjectId => if robo_mode { DataType::UInt64 } else { ref_struct_type.clone() },
+            FieldType::ObjectId => references.data_type(),
             FieldType::Boolean => DataType::Boolean,
             FieldType::Char => DataType::UInt16,
             FieldType::Float => DataType::Float32,
@@ -168,8 +531,378 @@ pub fn generate_schema_from_descriptors(
             FieldType::Int => DataType::Int32,
             FieldType::Long => DataType::Int64,
         };
-        field_vec.push(Field::new(field_name, data_type, false));
+        field_vec.push(Field::new(field_name, data_type, true));
     }
 
     Schema::new(field_vec)
-}
\ No newline at end of file
+}
+
+// ---------------------------------------------------------------------------
+// Shallow size estimates
+// ---------------------------------------------------------------------------
+// HPROF records field values but not the JVM's in-memory layout, so sizes are estimated
+// HotSpot-style: an object header of mark word + class pointer (one id each), arrays add a
+// 4-byte length, and every object is padded to 8 bytes. Field packing and compressed oops
+// are not modelled.
+
+pub fn id_size_bytes(id_size: IdSize) -> u64 {
+    match id_size {
+        IdSize::U32 => 4,
+        IdSize::U64 => 8,
+    }
+}
+
+/// Bytes one value of `field_type` takes; references are one id wide.
+pub fn field_type_size(field_type: FieldType, id_size: IdSize) -> u64 {
+    match field_type {
+        FieldType::ObjectId => id_size_bytes(id_size),
+        FieldType::Boolean | FieldType::Byte => 1,
+        FieldType::Char | FieldType::Short => 2,
+        FieldType::Float | FieldType::Int => 4,
+        FieldType::Double | FieldType::Long => 8,
+    }
+}
+
+pub fn primitive_array_element_size(primitive_type: PrimitiveArrayType) -> u64 {
+    match primitive_type {
+        PrimitiveArrayType::Boolean | PrimitiveArrayType::Byte => 1,
+        PrimitiveArrayType::Char | PrimitiveArrayType::Short => 2,
+        PrimitiveArrayType::Float | PrimitiveArrayType::Int => 4,
+        PrimitiveArrayType::Double | PrimitiveArrayType::Long => 8,
+    }
+}
+
+/// The Java name of an array of `primitive_type`, e.g. `int[]`, without allocating one per
+/// array or reference that needs it.
+pub fn primitive_array_type_name(primitive_type: PrimitiveArrayType) -> &'static str {
+    match primitive_type {
+        PrimitiveArrayType::Boolean => "boolean[]",
+        PrimitiveArrayType::Byte => "byte[]",
+        PrimitiveArrayType::Char => "char[]",
+        PrimitiveArrayType::Short => "short[]",
+        PrimitiveArrayType::Int => "int[]",
+        PrimitiveArrayType::Long => "long[]",
+        PrimitiveArrayType::Float => "float[]",
+        PrimitiveArrayType::Double => "double[]",
+    }
+}
+
+fn align_to_8(bytes: u64) -> u64 {
+    (bytes + 7) & !7
+}
+
+/// Estimated shallow size of an instance whose flattened fields have the given types.
+pub fn instance_shallow_size(field_types: impl IntoIterator<Item = FieldType>, id_size: IdSize) -> u64 {
+    let fields: u64 = field_types.into_iter().map(|ft| field_type_size(ft, id_size)).sum();
+    align_to_8(2 * id_size_bytes(id_size) + fields)
+}
+
+/// Estimated shallow size of an array of `length` elements of `element_size` bytes each.
+pub fn array_shallow_size(length: u64, element_size: u64, id_size: IdSize) -> u64 {
+    align_to_8(2 * id_size_bytes(id_size) + 4 + length * element_size)
+}
+
+/// Number of elements in a primitive array record.
+pub fn primitive_array_len(pa: &PrimitiveArray) -> u64 {
+    let len = match pa.primitive_type() {
+        PrimitiveArrayType::Boolean => pa.booleans().unwrap().count(),
+        PrimitiveArrayType::Char => pa.chars().unwrap().count(),
+        PrimitiveArrayType::Float => pa.floats().unwrap().count(),
+        PrimitiveArrayType::Double => pa.doubles().unwrap().count(),
+        PrimitiveArrayType::Byte => pa.bytes().unwrap().count(),
+        PrimitiveArrayType::Short => pa.shorts().unwrap().count(),
+        PrimitiveArrayType::Int => pa.ints().unwrap().count(),
+        PrimitiveArrayType::Long => pa.longs().unwrap().count(),
+    };
+    len as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use arrow_array::{Array, UInt64Array};
+    use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+    use parquet::file::properties::ReaderProperties;
+    use parquet::file::reader::{FileReader, RowGroupReader};
+    use parquet::file::serialized_reader::{ReadOptionsBuilder, SerializedFileReader};
+
+    fn id_batch(ids: Vec<u64>) -> RecordBatch {
+        let schema = Arc::new(Schema::new(vec![Field::new("obj_id", DataType::UInt64, false)]));
+        RecordBatch::try_new(schema, vec![Arc::new(UInt64Array::from(ids)) as Arc<dyn Array>]).unwrap()
+    }
+
+    #[test]
+    fn test_write_to_parquet_round_trip_multiple_batches() {
+        let out_dir = std::env::temp_dir()
+            .join(format!("heapdumpstardiver-write-to-parquet-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&out_dir);
+
+        // Written twice, as happens when a class shows up in several heap dump segments.
+        // The second write must replace the file, not append a second footer to it.
+        write_to_parquet(&out_dir, "com/example/Foo", &[id_batch(vec![1])], WriterSettings::new(Compression::SNAPPY)).unwrap();
+        let batches = [id_batch(vec![1, 2]), id_batch(vec![3, 4, 5])];
+        write_to_parquet(&out_dir, "com/example/Foo", &batches, WriterSettings::new(Compression::SNAPPY)).unwrap();
+
+        let file = std::fs::File::open(out_dir.join("com.example.Foo.parquet")).unwrap();
+        let reader = ParquetRecordBatchReaderBuilder::try_new(file).unwrap().build().unwrap();
+        let rows: usize = reader.map(|b| b.unwrap().num_rows()).sum();
+        assert_eq!(rows, 5);
+
+        std::fs::remove_dir_all(&out_dir).unwrap();
+    }
+
+    #[test]
+    fn test_create_reports_the_file_it_could_not_write() {
+        let missing = std::env::temp_dir()
+            .join(format!("heapdumpstardiver-missing-{}", std::process::id()))
+            .join("Foo.parquet");
+        let schema = id_batch(vec![1]).schema();
+        let writer = WriterSettings { emit_schema_json: true, ..WriterSettings::new(Compression::SNAPPY) };
+        let err = BatchWriter::create(&missing, schema.clone(), &writer).err().unwrap();
+        assert!(matches!(&err, AnalyzeError::Io { path, .. } if path.ends_with("Foo.schema.json")), "{}", err);
+        let err = BatchWriter::create(&missing, schema, &WriterSettings::new(Compression::SNAPPY)).err().unwrap();
+        assert!(matches!(&err, AnalyzeError::Io { path, .. } if *path == missing), "{}", err);
+    }
+
+    #[test]
+    fn test_row_group_size() {
+        let out_dir = std::env::temp_dir()
+            .join(format!("heapdumpstardiver-row-group-size-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&out_dir);
+
+        let batches = [id_batch((0..25).collect()), id_batch((25..50).collect())];
+        let writer = WriterSettings { max_row_group_size: Some(10), ..WriterSettings::new(Compression::SNAPPY) };
+        write_to_parquet(&out_dir, "Small", &batches, writer).unwrap();
+        write_to_parquet(&out_dir, "Default", &batches, WriterSettings::new(Compression::SNAPPY)).unwrap();
+
+        let row_groups = |name: &str| {
+            let file = std::fs::File::open(out_dir.join(name)).unwrap();
+            let metadata = ParquetRecordBatchReaderBuilder::try_new(file).unwrap().metadata().clone();
+            metadata.row_groups().iter().map(|rg| rg.num_rows()).collect::<Vec<i64>>()
+        };
+        // The writer fills each row group to the limit across batch boundaries
+        assert_eq!(row_groups("Small.parquet"), vec![10, 10, 10, 10, 10]);
+        assert_eq!(row_groups("Default.parquet"), vec![50]);
+
+        std::fs::remove_dir_all(&out_dir).unwrap();
+    }
+
+    #[test]
+    fn test_bloom_filter_on_id_columns() {
+        let out_dir = std::env::temp_dir()
+            .join(format!("heapdumpstardiver-bloom-filter-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&out_dir);
+
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("obj_id", DataType::UInt64, false),
+            Field::new("size", DataType::UInt64, false),
+        ]));
+        let batch = RecordBatch::try_new(schema, vec![
+            Arc::new(UInt64Array::from(vec![10, 20, 30])) as Arc<dyn Array>,
+            Arc::new(UInt64Array::from(vec![1, 2, 3])),
+        ]).unwrap();
+        let writer = WriterSettings { bloom_filter: true, ..WriterSettings::new(Compression::SNAPPY) };
+        write_to_parquet(&out_dir, "Filtered", &[batch.clone()], writer).unwrap();
+        write_to_parquet(&out_dir, "Plain", &[batch], WriterSettings::new(Compression::SNAPPY)).unwrap();
+
+        let open = |name: &str| {
+            let file = std::fs::File::open(out_dir.join(name)).unwrap();
+            ParquetRecordBatchReaderBuilder::try_new(file).unwrap()
+        };
+        let builder = open("Filtered.parquet");
+        let row_group = builder.metadata().row_group(0);
+        assert!(row_group.column(0).bloom_filter_offset().is_some(), "obj_id has a bloom filter");
+        assert!(row_group.column(1).bloom_filter_offset().is_none(), "size has none");
+        let stats = row_group.column(0).statistics().expect("obj_id has statistics");
+        assert!(stats.min_bytes_opt().is_some() && stats.max_bytes_opt().is_some());
+        // The filter itself reads back and knows the ids that were written
+        let options = ReadOptionsBuilder::new()
+            .with_reader_properties(ReaderProperties::builder().set_read_bloom_filter(true).build())
+            .build();
+        let file = std::fs::File::open(out_dir.join("Filtered.parquet")).unwrap();
+        let reader = SerializedFileReader::new_with_options(file, options).unwrap();
+        let row_group = reader.get_row_group(0).unwrap();
+        let filter = row_group.get_column_bloom_filter(0).expect("obj_id bloom filter");
+        assert!([10_i64, 20, 30].iter().all(|id| filter.check(id)));
+
+        let builder = open("Plain.parquet");
+        assert!(builder.metadata().row_group(0).column(0).bloom_filter_offset().is_none());
+
+        std::fs::remove_dir_all(&out_dir).unwrap();
+    }
+
+    #[test]
+    fn test_parse_compression() {
+        assert_eq!(parse_compression("snappy", None), Ok(Compression::SNAPPY));
+        assert_eq!(parse_compression("none", None), Ok(Compression::UNCOMPRESSED));
+        assert_eq!(parse_compression("lz4", None), Ok(Compression::LZ4_RAW));
+        assert_eq!(parse_compression("zstd", Some(19)), Ok(Compression::ZSTD(ZstdLevel::try_new(19).unwrap())));
+        assert_eq!(parse_compression("gzip", None), Ok(Compression::GZIP(GzipLevel::default())));
+        assert!(parse_compression("zstd", Some(99)).is_err());
+        assert!(parse_compression("snappy", Some(3)).unwrap_err().contains("only applies to gzip and zstd"));
+        assert!(parse_compression("brotli9", None).unwrap_err().contains("unknown compression codec 'brotli9'"));
+    }
+
+    #[test]
+    fn test_instance_shallow_size() {
+        // 16 byte header + int(4) + long(8) + ref(8) + boolean(1) = 37 -> padded to 40
+        let fields = [FieldType::Int, FieldType::Long, FieldType::ObjectId, FieldType::Boolean];
+        assert_eq!(instance_shallow_size(fields, IdSize::U64), 40);
+        // 8 byte header + int(4) + long(8) + ref(4) + boolean(1) = 25 -> padded to 32
+        assert_eq!(instance_shallow_size(fields, IdSize::U32), 32);
+        // No fields: header only
+        assert_eq!(instance_shallow_size([], IdSize::U64), 16);
+    }
+
+    /// Inverse of `parquet_file_stem`.
+    fn file_key_from_stem(stem: &str) -> String {
+        let mut bytes = Vec::new();
+        let mut rest = stem.as_bytes();
+        while let Some((&b, tail)) = rest.split_first() {
+            match b {
+                b'.' => bytes.push(b'/'),
+                b'~' => {
+                    let hex = std::str::from_utf8(&tail[..2]).unwrap();
+                    bytes.push(u8::from_str_radix(hex, 16).unwrap());
+                    rest = &tail[2..];
+                    continue;
+                }
+                _ => bytes.push(b),
+            }
+            rest = tail;
+        }
+        String::from_utf8(bytes).unwrap()
+    }
+
+    #[test]
+    fn test_parquet_file_stem() {
+        assert_eq!(parquet_file_stem("java/util/HashMap_12"), "java.util.HashMap_12");
+        // Inner and anonymous classes
+        assert_eq!(parquet_file_stem("java/util/HashMap$Node_34"), "java.util.HashMap~24Node_34");
+        assert_eq!(parquet_file_stem("com/example/Foo$1_56"), "com.example.Foo~241_56");
+        // Array classes
+        assert_eq!(parquet_file_stem("[Ljava/lang/String;_78"), "~5BLjava.lang.String~3B_78");
+        assert_eq!(parquet_file_stem("[[I_90"), "~5B~5BI_90");
+        // Windows device names, with or without something after a dot
+        assert_eq!(parquet_file_stem("CON"), "~43ON");
+        assert_eq!(parquet_file_stem("nul/Foo"), "~6Eul.Foo");
+        assert_eq!(parquet_file_stem("Console"), "Console");
+        assert_eq!(parquet_file_stem("_gc_roots"), "_gc_roots");
+
+        let keys = [
+            "java/util/HashMap$Node_34", "java/util/HashMap.Node_34", "java/util/HashMap~24Node_34",
+            "com/example/Foo$1_56", "com/example/Foo$$Lambda/0x0000000800c0b000_7",
+            "[Ljava/lang/String;_78", "CON", "lpt1", "caf\u{e9}/Na\u{ef}ve_9", "a b",
+        ];
+        let stems: collections::HashSet<String> = keys.iter().map(|k| parquet_file_stem(k)).collect();
+        assert_eq!(stems.len(), keys.len());
+        for key in keys {
+            let stem = parquet_file_stem(key);
+            assert!(stem.bytes().all(|b| b.is_ascii_alphanumeric() || b"._-~".contains(&b)), "{}", stem);
+            assert_eq!(file_key_from_stem(&stem), key);
+        }
+    }
+
+    #[test]
+    fn test_check_hprof_header() {
+        let path = Path::new("heap.hprof");
+        let mut header = b"JAVA PROFILE 1.0.2\0".to_vec();
+        header.extend_from_slice(&8u32.to_be_bytes());
+        header.extend_from_slice(&1_700_000_000_000u64.to_be_bytes());
+        assert!(check_hprof_header(path, &header).is_ok());
+
+        let reason = |bytes: &[u8]| check_hprof_header(path, bytes).unwrap_err().to_string();
+        assert_eq!(reason(&header[..22]), "heap.hprof is not a valid hprof file (header is truncated)");
+        let mut bad_id_size = header.clone();
+        bad_id_size[19..23].copy_from_slice(&3u32.to_be_bytes());
+        assert_eq!(reason(&bad_id_size), "heap.hprof is not a valid hprof file (unsupported id size 3)");
+        assert_eq!(reason(b"JAVA PROFILE 1.0.2 with no terminator"),
+            "heap.hprof is not a valid hprof file (format label is not terminated)");
+    }
+
+    #[test]
+    fn test_record_sizes_follow_framing() {
+        let mut dump = b"JAVA PROFILE 1.0.2\0".to_vec();
+        dump.extend_from_slice(&8u32.to_be_bytes());
+        dump.extend_from_slice(&0u64.to_be_bytes());
+        for body_len in [4u32, 0, 100] {
+            dump.push(0x01);
+            dump.extend_from_slice(&0u32.to_be_bytes());
+            dump.extend_from_slice(&body_len.to_be_bytes());
+            dump.extend(std::iter::repeat(0xAB).take(body_len as usize));
+        }
+        assert_eq!(record_sizes(&dump).collect::<Vec<u64>>(), vec![13, 9, 109]);
+        // A record whose body runs past the end of the file ends the iteration
+        assert_eq!(record_sizes(&dump[..dump.len() - 1]).collect::<Vec<u64>>(), vec![13, 9]);
+    }
+
+    #[test]
+    fn test_check_hprof_header_rejects_random_bytes() {
+        // Small xorshift so the bytes vary without a rand dependency
+        let mut state = 0x2545_f491_4f6c_dd1d_u64;
+        let mut random = |len: usize| -> Vec<u8> {
+            (0..len).map(|_| {
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                state as u8
+            }).collect()
+        };
+        for len in [0, 1, 12, 31, 4096] {
+            let err = check_hprof_header(Path::new("random.bin"), &random(len)).unwrap_err();
+            assert_eq!(err.to_string(), "random.bin is not a valid hprof file (expected magic 'JAVA PROFILE 1.0.x')");
+        }
+    }
+
+    #[test]
+    fn test_reference_type_is_dictionary() {
+        let dictionary = DataType::Dictionary(Box::new(DataType::Int32), Box::new(DataType::Utf8));
+        for references in [ReferenceEncoding::Struct, ReferenceEncoding::NullableStruct] {
+            let DataType::Struct(fields) = references.data_type() else { panic!("{:?} is not a struct", references) };
+            assert_eq!(fields[0].data_type(), &DataType::UInt64);
+            assert_eq!(fields[1].name(), "type");
+            assert_eq!(fields[1].data_type(), &dictionary);
+        }
+        assert_eq!(ReferenceEncoding::IdOnly.data_type(), DataType::UInt64);
+    }
+
+    #[test]
+    fn test_array_shallow_size() {
+        // 16 byte header + 4 byte length + 10 bytes = 30 -> padded to 32
+        assert_eq!(array_shallow_size(10, 1, IdSize::U64), 32);
+        // 16 + 4 + 3 * 8 = 44 -> 48
+        assert_eq!(array_shallow_size(3, 8, IdSize::U64), 48);
+        assert_eq!(array_shallow_size(0, 4, IdSize::U64), 24);
+    }
+
+    #[test]
+    fn test_disambiguated_field_names_shadowed_superclass_field() {
+        // class Child extends Parent { int count; long size; }  class Parent { int count; }
+        // Flattened descriptors list Child's fields first, then Parent's.
+        let declaring = vec!["com/example/Child", "com/example/Child", "com/example/Parent"];
+        let names = disambiguated_field_names(&["count", "size", "count"], Some(&declaring));
+        assert_eq!(names, vec!["count", "size", "Parent@count"]);
+    }
+
+    #[test]
+    fn test_disambiguated_field_names_three_levels() {
+        let declaring = vec!["a/C", "a/B", "a/A"];
+        let names = disambiguated_field_names(&["count", "count", "count"], Some(&declaring));
+        assert_eq!(names, vec!["count", "B@count", "A@count"]);
+    }
+
+    #[test]
+    fn test_disambiguated_field_names_are_unique_when_short_names_clash() {
+        // Two superclasses in different packages with the same simple name
+        let declaring = vec!["a/Node", "b/Node", "c/Node"];
+        let names = disambiguated_field_names(&["next", "next", "next"], Some(&declaring));
+        assert_eq!(names, vec!["next", "Node@next", "Node@next_2"]);
+    }
+
+    #[test]
+    fn test_disambiguated_field_names_without_declaring_classes() {
+        let names = disambiguated_field_names(&["x", "x"], None);
+        assert_eq!(names, vec!["x", "unknown@x"]);
+    }
+}
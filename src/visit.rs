@@ -0,0 +1,291 @@
+// SPDX-License-Identifier: MIT
+// Copyright (c) 2026 Zac Policzer
+
+//! Callback traversal of a heap dump, so an analysis only has to say what it does with
+//! each kind of record instead of repeating the record/segment/sub-record loops.
+//!
+//! A callback's error ends the walk and is returned from it, so write errors and
+//! `--fail-on-unresolved` stop where they happen; `finished` ends it early without one, as
+//! `--limit` does for `dump-objects`.
+
+use jvm_hprof::{Hprof, LoadClass, Record, RecordTag, Utf8};
+use jvm_hprof::heap_dump::{
+    Class, GcRootBusyMonitor, GcRootJavaStackFrame, GcRootJniGlobal, GcRootJniLocalRef, GcRootNativeStack,
+    GcRootSystemClass, GcRootThreadBlock, GcRootThreadObj, GcRootUnknown, Instance, ObjectArray, PrimitiveArray,
+    SubRecord,
+};
+use crate::analyzer::AnalyzerOptions;
+use crate::cancel::cancelled;
+use crate::error::AnalyzeError;
+use crate::skip_errors::sub_records;
+
+/// What to do with each record `visit_subrecords` or `visit_segment` walks over. Every
+/// callback defaults to doing nothing, so a visitor only implements the ones it cares about.
+/// `'a` is the lifetime of the dump's bytes, so visitors may keep borrowed names around.
+pub trait SubRecordVisitor<'a> {
+    /// Every sub-record of a segment comes through here first; the default hands it to the
+    /// callback for its kind. Visitors that handle sub-records of several kinds the same way
+    /// can take the whole `SubRecord` instead.
+    fn on_sub_record(&mut self, sub_record: SubRecord<'a>) -> Result<(), AnalyzeError> {
+        match sub_record {
+            SubRecord::Class(class) => self.on_class(&class),
+            SubRecord::Instance(instance) => self.on_instance(&instance),
+            SubRecord::ObjectArray(oa) => self.on_object_array(&oa),
+            SubRecord::PrimitiveArray(pa) => self.on_primitive_array(&pa),
+            SubRecord::GcRootUnknown(r) => self.on_gc_root_unknown(&r),
+            SubRecord::GcRootThreadObj(r) => self.on_gc_root_thread_obj(&r),
+            SubRecord::GcRootJniGlobal(r) => self.on_gc_root_jni_global(&r),
+            SubRecord::GcRootJniLocalRef(r) => self.on_gc_root_jni_local_ref(&r),
+            SubRecord::GcRootJavaStackFrame(r) => self.on_gc_root_java_stack_frame(&r),
+            SubRecord::GcRootNativeStack(r) => self.on_gc_root_native_stack(&r),
+            SubRecord::GcRootSystemClass(r) => self.on_gc_root_system_class(&r),
+            SubRecord::GcRootThreadBlock(r) => self.on_gc_root_thread_block(&r),
+            SubRecord::GcRootBusyMonitor(r) => self.on_gc_root_busy_monitor(&r),
+        }
+    }
+
+    /// True once the visitor wants nothing more; the walk stops before the next record or
+    /// sub-record.
+    fn finished(&self) -> bool {
+        false
+    }
+
+    fn on_utf8(&mut self, _utf8: &Utf8<'a>) -> Result<(), AnalyzeError> {
+        Ok(())
+    }
+    fn on_load_class(&mut self, _load_class: &LoadClass) -> Result<(), AnalyzeError> {
+        Ok(())
+    }
+    fn on_class(&mut self, _class: &Class<'a>) -> Result<(), AnalyzeError> {
+        Ok(())
+    }
+    fn on_instance(&mut self, _instance: &Instance<'a>) -> Result<(), AnalyzeError> {
+        Ok(())
+    }
+    fn on_object_array(&mut self, _object_array: &ObjectArray<'a>) -> Result<(), AnalyzeError> {
+        Ok(())
+    }
+    fn on_primitive_array(&mut self, _primitive_array: &PrimitiveArray<'a>) -> Result<(), AnalyzeError> {
+        Ok(())
+    }
+    fn on_gc_root_unknown(&mut self, _root: &GcRootUnknown) -> Result<(), AnalyzeError> {
+        Ok(())
+    }
+    fn on_gc_root_thread_obj(&mut self, _root: &GcRootThreadObj) -> Result<(), AnalyzeError> {
+        Ok(())
+    }
+    fn on_gc_root_jni_global(&mut self, _root: &GcRootJniGlobal) -> Result<(), AnalyzeError> {
+        Ok(())
+    }
+    fn on_gc_root_jni_local_ref(&mut self, _root: &GcRootJniLocalRef) -> Result<(), AnalyzeError> {
+        Ok(())
+    }
+    fn on_gc_root_java_stack_frame(&mut self, _root: &GcRootJavaStackFrame) -> Result<(), AnalyzeError> {
+        Ok(())
+    }
+    fn on_gc_root_native_stack(&mut self, _root: &GcRootNativeStack) -> Result<(), AnalyzeError> {
+        Ok(())
+    }
+    fn on_gc_root_system_class(&mut self, _root: &GcRootSystemClass) -> Result<(), AnalyzeError> {
+        Ok(())
+    }
+    fn on_gc_root_thread_block(&mut self, _root: &GcRootThreadBlock) -> Result<(), AnalyzeError> {
+        Ok(())
+    }
+    fn on_gc_root_busy_monitor(&mut self, _root: &GcRootBusyMonitor) -> Result<(), AnalyzeError> {
+        Ok(())
+    }
+}
+
+/// Walk every top-level record in dump order, calling `visitor` for utf8 and load class
+/// records and for each sub-record of every heap dump segment. Once `options.cancel` is set,
+/// or the visitor is finished, the walk ends at the next record.
+pub fn visit_subrecords<'a, V: SubRecordVisitor<'a>>(
+    hprof: &Hprof<'a>,
+    options: &AnalyzerOptions,
+    visitor: &mut V,
+) -> Result<(), AnalyzeError> {
+    for r in hprof.records_iter() {
+        if cancelled(&options.cancel) || visitor.finished() {
+            break;
+        }
+        let r = r.map_err(|_| AnalyzeError::parse("top level record"))?;
+        match r.tag() {
+            RecordTag::Utf8 => {
+                let utf8 = r.as_utf_8()
+                    .ok_or_else(|| AnalyzeError::parse("utf8 record"))?
+                    .map_err(|_| AnalyzeError::parse("utf8 record"))?;
+                visitor.on_utf8(&utf8)?;
+            }
+            RecordTag::LoadClass => {
+                let load_class = r.as_load_class()
+                    .ok_or_else(|| AnalyzeError::parse("load class record"))?
+                    .map_err(|_| AnalyzeError::parse("load class record"))?;
+                visitor.on_load_class(&load_class)?;
+            }
+            RecordTag::HeapDump | RecordTag::HeapDumpSegment => visit_segment(&r, options, visitor)?,
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+/// Call `visitor` for each sub-record of one heap dump segment. The parallel passes use
+/// this directly, one visitor per segment. With `--skip-errors`, a sub-record that doesn't
+/// parse ends the segment instead of failing; once `options.cancel` is set, so does the
+/// next one. The walk also ends once the visitor is finished.
+pub fn visit_segment<'a, V: SubRecordVisitor<'a>>(
+    record: &Record<'a>,
+    options: &AnalyzerOptions,
+    visitor: &mut V,
+) -> Result<(), AnalyzeError> {
+    let segment = record.as_heap_dump_segment()
+        .ok_or_else(|| AnalyzeError::parse("heap dump segment"))?
+        .map_err(|_| AnalyzeError::parse("heap dump segment"))?;
+    for p in sub_records(options, segment.sub_records()) {
+        visitor.on_sub_record(p?)?;
+        if visitor.finished() {
+            break;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hprof_builder::{HprofBuilder, Segment, Value};
+
+    #[derive(Default)]
+    struct CountingVisitor {
+        utf8: usize,
+        load_classes: usize,
+        classes: usize,
+        instances: usize,
+        object_arrays: usize,
+        primitive_arrays: usize,
+        unknown_roots: usize,
+        thread_obj_roots: usize,
+    }
+
+    impl<'a> SubRecordVisitor<'a> for CountingVisitor {
+        fn on_utf8(&mut self, _utf8: &Utf8<'a>) -> Result<(), AnalyzeError> {
+            self.utf8 += 1;
+            Ok(())
+        }
+        fn on_load_class(&mut self, _load_class: &LoadClass) -> Result<(), AnalyzeError> {
+            self.load_classes += 1;
+            Ok(())
+        }
+        fn on_class(&mut self, _class: &Class<'a>) -> Result<(), AnalyzeError> {
+            self.classes += 1;
+            Ok(())
+        }
+        fn on_instance(&mut self, _instance: &Instance<'a>) -> Result<(), AnalyzeError> {
+            self.instances += 1;
+            Ok(())
+        }
+        fn on_object_array(&mut self, _object_array: &ObjectArray<'a>) -> Result<(), AnalyzeError> {
+            self.object_arrays += 1;
+            Ok(())
+        }
+        fn on_primitive_array(&mut self, _primitive_array: &PrimitiveArray<'a>) -> Result<(), AnalyzeError> {
+            self.primitive_arrays += 1;
+            Ok(())
+        }
+        fn on_gc_root_unknown(&mut self, _root: &GcRootUnknown) -> Result<(), AnalyzeError> {
+            self.unknown_roots += 1;
+            Ok(())
+        }
+        fn on_gc_root_thread_obj(&mut self, _root: &GcRootThreadObj) -> Result<(), AnalyzeError> {
+            self.thread_obj_roots += 1;
+            Ok(())
+        }
+    }
+
+    /// A visitor that only cares about instances, relying on the no-op defaults.
+    #[derive(Default)]
+    struct InstanceIds(Vec<u64>);
+
+    impl<'a> SubRecordVisitor<'a> for InstanceIds {
+        fn on_instance(&mut self, instance: &Instance<'a>) -> Result<(), AnalyzeError> {
+            self.0.push(instance.obj_id().id());
+            Ok(())
+        }
+    }
+
+    /// Two utf8 records and a load class, then one segment with every GC root kind, two
+    /// instances, an object array and a primitive array.
+    fn tiny_dump() -> Vec<u8> {
+        let mut segment = Segment::default();
+        segment
+            .every_gc_root(500, 501, 100)
+            .instance(500, 100, &[Value::Int(7)])
+            .instance(501, 100, &[Value::Int(7)])
+            .object_array(600, 200, &[500, 0])
+            .int_array(700, &[1, 2, 3]);
+        HprofBuilder::new()
+            .utf8(2, "bar")
+            .named_class(1, 100, "Foo")
+            .heap_dump_segment(&segment)
+            .finish()
+    }
+
+    #[test]
+    fn test_counting_visitor() {
+        let dump = tiny_dump();
+        let hprof = jvm_hprof::parse_hprof(&dump[..]).unwrap();
+
+        let mut counts = CountingVisitor::default();
+        visit_subrecords(&hprof, &AnalyzerOptions::default(), &mut counts).unwrap();
+        assert_eq!(counts.utf8, 2);
+        assert_eq!(counts.load_classes, 1);
+        assert_eq!(counts.classes, 0);
+        assert_eq!(counts.instances, 2);
+        assert_eq!(counts.object_arrays, 1);
+        assert_eq!(counts.primitive_arrays, 1);
+        // Each root kind goes to its own callback
+        assert_eq!(counts.unknown_roots, 1);
+        assert_eq!(counts.thread_obj_roots, 1);
+
+        let mut ids = InstanceIds::default();
+        visit_subrecords(&hprof, &AnalyzerOptions::default(), &mut ids).unwrap();
+        assert_eq!(ids.0, vec![500, 501]);
+    }
+
+    /// Stops after `limit` instances, and fails on the instance with id `fail_on`.
+    struct FirstInstances {
+        ids: Vec<u64>,
+        limit: usize,
+        fail_on: u64,
+    }
+
+    impl<'a> SubRecordVisitor<'a> for FirstInstances {
+        fn on_instance(&mut self, instance: &Instance<'a>) -> Result<(), AnalyzeError> {
+            if instance.obj_id().id() == self.fail_on {
+                return Err(AnalyzeError::UnresolvedRef(self.fail_on));
+            }
+            self.ids.push(instance.obj_id().id());
+            Ok(())
+        }
+
+        fn finished(&self) -> bool {
+            self.ids.len() == self.limit
+        }
+    }
+
+    #[test]
+    fn test_visitor_stops_when_finished_or_failed() {
+        let dump = tiny_dump();
+        let hprof = jvm_hprof::parse_hprof(&dump[..]).unwrap();
+
+        let mut first = FirstInstances { ids: Vec::new(), limit: 1, fail_on: 501 };
+        visit_subrecords(&hprof, &AnalyzerOptions::default(), &mut first).unwrap();
+        assert_eq!(first.ids, vec![500], "finished before reaching the failing instance");
+
+        let mut all = FirstInstances { ids: Vec::new(), limit: usize::MAX, fail_on: 501 };
+        let err = visit_subrecords(&hprof, &AnalyzerOptions::default(), &mut all).unwrap_err();
+        assert!(matches!(err, AnalyzeError::UnresolvedRef(501)), "{}", err);
+        assert_eq!(all.ids, vec![500]);
+    }
+}
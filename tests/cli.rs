@@ -0,0 +1,62 @@
+// SPDX-License-Identifier: MIT
+// Copyright (c) 2026 Zac Policzer
+
+//! The binary's stdout, for commands whose output is meant to be piped into another tool:
+//! nothing but their own output may reach it.
+
+mod support;
+
+use std::path::PathBuf;
+use std::process::Command;
+use support::*;
+
+/// The fixture written to a temp file the binary can open.
+fn fixture_file(name: &str) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("hdsd-cli-{}-{}.hprof", name, std::process::id()));
+    std::fs::write(&path, fixture()).unwrap();
+    path
+}
+
+/// Run the binary on `file` with `args`, returning its stdout; it must exit successfully.
+fn stdout_of(file: &PathBuf, args: &[&str]) -> String {
+    let output = Command::new(env!("CARGO_BIN_EXE_HeapDumpStarDiver"))
+        .arg("--file")
+        .arg(file)
+        .args(args)
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    String::from_utf8(output.stdout).unwrap()
+}
+
+/// Every line of `stdout` as JSON, failing on the first that isn't.
+fn json_lines(stdout: &str) -> Vec<serde_json::Value> {
+    stdout.lines()
+        .map(|line| serde_json::from_str(line).unwrap_or_else(|e| panic!("{}: {:?}", e, line)))
+        .collect()
+}
+
+#[test]
+fn test_dump_objects_to_ndjson_stdout_is_only_json() {
+    let file = fixture_file("ndjson");
+    let stdout = stdout_of(&file, &["dump-objects-to-ndjson"]);
+    std::fs::remove_file(&file).unwrap();
+
+    // The three instances and two arrays, and not the index build's timings
+    let lines = json_lines(&stdout);
+    assert_eq!(lines.len(), 5, "{}", stdout);
+    assert_eq!(lines[0]["id"], FIRST_POINT_ID);
+}
+
+#[test]
+fn test_dump_objects_json_stdout_is_only_json() {
+    let file = fixture_file("json");
+    let stdout = stdout_of(&file, &["dump-objects", "--format", "json"]);
+    std::fs::remove_file(&file).unwrap();
+
+    // Three classes, three instances and two arrays, in dump order
+    let lines = json_lines(&stdout);
+    assert_eq!(lines.len(), 8, "{}", stdout);
+    assert_eq!(lines[0]["kind"], "class");
+    assert_eq!(lines[0]["id"], OBJECT_CLASS_ID);
+}
@@ -0,0 +1,326 @@
+// SPDX-License-Identifier: MIT
+// Copyright (c) 2026 Zac Policzer
+
+//! Export a dump with one field of every type to parquet and read the files back. To cover
+//! a new field type, add a row to `sample_fields`.
+
+mod support;
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use arrow_array::cast::AsArray;
+use arrow_array::types::{Float32Type, Float64Type, Int16Type, Int32Type, Int64Type, Int8Type, UInt16Type, UInt64Type};
+use arrow_array::{Array, RecordBatch};
+use arrow_schema::DataType;
+use heap_dump_star_diver::commands::{dump_objects_to_parquet, ParquetOptions};
+use heap_dump_star_diver::{parse_compression, HeapDumpAnalyzer, OutputFormat, WriterSettings};
+use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+use parquet::basic::Compression;
+use support::{parquet_options, reference_type, HprofBuilder, Segment, Value, OBJECT_CLASS_ID};
+
+const BASE_CLASS_ID: u64 = 510;
+const SAMPLE_CLASS_ID: u64 = 500;
+const SAMPLE_IDS: [u64; 2] = [5000, 5001];
+const INT_ARRAY_ID: u64 = 6000;
+
+/// A `com/example/Sample` field: its column name and the value in each of the two instances.
+struct SampleField {
+    name: &'static str,
+    values: [Value; 2],
+}
+
+/// Fields `com/example/Sample` declares, in declaration order.
+fn sample_fields() -> Vec<SampleField> {
+    vec![
+        SampleField { name: "flag", values: [Value::Boolean(true), Value::Boolean(false)] },
+        SampleField { name: "letter", values: [Value::Char(u16::from(b'A')), Value::Char(0x263A)] },
+        SampleField { name: "ratio", values: [Value::Float(0.25), Value::Float(-1.5)] },
+        SampleField { name: "precise", values: [Value::Double(1e100), Value::Double(-0.0)] },
+        SampleField { name: "small", values: [Value::Byte(-128), Value::Byte(127)] },
+        SampleField { name: "medium", values: [Value::Short(-300), Value::Short(300)] },
+        SampleField { name: "count", values: [Value::Int(42), Value::Int(i32::MIN)] },
+        SampleField { name: "big", values: [Value::Long(i64::MAX), Value::Long(-1)] },
+        SampleField { name: "peer", values: [Value::Object(SAMPLE_IDS[1]), Value::Object(0)] },
+        SampleField { name: "data", values: [Value::Object(INT_ARRAY_ID), Value::Object(SAMPLE_CLASS_ID)] },
+    ]
+}
+
+/// `com/example/Base`'s one field, shadowed by Sample's `count`.
+const BASE_COUNT: [i32; 2] = [7, 8];
+
+/// The runtime type a reference column should name for `id`.
+fn ref_type(id: u64) -> &'static str {
+    match id {
+        id if SAMPLE_IDS.contains(&id) => "com/example/Sample",
+        INT_ARRAY_ID => "int[]",
+        SAMPLE_CLASS_ID => "class com/example/Sample",
+        _ => panic!("no fixture object {}", id),
+    }
+}
+
+fn name_id(field: usize) -> u64 {
+    20_000 + field as u64
+}
+
+/// Object, Base and Sample classes, the two Sample instances and the `int[]` they point at.
+fn sample_dump() -> Vec<u8> {
+    let fields = sample_fields();
+    let base_count_name = name_id(fields.iter().position(|f| f.name == "count").unwrap());
+    let descriptors: Vec<(u64, u8)> = fields.iter().enumerate()
+        .map(|(i, f)| (name_id(i), f.values[0].type_code()))
+        .collect();
+
+    let mut segment = Segment::default();
+    segment
+        .class(OBJECT_CLASS_ID, 0, 0, &[], &[])
+        .class(BASE_CLASS_ID, OBJECT_CLASS_ID, 0, &[], &[(base_count_name, Value::Int(0).type_code())])
+        .class(SAMPLE_CLASS_ID, BASE_CLASS_ID, 0, &[], &descriptors);
+    for (row, obj_id) in SAMPLE_IDS.iter().enumerate() {
+        let mut values: Vec<Value> = fields.iter().map(|f| f.values[row]).collect();
+        values.push(Value::Int(BASE_COUNT[row]));
+        segment.instance(*obj_id, SAMPLE_CLASS_ID, &values);
+    }
+    segment.int_array(INT_ARRAY_ID, &[1, 2, 3]);
+
+    let mut builder = HprofBuilder::new();
+    for (i, f) in fields.iter().enumerate() {
+        builder.utf8(name_id(i), f.name);
+    }
+    builder
+        .named_class(1, OBJECT_CLASS_ID, "java/lang/Object")
+        .named_class(2, BASE_CLASS_ID, "com/example/Base")
+        .named_class(3, SAMPLE_CLASS_ID, "com/example/Sample")
+        .heap_dump_segment(&segment)
+        .finish()
+}
+
+/// A cell read back from parquet, in a form that compares against `Value`.
+#[derive(Debug, PartialEq)]
+enum Cell {
+    Value(Value),
+    Reference(u64, String),
+    Null,
+}
+
+fn expected_cell(value: Value) -> Cell {
+    match value {
+        Value::Object(0) => Cell::Null,
+        Value::Object(id) => Cell::Reference(id, ref_type(id).to_string()),
+        other => Cell::Value(other),
+    }
+}
+
+fn expected_type(value: Value) -> DataType {
+    match value {
+        Value::Object(_) => DataType::Struct(reference_fields()),
+        Value::Boolean(_) => DataType::Boolean,
+        Value::Char(_) => DataType::UInt16,
+        Value::Float(_) => DataType::Float32,
+        Value::Double(_) => DataType::Float64,
+        Value::Byte(_) => DataType::Int8,
+        Value::Short(_) => DataType::Int16,
+        Value::Int(_) => DataType::Int32,
+        Value::Long(_) => DataType::Int64,
+    }
+}
+
+/// The `{id, type}` struct reference columns are written as.
+fn reference_fields() -> arrow_schema::Fields {
+    arrow_schema::Fields::from(vec![
+        arrow_schema::Field::new("id", DataType::UInt64, false),
+        arrow_schema::Field::new("type", DataType::Utf8, false),
+    ])
+}
+
+fn actual_cell(column: &dyn Array, row: usize) -> Cell {
+    if column.is_null(row) {
+        return Cell::Null;
+    }
+    match column.data_type() {
+        DataType::Struct(_) => {
+            let refs = column.as_struct();
+            Cell::Reference(refs.column(0).as_primitive::<UInt64Type>().value(row), reference_type(column, row).to_string())
+        }
+        DataType::Boolean => Cell::Value(Value::Boolean(column.as_boolean().value(row))),
+        DataType::UInt16 => Cell::Value(Value::Char(column.as_primitive::<UInt16Type>().value(row))),
+        DataType::Float32 => Cell::Value(Value::Float(column.as_primitive::<Float32Type>().value(row))),
+        DataType::Float64 => Cell::Value(Value::Double(column.as_primitive::<Float64Type>().value(row))),
+        DataType::Int8 => Cell::Value(Value::Byte(column.as_primitive::<Int8Type>().value(row))),
+        DataType::Int16 => Cell::Value(Value::Short(column.as_primitive::<Int16Type>().value(row))),
+        DataType::Int32 => Cell::Value(Value::Int(column.as_primitive::<Int32Type>().value(row))),
+        DataType::Int64 => Cell::Value(Value::Long(column.as_primitive::<Int64Type>().value(row))),
+        other => panic!("unexpected column type {:?}", other),
+    }
+}
+
+/// Every batch of every parquet file in `dir`, by file name. Opening each file with the
+/// reader checks it has exactly one valid footer.
+fn read_back(dir: &Path) -> BTreeMap<String, Vec<RecordBatch>> {
+    std::fs::read_dir(dir).unwrap()
+        .map(|entry| {
+            let path = entry.unwrap().path();
+            let file = std::fs::File::open(&path).unwrap();
+            let batches = ParquetRecordBatchReaderBuilder::try_new(file)
+                .unwrap_or_else(|e| panic!("{}: {}", path.display(), e))
+                .build().unwrap()
+                .map(|b| b.unwrap())
+                .collect();
+            (path.file_name().unwrap().to_string_lossy().into_owned(), batches)
+        })
+        .collect()
+}
+
+fn export(name: &str) -> PathBuf {
+    export_with(name, |_| {})
+}
+
+fn export_with(name: &str, configure: impl FnOnce(&mut ParquetOptions)) -> PathBuf {
+    let dump = sample_dump();
+    let hprof = jvm_hprof::parse_hprof(&dump[..]).unwrap();
+    let out_dir = std::env::temp_dir().join(format!("hdsd-round-trip-{}-{}", name, std::process::id()));
+    let mut options = parquet_options(out_dir.clone());
+    configure(&mut options);
+    dump_objects_to_parquet(&HeapDumpAnalyzer::new(&hprof), &options).unwrap();
+    out_dir
+}
+
+#[test]
+fn test_instance_columns_round_trip() {
+    let out_dir = export("instances");
+    let files = read_back(&out_dir);
+    let batches = &files["com.example.Sample_500.parquet"];
+    assert_eq!(batches.len(), 1, "one segment, one batch");
+    let batch = &batches[0];
+
+    let fields = sample_fields();
+    let mut expected_names = vec!["obj_id"];
+    expected_names.extend(fields.iter().map(|f| f.name));
+    expected_names.push("Base@count");
+    let schema = batch.schema();
+    let names: Vec<&str> = schema.fields().iter().map(|f| f.name().as_str()).collect();
+    assert_eq!(names, expected_names);
+
+    assert_eq!(batch.column(0).as_primitive::<UInt64Type>().values().to_vec(), SAMPLE_IDS.to_vec());
+    for (column, field) in fields.iter().enumerate().map(|(i, f)| (i + 1, f)) {
+        assert_eq!(schema.field(column).data_type(), &expected_type(field.values[0]), "{}", field.name);
+        for (row, value) in field.values.iter().enumerate() {
+            assert_eq!(actual_cell(batch.column(column), row), expected_cell(*value), "{} row {}", field.name, row);
+        }
+    }
+    let base_count = batch.column(fields.len() + 1);
+    for (row, value) in BASE_COUNT.iter().enumerate() {
+        assert_eq!(actual_cell(base_count, row), Cell::Value(Value::Int(*value)));
+    }
+
+    std::fs::remove_dir_all(&out_dir).unwrap();
+}
+
+#[test]
+fn test_shallow_size_column() {
+    let out_dir = export_with("shallow", |options| options.shallow_size = true);
+    let batch = &read_back(&out_dir)["com.example.Sample_500.parquet"][0];
+    assert_eq!(batch.schema().field(1).name(), "shallow_bytes");
+    assert_eq!(batch.schema().field(1).data_type(), &DataType::Int32);
+
+    // 16 byte header with 8 byte ids, then boolean 1 + char 2 + float 4 + double 8 + byte 1
+    // + short 2 + int 4 + long 8 + two references 16 + Base's int 4 = 66, aligned to 72
+    let shallow = batch.column(1).as_primitive::<Int32Type>();
+    assert_eq!(shallow.values().to_vec(), vec![72, 72]);
+
+    std::fs::remove_dir_all(&out_dir).unwrap();
+}
+
+#[test]
+fn test_every_file_reads_back() {
+    let out_dir = export("files");
+    let rows: BTreeMap<String, usize> = read_back(&out_dir).into_iter()
+        .map(|(name, batches)| (name, batches.iter().map(RecordBatch::num_rows).sum()))
+        .collect();
+    assert_eq!(rows["com.example.Sample_500.parquet"], 2);
+    assert_eq!(rows["_primitive_arrays_int.parquet"], 1);
+    assert_eq!(rows["classes.parquet"], 3);
+    assert_eq!(rows["class_histogram.parquet"], 3);
+    assert!(rows.keys().all(|name| name.ends_with(".parquet")), "{:?}", rows.keys());
+
+    std::fs::remove_dir_all(&out_dir).unwrap();
+}
+
+#[test]
+fn test_schema_json_sidecar_lists_fields() {
+    let out_dir = export_with("schema-json", |options| options.writer.emit_schema_json = true);
+    let text = std::fs::read_to_string(out_dir.join("com.example.Sample_500.schema.json")).unwrap();
+    let json: serde_json::Value = serde_json::from_str(&text).unwrap_or_else(|e| panic!("{}: {}", e, text));
+    let fields = json["fields"].as_array().unwrap();
+
+    let mut expected_names = vec!["obj_id"];
+    expected_names.extend(sample_fields().iter().map(|f| f.name));
+    expected_names.push("Base@count");
+    let names: Vec<&str> = fields.iter().map(|f| f["name"].as_str().unwrap()).collect();
+    assert_eq!(names, expected_names);
+
+    let field = |name: &str| fields.iter().find(|f| f["name"] == name).unwrap();
+    assert_eq!(field("obj_id")["type"], "UInt64");
+    assert_eq!(field("obj_id")["nullable"], false);
+    assert_eq!(field("letter")["type"], "UInt16");
+    assert_eq!(field("precise")["type"], "Float64");
+    assert_eq!(field("count")["nullable"], true);
+    let peer = field("peer");
+    assert_eq!(peer["type"], "Struct");
+    let children: Vec<&str> = peer["fields"].as_array().unwrap().iter().map(|f| f["name"].as_str().unwrap()).collect();
+    assert_eq!(children, vec!["id", "type"]);
+    assert_eq!(peer["fields"][1]["type"], "Dictionary(Int32, Utf8)");
+
+    // Every file gets one, and the parquet files are unchanged
+    assert!(out_dir.join("classes.schema.json").exists());
+    assert!(out_dir.join("com.example.Sample_500.parquet").exists());
+    std::fs::remove_dir_all(&out_dir).unwrap();
+}
+
+#[test]
+fn test_avro_records_read_back() {
+    use apache_avro::types::Value as Avro;
+
+    let out_dir = export_with("avro", |options| options.writer.format = OutputFormat::Avro);
+    let file = std::fs::File::open(out_dir.join("com.example.Sample_500.avro")).unwrap();
+    let records: Vec<Vec<(String, Avro)>> = apache_avro::Reader::new(file).unwrap()
+        .map(|record| match record.unwrap() {
+            Avro::Record(fields) => fields,
+            other => panic!("not a record: {:?}", other),
+        })
+        .collect();
+    assert_eq!(records.len(), 2);
+
+    let field = |row: usize, name: &str| -> Avro {
+        records[row].iter().find(|(n, _)| n == name).unwrap_or_else(|| panic!("no field {}", name)).1.clone()
+    };
+    let some = |value| Avro::Union(1, Box::new(value));
+    assert_eq!(field(0, "obj_id"), Avro::Long(SAMPLE_IDS[0] as i64));
+    assert_eq!(field(0, "count"), some(Avro::Int(42)));
+    assert_eq!(field(1, "big"), some(Avro::Long(-1)));
+    assert_eq!(field(0, "Base_count"), some(Avro::Int(BASE_COUNT[0])), "Base@count, renamed for Avro");
+    assert_eq!(field(0, "peer"), some(Avro::Record(vec![
+        ("id".to_string(), Avro::Long(SAMPLE_IDS[1] as i64)),
+        ("type".to_string(), Avro::String(ref_type(SAMPLE_IDS[1]).to_string())),
+    ])));
+    assert_eq!(field(1, "peer"), Avro::Union(0, Box::new(Avro::Null)));
+
+    std::fs::remove_dir_all(&out_dir).unwrap();
+}
+
+#[test]
+fn test_zstd_level_round_trip() {
+    let snappy_dir = export("snappy");
+    let compression = parse_compression("zstd", Some(9)).unwrap();
+    let zstd_dir = export_with("zstd", |options| options.writer = WriterSettings::new(compression));
+
+    // The level isn't stored in the file, only the codec
+    let file = std::fs::File::open(zstd_dir.join("com.example.Sample_500.parquet")).unwrap();
+    let metadata = ParquetRecordBatchReaderBuilder::try_new(file).unwrap().metadata().clone();
+    for column in metadata.row_group(0).columns() {
+        assert!(matches!(column.compression(), Compression::ZSTD(_)), "{}", column.column_path());
+    }
+    assert_eq!(read_back(&zstd_dir), read_back(&snappy_dir));
+
+    std::fs::remove_dir_all(&snappy_dir).unwrap();
+    std::fs::remove_dir_all(&zstd_dir).unwrap();
+}
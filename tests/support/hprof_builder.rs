@@ -0,0 +1,289 @@
+// SPDX-License-Identifier: MIT
+// Copyright (c) 2026 Zac Policzer
+
+//! Builds small hprof byte streams in memory. Ids are 8 bytes unless a builder is made with
+//! `with_id_size(4)`, as a 32-bit JVM writes them. Uses nothing from the crate, so the unit
+//! tests in `src` include it too, through `#[path]` in `lib.rs`.
+
+// Every includer uses only part of it
+#![allow(dead_code)]
+
+/// hprof basic type codes, as used in field descriptors and primitive arrays.
+pub const TYPE_OBJECT: u8 = 2;
+pub const TYPE_BOOLEAN: u8 = 4;
+pub const TYPE_CHAR: u8 = 5;
+pub const TYPE_FLOAT: u8 = 6;
+pub const TYPE_DOUBLE: u8 = 7;
+pub const TYPE_BYTE: u8 = 8;
+pub const TYPE_SHORT: u8 = 9;
+pub const TYPE_INT: u8 = 10;
+pub const TYPE_LONG: u8 = 11;
+
+/// A field value in a class dump or instance; `Object(0)` is null.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Value {
+    Object(u64),
+    Boolean(bool),
+    Char(u16),
+    Float(f32),
+    Double(f64),
+    Byte(i8),
+    Short(i16),
+    Int(i32),
+    Long(i64),
+}
+
+impl Value {
+    pub fn type_code(&self) -> u8 {
+        match self {
+            Value::Object(_) => TYPE_OBJECT,
+            Value::Boolean(_) => TYPE_BOOLEAN,
+            Value::Char(_) => TYPE_CHAR,
+            Value::Float(_) => TYPE_FLOAT,
+            Value::Double(_) => TYPE_DOUBLE,
+            Value::Byte(_) => TYPE_BYTE,
+            Value::Short(_) => TYPE_SHORT,
+            Value::Int(_) => TYPE_INT,
+            Value::Long(_) => TYPE_LONG,
+        }
+    }
+
+    fn write(&self, out: &mut Vec<u8>, id_size: u32) {
+        match self {
+            Value::Object(id) => write_id(out, *id, id_size),
+            Value::Boolean(v) => out.push(*v as u8),
+            Value::Char(v) => out.extend_from_slice(&v.to_be_bytes()),
+            Value::Float(v) => out.extend_from_slice(&v.to_be_bytes()),
+            Value::Double(v) => out.extend_from_slice(&v.to_be_bytes()),
+            Value::Byte(v) => out.extend_from_slice(&v.to_be_bytes()),
+            Value::Short(v) => out.extend_from_slice(&v.to_be_bytes()),
+            Value::Int(v) => out.extend_from_slice(&v.to_be_bytes()),
+            Value::Long(v) => out.extend_from_slice(&v.to_be_bytes()),
+        }
+    }
+}
+
+/// Append `id` as an `id_size`-byte big-endian id.
+fn write_id(out: &mut Vec<u8>, id: u64, id_size: u32) {
+    match id_size {
+        4 => out.extend_from_slice(&u32::try_from(id).expect("id too large for 4-byte ids").to_be_bytes()),
+        8 => out.extend_from_slice(&id.to_be_bytes()),
+        other => panic!("unsupported id size {}", other),
+    }
+}
+
+/// Top-level records, written in the order they're added.
+pub struct HprofBuilder {
+    bytes: Vec<u8>,
+    id_size: u32,
+}
+
+impl HprofBuilder {
+    pub fn new() -> Self {
+        Self::with_id_size(8)
+    }
+
+    pub fn with_id_size(id_size: u32) -> Self {
+        let mut bytes = b"JAVA PROFILE 1.0.2\0".to_vec();
+        bytes.extend_from_slice(&id_size.to_be_bytes());
+        bytes.extend_from_slice(&0u64.to_be_bytes());
+        HprofBuilder { bytes, id_size }
+    }
+
+    fn record(&mut self, tag: u8, body: &[u8]) -> &mut Self {
+        self.bytes.push(tag);
+        self.bytes.extend_from_slice(&0u32.to_be_bytes());
+        self.bytes.extend_from_slice(&(body.len() as u32).to_be_bytes());
+        self.bytes.extend_from_slice(body);
+        self
+    }
+
+    pub fn utf8(&mut self, name_id: u64, text: &str) -> &mut Self {
+        self.utf8_bytes(name_id, text.as_bytes())
+    }
+
+    /// A utf8 record holding `bytes` as they are, valid UTF-8 or not.
+    pub fn utf8_bytes(&mut self, name_id: u64, bytes: &[u8]) -> &mut Self {
+        let mut body = Vec::new();
+        write_id(&mut body, name_id, self.id_size);
+        body.extend_from_slice(bytes);
+        self.record(0x01, &body)
+    }
+
+    pub fn load_class(&mut self, class_serial: u32, class_obj_id: u64, name_id: u64) -> &mut Self {
+        let mut body = class_serial.to_be_bytes().to_vec();
+        write_id(&mut body, class_obj_id, self.id_size);
+        body.extend_from_slice(&0u32.to_be_bytes());
+        write_id(&mut body, name_id, self.id_size);
+        self.record(0x02, &body)
+    }
+
+    /// Add a utf8 record for `name` and a load class record naming `class_obj_id` after it.
+    pub fn named_class(&mut self, class_serial: u32, class_obj_id: u64, name: &str) -> &mut Self {
+        let name_id = 10_000 + class_obj_id;
+        self.utf8(name_id, name).load_class(class_serial, class_obj_id, name_id)
+    }
+
+    /// A stack frame record; `line` is the line number, or -1 unknown, -2 compiled, -3 native.
+    pub fn stack_frame(&mut self, frame_id: u64, method_name_id: u64, source_file_id: u64, class_serial: u32, line: i32) -> &mut Self {
+        let mut body = Vec::new();
+        write_id(&mut body, frame_id, self.id_size);
+        write_id(&mut body, method_name_id, self.id_size);
+        write_id(&mut body, 0, self.id_size); // method signature
+        write_id(&mut body, source_file_id, self.id_size);
+        body.extend_from_slice(&class_serial.to_be_bytes());
+        body.extend_from_slice(&line.to_be_bytes());
+        self.record(0x04, &body)
+    }
+
+    pub fn stack_trace(&mut self, stack_trace_serial: u32, thread_serial: u32, frame_ids: &[u64]) -> &mut Self {
+        let mut body = stack_trace_serial.to_be_bytes().to_vec();
+        body.extend_from_slice(&thread_serial.to_be_bytes());
+        body.extend_from_slice(&(frame_ids.len() as u32).to_be_bytes());
+        for frame_id in frame_ids {
+            write_id(&mut body, *frame_id, self.id_size);
+        }
+        self.record(0x05, &body)
+    }
+
+    pub fn heap_dump_segment(&mut self, segment: &Segment) -> &mut Self {
+        assert_eq!(segment.id_size, self.id_size, "segment and dump id sizes differ");
+        self.record(0x1C, &segment.bytes)
+    }
+
+    pub fn finish(&mut self) -> Vec<u8> {
+        self.record(0x2C, &[]);
+        std::mem::take(&mut self.bytes)
+    }
+}
+
+impl Default for HprofBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The sub-records of one heap dump segment.
+pub struct Segment {
+    bytes: Vec<u8>,
+    id_size: u32,
+}
+
+impl Default for Segment {
+    fn default() -> Self {
+        Self::with_id_size(8)
+    }
+}
+
+impl Segment {
+    pub fn with_id_size(id_size: u32) -> Self {
+        Segment { bytes: Vec::new(), id_size }
+    }
+
+    fn id(&mut self, id: u64) -> &mut Self {
+        write_id(&mut self.bytes, id, self.id_size);
+        self
+    }
+
+    fn u32(&mut self, v: u32) -> &mut Self {
+        self.bytes.extend_from_slice(&v.to_be_bytes());
+        self
+    }
+
+    fn tag(&mut self, tag: u8) -> &mut Self {
+        self.bytes.push(tag);
+        self
+    }
+
+    /// One of each GC root sub-record, all pointing at `obj_id` or `thread_id`.
+    pub fn every_gc_root(&mut self, obj_id: u64, thread_id: u64, class_id: u64) -> &mut Self {
+        self.tag(0xFF).id(obj_id); // unknown
+        self.tag(0x01).id(obj_id).id(1); // JNI global, with its global ref id
+        self.tag(0x02).id(obj_id).u32(1).u32(0); // JNI local: thread serial, frame
+        self.tag(0x03).id(obj_id).u32(1).u32(0); // Java frame: thread serial, frame
+        self.tag(0x04).id(obj_id).u32(1); // native stack: thread serial
+        self.tag(0x05).id(class_id); // sticky (system) class
+        self.tag(0x06).id(obj_id).u32(1); // thread block: thread serial
+        self.tag(0x07).id(obj_id); // busy monitor
+        self.tag(0x08).id(thread_id).u32(1).u32(0) // thread object: thread serial, stack trace
+    }
+
+    /// A class dump with no constant pool.
+    pub fn class(
+        &mut self,
+        class_obj_id: u64,
+        super_class_obj_id: u64,
+        instance_size: u32,
+        static_fields: &[(u64, Value)],
+        instance_fields: &[(u64, u8)],
+    ) -> &mut Self {
+        self.tag(0x20).id(class_obj_id).u32(0).id(super_class_obj_id);
+        // class loader, signers, protection domain, two reserved ids
+        for _ in 0..5 {
+            self.id(0);
+        }
+        self.u32(instance_size);
+        self.bytes.extend_from_slice(&0u16.to_be_bytes());
+        self.bytes.extend_from_slice(&(static_fields.len() as u16).to_be_bytes());
+        for (name_id, value) in static_fields {
+            self.id(*name_id).tag(value.type_code());
+            value.write(&mut self.bytes, self.id_size);
+        }
+        self.bytes.extend_from_slice(&(instance_fields.len() as u16).to_be_bytes());
+        for (name_id, type_code) in instance_fields {
+            self.id(*name_id).tag(*type_code);
+        }
+        self
+    }
+
+    /// An instance whose field values are in descriptor order, the class's own fields first.
+    pub fn instance(&mut self, obj_id: u64, class_obj_id: u64, fields: &[Value]) -> &mut Self {
+        let mut values = Vec::new();
+        for value in fields {
+            value.write(&mut values, self.id_size);
+        }
+        self.tag(0x21).id(obj_id).u32(0).id(class_obj_id).u32(values.len() as u32);
+        self.bytes.extend_from_slice(&values);
+        self
+    }
+
+    /// An object array; 0 is a null element.
+    pub fn object_array(&mut self, obj_id: u64, array_class_obj_id: u64, elements: &[u64]) -> &mut Self {
+        self.tag(0x22).id(obj_id).u32(0).u32(elements.len() as u32).id(array_class_obj_id);
+        for element in elements {
+            self.id(*element);
+        }
+        self
+    }
+
+    pub fn byte_array(&mut self, obj_id: u64, elements: &[u8]) -> &mut Self {
+        self.tag(0x23).id(obj_id).u32(0).u32(elements.len() as u32).tag(TYPE_BYTE);
+        self.bytes.extend_from_slice(elements);
+        self
+    }
+
+    /// Drop the last `bytes` bytes, cutting the last sub-record short the way a JVM that dies
+    /// mid-write would.
+    pub fn truncate_last(&mut self, bytes: usize) -> &mut Self {
+        self.bytes.truncate(self.bytes.len() - bytes);
+        self
+    }
+
+    pub fn int_array(&mut self, obj_id: u64, elements: &[i32]) -> &mut Self {
+        self.tag(0x23).id(obj_id).u32(0).u32(elements.len() as u32).tag(TYPE_INT);
+        for element in elements {
+            self.bytes.extend_from_slice(&element.to_be_bytes());
+        }
+        self
+    }
+
+    /// A `char[]` holding `text` as UTF-16 code units.
+    pub fn char_array(&mut self, obj_id: u64, text: &str) -> &mut Self {
+        let units: Vec<u16> = text.encode_utf16().collect();
+        self.tag(0x23).id(obj_id).u32(0).u32(units.len() as u32).tag(TYPE_CHAR);
+        for unit in units {
+            self.bytes.extend_from_slice(&unit.to_be_bytes());
+        }
+        self
+    }
+}
@@ -0,0 +1,139 @@
+// SPDX-License-Identifier: MIT
+// Copyright (c) 2026 Zac Policzer
+
+//! The fixture dump and settings the integration tests share, so they can run the commands
+//! against objects they know the contents of. The dumps are written with `hprof_builder`.
+
+// Each test crate compiles its own copy of this module and uses only part of it
+#![allow(dead_code)]
+
+use std::path::PathBuf;
+use arrow_array::cast::AsArray;
+use arrow_array::types::Int32Type;
+use arrow_array::Array;
+use heap_dump_star_diver::commands::{
+    dump_objects, ByteArrayFormat, ClassSelection, DumpObjectsOptions, IdFormat, ObjectFormat, ObjectWindow, ParquetOptions,
+};
+use heap_dump_star_diver::{AnalyzeError, HeapDumpAnalyzer, ReferenceEncoding, WriterSettings};
+use parquet::basic::Compression;
+
+mod hprof_builder;
+pub use hprof_builder::*;
+
+pub const OBJECT_CLASS_ID: u64 = 100;
+pub const POINT_CLASS_ID: u64 = 200;
+pub const POINT_ARRAY_CLASS_ID: u64 = 300;
+pub const THREAD_ID: u64 = 400;
+/// Two `com/example/Point`s: `x = 3, y = 4, next = SECOND_POINT_ID` and `x = -1, y = 7, next = null`.
+pub const FIRST_POINT_ID: u64 = 1000;
+pub const SECOND_POINT_ID: u64 = 1001;
+/// `Point[] { FIRST_POINT_ID, null, SECOND_POINT_ID }`.
+pub const POINT_ARRAY_ID: u64 = 2000;
+/// `int[] { 1, 2, 3 }`.
+pub const INT_ARRAY_ID: u64 = 3000;
+/// One of each GC root kind.
+pub const GC_ROOT_COUNT: usize = 9;
+
+
+/// `dump-objects` settings that print every object in full, in `format`.
+pub fn dump_objects_options(format: ObjectFormat) -> DumpObjectsOptions {
+    DumpObjectsOptions {
+        filter: None,
+        window: ObjectWindow::default(),
+        sample: None,
+        max_array_elements: usize::MAX,
+        format,
+        id_format: IdFormat::Decimal,
+        decode_char_arrays: false,
+        byte_array_format: ByteArrayFormat::Hex,
+        expand_depth: 0,
+        fail_on_unresolved: false,
+    }
+}
+
+/// What `dump-objects` prints for the whole dump, as text.
+pub fn dump_text(analyzer: &HeapDumpAnalyzer) -> Result<String, AnalyzeError> {
+    let mut out = Vec::new();
+    dump_objects(analyzer, &dump_objects_options(ObjectFormat::Text), &mut out)?;
+    Ok(String::from_utf8(out).unwrap())
+}
+
+/// Default export settings, writing to `out_dir`.
+pub fn parquet_options(out_dir: PathBuf) -> ParquetOptions {
+    ParquetOptions {
+        out_dir,
+        writer_buffer_bytes: 16 << 20,
+        threads: 2,
+        writer: WriterSettings::new(Compression::SNAPPY),
+        robo_mode: false,
+        single_file: false,
+        partition_by_class: false,
+        reachability: false,
+        live_only: false,
+        retained_size: false,
+        shallow_size: false,
+        inline_primitive_arrays: false,
+        references: ReferenceEncoding::default(),
+        array_elements: true,
+        char_array_text: false,
+        binary_byte_arrays: false,
+        dump_utf8: false,
+        strict: true,
+        fail_on_unresolved: false,
+        sample: None,
+        classes: ClassSelection::default(),
+        summary: false,
+        objects_per_file: None,
+    }
+}
+
+/// The dictionary-encoded `type` at `row` of a `{id, type}` reference struct column.
+pub fn reference_type(refs: &dyn Array, row: usize) -> &str {
+    let types = refs.as_struct().column(1).as_dictionary::<Int32Type>();
+    types.values().as_string::<i32>().value(types.keys().value(row) as usize)
+}
+
+
+/// A dump with one of each sub-record kind: `java/lang/Object`, `com/example/Point` with
+/// `int x, y` and `Point next` plus a `static Point ORIGIN`, the `Point[]` class, two
+/// Points, a `Point[]`, an `int[]` and every GC root kind.
+pub fn fixture() -> Vec<u8> {
+    fixture_with_id_size(8)
+}
+
+/// `fixture`, written with `id_size`-byte ids.
+pub fn fixture_with_id_size(id_size: u32) -> Vec<u8> {
+    const X: u64 = 1;
+    const Y: u64 = 2;
+    const NEXT: u64 = 3;
+    const ORIGIN: u64 = 4;
+
+    let mut segment = Segment::with_id_size(id_size);
+    segment
+        .class(OBJECT_CLASS_ID, 0, 0, &[], &[])
+        .class(
+            POINT_CLASS_ID,
+            OBJECT_CLASS_ID,
+            16,
+            &[(ORIGIN, Value::Object(FIRST_POINT_ID))],
+            &[(X, TYPE_INT), (Y, TYPE_INT), (NEXT, TYPE_OBJECT)],
+        )
+        .class(POINT_ARRAY_CLASS_ID, OBJECT_CLASS_ID, 0, &[], &[])
+        .instance(FIRST_POINT_ID, POINT_CLASS_ID, &[Value::Int(3), Value::Int(4), Value::Object(SECOND_POINT_ID)])
+        .instance(SECOND_POINT_ID, POINT_CLASS_ID, &[Value::Int(-1), Value::Int(7), Value::Object(0)])
+        .object_array(POINT_ARRAY_ID, POINT_ARRAY_CLASS_ID, &[FIRST_POINT_ID, 0, SECOND_POINT_ID])
+        .int_array(INT_ARRAY_ID, &[1, 2, 3])
+        .instance(THREAD_ID, OBJECT_CLASS_ID, &[])
+        .every_gc_root(FIRST_POINT_ID, THREAD_ID, POINT_CLASS_ID);
+
+    HprofBuilder::with_id_size(id_size)
+        .utf8(X, "x")
+        .utf8(Y, "y")
+        .utf8(NEXT, "next")
+        .utf8(ORIGIN, "ORIGIN")
+        .named_class(1, OBJECT_CLASS_ID, "java/lang/Object")
+        .named_class(2, POINT_CLASS_ID, "com/example/Point")
+        .named_class(3, POINT_ARRAY_CLASS_ID, "[Lcom/example/Point;")
+        .heap_dump_segment(&segment)
+        .finish()
+}
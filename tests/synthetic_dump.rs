@@ -0,0 +1,1511 @@
+// SPDX-License-Identifier: MIT
+// Copyright (c) 2026 Zac Policzer
+
+//! The commands run end to end against the fixture dump from `support`.
+
+mod support;
+
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use heap_dump_star_diver::commands::{
+    dump_classes, dump_objects, dump_objects_to_ndjson, dump_objects_to_parquet, dump_strings, duplicate_classes, find_instances, histogram_diff,
+    monitors, stack_traces, ByteArrayFormat, ClassFilter, ClassSelection, Comparison, DumpObjectsOptions, DumpStringsOptions,
+    ExportSummary, FindOptions, IdFormat, ObjectFormat, ObjectWindow,
+};
+use heap_dump_star_diver::{
+    cancel_after, visit_subrecords, AnalyzeError, AnalyzerOptions, ClassStat, HeapDumpAnalyzer, OutputFormat, ReferenceEncoding, ResolvedValue,
+    SubRecordVisitor, Utf8Mode, WriterSettings,
+};
+use arrow_array::cast::AsArray;
+use arrow_array::types::{Int32Type, Int8Type, UInt64Type};
+use arrow_array::Array;
+use arrow_schema::DataType;
+use jvm_hprof::{IdSize, RecordTag};
+use jvm_hprof::heap_dump::{
+    Class, GcRootBusyMonitor, GcRootJavaStackFrame, GcRootJniGlobal, GcRootJniLocalRef, GcRootNativeStack, GcRootSystemClass,
+    GcRootThreadBlock, GcRootThreadObj, GcRootUnknown, Instance, ObjectArray, PrimitiveArray,
+};
+use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+use parquet::basic::Compression;
+use support::*;
+
+#[derive(Default)]
+struct Counts {
+    classes: Vec<u64>,
+    instances: Vec<u64>,
+    object_arrays: Vec<u64>,
+    primitive_arrays: Vec<u64>,
+    /// Root kinds in dump order.
+    gc_roots: Vec<&'static str>,
+}
+
+impl<'a> SubRecordVisitor<'a> for Counts {
+    fn on_class(&mut self, class: &Class<'a>) -> Result<(), AnalyzeError> {
+        self.classes.push(class.obj_id().id());
+        Ok(())
+    }
+    fn on_instance(&mut self, instance: &Instance<'a>) -> Result<(), AnalyzeError> {
+        self.instances.push(instance.obj_id().id());
+        Ok(())
+    }
+    fn on_object_array(&mut self, object_array: &ObjectArray<'a>) -> Result<(), AnalyzeError> {
+        self.object_arrays.push(object_array.obj_id().id());
+        Ok(())
+    }
+    fn on_primitive_array(&mut self, primitive_array: &PrimitiveArray<'a>) -> Result<(), AnalyzeError> {
+        self.primitive_arrays.push(primitive_array.obj_id().id());
+        Ok(())
+    }
+    fn on_gc_root_unknown(&mut self, _root: &GcRootUnknown) -> Result<(), AnalyzeError> {
+        self.gc_roots.push("Unknown");
+        Ok(())
+    }
+    fn on_gc_root_thread_obj(&mut self, _root: &GcRootThreadObj) -> Result<(), AnalyzeError> {
+        self.gc_roots.push("ThreadObj");
+        Ok(())
+    }
+    fn on_gc_root_jni_global(&mut self, _root: &GcRootJniGlobal) -> Result<(), AnalyzeError> {
+        self.gc_roots.push("JniGlobal");
+        Ok(())
+    }
+    fn on_gc_root_jni_local_ref(&mut self, _root: &GcRootJniLocalRef) -> Result<(), AnalyzeError> {
+        self.gc_roots.push("JniLocal");
+        Ok(())
+    }
+    fn on_gc_root_java_stack_frame(&mut self, _root: &GcRootJavaStackFrame) -> Result<(), AnalyzeError> {
+        self.gc_roots.push("JavaStackFrame");
+        Ok(())
+    }
+    fn on_gc_root_native_stack(&mut self, _root: &GcRootNativeStack) -> Result<(), AnalyzeError> {
+        self.gc_roots.push("NativeStack");
+        Ok(())
+    }
+    fn on_gc_root_system_class(&mut self, _root: &GcRootSystemClass) -> Result<(), AnalyzeError> {
+        self.gc_roots.push("SystemClass");
+        Ok(())
+    }
+    fn on_gc_root_thread_block(&mut self, _root: &GcRootThreadBlock) -> Result<(), AnalyzeError> {
+        self.gc_roots.push("ThreadBlock");
+        Ok(())
+    }
+    fn on_gc_root_busy_monitor(&mut self, _root: &GcRootBusyMonitor) -> Result<(), AnalyzeError> {
+        self.gc_roots.push("BusyMonitor");
+        Ok(())
+    }
+}
+
+/// Ids are 8 bytes in dumps from 64-bit JVMs and 4 in dumps from 32-bit ones.
+const ID_SIZES: [u32; 2] = [8, 4];
+
+fn dump_objects_output(id_size: u32, format: ObjectFormat) -> String {
+    let dump = fixture_with_id_size(id_size);
+    let hprof = jvm_hprof::parse_hprof(&dump[..]).unwrap();
+    let mut out = Vec::new();
+    dump_objects(&HeapDumpAnalyzer::new(&hprof), &dump_objects_options(format), &mut out).unwrap();
+    String::from_utf8(out).unwrap()
+}
+
+const STRING_ARRAY_ID: u64 = 7000;
+
+/// `String[] { "alpha", null, "beta", "gamma" }`, of JDK 9+ Latin-1 Strings backed by
+/// `byte[]` when `compact`, else of JDK 8 Strings backed by `char[]`.
+fn string_array_dump(compact: bool) -> Vec<u8> {
+    const VALUE: u64 = 1;
+    const CODER: u64 = 2;
+    const STRING_CLASS_ID: u64 = 500;
+    const STRING_ARRAY_CLASS_ID: u64 = 600;
+    let texts = ["alpha", "beta", "gamma"];
+
+    let mut segment = Segment::default();
+    let string_fields: &[(u64, u8)] = match compact {
+        true => &[(VALUE, TYPE_OBJECT), (CODER, TYPE_BYTE)],
+        false => &[(VALUE, TYPE_OBJECT)],
+    };
+    segment
+        .class(OBJECT_CLASS_ID, 0, 0, &[], &[])
+        .class(STRING_CLASS_ID, OBJECT_CLASS_ID, 12, &[], string_fields)
+        .class(STRING_ARRAY_CLASS_ID, OBJECT_CLASS_ID, 0, &[], &[]);
+    for (i, text) in texts.iter().enumerate() {
+        let (string_id, value_id) = (5000 + i as u64, 6000 + i as u64);
+        match compact {
+            true => segment
+                .byte_array(value_id, text.as_bytes())
+                .instance(string_id, STRING_CLASS_ID, &[Value::Object(value_id), Value::Byte(0)]),
+            false => segment
+                .char_array(value_id, text)
+                .instance(string_id, STRING_CLASS_ID, &[Value::Object(value_id)]),
+        };
+    }
+    segment.object_array(STRING_ARRAY_ID, STRING_ARRAY_CLASS_ID, &[5000, 0, 5001, 5002]);
+
+    HprofBuilder::new()
+        .utf8(VALUE, "value")
+        .utf8(CODER, "coder")
+        .named_class(1, OBJECT_CLASS_ID, "java/lang/Object")
+        .named_class(2, STRING_CLASS_ID, "java/lang/String")
+        .named_class(3, STRING_ARRAY_CLASS_ID, "[Ljava/lang/String;")
+        .heap_dump_segment(&segment)
+        .finish()
+}
+
+fn parquet_rows(path: &Path) -> i64 {
+    let file = std::fs::File::open(path).unwrap_or_else(|e| panic!("{}: {}", path.display(), e));
+    ParquetRecordBatchReaderBuilder::try_new(file).unwrap().metadata().file_metadata().num_rows()
+}
+
+#[test]
+fn test_fixture_has_every_sub_record_kind() {
+    for id_size in ID_SIZES {
+        let dump = fixture_with_id_size(id_size);
+        let hprof = jvm_hprof::parse_hprof(&dump[..]).unwrap();
+        let header_id_size = match hprof.header().id_size() {
+            IdSize::U32 => 4,
+            IdSize::U64 => 8,
+        };
+        assert_eq!(header_id_size, id_size);
+        let mut counts = Counts::default();
+        visit_subrecords(&hprof, &AnalyzerOptions::default(), &mut counts).unwrap();
+        assert_eq!(counts.classes, vec![OBJECT_CLASS_ID, POINT_CLASS_ID, POINT_ARRAY_CLASS_ID]);
+        assert_eq!(counts.instances, vec![FIRST_POINT_ID, SECOND_POINT_ID, THREAD_ID]);
+        assert_eq!(counts.object_arrays, vec![POINT_ARRAY_ID]);
+        assert_eq!(counts.primitive_arrays, vec![INT_ARRAY_ID]);
+        assert_eq!(
+            counts.gc_roots,
+            vec!["Unknown", "JniGlobal", "JniLocal", "JavaStackFrame", "NativeStack", "SystemClass", "ThreadBlock", "BusyMonitor", "ThreadObj"]
+        );
+        assert_eq!(counts.gc_roots.len(), GC_ROOT_COUNT);
+    }
+    assert!(fixture_with_id_size(4).len() < fixture().len());
+}
+
+#[test]
+fn test_dump_objects_prints_fixture_objects() {
+    for id_size in ID_SIZES {
+        assert_fixture_objects_printed(&dump_objects_output(id_size, ObjectFormat::Text));
+    }
+}
+
+fn assert_fixture_objects_printed(text: &str) {
+    for expected in [
+        "id 1000: com/example/Point\n  - x: int = 3\n  - y: int = 4\n  - next = id 1001 (com/example/Point)\n",
+        "id 1001: com/example/Point\n  - x: int = -1\n  - y: int = 7\n  - next = null\n",
+        "id 2000: [Lcom/example/Point; = [\n  - id 1000: com/example/Point\n  - null\n  - id 1001: com/example/Point\n]",
+        "3000: int[] = [1, 2, 3, ]",
+        "id 200: class com/example/Point\n  extends java/lang/Object\n  - ORIGIN = id 1000 (com/example/Point)\n",
+    ] {
+        assert!(text.contains(expected), "missing {:?} in:\n{}", expected, text);
+    }
+}
+
+#[test]
+fn test_dump_objects_prints_string_array_elements() {
+    let dump = string_array_dump(true);
+    let hprof = jvm_hprof::parse_hprof(&dump[..]).unwrap();
+    let print = |filter: Option<ClassFilter>, max_array_elements: usize| -> String {
+        let options = DumpObjectsOptions {
+            filter,
+            window: ObjectWindow::default(),
+            sample: None,
+            max_array_elements,
+            format: ObjectFormat::Text,
+            id_format: IdFormat::Decimal,
+            decode_char_arrays: false,
+            byte_array_format: ByteArrayFormat::Hex,
+            expand_depth: 0,
+            fail_on_unresolved: false,
+        };
+        let mut out = Vec::new();
+        dump_objects(&HeapDumpAnalyzer::new(&hprof), &options, &mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    };
+
+    let text = print(None, usize::MAX);
+    let expected = "id 7000: [Ljava/lang/String; = [\n  - id 5000: java/lang/String = \"alpha\"\n  - null\n  \
+        - id 5001: java/lang/String = \"beta\"\n  - id 5002: java/lang/String = \"gamma\"\n]";
+    assert!(text.contains(expected), "{}", text);
+
+    // Filtering to the array class still decodes its elements, and the cap still applies
+    let text = print(Some(ClassFilter::Exact("[Ljava.lang.String;".to_string())), 2);
+    assert!(text.contains("  - id 5000: java/lang/String = \"alpha\"\n  - null\n  ... (2 more)\n]"), "{}", text);
+    assert!(!text.contains("beta"), "{}", text);
+}
+
+#[test]
+fn test_strings_decode_with_either_string_layout() {
+    for compact in [true, false] {
+        let dump = string_array_dump(compact);
+        let hprof = jvm_hprof::parse_hprof(&dump[..]).unwrap();
+        let options = DumpObjectsOptions {
+            filter: None,
+            window: ObjectWindow::default(),
+            sample: None,
+            max_array_elements: usize::MAX,
+            format: ObjectFormat::Text,
+            id_format: IdFormat::Decimal,
+            decode_char_arrays: false,
+            byte_array_format: ByteArrayFormat::Hex,
+            expand_depth: 0,
+            fail_on_unresolved: false,
+        };
+        let mut out = Vec::new();
+        dump_objects(&HeapDumpAnalyzer::new(&hprof), &options, &mut out).unwrap();
+        let text = String::from_utf8(out).unwrap();
+        assert!(text.contains("id 5001: java/lang/String = \"beta\"\n"), "compact: {}: {}", compact, text);
+        assert!(text.contains("  - id 5002: java/lang/String = \"gamma\"\n]"), "compact: {}: {}", compact, text);
+
+        let out_dir = std::env::temp_dir().join(format!("hdsd-strings-{}-{}", compact, std::process::id()));
+        let options = DumpStringsOptions {
+            min_length: 0,
+            sort_by_value: false,
+            parquet_dir: Some(out_dir.clone()),
+            writer: WriterSettings::new(Compression::SNAPPY),
+        };
+        dump_strings(&HeapDumpAnalyzer::new(&hprof), &options).unwrap();
+        let file = std::fs::File::open(out_dir.join("strings.parquet")).unwrap();
+        let strings = ParquetRecordBatchReaderBuilder::try_new(file).unwrap().build().unwrap().next().unwrap().unwrap();
+        let values: Vec<_> = strings.column(2).as_string::<i32>().iter().flatten().collect();
+        assert_eq!(values, ["alpha", "beta", "gamma"], "compact: {}", compact);
+        std::fs::remove_dir_all(&out_dir).unwrap();
+    }
+}
+
+#[test]
+fn test_dump_objects_json_fixture_objects() {
+    for id_size in ID_SIZES {
+        let json = dump_objects_output(id_size, ObjectFormat::Json);
+        assert_eq!(json.lines().count(), 8);
+        assert!(json.contains(
+            r#"{"id":1000,"kind":"instance","class":"com/example/Point","fields":{"x":3,"y":4,"next":{"id":1001,"type":"com/example/Point"}}}"#
+        ), "{}", json);
+        assert!(json.contains(r#"{"id":3000,"kind":"primitive_array","class":"int[]","elements":[1,2,3],"length":3}"#), "{}", json);
+    }
+}
+
+#[test]
+fn test_dump_objects_limit_and_skip_window_the_fixture() {
+    let dump = fixture();
+    let hprof = jvm_hprof::parse_hprof(&dump[..]).unwrap();
+    let analyzer = HeapDumpAnalyzer::new(&hprof);
+    let printed_ids = |filter: Option<ClassFilter>, skip: usize, limit: Option<usize>| -> Vec<u64> {
+        let options = DumpObjectsOptions {
+            filter,
+            window: ObjectWindow { skip, limit },
+            ..dump_objects_options(ObjectFormat::Json)
+        };
+        let mut out = Vec::new();
+        dump_objects(&analyzer, &options, &mut out).unwrap();
+        String::from_utf8(out).unwrap().lines()
+            .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap()["id"].as_u64().unwrap())
+            .collect()
+    };
+
+    // Classes, instances and arrays in dump order
+    let all = vec![
+        OBJECT_CLASS_ID, POINT_CLASS_ID, POINT_ARRAY_CLASS_ID, FIRST_POINT_ID, SECOND_POINT_ID, POINT_ARRAY_ID, INT_ARRAY_ID,
+        THREAD_ID,
+    ];
+    assert_eq!(printed_ids(None, 0, None), all);
+    assert_eq!(printed_ids(None, 0, Some(2)), all[..2]);
+    assert_eq!(printed_ids(None, 3, Some(2)), [FIRST_POINT_ID, SECOND_POINT_ID]);
+    assert_eq!(printed_ids(None, 6, Some(5)), [INT_ARRAY_ID, THREAD_ID]);
+    assert!(printed_ids(None, 8, None).is_empty());
+    assert!(printed_ids(None, 0, Some(0)).is_empty());
+
+    // The window counts only what passes the filter: the Point class and its two instances
+    let points = || Some(ClassFilter::Exact("com.example.Point".to_string()));
+    assert_eq!(printed_ids(points(), 0, None), [POINT_CLASS_ID, FIRST_POINT_ID, SECOND_POINT_ID]);
+    assert_eq!(printed_ids(points(), 2, Some(5)), [SECOND_POINT_ID]);
+    assert_eq!(printed_ids(points(), 1, Some(1)), [FIRST_POINT_ID]);
+}
+
+#[test]
+fn test_ndjson_lines_are_valid_json() {
+    let dump = fixture();
+    let hprof = jvm_hprof::parse_hprof(&dump[..]).unwrap();
+    let mut out = Vec::new();
+    dump_objects_to_ndjson(&HeapDumpAnalyzer::new(&hprof), None, &mut out).unwrap();
+    let lines: Vec<serde_json::Value> = String::from_utf8(out).unwrap().lines()
+        .map(|line| serde_json::from_str(line).unwrap_or_else(|e| panic!("{}: {}", e, line)))
+        .collect();
+
+    // Three instances and two arrays, without the class records
+    assert_eq!(lines.len(), 5);
+    assert!(lines.iter().all(|l| l["kind"] != "class"));
+    let point = lines.iter().find(|l| l["id"] == FIRST_POINT_ID).unwrap();
+    assert_eq!(point["class"], "com/example/Point");
+    assert_eq!(point["fields"]["x"], 3);
+    assert_eq!(point["fields"]["next"], serde_json::json!({"id": SECOND_POINT_ID, "type": "com/example/Point"}));
+    let ints = lines.iter().find(|l| l["id"] == INT_ARRAY_ID).unwrap();
+    assert_eq!(ints["elements"], serde_json::json!([1, 2, 3]));
+}
+
+#[test]
+fn test_dump_objects_to_parquet_writes_fixture() {
+    for id_size in ID_SIZES {
+        assert_fixture_exported(id_size);
+    }
+}
+
+fn assert_fixture_exported(id_size: u32) {
+    let dump = fixture_with_id_size(id_size);
+    let hprof = jvm_hprof::parse_hprof(&dump[..]).unwrap();
+    let out_dir = std::env::temp_dir().join(format!("hdsd-synthetic-dump-{}-{}", id_size, std::process::id()));
+    dump_objects_to_parquet(&HeapDumpAnalyzer::new(&hprof), &parquet_options(out_dir.clone())).unwrap();
+
+    assert_eq!(parquet_rows(&out_dir.join("com.example.Point_200.parquet")), 2);
+    assert_eq!(parquet_rows(&out_dir.join("_object_arrays.parquet")), 1);
+    assert_eq!(parquet_rows(&out_dir.join("_primitive_arrays_int.parquet")), 1);
+    assert_eq!(parquet_rows(&out_dir.join("_gc_roots.parquet")), GC_ROOT_COUNT as i64);
+    assert_eq!(parquet_rows(&out_dir.join("classes.parquet")), 3);
+
+    // References come back as full ids with their classes resolved
+    let file = std::fs::File::open(out_dir.join("com.example.Point_200.parquet")).unwrap();
+    let batch = ParquetRecordBatchReaderBuilder::try_new(file).unwrap().build().unwrap().next().unwrap().unwrap();
+    let obj_ids = batch.column_by_name("obj_id").unwrap().as_primitive::<UInt64Type>();
+    assert_eq!(obj_ids.values().to_vec(), vec![FIRST_POINT_ID, SECOND_POINT_ID]);
+    let next = batch.column_by_name("next").unwrap();
+    assert_eq!(next.as_struct().column(0).as_primitive::<UInt64Type>().value(0), SECOND_POINT_ID);
+    assert_eq!(reference_type(next, 0), "com/example/Point");
+    assert!(next.is_null(1));
+    let x = batch.column_by_name("x").unwrap().as_primitive::<Int32Type>();
+    assert_eq!(x.values().to_vec(), vec![3, -1]);
+
+    std::fs::remove_dir_all(&out_dir).unwrap();
+}
+
+#[test]
+fn test_export_summary_counts_fixture() {
+    let dump = fixture();
+    let hprof = jvm_hprof::parse_hprof(&dump[..]).unwrap();
+    let out_dir = std::env::temp_dir().join(format!("hdsd-summary-{}", std::process::id()));
+    let summary = dump_objects_to_parquet(&HeapDumpAnalyzer::new(&hprof), &parquet_options(out_dir.clone())).unwrap();
+
+    // Two points of 32 bytes and a bare Object of 16, then a Point[3] of 48 and an int[3] of 32
+    let files = std::fs::read_dir(&out_dir).unwrap().count() as u64;
+    assert_eq!(summary, ExportSummary { classes: 3, instances: 3, arrays: 2, shallow_bytes: 160, files, unresolved_references: 0 });
+    assert_eq!(summary.objects(), 5);
+    std::fs::remove_dir_all(&out_dir).unwrap();
+}
+
+#[test]
+fn test_no_array_elements_keeps_lengths() {
+    let dump = fixture();
+    let hprof = jvm_hprof::parse_hprof(&dump[..]).unwrap();
+    let out_dir = std::env::temp_dir().join(format!("hdsd-no-array-elements-{}", std::process::id()));
+    let mut options = parquet_options(out_dir.clone());
+    options.array_elements = false;
+    dump_objects_to_parquet(&HeapDumpAnalyzer::new(&hprof), &options).unwrap();
+
+    let read = |name: &str| {
+        let file = std::fs::File::open(out_dir.join(name)).unwrap();
+        ParquetRecordBatchReaderBuilder::try_new(file).unwrap().build().unwrap().next().unwrap().unwrap()
+    };
+    let arrays = read("_object_arrays.parquet");
+    let schema = arrays.schema();
+    let names: Vec<&str> = schema.fields().iter().map(|f| f.name().as_str()).collect();
+    assert_eq!(names, vec!["obj_id", "class_name", "length"]);
+    assert_eq!(arrays.column(0).as_primitive::<UInt64Type>().value(0), POINT_ARRAY_ID);
+    assert_eq!(arrays.column(1).as_string::<i32>().value(0), "[Lcom/example/Point;");
+    // The null slot counts towards the length
+    assert_eq!(arrays.column(2).as_primitive::<Int32Type>().value(0), 3);
+
+    let ints = read("_primitive_arrays_int.parquet");
+    assert_eq!(ints.schema().field(1).name(), "length");
+    assert_eq!(ints.column(0).as_primitive::<UInt64Type>().value(0), INT_ARRAY_ID);
+    assert_eq!(ints.column(1).as_primitive::<Int32Type>().value(0), 3);
+
+    std::fs::remove_dir_all(&out_dir).unwrap();
+}
+
+#[test]
+fn test_include_classes_skips_jdk_classes() {
+    let dump = fixture();
+    let hprof = jvm_hprof::parse_hprof(&dump[..]).unwrap();
+    let export = |name: &str, classes: ClassSelection| -> Vec<String> {
+        let out_dir = std::env::temp_dir().join(format!("hdsd-include-{}-{}", name, std::process::id()));
+        let mut options = parquet_options(out_dir.clone());
+        options.classes = classes;
+        dump_objects_to_parquet(&HeapDumpAnalyzer::new(&hprof), &options).unwrap();
+        let mut files: Vec<String> = std::fs::read_dir(&out_dir).unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+        files.sort();
+        std::fs::remove_dir_all(&out_dir).unwrap();
+        files
+    };
+
+    let all = export("all", ClassSelection::default());
+    assert!(all.contains(&"java.lang.Object_100.parquet".to_string()), "{:?}", all);
+
+    let app = export("app", ClassSelection::new(&["com.example.*"], &[]));
+    assert!(app.contains(&"com.example.Point_200.parquet".to_string()), "{:?}", app);
+    assert!(!app.iter().any(|f| f.starts_with("java.")), "{:?}", app);
+    // Arrays, GC roots and class metadata are still written in full
+    assert!(app.contains(&"_gc_roots.parquet".to_string()));
+    assert!(app.contains(&"classes.parquet".to_string()));
+
+    let excluded = export("excluded", ClassSelection::new(&["com.example.*"], &["*.Point"]));
+    assert!(!excluded.iter().any(|f| f.starts_with("com.example.Point")), "{:?}", excluded);
+}
+
+/// `count` Points with one int field, and an `int[]` of `int_array`.
+fn points_dump(count: u64, int_array: &[i32]) -> Vec<u8> {
+    let mut segment = Segment::default();
+    segment
+        .class(OBJECT_CLASS_ID, 0, 0, &[], &[])
+        .class(POINT_CLASS_ID, OBJECT_CLASS_ID, 4, &[], &[(1, TYPE_INT)])
+        .int_array(INT_ARRAY_ID, int_array);
+    for i in 0..count {
+        segment.instance(FIRST_POINT_ID + i, POINT_CLASS_ID, &[Value::Int(0)]);
+    }
+    HprofBuilder::new()
+        .utf8(1, "x")
+        .named_class(1, OBJECT_CLASS_ID, "java/lang/Object")
+        .named_class(2, POINT_CLASS_ID, "com/example/Point")
+        .heap_dump_segment(&segment)
+        .finish()
+}
+
+#[test]
+fn test_analyzer_class_histogram() {
+    let dump = fixture();
+    let hprof = jvm_hprof::parse_hprof(&dump[..]).unwrap();
+    let stats: Vec<(String, u64, u64)> = HeapDumpAnalyzer::new(&hprof).class_histogram().unwrap().into_iter()
+        .map(|s| (s.class_name, s.instance_count, s.total_shallow_bytes))
+        .collect();
+    assert_eq!(stats, vec![
+        // 16 byte header, two ints and a reference
+        ("com/example/Point".to_string(), 2, 2 * 32),
+        // 16 byte header, the length and three references, aligned to 48
+        ("[Lcom/example/Point;".to_string(), 1, 48),
+        ("int[]".to_string(), 1, 32),
+        ("java/lang/Object".to_string(), 1, 16),
+    ]);
+}
+
+#[test]
+fn test_analyzer_instances_of() {
+    let dump = fixture();
+    let hprof = jvm_hprof::parse_hprof(&dump[..]).unwrap();
+    let analyzer = HeapDumpAnalyzer::new(&hprof);
+
+    let points: Vec<_> = analyzer.instances_of("com.example.Point").unwrap().collect::<Result<_, _>>().unwrap();
+    let ids: Vec<u64> = points.iter().map(|p| p.obj_id).collect();
+    assert_eq!(ids, vec![FIRST_POINT_ID, SECOND_POINT_ID]);
+    assert!(points.iter().all(|p| p.class_name == "com/example/Point"));
+    assert_eq!(points[0].field("x"), Some(&ResolvedValue::Int(3)));
+    assert_eq!(points[0].field("y"), Some(&ResolvedValue::Int(4)));
+    assert_eq!(
+        points[0].field("next"),
+        Some(&ResolvedValue::Reference { id: SECOND_POINT_ID, type_name: "com/example/Point".to_string() })
+    );
+    assert_eq!(points[1].field("next"), Some(&ResolvedValue::Null));
+
+    // Exactly the class: not its subclasses' instances, nor a class the dump doesn't have
+    assert_eq!(analyzer.instances_of("java/lang/Object").unwrap().count(), 1);
+    assert_eq!(analyzer.instances_of("com/example/Missing").unwrap().count(), 0);
+}
+
+#[test]
+fn test_analyzer_record_counts() {
+    let dump = fixture();
+    let hprof = jvm_hprof::parse_hprof(&dump[..]).unwrap();
+    let counts = HeapDumpAnalyzer::new(&hprof).record_counts().unwrap();
+    // Four field names, then a name and a load class record per class
+    assert_eq!(counts[&RecordTag::Utf8], 7);
+    assert_eq!(counts[&RecordTag::LoadClass], 3);
+    assert_eq!(counts[&RecordTag::HeapDumpSegment], 1);
+    assert_eq!(counts[&RecordTag::StackTrace], 0, "tags that don't occur are counted as 0");
+    // and the heap dump end record
+    assert_eq!(counts.values().sum::<u64>(), 12);
+}
+
+/// 250 Points in each of `segments` heap dump segments, the first also holding the classes.
+fn segmented_points_dump(segments: u64) -> Vec<u8> {
+    let mut builder = HprofBuilder::new();
+    builder
+        .utf8(1, "x")
+        .named_class(1, OBJECT_CLASS_ID, "java/lang/Object")
+        .named_class(2, POINT_CLASS_ID, "com/example/Point");
+    for n in 0..segments {
+        let mut segment = Segment::default();
+        if n == 0 {
+            segment
+                .class(OBJECT_CLASS_ID, 0, 0, &[], &[])
+                .class(POINT_CLASS_ID, OBJECT_CLASS_ID, 4, &[], &[(1, TYPE_INT)]);
+        }
+        for i in 0..250 {
+            segment.instance(FIRST_POINT_ID + n * 250 + i, POINT_CLASS_ID, &[Value::Int(0)]);
+        }
+        builder.heap_dump_segment(&segment);
+    }
+    builder.finish()
+}
+
+#[test]
+fn test_approximate_histogram_is_deterministic() {
+    let dump = segmented_points_dump(8);
+    let hprof = jvm_hprof::parse_hprof(&dump[..]).unwrap();
+    let rows = |stats: Vec<ClassStat>| -> Vec<(String, u64, u64)> {
+        stats.into_iter().map(|s| (s.class_name, s.instance_count, s.total_shallow_bytes)).collect()
+    };
+    let approximate = |fraction| rows(HeapDumpAnalyzer::new(&hprof).approximate_class_histogram(fraction).unwrap());
+
+    let first = approximate(0.25);
+    assert_eq!(first, approximate(0.25), "the same segments are sampled on every run");
+    // Segments 0 and 4 are read, and their 500 Points scaled up by 8 / 2
+    assert_eq!(first, vec![("com/example/Point".to_string(), 2000, 2000 * 24)]);
+    assert_eq!(approximate(1.0), rows(HeapDumpAnalyzer::new(&hprof).class_histogram().unwrap()));
+}
+
+#[test]
+fn test_diff_reports_grown_class() {
+    let (before, after) = (points_dump(2, &[1, 2]), points_dump(5, &[1, 2]));
+    let (before, after) = (jvm_hprof::parse_hprof(&before[..]).unwrap(), jvm_hprof::parse_hprof(&after[..]).unwrap());
+    let (before, after) = (HeapDumpAnalyzer::new(&before), HeapDumpAnalyzer::new(&after));
+    let deltas = histogram_diff(&before, &after).unwrap();
+    // The unchanged int[] is left out
+    assert_eq!(deltas.len(), 1, "{:?}", deltas);
+    let point = &deltas[0];
+    assert_eq!(point.class_name, "com/example/Point");
+    assert_eq!((point.count_before, point.count_after, point.count_delta), (2, 5, 3));
+    // 16 byte header plus one int, aligned to 24
+    assert_eq!(point.bytes_delta, 3 * 24);
+
+    // Swapped, the class shrank
+    let deltas = histogram_diff(&after, &before).unwrap();
+    assert_eq!(deltas[0].count_delta, -3);
+}
+
+/// A `java/lang/Thread` rooted as thread 1, whose trace has two frames, and a second trace
+/// for thread 2, which has no thread object.
+fn threads_dump() -> Vec<u8> {
+    const RUN: u64 = 1;
+    const SOURCE: u64 = 2;
+    const READ: u64 = 3;
+    const THREAD_CLASS_ID: u64 = 800;
+    const WORKER_CLASS_ID: u64 = 810;
+    const THREAD_OBJ_ID: u64 = 900;
+
+    let mut segment = Segment::default();
+    segment
+        .class(OBJECT_CLASS_ID, 0, 0, &[], &[])
+        .class(THREAD_CLASS_ID, OBJECT_CLASS_ID, 0, &[], &[])
+        .instance(THREAD_OBJ_ID, THREAD_CLASS_ID, &[])
+        .every_gc_root(THREAD_OBJ_ID, THREAD_OBJ_ID, THREAD_CLASS_ID);
+
+    HprofBuilder::new()
+        .utf8(RUN, "run")
+        .utf8(SOURCE, "Worker.java")
+        .utf8(READ, "read0")
+        .named_class(1, OBJECT_CLASS_ID, "java/lang/Object")
+        .named_class(2, THREAD_CLASS_ID, "java/lang/Thread")
+        .named_class(3, WORKER_CLASS_ID, "com/example/Worker")
+        .stack_frame(100, READ, SOURCE, 3, -3)
+        .stack_frame(101, RUN, SOURCE, 3, 12)
+        .stack_trace(2, 2, &[])
+        .stack_trace(1, 1, &[100, 101])
+        .heap_dump_segment(&segment)
+        .finish()
+}
+
+#[test]
+fn test_stack_traces_name_frames_and_threads() {
+    let dump = threads_dump();
+    let hprof = jvm_hprof::parse_hprof(&dump[..]).unwrap();
+    let mut out = Vec::new();
+    stack_traces(&HeapDumpAnalyzer::new(&hprof), &mut out).unwrap();
+    assert_eq!(String::from_utf8(out).unwrap(), "\
+trace 1 (thread 1, id 900: java/lang/Thread)
+  at com.example.Worker.read0(Native Method)
+  at com.example.Worker.run(Worker.java:12)
+trace 2 (thread 2)
+  (no frames)
+");
+}
+
+#[test]
+fn test_monitors_name_the_thread_holding_the_object() {
+    // The fixture's roots have point 1000 monitored and on the stack of thread 1
+    let dump = fixture();
+    let hprof = jvm_hprof::parse_hprof(&dump[..]).unwrap();
+    let mut out = Vec::new();
+    monitors(&HeapDumpAnalyzer::new(&hprof), &mut out).unwrap();
+    assert_eq!(String::from_utf8(out).unwrap(), "\
+id 1000: com/example/Point
+  on the stack of thread 1 (id 400: java/lang/Object)
+
+1 monitored objects
+");
+}
+
+/// A `com/example/Point` whose two int fields are named with different invalid UTF-8 bytes.
+fn invalid_names_dump() -> Vec<u8> {
+    let mut segment = Segment::default();
+    segment
+        .class(OBJECT_CLASS_ID, 0, 0, &[], &[])
+        .class(POINT_CLASS_ID, OBJECT_CLASS_ID, 8, &[], &[(1, TYPE_INT), (2, TYPE_INT)])
+        .instance(FIRST_POINT_ID, POINT_CLASS_ID, &[Value::Int(3), Value::Int(4)]);
+    HprofBuilder::new()
+        .utf8_bytes(1, b"x\xffa")
+        .utf8_bytes(2, b"x\xc3b")
+        .named_class(1, OBJECT_CLASS_ID, "java/lang/Object")
+        .named_class(2, POINT_CLASS_ID, "com/example/Point")
+        .heap_dump_segment(&segment)
+        .finish()
+}
+
+fn invalid_names_output(utf8: Utf8Mode) -> Result<String, AnalyzeError> {
+    let dump = invalid_names_dump();
+    let hprof = jvm_hprof::parse_hprof(&dump[..]).unwrap();
+    let options = DumpObjectsOptions {
+        filter: None,
+        window: ObjectWindow::default(),
+        sample: None,
+        max_array_elements: usize::MAX,
+        format: ObjectFormat::Text,
+        id_format: IdFormat::Decimal,
+        decode_char_arrays: false,
+        byte_array_format: ByteArrayFormat::Hex,
+        expand_depth: 0,
+        fail_on_unresolved: false,
+    };
+    let analyzer = HeapDumpAnalyzer::with_options(&hprof, AnalyzerOptions { utf8, ..AnalyzerOptions::default() });
+    let mut out = Vec::new();
+    dump_objects(&analyzer, &options, &mut out)?;
+    Ok(String::from_utf8(out).unwrap())
+}
+
+#[test]
+fn test_invalid_utf8_names() {
+    let text = invalid_names_output(Utf8Mode::Placeholder).unwrap();
+    assert!(text.contains("  - (invalid UTF-8): int = 3\n  - (invalid UTF-8): int = 4\n"), "{}", text);
+
+    // Each broken name keeps its valid parts, so the two fields stay distinct
+    let text = invalid_names_output(Utf8Mode::Lossy).unwrap();
+    assert!(text.contains("  - x\u{FFFD}a: int = 3\n  - x\u{FFFD}b: int = 4\n"), "{}", text);
+
+    let err = invalid_names_output(Utf8Mode::Strict).unwrap_err();
+    assert!(matches!(err, AnalyzeError::Parse(_)), "{:?}", err);
+    assert!(err.to_string().contains("utf8 record 1"), "{}", err);
+}
+
+const DANGLING_ID: u64 = 9999;
+const NODE_ARRAY_CLASS_ID: u64 = 610;
+const NODE_ARRAY_ID: u64 = 2100;
+
+/// Node 1000 points at node 1001, which points at an object id the dump has no record for
+/// when `dangling_field` is set, and at null otherwise. A `Node[]` holds node 1000 and then
+/// the missing id.
+fn dangling_dump(dangling_field: bool) -> Vec<u8> {
+    let next = if dangling_field { DANGLING_ID } else { 0 };
+    let mut segment = Segment::default();
+    segment
+        .class(OBJECT_CLASS_ID, 0, 0, &[], &[])
+        .class(NODE_CLASS_ID, OBJECT_CLASS_ID, 8, &[], &[(1, TYPE_OBJECT)])
+        .class(NODE_ARRAY_CLASS_ID, OBJECT_CLASS_ID, 0, &[], &[])
+        .instance(FIRST_POINT_ID, NODE_CLASS_ID, &[Value::Object(SECOND_POINT_ID)])
+        .instance(SECOND_POINT_ID, NODE_CLASS_ID, &[Value::Object(next)])
+        .object_array(NODE_ARRAY_ID, NODE_ARRAY_CLASS_ID, &[FIRST_POINT_ID, DANGLING_ID]);
+
+    HprofBuilder::new()
+        .utf8(1, "next")
+        .named_class(1, OBJECT_CLASS_ID, "java/lang/Object")
+        .named_class(2, NODE_CLASS_ID, "com/example/Node")
+        .named_class(3, NODE_ARRAY_CLASS_ID, "[Lcom/example/Node;")
+        .heap_dump_segment(&segment)
+        .finish()
+}
+
+#[test]
+fn test_unresolved_references_are_counted() {
+    let dump = dangling_dump(true);
+    let hprof = jvm_hprof::parse_hprof(&dump[..]).unwrap();
+    let analyzer = HeapDumpAnalyzer::new(&hprof);
+
+    let mut out = Vec::new();
+    let unresolved = dump_objects(&analyzer, &dump_objects_options(ObjectFormat::Text), &mut out).unwrap();
+    let text = String::from_utf8(out).unwrap();
+    assert!(text.contains("id 1001: com/example/Node\n  - next: "), "{}", text);
+    assert_eq!(unresolved, 2, "the dangling field and the dangling array element");
+
+    let out_dir = std::env::temp_dir().join(format!("hdsd-unresolved-{}", std::process::id()));
+    let summary = dump_objects_to_parquet(&analyzer, &parquet_options(out_dir.clone())).unwrap();
+    assert_eq!(summary.unresolved_references, 2);
+    std::fs::remove_dir_all(&out_dir).unwrap();
+}
+
+#[test]
+fn test_fail_on_unresolved_names_the_field_or_element() {
+    // The parquet export reports the lowest object id, dump-objects the first printed
+    for (dangling_field, obj_id, field) in [(true, SECOND_POINT_ID, "next"), (false, NODE_ARRAY_ID, "[1]")] {
+        let dump = dangling_dump(dangling_field);
+        let hprof = jvm_hprof::parse_hprof(&dump[..]).unwrap();
+        let analyzer = HeapDumpAnalyzer::new(&hprof);
+        let assert_dangling_error = |err: AnalyzeError| {
+            assert!(
+                matches!(
+                    &err,
+                    AnalyzeError::UnresolvedField { obj_id: o, field_name, ref_id: DANGLING_ID } if *o == obj_id && field_name == field
+                ),
+                "{}",
+                err
+            );
+        };
+
+        let mut options = dump_objects_options(ObjectFormat::Text);
+        options.fail_on_unresolved = true;
+        assert_dangling_error(dump_objects(&analyzer, &options, &mut std::io::sink()).unwrap_err());
+
+        let out_dir = std::env::temp_dir().join(format!("hdsd-fail-unresolved-{}-{}", std::process::id(), dangling_field));
+        let mut options = parquet_options(out_dir.clone());
+        options.fail_on_unresolved = true;
+        assert_dangling_error(dump_objects_to_parquet(&analyzer, &options).unwrap_err());
+        std::fs::remove_dir_all(&out_dir).unwrap();
+    }
+}
+
+const CHAR_ARRAY_ID: u64 = 9000;
+
+fn char_array_dump() -> Vec<u8> {
+    let mut segment = Segment::default();
+    segment
+        .class(OBJECT_CLASS_ID, 0, 0, &[], &[])
+        .char_array(CHAR_ARRAY_ID, "hello");
+    HprofBuilder::new()
+        .named_class(1, OBJECT_CLASS_ID, "java/lang/Object")
+        .heap_dump_segment(&segment)
+        .finish()
+}
+
+#[test]
+fn test_decode_char_arrays() {
+    let dump = char_array_dump();
+    let hprof = jvm_hprof::parse_hprof(&dump[..]).unwrap();
+    let print = |decode_char_arrays: bool, max_array_elements: usize| -> String {
+        let options = DumpObjectsOptions {
+            filter: None,
+            window: ObjectWindow::default(),
+            sample: None,
+            max_array_elements,
+            format: ObjectFormat::Text,
+            id_format: IdFormat::Decimal,
+            decode_char_arrays,
+            byte_array_format: ByteArrayFormat::Hex,
+            expand_depth: 0,
+            fail_on_unresolved: false,
+        };
+        let mut out = Vec::new();
+        dump_objects(&HeapDumpAnalyzer::new(&hprof), &options, &mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    };
+
+    let text = print(false, usize::MAX);
+    assert!(text.contains("9000: char[] = [104, 101, 108, 108, 111, ]"), "{}", text);
+    let text = print(true, usize::MAX);
+    assert!(text.contains("9000: char[] = \"hello\"\n"), "{}", text);
+    let text = print(true, 2);
+    assert!(text.contains("9000: char[] = \"he\" ... (3 more)\n"), "{}", text);
+
+    let out_dir = std::env::temp_dir().join(format!("hdsd-char-text-{}", std::process::id()));
+    let mut options = parquet_options(out_dir.clone());
+    options.char_array_text = true;
+    dump_objects_to_parquet(&HeapDumpAnalyzer::new(&hprof), &options).unwrap();
+    let file = std::fs::File::open(out_dir.join("_primitive_arrays_char.parquet")).unwrap();
+    let chars = ParquetRecordBatchReaderBuilder::try_new(file).unwrap().build().unwrap().next().unwrap().unwrap();
+    assert_eq!(chars.schema().field(2).name(), "text");
+    assert_eq!(chars.column(0).as_primitive::<UInt64Type>().value(0), CHAR_ARRAY_ID);
+    assert_eq!(chars.column(2).as_string::<i32>().value(0), "hello");
+    std::fs::remove_dir_all(&out_dir).unwrap();
+}
+
+#[test]
+fn test_binary_byte_arrays() {
+    let mut segment = Segment::default();
+    segment
+        .class(OBJECT_CLASS_ID, 0, 0, &[], &[])
+        .byte_array(9100, &[0xca, 0xfe, 0x00, 0x7f]);
+    let dump = HprofBuilder::new()
+        .named_class(1, OBJECT_CLASS_ID, "java/lang/Object")
+        .heap_dump_segment(&segment)
+        .finish();
+    let hprof = jvm_hprof::parse_hprof(&dump[..]).unwrap();
+
+    let out_dir = std::env::temp_dir().join(format!("hdsd-binary-bytes-{}", std::process::id()));
+    let mut options = parquet_options(out_dir.clone());
+    options.binary_byte_arrays = true;
+    dump_objects_to_parquet(&HeapDumpAnalyzer::new(&hprof), &options).unwrap();
+    let file = std::fs::File::open(out_dir.join("_primitive_arrays_byte.parquet")).unwrap();
+    let bytes = ParquetRecordBatchReaderBuilder::try_new(file).unwrap().build().unwrap().next().unwrap().unwrap();
+    assert_eq!(bytes.column(0).as_primitive::<UInt64Type>().value(0), 9100);
+    assert_eq!(bytes.column(1).as_binary::<i32>().value(0), &[0xca, 0xfe, 0x00, 0x7f]);
+    std::fs::remove_dir_all(&out_dir).unwrap();
+}
+
+#[test]
+fn test_ipc_output_matches_parquet_rows() {
+    let dump = fixture();
+    let hprof = jvm_hprof::parse_hprof(&dump[..]).unwrap();
+    let parquet_dir = std::env::temp_dir().join(format!("hdsd-ipc-parquet-{}", std::process::id()));
+    dump_objects_to_parquet(&HeapDumpAnalyzer::new(&hprof), &parquet_options(parquet_dir.clone())).unwrap();
+    let ipc_dir = std::env::temp_dir().join(format!("hdsd-ipc-{}", std::process::id()));
+    let mut options = parquet_options(ipc_dir.clone());
+    options.writer.format = OutputFormat::Ipc;
+    dump_objects_to_parquet(&HeapDumpAnalyzer::new(&hprof), &options).unwrap();
+
+    for stem in ["com.example.Point_200", "_object_arrays", "_primitive_arrays_int", "_gc_roots", "classes"] {
+        let path = ipc_dir.join(format!("{}.arrow", stem));
+        let file = std::fs::File::open(&path).unwrap_or_else(|e| panic!("{}: {}", path.display(), e));
+        let rows: usize = arrow_ipc::reader::FileReader::try_new(file, None).unwrap().map(|b| b.unwrap().num_rows()).sum();
+        assert_eq!(rows as i64, parquet_rows(&parquet_dir.join(format!("{}.parquet", stem))), "{}", stem);
+    }
+    assert!(!ipc_dir.join("com.example.Point_200.parquet").exists());
+    std::fs::remove_dir_all(&parquet_dir).unwrap();
+    std::fs::remove_dir_all(&ipc_dir).unwrap();
+}
+
+#[test]
+fn test_live_only_skips_unreachable_instances() {
+    let mut segment = Segment::default();
+    segment
+        .class(OBJECT_CLASS_ID, 0, 0, &[], &[])
+        .class(POINT_CLASS_ID, OBJECT_CLASS_ID, 4, &[], &[(1, TYPE_INT)])
+        .instance(FIRST_POINT_ID, POINT_CLASS_ID, &[Value::Int(3)])
+        // Nothing refers to the second point
+        .instance(SECOND_POINT_ID, POINT_CLASS_ID, &[Value::Int(4)])
+        .instance(THREAD_ID, OBJECT_CLASS_ID, &[])
+        .every_gc_root(FIRST_POINT_ID, THREAD_ID, POINT_CLASS_ID);
+    let dump = HprofBuilder::new()
+        .utf8(1, "x")
+        .named_class(1, OBJECT_CLASS_ID, "java/lang/Object")
+        .named_class(2, POINT_CLASS_ID, "com/example/Point")
+        .heap_dump_segment(&segment)
+        .finish();
+    let hprof = jvm_hprof::parse_hprof(&dump[..]).unwrap();
+
+    let out_dir = std::env::temp_dir().join(format!("hdsd-live-only-{}", std::process::id()));
+    let points = out_dir.join("com.example.Point_200.parquet");
+    dump_objects_to_parquet(&HeapDumpAnalyzer::new(&hprof), &parquet_options(out_dir.clone())).unwrap();
+    assert_eq!(parquet_rows(&points), 2);
+
+    let mut options = parquet_options(out_dir.clone());
+    options.live_only = true;
+    dump_objects_to_parquet(&HeapDumpAnalyzer::new(&hprof), &options).unwrap();
+    let file = std::fs::File::open(&points).unwrap();
+    let batch = ParquetRecordBatchReaderBuilder::try_new(file).unwrap().build().unwrap().next().unwrap().unwrap();
+    let obj_ids = batch.column_by_name("obj_id").unwrap().as_primitive::<UInt64Type>();
+    assert_eq!(obj_ids.values().to_vec(), vec![FIRST_POINT_ID]);
+    assert!(batch.column_by_name("reachable").is_none(), "the column still needs --reachability");
+    std::fs::remove_dir_all(&out_dir).unwrap();
+}
+
+const HOLDER_CLASS_ID: u64 = 700;
+const BYTE_ARRAY_ID: u64 = 7100;
+
+#[test]
+fn test_inline_primitive_arrays_embeds_byte_array() {
+    let mut segment = Segment::default();
+    segment
+        .class(OBJECT_CLASS_ID, 0, 0, &[], &[])
+        .class(HOLDER_CLASS_ID, OBJECT_CLASS_ID, 8, &[], &[(1, TYPE_OBJECT)])
+        .instance(FIRST_POINT_ID, HOLDER_CLASS_ID, &[Value::Object(BYTE_ARRAY_ID)])
+        .instance(SECOND_POINT_ID, HOLDER_CLASS_ID, &[Value::Object(0)])
+        .byte_array(BYTE_ARRAY_ID, &[0xca, 0xfe, 0x01]);
+    let dump = HprofBuilder::new()
+        .utf8(1, "data")
+        .named_class(1, OBJECT_CLASS_ID, "java/lang/Object")
+        .named_class(2, HOLDER_CLASS_ID, "com/example/Holder")
+        .heap_dump_segment(&segment)
+        .finish();
+    let hprof = jvm_hprof::parse_hprof(&dump[..]).unwrap();
+
+    let out_dir = std::env::temp_dir().join(format!("hdsd-inline-bytes-{}", std::process::id()));
+    let mut options = parquet_options(out_dir.clone());
+    options.inline_primitive_arrays = true;
+    dump_objects_to_parquet(&HeapDumpAnalyzer::new(&hprof), &options).unwrap();
+    let file = std::fs::File::open(out_dir.join("com.example.Holder_700.parquet")).unwrap();
+    let batch = ParquetRecordBatchReaderBuilder::try_new(file).unwrap().build().unwrap().next().unwrap().unwrap();
+    std::fs::remove_dir_all(&out_dir).unwrap();
+
+    let data = batch.column_by_name("data").unwrap().as_list::<i32>();
+    assert_eq!(data.value_type(), DataType::Int8);
+    let bytes = data.value(0);
+    assert_eq!(bytes.as_primitive::<Int8Type>().values().to_vec(), vec![0xca_u8 as i8, 0xfe_u8 as i8, 1]);
+    assert!(data.is_null(1), "a null reference is a null list");
+}
+
+const NODE_CLASS_ID: u64 = 600;
+
+/// Two nodes whose `next` fields point at each other.
+fn node_cycle_output(expand_depth: usize) -> String {
+    let mut segment = Segment::default();
+    segment
+        .class(OBJECT_CLASS_ID, 0, 0, &[], &[])
+        .class(NODE_CLASS_ID, OBJECT_CLASS_ID, 12, &[], &[(1, TYPE_INT), (2, TYPE_OBJECT)])
+        .instance(FIRST_POINT_ID, NODE_CLASS_ID, &[Value::Int(1), Value::Object(SECOND_POINT_ID)])
+        .instance(SECOND_POINT_ID, NODE_CLASS_ID, &[Value::Int(2), Value::Object(FIRST_POINT_ID)]);
+    let dump = HprofBuilder::new()
+        .utf8(1, "value")
+        .utf8(2, "next")
+        .named_class(1, OBJECT_CLASS_ID, "java/lang/Object")
+        .named_class(2, NODE_CLASS_ID, "com/example/Node")
+        .heap_dump_segment(&segment)
+        .finish();
+    let hprof = jvm_hprof::parse_hprof(&dump[..]).unwrap();
+    let options = DumpObjectsOptions {
+        filter: Some(ClassFilter::Exact("com.example.Node".to_string())),
+        window: ObjectWindow::default(),
+        sample: None,
+        max_array_elements: usize::MAX,
+        format: ObjectFormat::Text,
+        id_format: IdFormat::Decimal,
+        decode_char_arrays: false,
+        byte_array_format: ByteArrayFormat::Hex,
+        expand_depth,
+        fail_on_unresolved: false,
+    };
+    let mut out = Vec::new();
+    dump_objects(&HeapDumpAnalyzer::new(&hprof), &options, &mut out).unwrap();
+    String::from_utf8(out).unwrap()
+}
+
+#[test]
+fn test_expand_depth_nests_referent_fields() {
+    assert!(node_cycle_output(0).contains(
+        "id 1000: com/example/Node\n  - value: int = 1\n  - next = id 1001 (com/example/Node)\n\nid 1001"
+    ));
+    let text = node_cycle_output(1);
+    assert!(text.contains(concat!(
+        "id 1000: com/example/Node\n",
+        "  - value: int = 1\n",
+        "  - next = id 1001 (com/example/Node)\n",
+        "    - value: int = 2\n",
+        "    - next = id 1000 (com/example/Node)\n",
+        "\nid 1001",
+    )), "{}", text);
+}
+
+#[test]
+fn test_expand_depth_stops_at_cycles() {
+    let text = node_cycle_output(50);
+    assert!(text.contains(concat!(
+        "id 1000: com/example/Node\n",
+        "  - value: int = 1\n",
+        "  - next = id 1001 (com/example/Node)\n",
+        "    - value: int = 2\n",
+        "    - next = id 1000 (com/example/Node) (already shown)\n",
+        "\nid 1001: com/example/Node\n",
+        "  - value: int = 2\n",
+        "  - next = id 1000 (com/example/Node)\n",
+        "    - value: int = 1\n",
+        "    - next = id 1001 (com/example/Node) (already shown)\n",
+    )), "{}", text);
+}
+
+const UNUSED_CLASS_ID: u64 = 500;
+
+#[test]
+fn test_class_without_instances_gets_an_empty_histogram_row() {
+    let mut segment = Segment::default();
+    segment
+        .class(OBJECT_CLASS_ID, 0, 0, &[], &[])
+        .class(POINT_CLASS_ID, OBJECT_CLASS_ID, 4, &[], &[(1, TYPE_INT)])
+        // Loaded, but never instantiated
+        .class(UNUSED_CLASS_ID, OBJECT_CLASS_ID, 8, &[], &[(1, TYPE_LONG)])
+        .instance(FIRST_POINT_ID, POINT_CLASS_ID, &[Value::Int(3)]);
+    let dump = HprofBuilder::new()
+        .utf8(1, "x")
+        .named_class(1, OBJECT_CLASS_ID, "java/lang/Object")
+        .named_class(2, POINT_CLASS_ID, "com/example/Point")
+        .named_class(3, UNUSED_CLASS_ID, "com/example/Unused")
+        .heap_dump_segment(&segment)
+        .finish();
+    let hprof = jvm_hprof::parse_hprof(&dump[..]).unwrap();
+
+    let out_dir = std::env::temp_dir().join(format!("hdsd-no-instances-{}", std::process::id()));
+    dump_objects_to_parquet(&HeapDumpAnalyzer::new(&hprof), &parquet_options(out_dir.clone())).unwrap();
+    assert!(!out_dir.join("com.example.Unused_500.parquet").exists());
+    assert_eq!(parquet_rows(&out_dir.join("com.example.Point_200.parquet")), 1);
+
+    let read = |name: &str| {
+        let file = std::fs::File::open(out_dir.join(name)).unwrap();
+        ParquetRecordBatchReaderBuilder::try_new(file).unwrap().build().unwrap().next().unwrap().unwrap()
+    };
+    let classes = read("classes.parquet");
+    let class_ids = classes.column_by_name("class_id").unwrap().as_primitive::<UInt64Type>();
+    assert!(class_ids.values().contains(&UNUSED_CLASS_ID), "{:?}", class_ids);
+
+    let histogram = read("class_histogram.parquet");
+    let names = histogram.column_by_name("class_name").unwrap().as_string::<i32>();
+    let counts = histogram.column_by_name("instance_count").unwrap().as_primitive::<UInt64Type>();
+    let row = (0..histogram.num_rows()).find(|&row| names.value(row) == "com/example/Unused").unwrap();
+    assert_eq!(counts.value(row), 0);
+    let point = (0..histogram.num_rows()).find(|&row| names.value(row) == "com/example/Point").unwrap();
+    assert_eq!(counts.value(point), 1);
+    std::fs::remove_dir_all(&out_dir).unwrap();
+}
+
+#[test]
+fn test_partition_by_class_writes_hive_layout() {
+    let dump = fixture();
+    let hprof = jvm_hprof::parse_hprof(&dump[..]).unwrap();
+    for single_file in [false, true] {
+        let out_dir = std::env::temp_dir().join(format!("hdsd-partition-{}-{}", single_file, std::process::id()));
+        let mut options = parquet_options(out_dir.clone());
+        options.partition_by_class = true;
+        options.single_file = single_file;
+        dump_objects_to_parquet(&HeapDumpAnalyzer::new(&hprof), &options).unwrap();
+
+        let part = out_dir.join("class=com.example.Point_200").join("part-0.parquet");
+        assert_eq!(parquet_rows(&part), 2, "single_file: {}", single_file);
+        let file = std::fs::File::open(&part).unwrap();
+        let schema = ParquetRecordBatchReaderBuilder::try_new(file).unwrap().schema().clone();
+        assert_eq!(schema.field_with_name("class_name").is_ok(), single_file);
+        assert!(!out_dir.join("com.example.Point_200.parquet").exists());
+        assert!(!out_dir.join("instances.parquet").exists());
+        // Only instances are partitioned
+        assert!(out_dir.join("_object_arrays.parquet").is_file());
+        assert!(out_dir.join("classes.parquet").is_file());
+
+        // A flat rerun into the same directory clears the partitions
+        dump_objects_to_parquet(&HeapDumpAnalyzer::new(&hprof), &parquet_options(out_dir.clone())).unwrap();
+        assert!(!out_dir.join("class=com.example.Point_200").exists());
+        assert!(out_dir.join("com.example.Point_200.parquet").is_file());
+        std::fs::remove_dir_all(&out_dir).unwrap();
+    }
+}
+
+#[test]
+fn test_objects_per_file_splits_instances_into_parts() {
+    let mut segment = Segment::default();
+    segment
+        .class(OBJECT_CLASS_ID, 0, 0, &[], &[])
+        .class(POINT_CLASS_ID, OBJECT_CLASS_ID, 4, &[], &[(1, TYPE_INT)]);
+    for i in 0..5 {
+        segment.instance(FIRST_POINT_ID + i, POINT_CLASS_ID, &[Value::Int(i as i32)]);
+    }
+    let dump = HprofBuilder::new()
+        .utf8(1, "x")
+        .named_class(1, OBJECT_CLASS_ID, "java/lang/Object")
+        .named_class(2, POINT_CLASS_ID, "com/example/Point")
+        .heap_dump_segment(&segment)
+        .finish();
+    let hprof = jvm_hprof::parse_hprof(&dump[..]).unwrap();
+
+    let out_dir = std::env::temp_dir().join(format!("hdsd-objects-per-file-{}", std::process::id()));
+    let mut options = parquet_options(out_dir.clone());
+    options.objects_per_file = Some(2);
+    dump_objects_to_parquet(&HeapDumpAnalyzer::new(&hprof), &options).unwrap();
+
+    let rows: Vec<i64> = (0..3)
+        .map(|part| parquet_rows(&out_dir.join(format!("com.example.Point_200.part-{}.parquet", part))))
+        .collect();
+    assert_eq!(rows, vec![2, 2, 1]);
+    assert!(!out_dir.join("com.example.Point_200.part-3.parquet").exists());
+    assert!(!out_dir.join("com.example.Point_200.parquet").exists());
+    assert_eq!(parquet_rows(&out_dir.join("classes.parquet")), 2, "metadata files aren't split");
+
+    options.partition_by_class = true;
+    dump_objects_to_parquet(&HeapDumpAnalyzer::new(&hprof), &options).unwrap();
+    let dir = out_dir.join("class=com.example.Point_200");
+    let rows: i64 = (0..3).map(|part| parquet_rows(&dir.join(format!("part-{}.parquet", part)))).sum();
+    assert_eq!(rows, 5);
+    std::fs::remove_dir_all(&out_dir).unwrap();
+}
+
+#[test]
+fn test_reference_type_column_is_dictionary_encoded() {
+    let dump = fixture();
+    let hprof = jvm_hprof::parse_hprof(&dump[..]).unwrap();
+    let out_dir = std::env::temp_dir().join(format!("hdsd-type-dictionary-{}", std::process::id()));
+    let mut options = parquet_options(out_dir.clone());
+    options.references = ReferenceEncoding::Struct;
+    dump_objects_to_parquet(&HeapDumpAnalyzer::new(&hprof), &options).unwrap();
+    let read = |name: &str| {
+        let file = std::fs::File::open(out_dir.join(name)).unwrap();
+        ParquetRecordBatchReaderBuilder::try_new(file).unwrap().build().unwrap().next().unwrap().unwrap()
+    };
+    let points = read("com.example.Point_200.parquet");
+    let arrays = read("_object_arrays.parquet");
+    std::fs::remove_dir_all(&out_dir).unwrap();
+
+    let dictionary = DataType::Dictionary(Box::new(DataType::Int32), Box::new(DataType::Utf8));
+    let next = points.column_by_name("next").unwrap();
+    assert_eq!(next.as_struct().column(1).data_type(), &dictionary);
+    assert_eq!(reference_type(next, 0), "com/example/Point");
+    assert_eq!(reference_type(next, 1), "null");
+
+    // The Point[] holds point 1000, null and point 1001: two names, stored once each
+    let elements = arrays.column_by_name("elements").unwrap().as_list::<i32>().value(0);
+    let types = elements.as_struct().column(1).as_dictionary::<Int32Type>();
+    assert_eq!(types.values().len(), 2);
+    let names: Vec<&str> = (0..elements.len()).map(|row| reference_type(&elements, row)).collect();
+    assert_eq!(names, vec!["com/example/Point", "null", "com/example/Point"]);
+}
+
+#[test]
+fn test_dump_classes_prints_field_layout() {
+    let dump = fixture();
+    let hprof = jvm_hprof::parse_hprof(&dump[..]).unwrap();
+    let filter = ClassFilter::Regex(regex::Regex::new(r"\.Point$").unwrap());
+    let mut out = Vec::new();
+    dump_classes(&HeapDumpAnalyzer::new(&hprof), Some(&filter), IdFormat::Decimal, &mut out).unwrap();
+    let text = String::from_utf8(out).unwrap();
+
+    assert!(text.starts_with(
+        "\nid 200: class com/example/Point\n  extends java/lang/Object\n  instance fields:\n    - x: int\n    - y: int\n    - next: "
+    ), "{}", text);
+    assert!(text.ends_with("  static fields:\n    - ORIGIN = id 1000 (com/example/Point)\n"), "{}", text);
+    // The Point[] class and java/lang/Object don't match
+    assert_eq!(text.matches("class ").count(), 1, "{}", text);
+
+    let mut out = Vec::new();
+    dump_classes(&HeapDumpAnalyzer::new(&hprof), None, IdFormat::Decimal, &mut out).unwrap();
+    let text = String::from_utf8(out).unwrap();
+    let headers: Vec<&str> = text.lines().filter(|l| l.starts_with("id ")).collect();
+    assert_eq!(headers, vec!["id 300: class [Lcom/example/Point;", "id 200: class com/example/Point", "id 100: class java/lang/Object"]);
+}
+
+#[test]
+fn test_duplicate_classes() {
+    // com/example/Plugin defined twice, as two class loaders would
+    let mut segment = Segment::default();
+    segment
+        .class(OBJECT_CLASS_ID, 0, 0, &[], &[])
+        .class(9200, OBJECT_CLASS_ID, 0, &[], &[])
+        .class(9201, OBJECT_CLASS_ID, 0, &[], &[]);
+    let dump = HprofBuilder::new()
+        .named_class(1, OBJECT_CLASS_ID, "java/lang/Object")
+        .named_class(2, 9200, "com/example/Plugin")
+        .named_class(3, 9201, "com/example/Plugin")
+        .heap_dump_segment(&segment)
+        .finish();
+    let hprof = jvm_hprof::parse_hprof(&dump[..]).unwrap();
+
+    let mut out = Vec::new();
+    duplicate_classes(&HeapDumpAnalyzer::new(&hprof), &mut out).unwrap();
+    assert_eq!(String::from_utf8(out).unwrap(), "2  com/example/Plugin  9200, 9201\n");
+}
+
+#[test]
+fn test_reference_encodings() {
+    let dump = fixture();
+    let hprof = jvm_hprof::parse_hprof(&dump[..]).unwrap();
+    // Point 1001's `next` and the Point[]'s middle slot are null
+    let export = |references: ReferenceEncoding| {
+        let out_dir = std::env::temp_dir().join(format!("hdsd-references-{:?}-{}", references, std::process::id()));
+        let mut options = parquet_options(out_dir.clone());
+        options.references = references;
+        dump_objects_to_parquet(&HeapDumpAnalyzer::new(&hprof), &options).unwrap();
+        let read = |name: &str| {
+            let file = std::fs::File::open(out_dir.join(name)).unwrap();
+            ParquetRecordBatchReaderBuilder::try_new(file).unwrap().build().unwrap().next().unwrap().unwrap()
+        };
+        let points = read("com.example.Point_200.parquet");
+        let arrays = read("_object_arrays.parquet");
+        std::fs::remove_dir_all(&out_dir).unwrap();
+        let next = points.column_by_name("next").unwrap().clone();
+        let elements = arrays.column_by_name("elements").unwrap().as_list::<i32>().value(0);
+        (next, elements)
+    };
+
+    let (next, elements) = export(ReferenceEncoding::Struct);
+    assert_eq!(next.null_count(), 0);
+    assert_eq!(reference_type(&next, 1), "null");
+    assert_eq!(elements.null_count(), 0);
+    assert_eq!(elements.as_struct().column(0).as_primitive::<UInt64Type>().value(1), 0);
+    assert_eq!(reference_type(&elements, 1), "null");
+
+    let (next, elements) = export(ReferenceEncoding::NullableStruct);
+    assert!(next.is_null(1));
+    assert_eq!(reference_type(&next, 0), "com/example/Point");
+    assert!(elements.is_null(1) && elements.is_valid(0));
+
+    let (next, elements) = export(ReferenceEncoding::IdOnly);
+    let next = next.as_primitive::<UInt64Type>();
+    assert_eq!(next.value(0), SECOND_POINT_ID);
+    assert!(next.is_null(1));
+    let elements = elements.as_primitive::<UInt64Type>();
+    assert_eq!(elements.iter().collect::<Vec<_>>(), vec![Some(FIRST_POINT_ID), None, Some(SECOND_POINT_ID)]);
+}
+
+#[test]
+fn test_duplicate_utf8_names_keep_their_columns() {
+    // Fields a and b are both named "x" through two utf8 records; c's name has no record
+    let mut segment = Segment::default();
+    segment
+        .class(OBJECT_CLASS_ID, 0, 0, &[], &[])
+        .class(POINT_CLASS_ID, OBJECT_CLASS_ID, 12, &[], &[(1, TYPE_INT), (5, TYPE_INT), (9, TYPE_INT)])
+        .instance(FIRST_POINT_ID, POINT_CLASS_ID, &[Value::Int(1), Value::Int(2), Value::Int(3)]);
+    let dump = HprofBuilder::new()
+        .utf8(1, "x")
+        .utf8(5, "x")
+        .named_class(1, OBJECT_CLASS_ID, "java/lang/Object")
+        .named_class(2, POINT_CLASS_ID, "com/example/Point")
+        .heap_dump_segment(&segment)
+        .finish();
+    let hprof = jvm_hprof::parse_hprof(&dump[..]).unwrap();
+
+    let out_dir = std::env::temp_dir().join(format!("hdsd-duplicate-utf8-{}", std::process::id()));
+    dump_objects_to_parquet(&HeapDumpAnalyzer::new(&hprof), &parquet_options(out_dir.clone())).unwrap();
+    let file = std::fs::File::open(out_dir.join("com.example.Point_200.parquet")).unwrap();
+    let batch = ParquetRecordBatchReaderBuilder::try_new(file).unwrap().build().unwrap().next().unwrap().unwrap();
+    std::fs::remove_dir_all(&out_dir).unwrap();
+
+    let value = |name: &str| {
+        batch.column_by_name(name).unwrap_or_else(|| panic!("no {} column", name)).as_primitive::<Int32Type>().value(0)
+    };
+    assert_eq!(value("x"), 1);
+    assert_eq!(value("Point@x"), 2);
+    assert_eq!(value("(missing utf8)"), 3);
+}
+
+#[test]
+fn test_dump_objects_prints_superclass_chain() {
+    const SHAPE_CLASS_ID: u64 = 9300;
+    const CIRCLE_CLASS_ID: u64 = 9301;
+    let mut segment = Segment::default();
+    segment
+        .class(OBJECT_CLASS_ID, 0, 0, &[], &[])
+        .class(SHAPE_CLASS_ID, OBJECT_CLASS_ID, 4, &[], &[(1, TYPE_INT)])
+        .class(CIRCLE_CLASS_ID, SHAPE_CLASS_ID, 8, &[], &[(2, TYPE_INT)])
+        .instance(9302, CIRCLE_CLASS_ID, &[Value::Int(5), Value::Int(1)]);
+    let dump = HprofBuilder::new()
+        .utf8(1, "sides")
+        .utf8(2, "radius")
+        .named_class(1, OBJECT_CLASS_ID, "java/lang/Object")
+        .named_class(2, SHAPE_CLASS_ID, "com/example/Shape")
+        .named_class(3, CIRCLE_CLASS_ID, "com/example/Circle")
+        .heap_dump_segment(&segment)
+        .finish();
+    let hprof = jvm_hprof::parse_hprof(&dump[..]).unwrap();
+    let options = DumpObjectsOptions {
+        filter: None,
+        window: ObjectWindow::default(),
+        sample: None,
+        max_array_elements: usize::MAX,
+        format: ObjectFormat::Text,
+        id_format: IdFormat::Decimal,
+        decode_char_arrays: false,
+        byte_array_format: ByteArrayFormat::Hex,
+        expand_depth: 0,
+        fail_on_unresolved: false,
+    };
+    let mut out = Vec::new();
+    dump_objects(&HeapDumpAnalyzer::new(&hprof), &options, &mut out).unwrap();
+    let text = String::from_utf8(out).unwrap();
+
+    assert!(text.contains("id 9301: class com/example/Circle\n  extends com/example/Shape -> java/lang/Object\n"), "{}", text);
+    assert!(text.contains("id 9300: class com/example/Shape\n  extends java/lang/Object\n"), "{}", text);
+    assert!(!text.contains(&format!("id {}: class java/lang/Object\n  extends", OBJECT_CLASS_ID)), "{}", text);
+    // The subclass's own field comes first, then the inherited one
+    assert!(text.contains("id 9302: com/example/Circle\n  - radius: int = 5\n  - sides: int = 1\n"), "{}", text);
+}
+
+#[test]
+fn test_find_instances_by_int_field() {
+    let dump = fixture();
+    let hprof = jvm_hprof::parse_hprof(&dump[..]).unwrap();
+    let find = |field: &str, comparison: Comparison, value: &str, print_fields: bool| {
+        let options = FindOptions {
+            class_name: "com.example.Point".to_string(),
+            field: field.to_string(),
+            comparison,
+            value: value.to_string(),
+            print_fields,
+            id_format: IdFormat::Decimal,
+        };
+        let mut out = Vec::new();
+        find_instances(&HeapDumpAnalyzer::new(&hprof), &options, &mut out).map(|()| String::from_utf8(out).unwrap())
+    };
+
+    // Point 1000 is (3, 4), point 1001 is (-1, 7)
+    assert_eq!(find("x", Comparison::Eq, "3", false).unwrap(), "1000\n");
+    assert_eq!(find("x", Comparison::Lt, "3", false).unwrap(), "1001\n");
+    assert_eq!(find("y", Comparison::Gt, "0", false).unwrap(), "1000\n1001\n");
+    assert_eq!(find("y", Comparison::Gt, "7", false).unwrap(), "");
+    let text = find("x", Comparison::Eq, "-1", true).unwrap();
+    assert!(text.contains("id 1001: com/example/Point\n  - x: int = -1\n"), "{}", text);
+    assert!(!text.contains("id 1000"), "{}", text);
+
+    let err = find("z", Comparison::Eq, "1", false).unwrap_err();
+    assert!(err.to_string().contains("has no field z (fields: x, y, next)"), "{}", err);
+    assert!(find("next", Comparison::Eq, "0", false).is_err());
+}
+
+/// Peak memory of `dump_objects_to_parquet` on a dump where every instance holds a
+/// reference, so resolving and storing reference types is most of the work. Linux only: it
+/// resets and reads the peak RSS in `/proc`. Compare the number across builds. Run with
+/// `cargo test --release bench_reference_type_peak_rss -- --ignored --nocapture`.
+#[cfg(target_os = "linux")]
+#[test]
+#[ignore]
+fn bench_reference_type_peak_rss() {
+    const CLASSES: u64 = 20;
+    const INSTANCES_PER_CLASS: u64 = 200_000;
+    const NEXT: u64 = 1;
+    const FIRST_CLASS_ID: u64 = 10_000;
+    const FIRST_INSTANCE_ID: u64 = 1_000_000;
+
+    /// A `kB` line of `/proc/self/status`, such as `VmHWM:`.
+    fn status_kb(field: &str) -> u64 {
+        let status = std::fs::read_to_string("/proc/self/status").unwrap();
+        let line = status.lines().find_map(|l| l.strip_prefix(field)).unwrap();
+        line.trim().trim_end_matches("kB").trim().parse().unwrap()
+    }
+
+    let mut builder = HprofBuilder::new();
+    builder.utf8(NEXT, "next").named_class(1, OBJECT_CLASS_ID, "java/lang/Object");
+    let mut classes = Segment::default();
+    classes.class(OBJECT_CLASS_ID, 0, 0, &[], &[]);
+    for c in 0..CLASSES {
+        builder.named_class(2 + c as u32, FIRST_CLASS_ID + c, &format!("com/example/deeply/nested/package/Node{}", c));
+        classes.class(FIRST_CLASS_ID + c, OBJECT_CLASS_ID, 8, &[], &[(NEXT, TYPE_OBJECT)]);
+    }
+    builder.heap_dump_segment(&classes);
+    // Each instance points at one of the next class over, so every type gets referenced
+    let total = CLASSES * INSTANCES_PER_CLASS;
+    for c in 0..CLASSES {
+        let mut segment = Segment::default();
+        for i in 0..INSTANCES_PER_CLASS {
+            let obj_id = FIRST_INSTANCE_ID + c * INSTANCES_PER_CLASS + i;
+            let next = FIRST_INSTANCE_ID + (obj_id - FIRST_INSTANCE_ID + INSTANCES_PER_CLASS + 1) % total;
+            segment.instance(obj_id, FIRST_CLASS_ID + c, &[Value::Object(next)]);
+        }
+        builder.heap_dump_segment(&segment);
+    }
+    let dump = builder.finish();
+    let hprof = jvm_hprof::parse_hprof(&dump[..]).unwrap();
+
+    let out_dir = std::env::temp_dir().join(format!("hdsd-bench-type-rss-{}", std::process::id()));
+    // Writing 5 resets the peak to the current RSS, which already holds the dump
+    std::fs::write("/proc/self/clear_refs", "5").unwrap();
+    let before = status_kb("VmRSS:");
+    dump_objects_to_parquet(&HeapDumpAnalyzer::new(&hprof), &parquet_options(out_dir.clone())).unwrap();
+    let peak = status_kb("VmHWM:");
+    std::fs::remove_dir_all(&out_dir).unwrap();
+
+    println!("dump-objects-to-parquet over {} references: peak RSS {} MiB, {} MiB above the {} MiB before it",
+        total, peak / 1024, peak.saturating_sub(before) / 1024, before / 1024);
+}
+
+#[test]
+fn test_timeout_stops_cleanly() {
+    let dump = fixture();
+    let hprof = jvm_hprof::parse_hprof(&dump[..]).unwrap();
+    let cancel = Arc::new(AtomicBool::new(false));
+    cancel_after(&cancel, Duration::from_millis(1));
+    while !cancel.load(Ordering::Relaxed) {
+        std::thread::sleep(Duration::from_millis(1));
+    }
+    let analyzer = HeapDumpAnalyzer::with_options(&hprof, AnalyzerOptions { cancel, ..AnalyzerOptions::default() });
+    assert!(analyzer.cancelled());
+
+    // The export stops and says so, leaving only readable files behind
+    let out_dir = std::env::temp_dir().join(format!("hdsd-cancel-{}", std::process::id()));
+    let err = dump_objects_to_parquet(&analyzer, &parquet_options(out_dir.clone())).unwrap_err();
+    assert!(matches!(err, AnalyzeError::Cancelled), "{}", err);
+    for entry in std::fs::read_dir(&out_dir).unwrap() {
+        let file = std::fs::File::open(entry.unwrap().path()).unwrap();
+        ParquetRecordBatchReaderBuilder::try_new(file).unwrap();
+    }
+    std::fs::remove_dir_all(&out_dir).unwrap();
+
+    // Other commands finish with what they read before the cancel, here nothing
+    assert!(!dump_text(&analyzer).unwrap().contains("id 1000"));
+    assert_eq!(analyzer.record_counts().unwrap().values().sum::<u64>(), 0);
+    // The token belongs to that analyzer, so others in the process read the whole dump
+    assert!(dump_text(&HeapDumpAnalyzer::new(&hprof)).unwrap().contains("id 1000: com/example/Point"));
+}
+
+/// Wall time of `dump-objects` then `dump-objects-to-parquet` on one dump, each indexing it
+/// for itself as the commands used to, against both sharing one analyzer and its index. Run
+/// with `cargo test --release bench_shared_index -- --ignored --nocapture`.
+#[test]
+#[ignore]
+fn bench_shared_index() {
+    use std::time::Instant;
+
+    let dump = segmented_points_dump(2_000);
+    let hprof = jvm_hprof::parse_hprof(&dump[..]).unwrap();
+    let out_dir = std::env::temp_dir().join(format!("hdsd-bench-shared-index-{}", std::process::id()));
+    let options = dump_objects_options(ObjectFormat::Json);
+    let run_both = |text: &HeapDumpAnalyzer, parquet: &HeapDumpAnalyzer| {
+        let start = Instant::now();
+        dump_objects(text, &options, &mut std::io::sink()).unwrap();
+        dump_objects_to_parquet(parquet, &parquet_options(out_dir.clone())).unwrap();
+        start.elapsed()
+    };
+
+    let separate = run_both(&HeapDumpAnalyzer::new(&hprof), &HeapDumpAnalyzer::new(&hprof));
+    let analyzer = HeapDumpAnalyzer::new(&hprof);
+    let shared = run_both(&analyzer, &analyzer);
+    std::fs::remove_dir_all(&out_dir).unwrap();
+
+    println!("dump-objects + dump-objects-to-parquet over {} objects: separate indexes {:.3}s, shared index {:.3}s",
+        2_000 * 250, separate.as_secs_f64(), shared.as_secs_f64());
+}
+
+const THIRD_POINT_ID: u64 = 1002;
+
+/// Points 1000 and 1001 in one segment, the second missing the last bytes of its `x`, then
+/// point 1002 in a second, intact segment.
+fn truncated_dump() -> Vec<u8> {
+    let mut classes = Segment::default();
+    classes
+        .class(OBJECT_CLASS_ID, 0, 0, &[], &[])
+        .class(POINT_CLASS_ID, OBJECT_CLASS_ID, 4, &[], &[(1, TYPE_INT)]);
+    let mut truncated = Segment::default();
+    truncated
+        .instance(FIRST_POINT_ID, POINT_CLASS_ID, &[Value::Int(3)])
+        .instance(SECOND_POINT_ID, POINT_CLASS_ID, &[Value::Int(4)])
+        .truncate_last(2);
+    let mut intact = Segment::default();
+    intact.instance(THIRD_POINT_ID, POINT_CLASS_ID, &[Value::Int(5)]);
+
+    HprofBuilder::new()
+        .utf8(1, "x")
+        .named_class(1, OBJECT_CLASS_ID, "java/lang/Object")
+        .named_class(2, POINT_CLASS_ID, "com/example/Point")
+        .heap_dump_segment(&classes)
+        .heap_dump_segment(&truncated)
+        .heap_dump_segment(&intact)
+        .finish()
+}
+
+#[test]
+fn test_skip_errors_reads_past_truncated_sub_record() {
+    let dump = truncated_dump();
+    let hprof = jvm_hprof::parse_hprof(&dump[..]).unwrap();
+
+    let err = dump_text(&HeapDumpAnalyzer::new(&hprof)).unwrap_err();
+    match err {
+        AnalyzeError::BadSubRecord { segment, position, offset, .. } => {
+            assert_eq!((segment, position), (1, 1));
+            assert!(offset.is_none_or(|o| o < dump.len() as u64), "{:?}", offset);
+        }
+        other => panic!("fails by default, naming the sub-record: {}", other),
+    }
+
+    let analyzer = HeapDumpAnalyzer::with_options(&hprof, AnalyzerOptions { skip_errors: true, ..AnalyzerOptions::default() });
+    let text = dump_text(&analyzer).unwrap();
+    assert!(text.contains("id 1000: com/example/Point\n  - x: int = 3\n"), "{}", text);
+    assert!(!text.contains("id 1001"), "{}", text);
+    assert!(text.contains("id 1002: com/example/Point\n  - x: int = 5\n"), "the next segment is still read: {}", text);
+    assert_eq!(analyzer.skipped_segments(), 1);
+
+    // The parquet export fails the same way, and with skip_errors writes the same rows
+    let out_dir = std::env::temp_dir().join(format!("hdsd-truncated-{}", std::process::id()));
+    let err = dump_objects_to_parquet(&HeapDumpAnalyzer::new(&hprof), &parquet_options(out_dir.clone())).unwrap_err();
+    assert!(matches!(err, AnalyzeError::BadSubRecord { segment: 1, position: 1, .. }), "{}", err);
+    let analyzer = HeapDumpAnalyzer::with_options(&hprof, AnalyzerOptions { skip_errors: true, ..AnalyzerOptions::default() });
+    dump_objects_to_parquet(&analyzer, &parquet_options(out_dir.clone())).unwrap();
+    let file = std::fs::File::open(out_dir.join("com.example.Point_200.parquet")).unwrap();
+    let obj_ids: Vec<u64> = ParquetRecordBatchReaderBuilder::try_new(file).unwrap().build().unwrap()
+        .flat_map(|batch| batch.unwrap().column_by_name("obj_id").unwrap().as_primitive::<UInt64Type>().values().to_vec())
+        .collect();
+    assert_eq!(obj_ids, vec![FIRST_POINT_ID, THIRD_POINT_ID]);
+    std::fs::remove_dir_all(&out_dir).unwrap();
+}
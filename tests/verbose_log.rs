@@ -0,0 +1,50 @@
+// SPDX-License-Identifier: MIT
+// Copyright (c) 2026 Zac Policzer
+
+//! The per-file lines `--verbose` shows. `log` takes one logger per process and it can't be
+//! swapped out afterwards, so these tests keep a binary to themselves.
+
+mod support;
+
+use std::sync::Mutex;
+use heap_dump_star_diver::commands::dump_objects_to_parquet;
+use heap_dump_star_diver::HeapDumpAnalyzer;
+use support::*;
+
+static LINES: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+struct CaptureLog;
+
+impl log::Log for CaptureLog {
+    fn enabled(&self, metadata: &log::Metadata) -> bool {
+        metadata.level() <= log::Level::Info
+    }
+
+    fn log(&self, record: &log::Record) {
+        if self.enabled(record.metadata()) {
+            LINES.lock().unwrap().push(record.args().to_string());
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+#[test]
+fn test_verbose_logs_rows_columns_and_bytes_per_file() {
+    log::set_logger(&CaptureLog).unwrap();
+    log::set_max_level(log::LevelFilter::Info);
+
+    let dump = fixture();
+    let hprof = jvm_hprof::parse_hprof(&dump[..]).unwrap();
+    let out_dir = std::env::temp_dir().join(format!("hdsd-verbose-{}", std::process::id()));
+    dump_objects_to_parquet(&HeapDumpAnalyzer::new(&hprof), &parquet_options(out_dir.clone())).unwrap();
+    let path = out_dir.join("com.example.Point_200.parquet");
+    let bytes = std::fs::metadata(&path).unwrap().len();
+    std::fs::remove_dir_all(&out_dir).unwrap();
+
+    let lines = LINES.lock().unwrap();
+    let prefix = format!("wrote com/example/Point_200 to {}: ", path.display());
+    let line = lines.iter().find(|l| l.starts_with(&prefix)).unwrap_or_else(|| panic!("{:?}", lines));
+    // obj_id, x, y and next
+    assert_eq!(&line[prefix.len()..], format!("2 rows, 4 columns, {} bytes", bytes));
+}